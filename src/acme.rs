@@ -1,14 +1,225 @@
+use std::sync::LazyLock;
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
 use color_eyre::eyre::{Context, eyre};
 use instant_acme::{
-    Account, AccountCredentials, AuthorizationStatus, ChallengeType, Identifier, LetsEncrypt,
-    NewAccount, NewOrder, OrderStatus,
+    Account, AccountCredentials, AuthorizationStatus, ChallengeType, ExternalAccountKey,
+    Identifier, LetsEncrypt, NewAccount, NewOrder, OrderStatus,
 };
+use jiff::{SignedDuration, Timestamp};
+use pingora::tls::pkey::PKey;
+use pingora::tls::rsa::Rsa;
 use rcgen::{CertificateParams, DistinguishedName, KeyPair};
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 use crate::challenge::{ChallengeStore, add_challenge, remove_challenge};
-use crate::config::TlsConfig;
+use crate::config::{KeyType, TlsConfig};
 use crate::db::SqliteDatabase;
+use crate::outbound::AcmeHttp;
+
+/// Delay after the first failed issuance; doubled for each further failure.
+const BACKOFF_BASE_SECS: i64 = 300;
+
+/// Upper bound on the delay between issuance attempts.
+const BACKOFF_MAX_SECS: i64 = 86_400;
+
+/// Minimum delay after a rate-limit response without a parsable retry time.
+const RATE_LIMIT_FALLBACK_SECS: i64 = 3_600;
+
+const RATE_LIMITED_PROBLEM: &str = "urn:ietf:params:acme:error:rateLimited";
+
+/// Delay before the next attempt after `failures` consecutive failures.
+fn backoff_delay(failures: u32) -> SignedDuration {
+    let exponent = failures.saturating_sub(1).min(16);
+    let secs = BACKOFF_BASE_SECS
+        .saturating_mul(1 << exponent)
+        .min(BACKOFF_MAX_SECS);
+    SignedDuration::from_secs(secs)
+}
+
+/// Returns the ACME problem detail if the error is a rate-limit response.
+fn rate_limit_detail(error: &color_eyre::Report) -> Option<String> {
+    error.chain().find_map(|cause| match cause.downcast_ref() {
+        Some(instant_acme::Error::Api(problem))
+            if problem.r#type.as_deref() == Some(RATE_LIMITED_PROBLEM) =>
+        {
+            Some(problem.detail.clone().unwrap_or_default())
+        }
+        _ => None,
+    })
+}
+
+/// Parses the "retry after YYYY-MM-DD HH:MM:SS UTC" hint Let's Encrypt
+/// includes in rate-limit problem details.
+fn parse_retry_after(detail: &str) -> Option<Timestamp> {
+    let (_, rest) = detail.split_once("retry after ")?;
+    let (datetime, _) = rest.split_once(" UTC")?;
+    let datetime: jiff::civil::DateTime = datetime.parse().ok()?;
+    datetime
+        .to_zoned(jiff::tz::TimeZone::UTC)
+        .ok()
+        .map(|z| z.timestamp())
+}
+
+/// Returns when issuance for `domain` may next be attempted, if a previous
+/// failure is still backing off.
+pub async fn backoff_until(db: &SqliteDatabase, domain: &str) -> Option<Timestamp> {
+    let (_, retry_at) = match db.get_certificate_failure(domain).await {
+        Ok(failure) => failure?,
+        Err(e) => {
+            warn!(domain = %domain, error = %e, "failed to read certificate failure state");
+            return None;
+        }
+    };
+    let retry_at = Timestamp::from_millisecond(retry_at).ok()?;
+    (retry_at > Timestamp::now()).then_some(retry_at)
+}
+
+/// Persists a failed issuance and schedules the next attempt, honouring
+/// rate-limit responses from the CA. Returns the next retry time.
+pub async fn record_failure(
+    db: &SqliteDatabase,
+    domain: &str,
+    error: &color_eyre::Report,
+) -> Timestamp {
+    let failures = match db.get_certificate_failure(domain).await {
+        Ok(failure) => failure.map_or(0, |(failures, _)| failures),
+        Err(e) => {
+            warn!(domain = %domain, error = %e, "failed to read certificate failure state");
+            0
+        }
+    } + 1;
+
+    let now = Timestamp::now();
+    let backoff = now + backoff_delay(failures);
+    let rate_limit = rate_limit_detail(error);
+    let retry_at = match &rate_limit {
+        Some(detail) => parse_retry_after(detail)
+            .unwrap_or(now + SignedDuration::from_secs(RATE_LIMIT_FALLBACK_SECS))
+            .max(backoff),
+        None => backoff,
+    };
+
+    if rate_limit.is_some() {
+        warn!(domain = %domain, retry_at = %retry_at, "rate limited by ACME server");
+    }
+
+    if let Err(e) = db
+        .save_certificate_failure(
+            domain,
+            failures,
+            &error.to_string(),
+            rate_limit.is_some(),
+            retry_at.as_millisecond(),
+        )
+        .await
+    {
+        warn!(domain = %domain, error = %e, "failed to save certificate failure state");
+    }
+
+    retry_at
+}
+
+/// Clears the failure state for a domain after a successful issuance.
+pub async fn clear_failure(db: &SqliteDatabase, domain: &str) {
+    if let Err(e) = db.clear_certificate_failure(domain).await {
+        warn!(domain = %domain, error = %e, "failed to clear certificate failure state");
+    }
+}
+
+/// Identifies this process as the holder of issuance leases.
+static INSTANCE_ID: LazyLock<String> = LazyLock::new(|| ulid::Ulid::new().to_string());
+
+/// How long an issuance lease lasts. Longer than any order takes, so it
+/// only runs out when the instance holding it died mid-order.
+const ISSUANCE_LEASE_SECS: i64 = 1_800;
+
+fn issuance_lease(domain: &str) -> String {
+    format!("issuance:{domain}")
+}
+
+/// With `shared_storage`, claims issuance of the certificate for `domain`
+/// among the instances sharing the database. Returns false if another
+/// instance is issuing it; always true otherwise.
+pub async fn claim_issuance(db: &SqliteDatabase, tls_config: &TlsConfig, domain: &str) -> bool {
+    if !tls_config.shared_storage {
+        return true;
+    }
+    let expires_at = Timestamp::now() + SignedDuration::from_secs(ISSUANCE_LEASE_SECS);
+    match db
+        .acquire_lease(
+            &issuance_lease(domain),
+            &INSTANCE_ID,
+            expires_at.as_millisecond(),
+        )
+        .await
+    {
+        Ok(claimed) => claimed,
+        Err(e) => {
+            warn!(domain = %domain, error = %e, "failed to claim certificate issuance");
+            false
+        }
+    }
+}
+
+/// Lets other instances issue the certificate for `domain` again.
+pub async fn release_issuance(db: &SqliteDatabase, tls_config: &TlsConfig, domain: &str) {
+    if !tls_config.shared_storage {
+        return;
+    }
+    if let Err(e) = db
+        .release_lease(&issuance_lease(domain), &INSTANCE_ID)
+        .await
+    {
+        warn!(domain = %domain, error = %e, "failed to release certificate issuance");
+    }
+}
+
+/// Timeout for fetching our own challenge response before telling the CA.
+const SELF_CHECK_TIMEOUT_SECS: u64 = 10;
+
+/// Fetches the HTTP-01 response for `token` from `domain` the way the CA
+/// will, so DNS or firewall problems surface before the order is spent.
+async fn verify_challenge(
+    http: &reqwest::Client,
+    domain: &str,
+    token: &str,
+    key_auth: &str,
+) -> color_eyre::Result<()> {
+    let url = format!("http://{domain}/.well-known/acme-challenge/{token}");
+    let hint = format!(
+        "make sure {domain} resolves to this server and port 80 is reachable from the internet \
+         (set verify_challenges = false in [tls] to skip this check)"
+    );
+
+    let response = http
+        .get(&url)
+        .timeout(std::time::Duration::from_secs(SELF_CHECK_TIMEOUT_SECS))
+        .send()
+        .await
+        .map_err(|e| eyre!("challenge self-check could not fetch {url}: {e}; {hint}"))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        return Err(eyre!(
+            "challenge self-check got HTTP {status} from {url}; {hint}"
+        ));
+    }
+
+    let body = response
+        .text()
+        .await
+        .map_err(|e| eyre!("challenge self-check could not read {url}: {e}; {hint}"))?;
+    if body.trim() != key_auth {
+        return Err(eyre!(
+            "challenge self-check got an unexpected response from {url} (is another server answering for {domain}?); {hint}"
+        ));
+    }
+
+    debug!(domain = %domain, "challenge self-check passed");
+    Ok(())
+}
 
 async fn cleanup_pending_challenges(challenge_store: &ChallengeStore, tokens: &[String]) {
     for token in tokens {
@@ -20,6 +231,9 @@ async fn cleanup_pending_challenges(challenge_store: &ChallengeStore, tokens: &[
 pub struct AcmeClient {
     account: Account,
     staging: bool,
+    key_type: KeyType,
+    verify_challenges: bool,
+    http: reqwest::Client,
     order_poll_interval_secs: u64,
     order_poll_max_retries: u32,
     cert_poll_interval_secs: u64,
@@ -36,8 +250,7 @@ impl AcmeClient {
             }
             None => {
                 info!("creating new ACME account");
-                let (account, pem) =
-                    Self::create_account(&config.acme_email, config.staging).await?;
+                let (account, pem) = Self::create_account(config).await?;
                 db.save_acme_account(&pem).await?;
                 account
             }
@@ -46,6 +259,9 @@ impl AcmeClient {
         Ok(Self {
             account,
             staging: config.staging,
+            key_type: config.key_type,
+            verify_challenges: config.verify_challenges,
+            http: crate::outbound::client(),
             order_poll_interval_secs: config.order_poll_interval_secs,
             order_poll_max_retries: config.order_poll_max_retries,
             cert_poll_interval_secs: config.cert_poll_interval_secs,
@@ -53,24 +269,47 @@ impl AcmeClient {
         })
     }
 
+    /// Returns the ACME directory URL for the configured CA.
+    fn directory_url(config: &TlsConfig) -> String {
+        match &config.acme_directory_url {
+            Some(url) => url.clone(),
+            None if config.staging => LetsEncrypt::Staging.url().to_owned(),
+            None => LetsEncrypt::Production.url().to_owned(),
+        }
+    }
+
+    /// Builds the External Account Binding key if both EAB fields are configured.
+    fn external_account_key(config: &TlsConfig) -> color_eyre::Result<Option<ExternalAccountKey>> {
+        match (&config.eab_kid, &config.eab_hmac_key) {
+            (Some(kid), Some(hmac_key)) => {
+                let key = URL_SAFE_NO_PAD
+                    .decode(hmac_key.trim_end_matches('='))
+                    .wrap_err("failed to decode eab_hmac_key as base64url")?;
+                Ok(Some(ExternalAccountKey::new(kid.clone(), &key)))
+            }
+            (None, None) => Ok(None),
+            _ => Err(eyre!("eab_kid and eab_hmac_key must be set together")),
+        }
+    }
+
     /// Creates a new ACME account and returns it along with the private key PEM.
-    async fn create_account(email: &str, staging: bool) -> color_eyre::Result<(Account, String)> {
-        let url = if staging {
-            LetsEncrypt::Staging.url()
-        } else {
-            LetsEncrypt::Production.url()
-        };
+    async fn create_account(config: &TlsConfig) -> color_eyre::Result<(Account, String)> {
+        let url = Self::directory_url(config);
+        let external_account = Self::external_account_key(config)?;
+
+        if external_account.is_some() {
+            info!(directory = %url, "registering ACME account with external account binding");
+        }
 
-        let (account, credentials) = Account::builder()
-            .wrap_err("failed to create ACME account builder")?
+        let (account, credentials) = Account::builder_with_http(Box::new(AcmeHttp::default()))
             .create(
                 &NewAccount {
-                    contact: &[&format!("mailto:{}", email)],
+                    contact: &[&format!("mailto:{}", config.acme_email)],
                     terms_of_service_agreed: true,
                     only_return_existing: false,
                 },
-                url.to_string(),
-                None,
+                url,
+                external_account.as_ref(),
             )
             .await
             .wrap_err("failed to create ACME account")?;
@@ -86,19 +325,55 @@ impl AcmeClient {
         let credentials: AccountCredentials =
             serde_json::from_str(pem).wrap_err("failed to deserialize ACME credentials")?;
 
-        Account::builder()
-            .wrap_err("failed to create ACME account builder")?
+        Account::builder_with_http(Box::new(AcmeHttp::default()))
             .from_credentials(credentials)
             .await
             .wrap_err("failed to load ACME account")
     }
 
+    /// Generates a fresh private key of the configured type.
+    fn generate_key_pair(&self) -> color_eyre::Result<KeyPair> {
+        match self.key_type {
+            KeyType::EcdsaP256 => KeyPair::generate_for(&rcgen::PKCS_ECDSA_P256_SHA256)
+                .wrap_err("failed to generate ECDSA key pair"),
+            KeyType::Rsa2048 => {
+                let rsa = Rsa::generate(2048).wrap_err("failed to generate RSA key")?;
+                let pem = PKey::from_rsa(rsa)
+                    .and_then(|key| key.private_key_to_pem_pkcs8())
+                    .wrap_err("failed to encode RSA key")?;
+                let pem = String::from_utf8(pem).wrap_err("RSA key PEM is not valid UTF-8")?;
+                KeyPair::from_pem_and_sign_algo(&pem, &rcgen::PKCS_RSA_SHA256)
+                    .wrap_err("failed to load RSA key pair")
+            }
+        }
+    }
+
+    /// Returns the key pair for the CSR: the previous key when one is given
+    /// and parses, otherwise a freshly generated one.
+    fn csr_key_pair(&self, reuse_key_pem: Option<&str>) -> color_eyre::Result<KeyPair> {
+        if let Some(pem) = reuse_key_pem {
+            match KeyPair::from_pem(pem) {
+                Ok(key_pair) => {
+                    debug!("reusing existing private key");
+                    return Ok(key_pair);
+                }
+                Err(e) => {
+                    warn!(error = %e, "failed to parse existing private key, generating a new one");
+                }
+            }
+        }
+
+        self.generate_key_pair()
+    }
+
     /// Requests a certificate for the given domains.
     /// Returns the certificate and private key as PEM strings.
+    /// When `reuse_key_pem` is set, the CSR is signed with that key instead of a new one.
     pub async fn obtain_certificate(
         &self,
         domains: &[&str],
         challenge_store: &ChallengeStore,
+        reuse_key_pem: Option<&str>,
     ) -> color_eyre::Result<(String, String)> {
         if domains.is_empty() {
             return Err(eyre!("no domains provided"));
@@ -142,6 +417,11 @@ impl AcmeClient {
                 }
             }
 
+            let domain = match auth.identifier().identifier {
+                Identifier::Dns(domain) => Some(domain.clone()),
+                _ => None,
+            };
+
             let mut challenge = auth
                 .challenge(ChallengeType::Http01)
                 .ok_or_else(|| eyre!("no HTTP-01 challenge found"))?;
@@ -149,8 +429,16 @@ impl AcmeClient {
             let token = challenge.token.clone();
             let key_auth = challenge.key_authorization().as_str().to_owned();
 
-            add_challenge(challenge_store, token.clone(), key_auth).await;
-            pending_tokens.push(token);
+            add_challenge(challenge_store, token.clone(), key_auth.clone()).await;
+            pending_tokens.push(token.clone());
+
+            if self.verify_challenges
+                && let Some(domain) = &domain
+                && let Err(e) = verify_challenge(&self.http, domain, &token, &key_auth).await
+            {
+                cleanup_pending_challenges(challenge_store, &pending_tokens).await;
+                return Err(e);
+            }
 
             challenge
                 .set_ready()
@@ -196,7 +484,7 @@ impl AcmeClient {
         cleanup_pending_challenges(challenge_store, &pending_tokens).await;
 
         // Generate CSR
-        let key_pair = KeyPair::generate().wrap_err("failed to generate key pair")?;
+        let key_pair = self.csr_key_pair(reuse_key_pem)?;
         let private_key_pem = key_pair.serialize_pem();
 
         let domain_strings: Vec<String> = domains.iter().map(|s| (*s).to_owned()).collect();
@@ -239,3 +527,52 @@ impl AcmeClient {
         Ok((cert_chain_pem, private_key_pem))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn leases_are_held_by_one_instance_until_released_or_expired() {
+        let db = SqliteDatabase::new("sqlite::memory:").await.unwrap();
+        let later = Timestamp::now().as_millisecond() + 60_000;
+        let earlier = Timestamp::now().as_millisecond() - 1;
+
+        assert!(db.acquire_lease("issuance:a", "one", later).await.unwrap());
+        assert!(!db.acquire_lease("issuance:a", "two", later).await.unwrap());
+        assert!(db.acquire_lease("issuance:a", "one", later).await.unwrap());
+        assert!(db.acquire_lease("issuance:b", "two", later).await.unwrap());
+
+        db.release_lease("issuance:a", "two").await.unwrap();
+        assert!(!db.acquire_lease("issuance:a", "two", later).await.unwrap());
+        db.release_lease("issuance:a", "one").await.unwrap();
+        assert!(
+            db.acquire_lease("issuance:a", "two", earlier)
+                .await
+                .unwrap()
+        );
+
+        // An expired lease is up for grabs.
+        assert!(db.acquire_lease("issuance:a", "one", later).await.unwrap());
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_cap() {
+        assert_eq!(backoff_delay(1), SignedDuration::from_secs(300));
+        assert_eq!(backoff_delay(2), SignedDuration::from_secs(600));
+        assert_eq!(backoff_delay(4), SignedDuration::from_secs(2400));
+        assert_eq!(backoff_delay(20), SignedDuration::from_secs(86_400));
+    }
+
+    #[test]
+    fn parse_lets_encrypt_retry_after() {
+        let detail = "too many certificates (5) already issued for this exact set of \
+                      domains in the last 168h0m0s, retry after 2025-03-04 05:06:07 UTC: \
+                      see https://letsencrypt.org/docs/rate-limits/";
+        assert_eq!(
+            parse_retry_after(detail),
+            Some("2025-03-04T05:06:07Z".parse().unwrap())
+        );
+        assert_eq!(parse_retry_after("too many new orders recently"), None);
+    }
+}
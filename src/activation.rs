@@ -0,0 +1,189 @@
+use std::net::{SocketAddr, TcpListener};
+use std::os::fd::{FromRawFd, IntoRawFd, RawFd};
+
+use color_eyre::eyre::Context;
+use pingora::server::{ListenFds, ShutdownWatch};
+use pingora::services::Service;
+use tracing::info;
+
+/// First file descriptor passed by systemd (`SD_LISTEN_FDS_START`).
+const LISTEN_FDS_START: RawFd = 3;
+
+/// A listening socket handed over by systemd socket activation.
+#[derive(Debug)]
+pub struct ActivatedSocket {
+    /// The `FileDescriptorName=` of the socket unit, if any.
+    pub name: String,
+    pub addr: SocketAddr,
+    pub fd: RawFd,
+}
+
+/// Takes the listening sockets passed by systemd (`LISTEN_FDS`), if any.
+///
+/// Clears the activation variables so app processes do not inherit them, so
+/// this must run before any other threads are spawned.
+pub fn take_listen_fds() -> color_eyre::Result<Vec<ActivatedSocket>> {
+    let pid = std::env::var("LISTEN_PID").ok();
+    let count = std::env::var("LISTEN_FDS").ok();
+    let names = std::env::var("LISTEN_FDNAMES").unwrap_or_default();
+    for var in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        // SAFETY: called during startup, before penny spawns any threads.
+        unsafe { std::env::remove_var(var) };
+    }
+
+    let (Some(pid), Some(count)) = (pid, count) else {
+        return Ok(Vec::new());
+    };
+    if pid.parse::<u32>().ok() != Some(std::process::id()) {
+        return Ok(Vec::new());
+    }
+    let count: RawFd = count
+        .parse()
+        .wrap_err_with(|| format!("invalid LISTEN_FDS value '{count}'"))?;
+    let names: Vec<&str> = names.split(':').collect();
+
+    (0..count)
+        .map(|i| {
+            let fd = LISTEN_FDS_START + i;
+            // SAFETY: systemd hands this process ownership of fds
+            // 3..3+LISTEN_FDS, and nothing else has claimed them.
+            let listener = unsafe { TcpListener::from_raw_fd(fd) };
+            let addr = listener
+                .local_addr()
+                .wrap_err_with(|| format!("activated fd {fd} is not a TCP listener"))?;
+
+            // Duplicating sets close-on-exec, so app processes started by
+            // penny do not inherit the listener.
+            let listener = listener
+                .try_clone()
+                .wrap_err_with(|| format!("failed to take over activated fd {fd}"))?;
+            listener.set_nonblocking(true)?;
+
+            let name = names.get(i as usize).copied().unwrap_or_default();
+            info!(fd, name, address = %addr, "received socket from systemd");
+            Ok(ActivatedSocket {
+                name: name.to_owned(),
+                addr,
+                fd: listener.into_raw_fd(),
+            })
+        })
+        .collect()
+}
+
+/// Finds the activated socket for each of a listener's `addresses`. An
+/// address served by a socket bound to it takes that one; the rest take the
+/// sockets named `name` (`FileDescriptorName=`) in order, so one socket unit
+/// with several `ListenStream=` lines covers several addresses.
+pub fn find_all<'a>(
+    sockets: &'a [ActivatedSocket],
+    name: &str,
+    addresses: &[String],
+) -> Vec<Option<&'a ActivatedSocket>> {
+    let addresses: Vec<Option<SocketAddr>> = addresses
+        .iter()
+        .map(|address| address.parse().ok())
+        .collect();
+    let mut named = sockets
+        .iter()
+        .filter(|socket| socket.name == name && !addresses.contains(&Some(socket.addr)));
+
+    addresses
+        .iter()
+        .map(|address| {
+            sockets
+                .iter()
+                .find(|socket| Some(socket.addr) == *address)
+                .or_else(|| named.next())
+        })
+        .collect()
+}
+
+/// Wraps a pingora service so it listens on sockets penny already holds
+/// (from systemd, or bound before dropping privileges) instead of binding
+/// its own. Pingora looks listeners up by the address they were added with
+/// before binding, the same table it fills on graceful upgrade.
+pub struct SocketActivated<S> {
+    inner: S,
+    fds: Vec<(String, RawFd)>,
+}
+
+impl<S> SocketActivated<S> {
+    /// `fds` maps each address passed to `add_tcp`/`add_tls_*` to the
+    /// socket that should serve it.
+    pub fn new(inner: S, fds: Vec<(String, RawFd)>) -> Self {
+        Self { inner, fds }
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: Service> Service for SocketActivated<S> {
+    async fn start_service(
+        &mut self,
+        fds: Option<ListenFds>,
+        shutdown: ShutdownWatch,
+        listeners_per_fd: usize,
+    ) {
+        if let Some(table) = &fds {
+            let mut table = table.lock().await;
+            for (address, fd) in &self.fds {
+                table.add(address.clone(), *fd);
+            }
+        }
+        self.inner
+            .start_service(fds, shutdown, listeners_per_fd)
+            .await
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn threads(&self) -> Option<usize> {
+        self.inner.threads()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn socket(name: &str, addr: &str, fd: RawFd) -> ActivatedSocket {
+        ActivatedSocket {
+            name: name.to_owned(),
+            addr: addr.parse().unwrap(),
+            fd,
+        }
+    }
+
+    fn find(sockets: &[ActivatedSocket], name: &str, address: &str) -> Option<RawFd> {
+        find_all(sockets, name, &[address.to_owned()])[0].map(|socket| socket.fd)
+    }
+
+    #[test]
+    fn find_matches_address_then_name() {
+        let sockets = vec![
+            socket("unknown", "0.0.0.0:443", 3),
+            socket("http", "0.0.0.0:8080", 4),
+        ];
+
+        assert_eq!(find(&sockets, "http", "0.0.0.0:80"), Some(4));
+        assert_eq!(find(&sockets, "https", "0.0.0.0:443"), Some(3));
+        assert!(find(&sockets, "https", "0.0.0.0:8443").is_none());
+    }
+
+    #[test]
+    fn find_all_spreads_named_sockets_over_addresses() {
+        let sockets = vec![
+            socket("http", "0.0.0.0:80", 3),
+            socket("http", "[::]:80", 4),
+            socket("http", "[::]:8080", 5),
+        ];
+        let addresses = ["[::]:80".to_owned(), "10.0.0.1:80".to_owned()];
+
+        let fds: Vec<_> = find_all(&sockets, "http", &addresses)
+            .into_iter()
+            .map(|socket| socket.map(|socket| socket.fd))
+            .collect();
+        assert_eq!(fds, [Some(4), Some(3)]);
+    }
+}
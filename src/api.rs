@@ -1,16 +1,25 @@
 use axum::extract::{Query, State};
+use axum::http::Method;
 use axum::middleware;
-use axum::routing::get;
+use axum::routing::{get, post};
 use axum::{Extension, Json, Router};
 use serde::Serialize;
 use tower_http::cors::{Any, CorsLayer};
 
-use crate::auth::{auth_middleware, auth_status_handler};
+use crate::auth::{auth_middleware, auth_status_handler, password_required_middleware};
+use crate::collector::Collector;
+use crate::config::{App, Config, SharedConfig, normalize_hostname};
+use crate::idle::IdleScheduler;
+use crate::procfs;
+use crate::reload::ConfigFile;
+use crate::tls::CertificateStore;
 
 use crate::reporter::{
-    AppOverview, AppRun, PaginatedResponse, PaginationParams, Reporter, TimeRange, TotalOverview,
+    AppOverview, AppRun, CertificateEventEntry, CertificateFailureEntry, MonitorHistory,
+    PaginatedResponse, PaginationParams, ProxyErrorEntry, Reporter, StorageReport, TimeRange,
+    TimeRangeQuery, TotalOverview, WaitDecisionEntry, WakeStats,
 };
-use crate::types::{Host, RunId};
+use crate::types::{Host, RunId, WakeReason};
 
 #[derive(Debug, Clone)]
 pub struct PaginationConfig {
@@ -18,6 +27,48 @@ pub struct PaginationConfig {
     pub max_limit: u32,
 }
 
+impl PaginationConfig {
+    /// The page size for a request asking for `requested` items, if it
+    /// asked at all.
+    fn limit(&self, requested: Option<u32>) -> u32 {
+        requested
+            .unwrap_or(self.default_limit)
+            .min(self.max_limit)
+            .max(1)
+    }
+}
+
+impl From<&Config> for PaginationConfig {
+    fn from(config: &Config) -> Self {
+        Self {
+            default_limit: config.default_page_limit,
+            max_limit: config.max_page_limit,
+        }
+    }
+}
+
+/// The time range a request asks for, from `start`/`end` or `since`/`tz`.
+/// Rejects the request with a 400 if it cannot be resolved.
+struct RequestedRange(Option<TimeRange>);
+
+impl<S: Send + Sync> axum::extract::FromRequestParts<S> for RequestedRange {
+    type Rejection = (axum::http::StatusCode, String);
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let bad_request = |e: String| (axum::http::StatusCode::BAD_REQUEST, e);
+        let Query(query) = Query::<TimeRangeQuery>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| bad_request(e.body_text()))?;
+        query
+            .resolve(jiff::Timestamp::now())
+            .map(Self)
+            .map_err(bad_request)
+    }
+}
+
 #[derive(rust_embed::RustEmbed)]
 #[folder = "ui/dist"]
 pub struct UiAssets;
@@ -33,7 +84,22 @@ async fn version_handler() -> Json<VersionResponse> {
     })
 }
 
-async fn static_handler(uri: axum::http::Uri) -> impl axum::response::IntoResponse {
+/// Penny's own metrics in the Prometheus text format: its idle scheduler,
+/// waits for app locks, and storing app output.
+async fn metrics_handler() -> impl axum::response::IntoResponse {
+    (
+        [(
+            axum::http::header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        crate::metrics::render(),
+    )
+}
+
+async fn static_handler(
+    uri: axum::http::Uri,
+    headers: axum::http::HeaderMap,
+) -> impl axum::response::IntoResponse {
     use axum::response::IntoResponse;
 
     let path = uri.path().trim_start_matches('/');
@@ -48,52 +114,135 @@ async fn static_handler(uri: axum::http::Uri) -> impl axum::response::IntoRespon
             .into_response();
     }
 
-    // SPA fallback: serve index.html for all other routes
+    // SPA fallback: serve index.html for all other routes. Its asset paths
+    // are relative, so point them at the root the dashboard is served under.
+    let prefix = headers
+        .get("X-Forwarded-Prefix")
+        .and_then(|prefix| prefix.to_str().ok())
+        .filter(|prefix| *prefix == crate::config::API_PATH_PREFIX)
+        .unwrap_or_default();
     match UiAssets::get("index.html") {
         Some(content) => (
             [(axum::http::header::CONTENT_TYPE, "text/html")],
-            content.data.into_owned(),
+            String::from_utf8_lossy(&content.data).replacen(
+                "<head>",
+                &format!("<head><base href=\"{prefix}/\">"),
+                1,
+            ),
         )
             .into_response(),
         None => axum::http::StatusCode::NOT_FOUND.into_response(),
     }
 }
 
+/// Energy an app drawing `watts` while awake did not use while asleep.
+fn energy_kwh(watts: f64, sleep_time_ms: i64) -> f64 {
+    watts * sleep_time_ms as f64 / 3_600_000.0 / 1000.0
+}
+
+/// Fills in the energy saved by apps that set `watts`.
+async fn estimate_energy(config: &SharedConfig, overviews: &mut [AppOverview]) {
+    let config = config.read().await;
+    for overview in overviews {
+        if let Some(app) = config.apps.get(&overview.host)
+            && let Some(watts) = app.read().await.watts
+        {
+            overview.energy_saved_kwh = Some(energy_kwh(watts, overview.total_sleep_time_ms));
+        }
+    }
+}
+
 async fn total_overview_handler<R: Reporter>(
     State(reporter): State<R>,
-    Query(time_range): Query<TimeRange>,
+    Extension(config): Extension<SharedConfig>,
+    RequestedRange(time_range): RequestedRange,
 ) -> Json<TotalOverview> {
-    let time_range = time_range.into_option();
-    Json(reporter.total_overview(time_range).await)
+    let mut total = reporter.total_overview(time_range.clone()).await;
+
+    let mut apps = reporter.apps_overview(time_range).await;
+    estimate_energy(&config, &mut apps).await;
+    total.energy_saved_kwh = apps
+        .iter()
+        .filter_map(|app| app.energy_saved_kwh)
+        .reduce(|a, b| a + b);
+    Json(total)
 }
 
 async fn apps_overview_handler<R: Reporter>(
     State(reporter): State<R>,
-    Query(time_range): Query<TimeRange>,
+    Extension(config): Extension<SharedConfig>,
+    RequestedRange(time_range): RequestedRange,
 ) -> Json<Vec<AppOverview>> {
-    let time_range = time_range.into_option();
-    Json(reporter.apps_overview(time_range).await)
+    let mut apps = reporter.apps_overview(time_range).await;
+    estimate_energy(&config, &mut apps).await;
+    Json(apps)
 }
 
 async fn app_overview_handler<R: Reporter>(
     State(reporter): State<R>,
+    Extension(config): Extension<SharedConfig>,
     axum::extract::Path(host): axum::extract::Path<String>,
-    Query(time_range): Query<TimeRange>,
+    RequestedRange(time_range): RequestedRange,
 ) -> impl axum::response::IntoResponse {
     use axum::response::IntoResponse;
 
-    let time_range = time_range.into_option();
-
     match reporter.app_overview(&Host(host), time_range).await {
-        Some(overview) => Json(overview).into_response(),
+        Some(mut overview) => {
+            estimate_energy(&config, std::slice::from_mut(&mut overview)).await;
+            Json(overview).into_response()
+        }
         None => axum::http::StatusCode::NOT_FOUND.into_response(),
     }
 }
 
+async fn wake_stats_handler<R: Reporter>(
+    State(reporter): State<R>,
+    axum::extract::Path(host): axum::extract::Path<String>,
+    RequestedRange(time_range): RequestedRange,
+) -> Json<WakeStats> {
+    let host = normalize_hostname(&host).unwrap_or(host);
+    Json(reporter.wake_stats(&Host(host), time_range).await)
+}
+
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+struct HistoryQuery {
+    limit: Option<u32>,
+}
+
+async fn monitor_handler<R: Reporter>(
+    State(reporter): State<R>,
+    Extension(pagination_config): Extension<PaginationConfig>,
+    axum::extract::Path(host): axum::extract::Path<String>,
+    RequestedRange(time_range): RequestedRange,
+    Query(query): Query<HistoryQuery>,
+) -> Json<MonitorHistory> {
+    let host = normalize_hostname(&host).unwrap_or(host);
+    let limit = pagination_config.limit(query.limit);
+    Json(
+        reporter
+            .monitor_history(&Host(host), time_range, limit)
+            .await,
+    )
+}
+
+async fn wait_decisions_handler<R: Reporter>(
+    State(reporter): State<R>,
+    Extension(pagination_config): Extension<PaginationConfig>,
+    axum::extract::Path(host): axum::extract::Path<String>,
+    RequestedRange(time_range): RequestedRange,
+    Query(query): Query<HistoryQuery>,
+) -> Json<Vec<WaitDecisionEntry>> {
+    let host = normalize_hostname(&host).unwrap_or(host);
+    let limit = pagination_config.limit(query.limit);
+    Json(
+        reporter
+            .wait_decisions(&Host(host), time_range, limit)
+            .await,
+    )
+}
+
 #[derive(Debug, Clone, Default, serde::Deserialize)]
 struct AppRunsQuery {
-    start: Option<i64>,
-    end: Option<i64>,
     cursor: Option<i64>,
     limit: Option<u32>,
 }
@@ -102,25 +251,14 @@ async fn app_runs_handler<R: Reporter>(
     State(reporter): State<R>,
     Extension(pagination_config): Extension<PaginationConfig>,
     axum::extract::Path(host): axum::extract::Path<String>,
+    RequestedRange(time_range): RequestedRange,
     Query(query): Query<AppRunsQuery>,
 ) -> Json<PaginatedResponse<AppRun>> {
-    let time_range = if query.start.is_some() || query.end.is_some() {
-        Some(TimeRange {
-            start: query.start,
-            end: query.end,
-        })
-    } else {
-        None
-    };
-
-    let limit = query
-        .limit
-        .unwrap_or(pagination_config.default_limit)
-        .min(pagination_config.max_limit);
+    let limit = pagination_config.limit(query.limit);
 
     let pagination = PaginationParams {
         cursor: query.cursor,
-        limit: Some(limit),
+        limit,
     };
 
     Json(reporter.app_runs(&Host(host), time_range, pagination).await)
@@ -138,24 +276,668 @@ async fn run_logs_handler<R: Reporter>(
     }
 }
 
-pub fn create_api_router<R: Reporter>(reporter: R, pagination_config: PaginationConfig) -> Router {
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+struct CertificateEventsQuery {
+    domain: Option<String>,
+    limit: Option<u32>,
+}
+
+async fn certificate_events_handler<R: Reporter>(
+    State(reporter): State<R>,
+    Extension(pagination_config): Extension<PaginationConfig>,
+    Query(query): Query<CertificateEventsQuery>,
+) -> Json<Vec<CertificateEventEntry>> {
+    let limit = pagination_config.limit(query.limit);
+
+    Json(
+        reporter
+            .certificate_events(query.domain.as_deref(), limit)
+            .await,
+    )
+}
+
+async fn certificate_failures_handler<R: Reporter>(
+    State(reporter): State<R>,
+) -> Json<Vec<CertificateFailureEntry>> {
+    Json(reporter.certificate_failures().await)
+}
+
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+struct ProxyErrorsQuery {
+    host: Option<String>,
+    limit: Option<u32>,
+}
+
+async fn proxy_errors_handler<R: Reporter>(
+    State(reporter): State<R>,
+    Extension(pagination_config): Extension<PaginationConfig>,
+    RequestedRange(time_range): RequestedRange,
+    Query(query): Query<ProxyErrorsQuery>,
+) -> Json<Vec<ProxyErrorEntry>> {
+    let host = query
+        .host
+        .map(|host| normalize_hostname(&host).unwrap_or(host));
+    let limit = pagination_config.limit(query.limit);
+
+    Json(
+        reporter
+            .proxy_errors(host.as_deref(), time_range, limit)
+            .await,
+    )
+}
+
+/// A certificate that expires within `expiry_warning_days`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExpiringCertificate {
+    pub domain: String,
+    /// Unix milliseconds.
+    pub expires_at: i64,
+}
+
+/// Every app at a glance, for the dashboard's home page.
+#[derive(Debug, Clone, Serialize)]
+pub struct FleetSummary {
+    pub apps: usize,
+    /// Apps running or starting.
+    pub awake: usize,
+    /// Resident memory of the processes penny started for its apps, and
+    /// everything they started in turn. None without `/proc`.
+    pub memory_bytes: Option<u64>,
+    /// App starts over the last 24 hours.
+    pub starts_last_24h: i64,
+    pub expiring_certificates: Vec<ExpiringCertificate>,
+}
+
+async fn fleet_handler<R: Reporter>(
+    State(reporter): State<R>,
+    Extension(config): Extension<SharedConfig>,
+    Extension(certificates): Extension<Option<CertificateStore>>,
+) -> Json<FleetSummary> {
+    let statuses = live_statuses(&config).await;
+    let awake = statuses
+        .iter()
+        .filter(|status| matches!(status.state.as_str(), "running" | "starting"))
+        .count();
+
+    let config = config.read().await;
+    let mut pids = Vec::new();
+    for app in config.apps.values() {
+        pids.extend(app.read().await.command.child_pid());
+    }
+    let warning_days = config.tls.as_ref().map_or(0, |tls| tls.expiry_warning_days);
+    drop(config);
+
+    let now = jiff::Timestamp::now();
+    let day_ago = now - jiff::SignedDuration::from_hours(24);
+    let starts = reporter
+        .total_overview(Some(TimeRange {
+            start: Some(day_ago.as_millisecond()),
+            end: None,
+        }))
+        .await;
+
+    let mut expiring_certificates = Vec::new();
+    if let Some(certificates) = certificates {
+        let warn_before = now + jiff::SignedDuration::from_hours(i64::from(warning_days) * 24);
+        for domain in certificates.list_domains().await.unwrap_or_default() {
+            if let Ok(expiry) = certificates.certificate_expiry(&domain).await
+                && expiry <= warn_before
+            {
+                expiring_certificates.push(ExpiringCertificate {
+                    domain,
+                    expires_at: expiry.as_millisecond(),
+                });
+            }
+        }
+    }
+    expiring_certificates.sort_by_key(|certificate| certificate.expires_at);
+
+    Json(FleetSummary {
+        apps: statuses.len(),
+        awake,
+        memory_bytes: tokio::task::spawn_blocking(move || procfs::tree_memory_bytes(&pids))
+            .await
+            .ok()
+            .flatten(),
+        starts_last_24h: starts.total_runs,
+        expiring_certificates,
+    })
+}
+
+async fn storage_handler<R: Reporter>(
+    State(reporter): State<R>,
+    Extension(config): Extension<SharedConfig>,
+) -> Json<StorageReport> {
+    let mut storage = reporter.storage().await;
+    storage.max_bytes = config
+        .read()
+        .await
+        .max_database_mb
+        .map(crate::storage::megabytes);
+    Json(storage)
+}
+
+#[derive(Debug, Clone, Serialize, serde::Deserialize)]
+pub struct LiveAppStatus {
+    pub host: String,
+    /// One of `running`, `starting`, or `sleeping`; `running` or `down`
+    /// for apps with `managed = false`.
+    pub state: String,
+    /// Requests per minute over the short and long rate windows.
+    pub requests_per_min: f64,
+    pub requests_per_min_long: f64,
+    /// Time from spawning the process to its first passing health check.
+    pub last_cold_start_ms: Option<u64>,
+    /// Unix milliseconds of the latest request, kept across restarts.
+    #[serde(default)]
+    pub last_request_at: Option<i64>,
+    /// While `starting`: time since the start began, the start timeout, and
+    /// the latest health check.
+    #[serde(default)]
+    pub starting_for_ms: Option<u64>,
+    #[serde(default)]
+    pub start_timeout_ms: Option<u64>,
+    #[serde(default)]
+    pub health_checks: Option<u32>,
+    #[serde(default)]
+    pub last_health_error: Option<String>,
+    /// While `starting`: the app's latest stdout lines, with
+    /// `cold_start_output_lines`.
+    #[serde(default)]
+    pub start_output: Vec<String>,
+    /// Resident memory and CPU use of the app's processes at the latest
+    /// sample, while they run.
+    #[serde(default)]
+    pub memory_bytes: Option<u64>,
+    #[serde(default)]
+    pub cpu_percent: Option<f64>,
+}
+
+async fn live_handler(Extension(config): Extension<SharedConfig>) -> Json<Vec<LiveAppStatus>> {
+    Json(live_statuses(&config).await)
+}
+
+/// Current state of every app, sorted by host.
+pub async fn live_statuses(config: &SharedConfig) -> Vec<LiveAppStatus> {
+    // Taken out of the config first, so a reload does not wait on the
+    // apps' locks.
+    let apps: Vec<_> = config
+        .read()
+        .await
+        .apps
+        .iter()
+        .map(|(host, app)| (host.clone(), app.clone()))
+        .collect();
+    let mut statuses = Vec::with_capacity(apps.len());
+
+    for (host, app) in apps {
+        let guard = app.read().await;
+        let state = app_state(&guard);
+        let (short_rate, long_rate) = guard.request_tracker.request_rates();
+        let progress = (state == "starting")
+            .then(|| guard.start_progress())
+            .flatten();
+        let start_output = progress
+            .as_ref()
+            .map(|_| guard.start_output.lines())
+            .unwrap_or_default();
+
+        statuses.push(LiveAppStatus {
+            host,
+            state: state.to_owned(),
+            requests_per_min: short_rate,
+            requests_per_min_long: long_rate,
+            last_cold_start_ms: guard.last_cold_start_ms,
+            last_request_at: guard.request_tracker.last_request_at(),
+            starting_for_ms: progress
+                .as_ref()
+                .map(|progress| progress.since.elapsed().as_millis() as u64),
+            start_timeout_ms: progress
+                .is_some()
+                .then(|| guard.start_timeout.unsigned_abs().as_millis() as u64),
+            health_checks: progress.as_ref().map(|progress| progress.checks),
+            last_health_error: progress.and_then(|progress| progress.last_error),
+            start_output,
+            memory_bytes: guard.usage.map(|usage| usage.memory_bytes),
+            cpu_percent: guard.usage.map(|usage| usage.cpu_percent),
+        });
+    }
+
+    statuses.sort_by(|a, b| a.host.cmp(&b.host));
+    statuses
+}
+
+/// One of `running`, `starting`, or `sleeping`, or `down` for an unmanaged
+/// app that failed its health check.
+fn app_state(app: &App) -> &'static str {
+    match (app.command.is_running(), app.confirmed_healthy) {
+        _ if !app.managed && app.confirmed_healthy => "running",
+        _ if !app.managed => "down",
+        (true, true) => "running",
+        (true, false) => "starting",
+        (false, _) => "sleeping",
+    }
+}
+
+/// A hostname and path prefix the proxy routes somewhere.
+#[derive(Debug, Clone, Serialize, serde::Deserialize)]
+pub struct RouteEntry {
+    pub host: String,
+    /// Path prefix the rule matches, `/` for the whole host.
+    pub path: String,
+    /// The app's address, `dashboard`, or the ports of a preview.
+    pub target: String,
+    /// Whether the route is served over HTTPS.
+    pub tls: bool,
+    /// The app's state, as in `/api/live`. Unset for the dashboard.
+    #[serde(default)]
+    pub state: Option<String>,
+}
+
+async fn routes_handler(Extension(config): Extension<SharedConfig>) -> Json<Vec<RouteEntry>> {
+    Json(routes(&config).await)
+}
+
+/// Every routing rule, sorted by host and then path.
+pub async fn routes(config: &SharedConfig) -> Vec<RouteEntry> {
+    let config = config.read().await;
+    let tls = config.tls.as_ref().is_some_and(|tls| tls.enabled);
+    let dashboard_path =
+        config.api_path && crate::auth::is_auth_required() && config.api_address.is_some();
+    let mut routes = Vec::with_capacity(config.apps.len());
+
+    for preview in &config.previews {
+        routes.push(RouteEntry {
+            host: format!("*.{}", preview.domain),
+            path: "/".to_owned(),
+            target: format!("preview on 127.0.0.1:{}", preview.ports),
+            tls: false,
+            state: None,
+        });
+    }
+
+    if let Some(api_domain) = &config.api_domain
+        && config.api_address.is_some()
+        && !config.apps.contains_key(api_domain)
+    {
+        routes.push(RouteEntry {
+            host: api_domain.clone(),
+            path: "/".to_owned(),
+            target: "dashboard".to_owned(),
+            tls,
+            state: None,
+        });
+    }
+
+    // Taken out of the config first, so a reload does not wait on the
+    // apps' locks.
+    let apps: Vec<_> = config
+        .apps
+        .iter()
+        .map(|(host, app)| (host.clone(), app.clone()))
+        .collect();
+    drop(config);
+
+    for (host, app) in apps {
+        let guard = app.read().await;
+        let app_tls = tls && guard.tls;
+        if dashboard_path {
+            routes.push(RouteEntry {
+                host: host.clone(),
+                path: format!("{}/", crate::config::API_PATH_PREFIX),
+                target: "dashboard".to_owned(),
+                tls: app_tls,
+                state: None,
+            });
+        }
+        routes.push(RouteEntry {
+            host,
+            path: "/".to_owned(),
+            target: guard.address.to_string(),
+            tls: app_tls,
+            state: Some(app_state(&guard).to_owned()),
+        });
+    }
+
+    routes.sort_by(|a, b| a.host.cmp(&b.host).then_with(|| a.path.cmp(&b.path)));
+    routes
+}
+
+#[derive(Debug, Clone, Serialize, serde::Deserialize)]
+pub struct AppActionResponse {
+    pub host: String,
+    /// One of `running`, `starting`, or `sleeping`.
+    pub state: String,
+}
+
+/// Why an app action failed, with the HTTP status the API answers with.
+pub type ActionError = (axum::http::StatusCode, String);
+
+async fn find_app(
+    config: &SharedConfig,
+    host: String,
+) -> Result<(Host, std::sync::Arc<tokio::sync::RwLock<App>>), ActionError> {
+    let host = normalize_hostname(&host).unwrap_or(host);
+    let Some(app) = config.read().await.apps.get(&host).cloned() else {
+        return Err((
+            axum::http::StatusCode::NOT_FOUND,
+            format!("no app configured for host '{host}'"),
+        ));
+    };
+    Ok((Host(host), app))
+}
+
+/// Wakes an app without waiting for it, as if it had received a request.
+pub async fn start_app(
+    config: &SharedConfig,
+    idle: &IdleScheduler,
+    collector: impl Collector,
+    host: String,
+    reason: WakeReason,
+) -> Result<AppActionResponse, ActionError> {
+    let (host, app) = find_app(config, host).await?;
+
+    match App::begin_start_app(&host, &app, collector, reason).await {
+        Ok(ready) => {
+            idle.schedule_stop(&host, &app).await;
+            let state = if ready { "running" } else { "starting" };
+            Ok(AppActionResponse {
+                host: host.0,
+                state: state.to_owned(),
+            })
+        }
+        Err(e) => Err((axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+/// Puts an app to sleep now.
+pub async fn stop_app(
+    config: &SharedConfig,
+    collector: impl Collector,
+    host: String,
+) -> Result<AppActionResponse, ActionError> {
+    let (host, app) = find_app(config, host).await?;
+
+    let mut guard = app.write().await;
+    if !guard.managed {
+        return Err((
+            axum::http::StatusCode::CONFLICT,
+            format!("'{host}' is not managed by penny"),
+        ));
+    }
+    let awake = guard.idle_deadline.cancel() || guard.command.is_child_running();
+    drop(guard);
+
+    if awake {
+        App::stop_app(&host, &app, collector).await;
+    }
+
+    Ok(AppActionResponse {
+        host: host.0,
+        state: "sleeping".to_owned(),
+    })
+}
+
+async fn start_app_handler<R: Reporter + Collector>(
+    State(reporter): State<R>,
+    Extension(config): Extension<SharedConfig>,
+    Extension(idle): Extension<IdleScheduler>,
+    axum::extract::Path(host): axum::extract::Path<String>,
+) -> impl axum::response::IntoResponse {
+    use axum::response::IntoResponse;
+
+    match start_app(&config, &idle, reporter, host, WakeReason::Api).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn stop_app_handler<R: Reporter + Collector>(
+    State(reporter): State<R>,
+    Extension(config): Extension<SharedConfig>,
+    axum::extract::Path(host): axum::extract::Path<String>,
+) -> impl axum::response::IntoResponse {
+    use axum::response::IntoResponse;
+
+    match stop_app(&config, reporter, host).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn redeploy_app_handler<R: Reporter + Collector>(
+    State(reporter): State<R>,
+    Extension(config): Extension<SharedConfig>,
+    Extension(idle): Extension<IdleScheduler>,
+    axum::extract::Path(host): axum::extract::Path<String>,
+) -> impl axum::response::IntoResponse {
+    use axum::response::IntoResponse;
+
+    let (host, app) = match find_app(&config, host).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+    let guard = app.read().await;
+    if !guard.managed {
+        return (
+            axum::http::StatusCode::CONFLICT,
+            format!("'{host}' is not managed by penny"),
+        )
+            .into_response();
+    }
+    if guard.redeploy_command.is_none() {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            format!("no redeploy_command configured for host '{host}'"),
+        )
+            .into_response();
+    }
+    drop(guard);
+
+    match App::redeploy(&host, &app, reporter).await {
+        Ok(ready) => {
+            idle.schedule_stop(&host, &app).await;
+            let state = if ready { "running" } else { "starting" };
+            Json(AppActionResponse {
+                host: host.0,
+                state: state.to_owned(),
+            })
+            .into_response()
+        }
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+async fn rolling_restart_handler<R: Reporter + Collector>(
+    State(reporter): State<R>,
+    Extension(config): Extension<SharedConfig>,
+    axum::extract::Path(host): axum::extract::Path<String>,
+) -> impl axum::response::IntoResponse {
+    use axum::response::IntoResponse;
+
+    let (host, app) = match find_app(&config, host).await {
+        Ok(found) => found,
+        Err(e) => return e.into_response(),
+    };
+    let mut guard = app.write().await;
+    if guard.rolling_ports.is_none() {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            format!("no rolling_ports configured for host '{host}'"),
+        )
+            .into_response();
+    }
+    if !guard.command.is_child_running() {
+        return (
+            axum::http::StatusCode::CONFLICT,
+            format!("'{host}' is not running, start it instead"),
+        )
+            .into_response();
+    }
+    drop(guard);
+
+    match App::rolling_restart(&host, &app, reporter).await {
+        Ok(_) => Json(AppActionResponse {
+            host: host.0,
+            state: "running".to_owned(),
+        })
+        .into_response(),
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// An app to add to the config file.
+#[derive(Debug, serde::Deserialize)]
+struct NewApp {
+    host: String,
+    /// The app's options, as they would appear under its table in the
+    /// config file.
+    #[serde(default)]
+    settings: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Adds an app to the config file and loads it, without starting it.
+async fn create_app(
+    config: &SharedConfig,
+    file: &ConfigFile,
+    new_app: NewApp,
+) -> Result<AppActionResponse, ActionError> {
+    let bad_request = |message: String| (axum::http::StatusCode::BAD_REQUEST, message);
+    let host = normalize_hostname(&new_app.host)
+        .ok_or_else(|| bad_request(format!("invalid hostname '{}'", new_app.host)))?;
+    if config.read().await.apps.contains_key(&host) {
+        return Err((
+            axum::http::StatusCode::CONFLICT,
+            format!("an app for '{host}' already exists"),
+        ));
+    }
+    let settings = match toml::Value::try_from(new_app.settings) {
+        Ok(toml::Value::Table(settings)) => settings,
+        Ok(_) => return Err(bad_request("settings must be an object".to_owned())),
+        Err(e) => return Err(bad_request(format!("invalid settings: {e}"))),
+    };
+
+    match file.add_app(config, &host, settings).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => return Err(bad_request(e)),
+        Err(e) => {
+            return Err((
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                format!("{e:#}"),
+            ));
+        }
+    }
+
+    let (host, app) = find_app(config, host).await?;
+    let state = app_state(&*app.read().await);
+    Ok(AppActionResponse {
+        host: host.0,
+        state: state.to_owned(),
+    })
+}
+
+async fn create_app_handler(
+    Extension(config): Extension<SharedConfig>,
+    Extension(file): Extension<ConfigFile>,
+    Json(new_app): Json<NewApp>,
+) -> impl axum::response::IntoResponse {
+    use axum::response::IntoResponse;
+
+    match create_app(&config, &file, new_app).await {
+        Ok(response) => (axum::http::StatusCode::CREATED, Json(response)).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Adds an app under a new hostname with `template` set to an existing one,
+/// so it inherits every setting `settings` does not replace.
+async fn clone_app_handler(
+    Extension(config): Extension<SharedConfig>,
+    Extension(file): Extension<ConfigFile>,
+    axum::extract::Path(host): axum::extract::Path<String>,
+    Json(mut new_app): Json<NewApp>,
+) -> impl axum::response::IntoResponse {
+    use axum::response::IntoResponse;
+
+    let template = match find_app(&config, host).await {
+        Ok((template, _)) => template,
+        Err(e) => return e.into_response(),
+    };
+    new_app
+        .settings
+        .insert("template".to_owned(), template.0.into());
+
+    match create_app(&config, &file, new_app).await {
+        Ok(response) => (axum::http::StatusCode::CREATED, Json(response)).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+pub fn create_api_router<R: Reporter + Collector>(
+    reporter: R,
+    pagination_config: PaginationConfig,
+    config: SharedConfig,
+    idle: IdleScheduler,
+    config_file: ConfigFile,
+    certificates: Option<CertificateStore>,
+) -> Router {
+    // Other sites may read from the API, but only the dashboard itself can
+    // change anything.
     let cors = CorsLayer::new()
         .allow_origin(Any)
-        .allow_methods(Any)
+        .allow_methods([Method::GET])
         .allow_headers(Any);
 
     // Public routes (no auth required)
     let public_routes = Router::new().route("/api/auth/status", get(auth_status_handler));
 
+    // Routes that change what penny runs, refused until a password is set
+    let admin_routes = Router::new()
+        .route("/api/config/apps", post(create_app_handler))
+        .route("/api/apps/{host}/clone", post(clone_app_handler))
+        .route("/api/apps/{host}/start", post(start_app_handler::<R>))
+        .route("/api/apps/{host}/stop", post(stop_app_handler::<R>))
+        .route("/api/apps/{host}/redeploy", post(redeploy_app_handler::<R>))
+        .route(
+            "/api/apps/{host}/rolling-restart",
+            post(rolling_restart_handler::<R>),
+        )
+        .route_layer(middleware::from_fn(password_required_middleware));
+
     // Protected routes (auth middleware applied)
     let protected_routes = Router::new()
         .route("/api/version", get(version_handler))
+        .route("/api/metrics", get(metrics_handler))
         .route("/api/total-overview", get(total_overview_handler::<R>))
         .route("/api/apps-overview", get(apps_overview_handler::<R>))
         .route("/api/app-overview/{host}", get(app_overview_handler::<R>))
         .route("/api/app-runs/{host}", get(app_runs_handler::<R>))
         .route("/api/run-logs/{run_id}", get(run_logs_handler::<R>))
+        .route(
+            "/api/certificate-events",
+            get(certificate_events_handler::<R>),
+        )
+        .route(
+            "/api/certificate-failures",
+            get(certificate_failures_handler::<R>),
+        )
+        .route("/api/errors", get(proxy_errors_handler::<R>))
+        .route("/api/fleet", get(fleet_handler::<R>))
+        .route("/api/storage", get(storage_handler::<R>))
+        .route("/api/live", get(live_handler))
+        .route("/api/routes", get(routes_handler))
+        .route("/api/apps/{host}/wake-stats", get(wake_stats_handler::<R>))
+        .route("/api/apps/{host}/monitor", get(monitor_handler::<R>))
+        .route(
+            "/api/apps/{host}/wait-decisions",
+            get(wait_decisions_handler::<R>),
+        )
+        .merge(admin_routes)
         .layer(Extension(pagination_config))
+        .layer(Extension(config))
+        .layer(Extension(idle))
+        .layer(Extension(config_file))
+        .layer(Extension(certificates))
         .layer(middleware::from_fn(auth_middleware))
         .with_state(reporter);
 
@@ -37,6 +37,21 @@ pub async fn auth_status_handler() -> Json<AuthStatusResponse> {
     })
 }
 
+/// Refuses every request while no dashboard password is set. For endpoints
+/// that change which processes penny runs, which would otherwise be open to
+/// anyone who can reach the API.
+pub async fn password_required_middleware(request: Request, next: Next) -> Response {
+    if !is_auth_required() {
+        return (
+            StatusCode::FORBIDDEN,
+            "this endpoint needs a dashboard password, set one with --password or PENNY_PASSWORD",
+        )
+            .into_response();
+    }
+
+    next.run(request).await
+}
+
 pub async fn auth_middleware(request: Request, next: Next) -> Response {
     let Some(expected_password) = get_password() else {
         return next.run(request).await;
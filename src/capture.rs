@@ -0,0 +1,213 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// Which of an app's output streams penny stores.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CapturePolicy {
+    #[default]
+    All,
+    StderrOnly,
+    None,
+}
+
+impl CapturePolicy {
+    pub fn stores_stdout(self) -> bool {
+        self == CapturePolicy::All
+    }
+
+    pub fn stores_stderr(self) -> bool {
+        self != CapturePolicy::None
+    }
+}
+
+/// Caps how many lines a run stores each second, across stdout and stderr,
+/// and counts the lines dropped over the cap. Clones share the count.
+#[derive(Debug, Clone, Default)]
+pub struct LineLimiter {
+    max_per_sec: Option<u32>,
+    window: Arc<Mutex<Window>>,
+}
+
+#[derive(Debug, Default)]
+struct Window {
+    started: Option<Instant>,
+    lines: u32,
+    dropped: u64,
+}
+
+impl LineLimiter {
+    pub fn new(max_per_sec: Option<u32>) -> Self {
+        Self {
+            max_per_sec,
+            window: Arc::default(),
+        }
+    }
+
+    /// Whether to store a line arriving now.
+    pub fn allow(&self) -> bool {
+        self.allow_at(Instant::now())
+    }
+
+    fn allow_at(&self, now: Instant) -> bool {
+        let Some(max) = self.max_per_sec else {
+            return true;
+        };
+        let mut window = self.window.lock().unwrap_or_else(|e| e.into_inner());
+        if window
+            .started
+            .is_none_or(|started| now.duration_since(started) >= Duration::from_secs(1))
+        {
+            window.started = Some(now);
+            window.lines = 0;
+        }
+        if window.lines < max {
+            window.lines += 1;
+            true
+        } else {
+            window.dropped += 1;
+            false
+        }
+    }
+
+    /// Takes the number of lines dropped since the last call.
+    pub fn take_dropped(&self) -> u64 {
+        std::mem::take(
+            &mut self
+                .window
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .dropped,
+        )
+    }
+}
+
+/// Longest line an [`OutputTail`] keeps, in characters.
+const TAIL_LINE_CHARS: usize = 200;
+
+/// The last few stdout lines of an app, shown on its cold start page while
+/// it starts. Clones share the lines.
+#[derive(Debug, Clone, Default)]
+pub struct OutputTail {
+    max_lines: usize,
+    lines: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl OutputTail {
+    pub fn new(max_lines: usize) -> Self {
+        Self {
+            max_lines,
+            lines: Arc::default(),
+        }
+    }
+
+    /// Whether any lines are kept at all.
+    pub fn is_enabled(&self) -> bool {
+        self.max_lines > 0
+    }
+
+    /// Keeps `line` in place of the oldest one. Escape sequences such as
+    /// colors and other control characters are removed, and long lines
+    /// cut short.
+    pub fn push(&self, line: &str) {
+        if !self.is_enabled() {
+            return;
+        }
+        let line = sanitize(line);
+        let mut lines = self.lines.lock().unwrap_or_else(|e| e.into_inner());
+        if lines.len() == self.max_lines {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    /// Forgets the lines of a previous start.
+    pub fn clear(&self) {
+        self.lines.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+
+    /// The kept lines, oldest first.
+    pub fn lines(&self) -> Vec<String> {
+        self.lines
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .cloned()
+            .collect()
+    }
+}
+
+/// `line` without ANSI escape sequences and control characters, cut to
+/// [`TAIL_LINE_CHARS`].
+fn sanitize(line: &str) -> String {
+    let mut clean = String::with_capacity(line.len().min(TAIL_LINE_CHARS));
+    let mut chars = line.chars();
+    let mut kept = 0;
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // A CSI sequence like `\x1b[32m` ends with a letter; other
+            // escapes are two characters.
+            if chars.next() == Some('[') {
+                for c in chars.by_ref() {
+                    if c.is_ascii_alphabetic() {
+                        break;
+                    }
+                }
+            }
+            continue;
+        }
+        if c.is_control() {
+            continue;
+        }
+        if kept == TAIL_LINE_CHARS {
+            clean.push('…');
+            break;
+        }
+        clean.push(c);
+        kept += 1;
+    }
+    clean.trim_end().to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limiter_drops_lines_over_the_cap_each_second() {
+        let limiter = LineLimiter::new(Some(2));
+        let start = Instant::now();
+
+        assert!(limiter.allow_at(start));
+        assert!(limiter.clone().allow_at(start));
+        assert!(!limiter.allow_at(start + Duration::from_millis(500)));
+        assert!(!limiter.allow_at(start + Duration::from_millis(900)));
+        assert_eq!(limiter.take_dropped(), 2);
+        assert_eq!(limiter.take_dropped(), 0);
+
+        assert!(limiter.allow_at(start + Duration::from_secs(1)));
+        assert!(LineLimiter::default().allow_at(start));
+    }
+
+    #[test]
+    fn tail_keeps_the_last_lines_cleaned_up() {
+        let tail = OutputTail::new(2);
+        tail.push("Running migrations...");
+        tail.clone().push("\x1b[32m  applied 0001_init\x1b[0m\r");
+        tail.push(&"x".repeat(300));
+        let lines = tail.lines();
+        assert_eq!(lines[0], "  applied 0001_init");
+        assert_eq!(lines[1].chars().count(), TAIL_LINE_CHARS + 1);
+        assert!(lines[1].ends_with('…'));
+
+        tail.clear();
+        assert!(tail.lines().is_empty());
+
+        let disabled = OutputTail::default();
+        disabled.push("ignored");
+        assert!(disabled.lines().is_empty());
+    }
+}
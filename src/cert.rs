@@ -0,0 +1,201 @@
+use std::path::Path;
+
+use color_eyre::eyre::{Context, eyre};
+use pingora::tls::pkey::PKey;
+use pingora::tls::x509::X509;
+
+use crate::acme::{self, AcmeClient};
+use crate::challenge::{ACME_CHALLENGE_PATH, challenge_router, create_challenge_store};
+use crate::config::{CertStrategy, Config, TlsConfig};
+use crate::db::SqliteDatabase;
+use crate::tls::CertificateStore;
+
+fn tls_config(config: &Config) -> color_eyre::Result<&TlsConfig> {
+    config
+        .tls
+        .as_ref()
+        .ok_or_else(|| eyre!("no [tls] section in config file"))
+}
+
+/// Opens the database and the certificate store configured in `[tls]`.
+async fn open_store(config: &Config) -> color_eyre::Result<(SqliteDatabase, CertificateStore)> {
+    let tls_config = tls_config(config)?;
+    let db = SqliteDatabase::new(&config.database_url).await?;
+    let cert_store = CertificateStore::from_config(tls_config, &db)?;
+    Ok((db, cert_store))
+}
+
+async fn ensure_configured(config: &Config, domain: &str) -> color_eyre::Result<()> {
+    if config.tls_domains().await.iter().any(|d| d == domain) {
+        Ok(())
+    } else {
+        Err(eyre!(
+            "domain '{domain}' is not configured for TLS in the config file"
+        ))
+    }
+}
+
+/// Lists configured and stored certificates with their expiry.
+pub async fn list(config: &Config) -> color_eyre::Result<()> {
+    let tls_config = tls_config(config)?;
+    let (_, cert_store) = open_store(config).await?;
+
+    let mut domains = config.tls_domains().await;
+    for stored in cert_store.list_domains().await? {
+        if !domains.contains(&stored) {
+            domains.push(stored);
+        }
+    }
+    domains.sort();
+
+    if domains.is_empty() {
+        println!("No certificates.");
+        return Ok(());
+    }
+
+    let now = jiff::Timestamp::now();
+    println!("{:<40} {:<26} STATUS", "DOMAIN", "EXPIRES");
+    for domain in domains {
+        if cert_store.get_certificate(&domain).await.is_none() {
+            println!("{:<40} {:<26} missing", domain, "-");
+            continue;
+        }
+
+        match cert_store.certificate_expiry(&domain).await {
+            Ok(expiry) => {
+                let status = if expiry < now {
+                    "expired"
+                } else if cert_store
+                    .needs_renewal(&domain, tls_config.renewal_days)
+                    .await
+                {
+                    "renewal due"
+                } else {
+                    "valid"
+                };
+                let expiry = expiry.to_string();
+                println!("{:<40} {:<26} {}", domain, expiry, status);
+            }
+            Err(e) => println!("{:<40} {:<26} unreadable ({e})", domain, "-"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Obtains a fresh certificate for a domain, serving the HTTP-01 challenge
+/// from a temporary listener on `address`. With `cert_strategy = "combined"`
+/// the shared certificate for all domains is renewed.
+pub async fn renew(config: &Config, domain: &str, address: &str) -> color_eyre::Result<()> {
+    let tls_config = tls_config(config)?;
+    ensure_configured(config, domain).await?;
+
+    let group = match tls_config.cert_strategy {
+        CertStrategy::PerDomain => vec![domain.to_owned()],
+        CertStrategy::Combined => {
+            let mut domains = config.tls_domains().await;
+            domains.sort();
+            domains
+        }
+    };
+    let primary = group[0].as_str();
+    let names: Vec<&str> = group.iter().map(String::as_str).collect();
+
+    let (db, cert_store) = open_store(config).await?;
+    let challenge_store =
+        create_challenge_store(tls_config.persists_challenges().then(|| db.clone()));
+
+    // A running `penny serve` sharing the database answers persisted
+    // challenges, so the port being taken is fine then.
+    let server = match tokio::net::TcpListener::bind(address).await {
+        Ok(listener) => {
+            let router = challenge_router(challenge_store.clone(), ACME_CHALLENGE_PATH);
+            Some(tokio::spawn(
+                async move { axum::serve(listener, router).await },
+            ))
+        }
+        Err(_) if tls_config.persists_challenges() => {
+            println!("{address} is taken; leaving the HTTP-01 challenge to the running penny");
+            None
+        }
+        Err(e) => {
+            return Err(e).wrap_err_with(|| {
+                format!(
+                    "failed to bind {address} for the HTTP-01 challenge (is `penny serve` running?)"
+                )
+            });
+        }
+    };
+
+    println!("requesting certificate for {}...", names.join(", "));
+    let reuse_key = cert_store.reusable_key(primary, tls_config).await;
+    let acme_client = AcmeClient::new(tls_config, &db).await?;
+    let result = acme_client
+        .obtain_certificate(&names, &challenge_store, reuse_key.as_deref())
+        .await;
+    if let Some(server) = server {
+        server.abort();
+    }
+
+    let (cert_pem, key_pem) = match result {
+        Ok(pair) => pair,
+        Err(e) => {
+            acme::record_failure(&db, primary, &e).await;
+            return Err(e);
+        }
+    };
+    for name in &names {
+        cert_store
+            .store_certificate(name, &cert_pem, &key_pem)
+            .await?;
+        println!("stored new certificate for {name}");
+    }
+    acme::clear_failure(&db, primary).await;
+
+    Ok(())
+}
+
+/// Deletes the stored certificate for a domain.
+pub async fn delete(config: &Config, domain: &str) -> color_eyre::Result<()> {
+    let (_, cert_store) = open_store(config).await?;
+
+    if !cert_store.delete_certificate(domain).await? {
+        return Err(eyre!("no certificate stored for '{domain}'"));
+    }
+    println!("deleted certificate for {domain}");
+
+    Ok(())
+}
+
+/// Imports an existing certificate chain and private key for a domain.
+pub async fn import(
+    config: &Config,
+    domain: &str,
+    cert_path: &Path,
+    key_path: &Path,
+) -> color_eyre::Result<()> {
+    let (_, cert_store) = open_store(config).await?;
+
+    let cert_pem = std::fs::read_to_string(cert_path)
+        .wrap_err_with(|| format!("failed to read certificate: {}", cert_path.display()))?;
+    let key_pem = std::fs::read_to_string(key_path)
+        .wrap_err_with(|| format!("failed to read private key: {}", key_path.display()))?;
+
+    let cert = X509::from_pem(cert_pem.as_bytes()).wrap_err("failed to parse certificate")?;
+    let key =
+        PKey::private_key_from_pem(key_pem.as_bytes()).wrap_err("failed to parse private key")?;
+    let public_key = cert
+        .public_key()
+        .wrap_err("failed to read certificate public key")?;
+    if !public_key.public_eq(&key) {
+        return Err(eyre!("private key does not match the certificate"));
+    }
+
+    cert_store
+        .store_certificate(domain, &cert_pem, &key_pem)
+        .await?;
+    let expiry = cert_store.certificate_expiry(domain).await?;
+    println!("imported certificate for {domain} (expires {expiry})");
+
+    Ok(())
+}
@@ -1,24 +1,153 @@
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 
+use axum::Router;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::get;
+use jiff::{SignedDuration, Timestamp};
 use tokio::sync::RwLock;
+use tracing::{debug, error};
 
-/// In-memory store for active ACME HTTP-01 challenges.
-/// Maps challenge token to key authorization.
-pub type ChallengeStore = Arc<RwLock<HashMap<String, String>>>;
+use crate::db::SqliteDatabase;
 
-pub fn create_challenge_store() -> ChallengeStore {
-    Arc::new(RwLock::new(HashMap::new()))
+/// Path the CA requests HTTP-01 challenges from.
+pub const ACME_CHALLENGE_PATH: &str = "/.well-known/acme-challenge";
+
+/// How long a challenge is answered. Orders finish or fail well within it,
+/// so older tokens were left behind by an order that never cleaned up.
+const CHALLENGE_TTL: SignedDuration = SignedDuration::from_hours(1);
+
+/// How often stale challenges are removed.
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Store for active ACME HTTP-01 challenges, mapping challenge token to key
+/// authorization. With a database, challenges are also kept there, so they
+/// are still answered after a restart or by another penny sharing it.
+#[derive(Debug, Clone)]
+pub struct ChallengeStore {
+    memory: Arc<RwLock<HashMap<String, (String, Timestamp)>>>,
+    db: Option<SqliteDatabase>,
+}
+
+pub fn create_challenge_store(db: Option<SqliteDatabase>) -> ChallengeStore {
+    ChallengeStore {
+        memory: Arc::default(),
+        db,
+    }
 }
 
 pub async fn add_challenge(store: &ChallengeStore, token: String, key_auth: String) {
-    store.write().await.insert(token, key_auth);
+    if let Some(db) = &store.db
+        && let Err(e) = db.save_acme_challenge(&token, &key_auth).await
+    {
+        error!(error = %e, "failed to persist ACME challenge");
+    }
+    store
+        .memory
+        .write()
+        .await
+        .insert(token, (key_auth, Timestamp::now()));
 }
 
 pub async fn get_challenge(store: &ChallengeStore, token: &str) -> Option<String> {
-    store.read().await.get(token).cloned()
+    let since = Timestamp::now() - CHALLENGE_TTL;
+    if let Some((key_auth, added)) = store.memory.read().await.get(token)
+        && *added > since
+    {
+        return Some(key_auth.clone());
+    }
+    let db = store.db.as_ref()?;
+    match db.get_acme_challenge(token, since.as_millisecond()).await {
+        Ok(key_auth) => key_auth,
+        Err(e) => {
+            error!(error = %e, "failed to look up ACME challenge");
+            None
+        }
+    }
 }
 
 pub async fn remove_challenge(store: &ChallengeStore, token: &str) {
-    store.write().await.remove(token);
+    store.memory.write().await.remove(token);
+    if let Some(db) = &store.db
+        && let Err(e) = db.remove_acme_challenge(token).await
+    {
+        error!(error = %e, "failed to remove persisted ACME challenge");
+    }
+}
+
+/// Removes challenges older than [`CHALLENGE_TTL`].
+async fn remove_stale(store: &ChallengeStore) {
+    let cutoff = Timestamp::now() - CHALLENGE_TTL;
+    store
+        .memory
+        .write()
+        .await
+        .retain(|_, (_, added)| *added > cutoff);
+    if let Some(db) = &store.db {
+        match db
+            .remove_stale_acme_challenges(cutoff.as_millisecond())
+            .await
+        {
+            Ok(0) => {}
+            Ok(removed) => debug!(removed, "removed stale ACME challenges"),
+            Err(e) => error!(error = %e, "failed to remove stale ACME challenges"),
+        }
+    }
+}
+
+/// Removes stale challenges every [`CLEANUP_INTERVAL`].
+pub fn spawn_cleanup(store: ChallengeStore) {
+    tokio::spawn(async move {
+        loop {
+            remove_stale(&store).await;
+            tokio::time::sleep(CLEANUP_INTERVAL).await;
+        }
+    });
+}
+
+async fn challenge_handler(
+    State(store): State<ChallengeStore>,
+    Path(token): Path<String>,
+) -> Result<String, StatusCode> {
+    get_challenge(&store, &token)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Router answering HTTP-01 challenges at `{path}/{token}`, for listeners
+/// that serve only challenges.
+pub fn challenge_router(store: ChallengeStore, path: &str) -> Router {
+    let route = format!("{}/{{token}}", path.trim_end_matches('/'));
+    Router::new()
+        .route(&route, get(challenge_handler))
+        .with_state(store)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn persisted_challenges_are_answered_by_every_store() {
+        let db = SqliteDatabase::new("sqlite::memory:").await.unwrap();
+        let issuing = create_challenge_store(Some(db.clone()));
+        let restarted = create_challenge_store(Some(db));
+
+        add_challenge(&issuing, "token".to_owned(), "token.key".to_owned()).await;
+        assert_eq!(
+            get_challenge(&restarted, "token").await.as_deref(),
+            Some("token.key")
+        );
+        assert_eq!(get_challenge(&restarted, "other").await, None);
+
+        remove_challenge(&issuing, "token").await;
+        assert_eq!(get_challenge(&restarted, "token").await, None);
+
+        let memory = create_challenge_store(None);
+        add_challenge(&memory, "token".to_owned(), "token.key".to_owned()).await;
+        remove_stale(&memory).await;
+        assert!(get_challenge(&memory, "token").await.is_some());
+    }
 }
@@ -1,15 +1,48 @@
-use std::sync::Arc;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+use serde::Serialize;
 use tokio::sync::RwLock;
 use tracing::{error, info};
 
+use crate::api::{AppActionResponse, LiveAppStatus};
+use crate::capture::{CapturePolicy, LineLimiter, OutputTail};
+use crate::client::ApiClient;
 use crate::collector::Collector;
-use crate::config::{App, Config};
-use crate::types::{Host, RunId};
+use crate::config::{App, Config, RunOptions};
+use crate::types::{
+    CertificateEvent, Host, ProxyFailure, RequestCounts, RunId, UsageSample, WaitDecision,
+    WakeReason,
+};
+
+/// Number of trailing stderr lines kept for each checked app.
+const STDERR_SNIPPET_LINES: usize = 20;
+
+/// Output format for check results.
+#[derive(Debug, Clone, Copy, Default, clap::ValueEnum)]
+pub enum CheckFormat {
+    /// Human-readable report.
+    #[default]
+    Text,
+    /// A single JSON document.
+    Json,
+    /// JUnit XML, for CI test reporters.
+    Junit,
+}
 
-/// A collector that does nothing (no database needed for check).
-#[derive(Debug, Clone)]
-pub struct NoOpCollector;
+/// A collector that records nothing except the last few stderr lines
+/// (no database needed for check).
+#[derive(Debug, Clone, Default)]
+pub struct NoOpCollector {
+    stderr: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl NoOpCollector {
+    fn stderr_snippet(&self) -> Vec<String> {
+        self.stderr.lock().unwrap().iter().cloned().collect()
+    }
+}
 
 #[async_trait::async_trait]
 impl Collector for NoOpCollector {
@@ -17,6 +50,22 @@ impl Collector for NoOpCollector {
         Ok(RunId::default())
     }
 
+    async fn app_woken(&self, _run_id: &RunId, _reason: WakeReason) -> color_eyre::Result<()> {
+        Ok(())
+    }
+
+    async fn lines_dropped(&self, _run_id: &RunId, _count: u64) -> color_eyre::Result<()> {
+        Ok(())
+    }
+
+    async fn usage_sampled(
+        &self,
+        _run_id: &RunId,
+        _sample: &UsageSample,
+    ) -> color_eyre::Result<()> {
+        Ok(())
+    }
+
     async fn app_stopped(&self, _host: &Host) -> color_eyre::Result<()> {
         Ok(())
     }
@@ -33,57 +82,228 @@ impl Collector for NoOpCollector {
         Ok(())
     }
 
-    async fn append_stderr(&self, _run_id: &RunId, _line: String) -> color_eyre::Result<()> {
+    async fn append_stderr(&self, _run_id: &RunId, line: String) -> color_eyre::Result<()> {
+        let mut stderr = self.stderr.lock().unwrap();
+        if stderr.len() == STDERR_SNIPPET_LINES {
+            stderr.pop_front();
+        }
+        stderr.push_back(line);
+        Ok(())
+    }
+
+    async fn requests_completed(
+        &self,
+        _host: &Host,
+        _counts: &RequestCounts,
+    ) -> color_eyre::Result<()> {
+        Ok(())
+    }
+
+    async fn monitor_checked(
+        &self,
+        _host: &Host,
+        _status: Option<u16>,
+        _latency_ms: u64,
+        _error: Option<&str>,
+        _asleep: bool,
+    ) -> color_eyre::Result<()> {
+        Ok(())
+    }
+
+    async fn wait_decided(&self, _host: &Host, _decision: &WaitDecision) -> color_eyre::Result<()> {
+        Ok(())
+    }
+
+    async fn proxy_failed(
+        &self,
+        _host: &Host,
+        _path: &str,
+        _failure: ProxyFailure,
+        _detail: &str,
+    ) -> color_eyre::Result<()> {
         Ok(())
     }
+
+    async fn certificate_event(
+        &self,
+        _domain: &str,
+        _event: CertificateEvent,
+        _detail: Option<&str>,
+    ) -> color_eyre::Result<()> {
+        Ok(())
+    }
+
+    async fn claim_start(
+        &self,
+        _node: &str,
+        _host: &Host,
+        _lease: Duration,
+    ) -> color_eyre::Result<bool> {
+        Ok(true)
+    }
+
+    async fn release_start(&self, _node: &str, _host: &Host) -> color_eyre::Result<()> {
+        Ok(())
+    }
+
+    async fn app_seen(
+        &self,
+        _node: &str,
+        _host: &Host,
+        _last_request_at: Option<i64>,
+    ) -> color_eyre::Result<()> {
+        Ok(())
+    }
+
+    async fn last_request_elsewhere(
+        &self,
+        _node: &str,
+        _host: &Host,
+    ) -> color_eyre::Result<Option<i64>> {
+        Ok(None)
+    }
 }
 
 /// Tracks check results for a single app.
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct AppCheckResult {
     pub hostname: String,
+    pub success: bool,
     pub start_success: bool,
     pub health_check_success: bool,
     pub stop_success: bool,
+    /// The app was already up, so it was only health-checked.
+    pub already_running: bool,
+    /// The stop phase was skipped because of `--keep-running`.
+    pub stop_skipped: bool,
     pub start_error: Option<String>,
     pub health_check_error: Option<String>,
     pub stop_error: Option<String>,
+    pub start_duration_ms: u64,
+    pub health_check_duration_ms: u64,
+    pub stop_duration_ms: u64,
+    /// The last lines the app wrote to stderr.
+    pub stderr: Vec<String>,
 }
 
 impl AppCheckResult {
     pub fn new(hostname: String) -> Self {
         Self {
             hostname,
+            success: false,
             start_success: false,
             health_check_success: false,
             stop_success: false,
+            already_running: false,
+            stop_skipped: false,
             start_error: None,
             health_check_error: None,
             stop_error: None,
+            start_duration_ms: 0,
+            health_check_duration_ms: 0,
+            stop_duration_ms: 0,
+            stderr: Vec::new(),
         }
     }
 
     pub fn is_success(&self) -> bool {
         self.start_success && self.health_check_success && self.stop_success
     }
+
+    /// Total time spent on this app, in milliseconds.
+    pub fn duration_ms(&self) -> u64 {
+        self.start_duration_ms + self.health_check_duration_ms + self.stop_duration_ms
+    }
+
+    /// Failure messages for each step that did not succeed.
+    fn failures(&self) -> Vec<String> {
+        let mut failures = Vec::new();
+        if !self.start_success {
+            let error = self.start_error.as_deref().unwrap_or("Unknown error");
+            failures.push(format!("start: {error}"));
+        }
+        if !self.health_check_success {
+            let error = self
+                .health_check_error
+                .as_deref()
+                .unwrap_or("Unknown error");
+            failures.push(format!("health check: {error}"));
+        }
+        if !self.stop_success {
+            let error = self.stop_error.as_deref().unwrap_or("Unknown error");
+            failures.push(format!("stop: {error}"));
+        }
+        failures
+    }
+}
+
+fn elapsed_ms(since: Instant) -> u64 {
+    since.elapsed().as_millis() as u64
+}
+
+/// Options controlling how apps are checked.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CheckOptions {
+    pub format: CheckFormat,
+    /// Leave apps running after a successful health check.
+    pub keep_running: bool,
+    /// Health-check apps that are already up instead of failing.
+    pub allow_running: bool,
 }
 
 /// Runs the check for a single app.
-async fn check_app(hostname: &str, app: &Arc<RwLock<App>>) -> AppCheckResult {
+async fn check_app(
+    hostname: &str,
+    app: &Arc<RwLock<App>>,
+    options: CheckOptions,
+) -> AppCheckResult {
     let mut result = AppCheckResult::new(hostname.to_string());
-
-    // Start the app
-    info!(hostname = %hostname, "starting app");
+    let collector = NoOpCollector::default();
     let cwd = app.read().await.cwd.clone();
-    app.write()
-        .await
-        .command
-        .start::<NoOpCollector>(cwd.as_ref(), None);
-    result.start_success = true;
+
+    if !app.read().await.managed {
+        info!(hostname = %hostname, "app is not managed by penny, only checking health");
+        result.already_running = true;
+        result.start_success = true;
+    } else if app.read().await.is_running().await {
+        if !options.allow_running {
+            result.start_error = Some(
+                "App is already running (use --allow-running to check it in place)".to_string(),
+            );
+            error!(hostname = %hostname, "app is already running");
+            return result;
+        }
+
+        info!(hostname = %hostname, "app already running, only checking health");
+        result.already_running = true;
+        result.start_success = true;
+    } else {
+        // Start the app. With --keep-running the app outlives this process,
+        // so its output is not captured.
+        info!(hostname = %hostname, "starting app");
+        let started = Instant::now();
+        let mut guard = app.write().await;
+        // The stderr snippet ignores the app's capture settings; nothing is
+        // stored.
+        let opts = (!options.keep_running).then(|| RunOptions {
+            run_id: RunId::default(),
+            collector: collector.clone(),
+            redactor: guard.redactor.clone(),
+            capture: CapturePolicy::All,
+            limiter: LineLimiter::default(),
+            start_output: OutputTail::default(),
+        });
+        guard.command.start(cwd.as_ref(), opts).await;
+        drop(guard);
+        result.start_success = true;
+        result.start_duration_ms = elapsed_ms(started);
+    }
 
     // Wait for healthy
     info!(hostname = %hostname, "waiting for health check");
-    match app.read().await.wait_for_running().await {
+    let health_started = Instant::now();
+    let probe = app.read().await.health_probe();
+    match probe.wait_for_running().await {
         Ok(()) => {
             result.health_check_success = true;
         }
@@ -92,33 +312,179 @@ async fn check_app(hostname: &str, app: &Arc<RwLock<App>>) -> AppCheckResult {
             error!(hostname = %hostname, "health check failed");
         }
     }
+    result.health_check_duration_ms = elapsed_ms(health_started);
+
+    // Apps that were already up are left alone, and --keep-running leaves
+    // healthy apps up.
+    if result.already_running || (options.keep_running && result.health_check_success) {
+        info!(hostname = %hostname, "leaving app running");
+        result.stop_success = true;
+        result.stop_skipped = true;
+    } else {
+        // Stop the app
+        info!(hostname = %hostname, "stopping app");
+        let stop_started = Instant::now();
+        app.write().await.command.stop(cwd.as_ref()).await;
+
+        // Wait for stopped
+        info!(hostname = %hostname, "waiting for app to stop");
+        match app.read().await.wait_for_stopped().await {
+            Ok(()) => {
+                result.stop_success = true;
+            }
+            Err(_) => {
+                result.stop_error = Some("Stop timed out".to_string());
+                error!(hostname = %hostname, "stop timed out");
+            }
+        }
+        result.stop_duration_ms = elapsed_ms(stop_started);
+    }
+
+    result.stderr = collector.stderr_snippet();
+    result.success = result.is_success();
+    result
+}
 
-    // Stop the app
-    info!(hostname = %hostname, "stopping app");
-    app.write().await.command.stop(cwd.as_ref()).await;
+/// How much longer than an app's `start_timeout` a remote check waits for
+/// its first response, for the proxy's own overhead.
+const REMOTE_TIMEOUT_SLACK: Duration = Duration::from_secs(10);
 
-    // Wait for stopped
-    info!(hostname = %hostname, "waiting for app to stop");
-    match app.read().await.wait_for_stopped().await {
-        Ok(()) => {
-            result.stop_success = true;
+/// The URL of an app's health check on the public proxy.
+fn remote_url(hostname: &str, health_check: &str, https: bool) -> String {
+    let scheme = if https { "https" } else { "http" };
+    format!("{scheme}://{hostname}{health_check}")
+}
+
+/// Runs the check for a single app through a running penny: the app is put
+/// to sleep through the API so the health check request has to wake it
+/// through the proxy.
+async fn check_remote_app(
+    hostname: &str,
+    app: &Arc<RwLock<App>>,
+    api: &ApiClient,
+    https: bool,
+    options: CheckOptions,
+) -> AppCheckResult {
+    let mut result = AppCheckResult::new(hostname.to_string());
+    let guard = app.read().await;
+    let managed = guard.managed;
+    let url = remote_url(hostname, &guard.health_check, https);
+    let deadline = guard.start_timeout.unsigned_abs() + REMOTE_TIMEOUT_SLACK;
+    drop(guard);
+
+    let stop = |hostname: &str| {
+        let path = format!("/api/apps/{hostname}/stop");
+        async move { api.post::<AppActionResponse>(&path).await }
+    };
+
+    if !managed {
+        info!(hostname = %hostname, "app is not managed by penny, only checking health");
+        result.already_running = true;
+    } else if options.allow_running && remote_is_awake(api, hostname).await {
+        info!(hostname = %hostname, "app already running, only checking health");
+        result.already_running = true;
+    } else {
+        info!(hostname = %hostname, "putting app to sleep to force a cold start");
+        if let Err(e) = stop(hostname).await {
+            result.start_error = Some(format!("failed to put the app to sleep: {e:#}"));
+            error!(hostname = %hostname, error = %e, "failed to stop app before checking it");
+            return result;
         }
-        Err(_) => {
-            result.stop_error = Some("Stop timed out".to_string());
-            error!(hostname = %hostname, "stop timed out");
+    }
+
+    info!(hostname = %hostname, url = %url, "requesting app through the proxy");
+    let started = Instant::now();
+    match wait_for_response(&url, deadline).await {
+        Ok(status) => {
+            result.start_success = true;
+            if status == reqwest::StatusCode::OK {
+                result.health_check_success = true;
+            } else {
+                result.health_check_error = Some(format!("responded with {status}"));
+                error!(hostname = %hostname, %status, "health check failed");
+            }
+        }
+        Err(e) => {
+            result.start_error = Some(e);
+            error!(hostname = %hostname, "app did not respond through the proxy");
         }
     }
+    result.start_duration_ms = elapsed_ms(started);
 
+    if result.already_running || (options.keep_running && result.health_check_success) {
+        info!(hostname = %hostname, "leaving app running");
+        result.stop_success = true;
+        result.stop_skipped = true;
+    } else {
+        info!(hostname = %hostname, "putting app back to sleep");
+        let stop_started = Instant::now();
+        match stop(hostname).await {
+            Ok(_) => result.stop_success = true,
+            Err(e) => {
+                result.stop_error = Some(format!("{e:#}"));
+                error!(hostname = %hostname, error = %e, "failed to stop app");
+            }
+        }
+        result.stop_duration_ms = elapsed_ms(stop_started);
+    }
+
+    result.success = result.is_success();
     result
 }
 
+/// Whether the running penny reports the app as up or starting.
+async fn remote_is_awake(api: &ApiClient, hostname: &str) -> bool {
+    match api.get::<Vec<LiveAppStatus>>("/api/live").await {
+        Ok(statuses) => statuses
+            .iter()
+            .any(|status| status.host == hostname && status.state != "sleeping"),
+        Err(e) => {
+            error!(error = %e, "failed to fetch app states");
+            false
+        }
+    }
+}
+
+/// Requests `url` until it answers with something other than a
+/// `503 Service Unavailable` asking to retry, or `timeout` passes.
+async fn wait_for_response(url: &str, timeout: Duration) -> Result<reqwest::StatusCode, String> {
+    let client = reqwest::Client::builder()
+        .timeout(timeout)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let started = Instant::now();
+
+    loop {
+        let response = client.get(url).send().await.map_err(|e| e.to_string())?;
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok());
+
+        match retry_after {
+            Some(secs) if status == reqwest::StatusCode::SERVICE_UNAVAILABLE => {
+                let wait = Duration::from_secs(secs.max(1));
+                if started.elapsed() + wait > timeout {
+                    return Err(format!("still starting after {}s", timeout.as_secs()));
+                }
+                tokio::time::sleep(wait).await;
+            }
+            _ => return Ok(status),
+        }
+    }
+}
+
 /// Prints the result for a single app.
 fn print_app_result(result: &AppCheckResult) {
     println!("========================================");
     println!("Checking: {}", result.hostname);
     println!("========================================");
 
-    if result.start_success {
+    if result.already_running {
+        println!("  \u{2713} Already running, start skipped");
+    } else if result.start_success {
         println!("  \u{2713} Start command executed");
     } else {
         let error = result.start_error.as_deref().unwrap_or("Unknown error");
@@ -135,7 +501,9 @@ fn print_app_result(result: &AppCheckResult) {
         println!("  \u{2717} Health check failed: {}", error);
     }
 
-    if result.stop_success {
+    if result.stop_skipped {
+        println!("  - Left running, stop skipped");
+    } else if result.stop_success {
         println!("  \u{2713} Stop completed");
     } else {
         let error = result.stop_error.as_deref().unwrap_or("Unknown error");
@@ -157,11 +525,82 @@ fn print_summary(results: &[AppCheckResult]) {
     println!("Total: {} | Passed: {} | Failed: {}", total, passed, failed);
 }
 
+#[derive(Debug, Serialize)]
+struct CheckReport<'a> {
+    total: usize,
+    passed: usize,
+    failed: usize,
+    apps: &'a [AppCheckResult],
+}
+
+fn print_json(results: &[AppCheckResult]) -> color_eyre::Result<()> {
+    let passed = results.iter().filter(|r| r.is_success()).count();
+    let report = CheckReport {
+        total: results.len(),
+        passed,
+        failed: results.len() - passed,
+        apps: results,
+    };
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn junit_report(results: &[AppCheckResult]) -> String {
+    let failed = results.iter().filter(|r| !r.is_success()).count();
+    let time = results.iter().map(|r| r.duration_ms()).sum::<u64>() as f64 / 1000.0;
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuites name=\"penny check\" tests=\"{}\" failures=\"{failed}\" time=\"{time:.3}\">\n",
+        results.len()
+    ));
+    xml.push_str(&format!(
+        "  <testsuite name=\"penny check\" tests=\"{}\" failures=\"{failed}\" time=\"{time:.3}\">\n",
+        results.len()
+    ));
+
+    for result in results {
+        xml.push_str(&format!(
+            "    <testcase classname=\"penny.check\" name=\"{}\" time=\"{:.3}\">\n",
+            escape_xml(&result.hostname),
+            result.duration_ms() as f64 / 1000.0
+        ));
+        let failures = result.failures();
+        if !failures.is_empty() {
+            let message = escape_xml(&failures.join("; "));
+            xml.push_str(&format!(
+                "      <failure message=\"{message}\">{message}</failure>\n"
+            ));
+        }
+        if !result.stderr.is_empty() {
+            xml.push_str(&format!(
+                "      <system-err>{}</system-err>\n",
+                escape_xml(&result.stderr.join("\n"))
+            ));
+        }
+        xml.push_str("    </testcase>\n");
+    }
+
+    xml.push_str("  </testsuite>\n</testsuites>");
+    xml
+}
+
 /// Main entry point for the check command.
 pub async fn run_check(
     config: &Config,
     apps_filter: Option<Vec<String>>,
+    options: CheckOptions,
+    remote: Option<&ApiClient>,
 ) -> color_eyre::Result<()> {
+    let format = options.format;
     info!(apps_count = config.apps.len(), "loaded configuration");
 
     // Filter apps if specified
@@ -175,7 +614,7 @@ pub async fn run_check(
         config.apps.iter().collect()
     };
 
-    if apps_to_check.is_empty() {
+    if apps_to_check.is_empty() && matches!(format, CheckFormat::Text) {
         println!("No apps to check.");
         return Ok(());
     }
@@ -183,12 +622,25 @@ pub async fn run_check(
     let mut results = Vec::new();
 
     for (hostname, app) in apps_to_check {
-        let result = check_app(hostname, app).await;
-        print_app_result(&result);
+        let result = match remote {
+            Some(api) => {
+                let https =
+                    config.tls.as_ref().is_some_and(|tls| tls.enabled) && app.read().await.tls;
+                check_remote_app(hostname, app, api, https, options).await
+            }
+            None => check_app(hostname, app, options).await,
+        };
+        if let CheckFormat::Text = format {
+            print_app_result(&result);
+        }
         results.push(result);
     }
 
-    print_summary(&results);
+    match format {
+        CheckFormat::Text => print_summary(&results),
+        CheckFormat::Json => print_json(&results)?,
+        CheckFormat::Junit => println!("{}", junit_report(&results)),
+    }
 
     // Return error if any checks failed
     let failed_count = results.iter().filter(|r| !r.is_success()).count();
@@ -198,3 +650,20 @@ pub async fn run_check(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remote_url_uses_the_public_hostname() {
+        assert_eq!(
+            remote_url("myapp.example.com", "/health", true),
+            "https://myapp.example.com/health"
+        );
+        assert_eq!(
+            remote_url("myapp.example.com", "/", false),
+            "http://myapp.example.com/"
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use color_eyre::eyre::{Context, eyre};
+use serde::de::DeserializeOwned;
+
+use crate::api::{AppActionResponse, RouteEntry};
+use crate::config::Config;
+
+/// Returns the dashboard API URL for a config, using loopback when
+/// `api_address` binds every interface.
+pub fn api_url(config: &Config) -> color_eyre::Result<String> {
+    let mut address = config
+        .api_address
+        .ok_or_else(|| eyre!("api_address is not set in the config file; pass --url instead"))?;
+    if address.ip().is_unspecified() {
+        address.set_ip(match address {
+            std::net::SocketAddr::V4(_) => std::net::Ipv4Addr::LOCALHOST.into(),
+            std::net::SocketAddr::V6(_) => std::net::Ipv6Addr::LOCALHOST.into(),
+        });
+    }
+    Ok(format!("http://{address}"))
+}
+
+/// Minimal client for a running penny's dashboard API.
+pub struct ApiClient {
+    http: reqwest::Client,
+    url: String,
+    password: Option<String>,
+}
+
+impl ApiClient {
+    pub fn new(url: String, password: Option<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            url: url.trim_end_matches('/').to_owned(),
+            password,
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    async fn send<T: DeserializeOwned>(
+        &self,
+        request: reqwest::RequestBuilder,
+        path: &str,
+    ) -> color_eyre::Result<T> {
+        let request = match &self.password {
+            Some(password) => request.bearer_auth(STANDARD.encode(password)),
+            None => request,
+        };
+
+        let response = request
+            .send()
+            .await
+            .wrap_err_with(|| format!("failed to reach {}{path}", self.url))?;
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .wrap_err_with(|| format!("failed to read {path}"))?;
+
+        if status == reqwest::StatusCode::UNAUTHORIZED {
+            return Err(eyre!("{path}: unauthorized, check --password"));
+        }
+        if !status.is_success() {
+            let body = body.trim();
+            return Err(if body.is_empty() {
+                eyre!("{path}: {status}")
+            } else {
+                eyre!("{path}: {status}: {body}")
+            });
+        }
+
+        serde_json::from_str(&body).wrap_err_with(|| format!("unexpected response from {path}"))
+    }
+
+    pub async fn get<T: DeserializeOwned>(&self, path: &str) -> color_eyre::Result<T> {
+        self.send(self.http.get(format!("{}{path}", self.url)), path)
+            .await
+    }
+
+    pub async fn post<T: DeserializeOwned>(&self, path: &str) -> color_eyre::Result<T> {
+        self.send(self.http.post(format!("{}{path}", self.url)), path)
+            .await
+    }
+}
+
+/// Runs an app action (`start`, `stop`, `redeploy`, or `rolling-restart`)
+/// through the API and reports the resulting state.
+pub async fn app_action(
+    url: String,
+    password: Option<String>,
+    host: &str,
+    action: &str,
+) -> color_eyre::Result<()> {
+    let client = ApiClient::new(url, password);
+    let response: AppActionResponse = client.post(&format!("/api/apps/{host}/{action}")).await?;
+
+    println!("{}: {}", response.host, response.state);
+    Ok(())
+}
+
+/// Prints every routing rule of a running penny.
+pub async fn routes(url: String, password: Option<String>) -> color_eyre::Result<()> {
+    let client = ApiClient::new(url, password);
+    let routes: Vec<RouteEntry> = client.get("/api/routes").await?;
+
+    if routes.is_empty() {
+        println!("No routes.");
+        return Ok(());
+    }
+
+    println!(
+        "{:<40} {:<10} {:<24} {:<5} STATE",
+        "HOST", "PATH", "TARGET", "TLS"
+    );
+    for route in routes {
+        let tls = if route.tls { "yes" } else { "no" };
+        println!(
+            "{:<40} {:<10} {:<24} {:<5} {}",
+            route.host,
+            route.path,
+            route.target,
+            tls,
+            route.state.as_deref().unwrap_or("-")
+        );
+    }
+    Ok(())
+}
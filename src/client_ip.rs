@@ -0,0 +1,179 @@
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// A network like `10.0.0.0/8` or `2001:db8::/32`. A bare address is a
+/// network of one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for Cidr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(cidr: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid network '{cidr}', expected e.g. '10.0.0.0/8'");
+        let (network, prefix) = match cidr.trim().split_once('/') {
+            Some((network, prefix)) => (network, Some(prefix)),
+            None => (cidr.trim(), None),
+        };
+        let network: IpAddr = network.parse().map_err(|_| invalid())?;
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|&prefix| prefix <= max)
+                .ok_or_else(invalid)?,
+            None => max,
+        };
+        Ok(Self { network, prefix })
+    }
+}
+
+impl Serialize for Cidr {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Cidr {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let cidr = String::deserialize(deserializer)?;
+        cidr.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+fn is_trusted(ip: IpAddr, trusted: &[Cidr]) -> bool {
+    trusted.iter().any(|cidr| cidr.contains(ip))
+}
+
+/// Who sent a request, and the `X-Forwarded-For` chain to pass upstream.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientIp {
+    pub ip: IpAddr,
+    pub forwarded_for: String,
+}
+
+/// Resolves the client of a request from the connection's `peer`. Only a
+/// trusted peer's `X-Forwarded-For` is honored: the client is the last
+/// address in it that is not a trusted proxy itself, since anything before
+/// that could have been made up by the client.
+pub fn resolve(peer: IpAddr, forwarded_for: Option<&str>, trusted: &[Cidr]) -> ClientIp {
+    let peer = peer.to_canonical();
+    let chain = forwarded_for
+        .filter(|_| is_trusted(peer, trusted))
+        .map(|chain| {
+            chain
+                .split(',')
+                .map(str::trim)
+                .filter(|hop| !hop.is_empty())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    let mut ip = peer;
+    for hop in chain.iter().rev() {
+        let Ok(hop) = hop.parse::<IpAddr>() else {
+            break;
+        };
+        ip = hop.to_canonical();
+        if !is_trusted(ip, trusted) {
+            break;
+        }
+    }
+
+    let peer = peer.to_string();
+    let forwarded_for = if chain.is_empty() {
+        peer
+    } else {
+        format!("{}, {peer}", chain.join(", "))
+    };
+    ClientIp { ip, forwarded_for }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn networks_match_addresses_inside_them() {
+        let cidr: Cidr = "10.0.0.0/8".parse().unwrap();
+        assert!(cidr.contains("10.1.2.3".parse().unwrap()));
+        assert!(cidr.contains("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(!cidr.contains("11.0.0.1".parse().unwrap()));
+
+        let single: Cidr = "2001:db8::1".parse().unwrap();
+        assert!(single.contains("2001:db8::1".parse().unwrap()));
+        assert!(!single.contains("2001:db8::2".parse().unwrap()));
+        assert!(
+            "0.0.0.0/0"
+                .parse::<Cidr>()
+                .unwrap()
+                .contains("1.2.3.4".parse().unwrap())
+        );
+
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("example.com".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn forwarded_for_is_only_honored_from_trusted_proxies() {
+        let trusted = ["10.0.0.0/8".parse().unwrap()];
+        let ip = |peer: &str, forwarded_for| {
+            resolve(peer.parse().unwrap(), forwarded_for, &trusted)
+                .ip
+                .to_string()
+        };
+
+        assert_eq!(ip("203.0.113.9", Some("1.1.1.1")), "203.0.113.9");
+        assert_eq!(ip("10.0.0.2", Some("198.51.100.7")), "198.51.100.7");
+        // A client prepending its own entry is ignored.
+        assert_eq!(
+            ip("10.0.0.2", Some("1.1.1.1, 198.51.100.7, 10.0.0.3")),
+            "198.51.100.7"
+        );
+        assert_eq!(ip("10.0.0.2", None), "10.0.0.2");
+
+        let forwarded = resolve("10.0.0.2".parse().unwrap(), Some("198.51.100.7"), &trusted);
+        assert_eq!(forwarded.forwarded_for, "198.51.100.7, 10.0.0.2");
+        let direct = resolve("203.0.113.9".parse().unwrap(), Some("1.1.1.1"), &trusted);
+        assert_eq!(direct.forwarded_for, "203.0.113.9");
+    }
+}
@@ -0,0 +1,125 @@
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::collector::Collector;
+use crate::config::SharedConfig;
+use crate::types::Host;
+
+/// How long a node's claim on an app it started lasts without being
+/// renewed. Nodes renew the claims of running apps every
+/// [`HEARTBEAT_INTERVAL`], so it only runs out when the node went away.
+pub const CLAIM_LEASE: Duration = Duration::from_secs(120);
+
+/// How often a node shares which apps are awake on it.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Names this process when `[cluster]` sets no `node`. Kept for the whole
+/// process, so reloads do not turn its own claims into someone else's.
+static PROCESS_NODE: LazyLock<String> = LazyLock::new(|| ulid::Ulid::new().to_string());
+
+/// Several penny nodes sharing one database (experimental). Only one node
+/// starts a sleeping app, and an app is not stopped while another node
+/// still sends it requests.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ClusterConfig {
+    /// This node's name among the others. Defaults to a random one per
+    /// process.
+    #[serde(default = "default_node")]
+    pub node: String,
+}
+
+fn default_node() -> String {
+    PROCESS_NODE.clone()
+}
+
+/// Records the last request to every app awake on this node, and renews
+/// the claims of the ones it runs.
+async fn heartbeat<C: Collector>(config: &SharedConfig, collector: &C) {
+    let config = config.read().await;
+    let Some(cluster) = &config.cluster else {
+        return;
+    };
+
+    for (host, app) in &config.apps {
+        let mut guard = app.write().await;
+        let running = guard.command.is_child_running();
+        if !running && !guard.idle_deadline.is_pending() {
+            continue;
+        }
+        let last_request_at = guard.request_tracker.last_request_at();
+        drop(guard);
+
+        let host = Host(host.clone());
+        if let Err(e) = collector
+            .app_seen(&cluster.node, &host, last_request_at)
+            .await
+        {
+            warn!(host = %host, error = %e, "failed to share app activity with the cluster");
+        }
+        if running
+            && let Ok(false) = collector
+                .claim_start(&cluster.node, &host, CLAIM_LEASE)
+                .await
+        {
+            warn!(host = %host, "another node claimed an app running here");
+        }
+    }
+}
+
+/// Shares app activity with the other nodes while `[cluster]` is set.
+pub fn spawn<C: Collector>(config: SharedConfig, collector: C) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+            heartbeat(&config, &collector).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::SqliteDatabase;
+
+    #[tokio::test]
+    async fn one_node_starts_an_app_and_all_see_its_requests() {
+        let db = SqliteDatabase::new("sqlite::memory:").await.unwrap();
+        let host = Host("myapp.local".to_string());
+
+        assert!(db.claim_start("a", &host, CLAIM_LEASE).await.unwrap());
+        assert!(!db.claim_start("b", &host, CLAIM_LEASE).await.unwrap());
+        assert!(db.claim_start("a", &host, CLAIM_LEASE).await.unwrap());
+        db.release_start("a", &host).await.unwrap();
+        assert!(db.claim_start("b", &host, Duration::ZERO).await.unwrap());
+        // A claim nobody renewed is up for grabs.
+        assert!(db.claim_start("a", &host, CLAIM_LEASE).await.unwrap());
+
+        assert_eq!(db.last_request_elsewhere("a", &host).await.unwrap(), None);
+        db.app_seen("a", &host, Some(1_000)).await.unwrap();
+        db.app_seen("b", &host, Some(2_000)).await.unwrap();
+        db.app_seen("c", &host, None).await.unwrap();
+        assert_eq!(
+            db.last_request_elsewhere("a", &host).await.unwrap(),
+            Some(2_000)
+        );
+        assert_eq!(
+            db.last_request_elsewhere("b", &host).await.unwrap(),
+            Some(1_000)
+        );
+    }
+
+    #[test]
+    fn nodes_without_a_name_keep_one_per_process() {
+        let config: ClusterConfig = toml::from_str("").unwrap();
+        let again: ClusterConfig = toml::from_str("").unwrap();
+        assert_eq!(config.node, again.node);
+        let named: ClusterConfig = toml::from_str("node = \"edge-1\"").unwrap();
+        assert_eq!(named.node, "edge-1");
+    }
+}
@@ -1,14 +1,20 @@
 use std::fmt::Debug;
+use std::time::{Duration, Instant};
 
 use color_eyre::Result;
 use jiff::Timestamp;
 
 use crate::db::SqliteDatabase;
-use crate::types::{Host, RunId};
+use crate::types::{
+    CertificateEvent, Host, ProxyFailure, RequestCounts, RunId, UsageSample, WaitDecision,
+    WakeReason,
+};
 
 #[async_trait::async_trait]
 pub trait Collector: Sync + Send + Clone + Debug + 'static {
     async fn app_started(&self, host: &Host) -> Result<RunId>;
+    /// Records what caused the run penny just started.
+    async fn app_woken(&self, run_id: &RunId, reason: WakeReason) -> Result<()>;
     async fn app_stopped(&self, host: &Host) -> Result<()>;
 
     async fn app_start_failed(&self, host: &Host) -> Result<()>;
@@ -16,6 +22,61 @@ pub trait Collector: Sync + Send + Clone + Debug + 'static {
 
     async fn append_stdout(&self, run_id: &RunId, line: String) -> Result<()>;
     async fn append_stderr(&self, run_id: &RunId, line: String) -> Result<()>;
+    /// Records output lines not stored for being over the app's
+    /// `capture_rate_limit`.
+    async fn lines_dropped(&self, run_id: &RunId, count: u64) -> Result<()>;
+    /// Adds a resource usage sample to a run's peak and average.
+    async fn usage_sampled(&self, run_id: &RunId, sample: &UsageSample) -> Result<()>;
+
+    /// Adds requests proxied to an app to its totals.
+    async fn requests_completed(&self, host: &Host, counts: &RequestCounts) -> Result<()>;
+
+    /// Records an uptime monitor check of an app. `status` is missing when
+    /// the request failed outright.
+    async fn monitor_checked(
+        &self,
+        host: &Host,
+        status: Option<u16>,
+        latency_ms: u64,
+        error: Option<&str>,
+        asleep: bool,
+    ) -> Result<()>;
+
+    /// Records the wait period chosen when an app's idle stop was
+    /// scheduled.
+    async fn wait_decided(&self, host: &Host, decision: &WaitDecision) -> Result<()>;
+
+    /// Records a request penny could not serve and why.
+    async fn proxy_failed(
+        &self,
+        host: &Host,
+        path: &str,
+        failure: ProxyFailure,
+        detail: &str,
+    ) -> Result<()>;
+
+    async fn certificate_event(
+        &self,
+        domain: &str,
+        event: CertificateEvent,
+        detail: Option<&str>,
+    ) -> Result<()>;
+
+    /// Claims starting an app for cluster node `node`, or extends its
+    /// claim, for `lease`. Returns false if another node holds one.
+    async fn claim_start(&self, node: &str, host: &Host, lease: Duration) -> Result<bool>;
+    /// Gives up `node`'s claim on an app it stopped.
+    async fn release_start(&self, node: &str, host: &Host) -> Result<()>;
+    /// Shares with the cluster that an app is awake on `node`, and when it
+    /// last got a request there.
+    async fn app_seen(&self, node: &str, host: &Host, last_request_at: Option<i64>) -> Result<()>;
+    /// The last request to an app seen by any cluster node but `node`.
+    async fn last_request_elsewhere(&self, node: &str, host: &Host) -> Result<Option<i64>>;
+}
+
+/// The lease a cluster node holds on an app it starts.
+fn start_lease(host: &Host) -> String {
+    format!("start:{host}")
 }
 
 #[async_trait::async_trait]
@@ -31,19 +92,51 @@ impl Collector for SqliteDatabase {
             .execute(&self.pool)
             .await?;
 
+        self.run_clocks
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(run_id.0.clone(), Instant::now());
+
         Ok(run_id)
     }
 
+    async fn app_woken(&self, run_id: &RunId, reason: WakeReason) -> Result<()> {
+        sqlx::query("UPDATE runs SET wake_reason = ? WHERE run_id = ?")
+            .bind(reason.as_str())
+            .bind(&run_id.0)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
     async fn app_stopped(&self, host: &Host) -> Result<()> {
         let stopped_at = Timestamp::now().as_millisecond();
 
-        sqlx::query(
-            "UPDATE runs SET stopped_at = ? WHERE run_id = (SELECT run_id FROM runs WHERE host = ? AND stopped_at IS NULL ORDER BY started_at DESC LIMIT 1)",
+        let run_id: Option<(String,)> = sqlx::query_as(
+            "SELECT run_id FROM runs WHERE host = ? AND stopped_at IS NULL ORDER BY started_at DESC LIMIT 1",
         )
-        .bind(stopped_at)
         .bind(&host.0)
-        .execute(&self.pool)
+        .fetch_optional(&self.pool)
         .await?;
+        let Some((run_id,)) = run_id else {
+            return Ok(());
+        };
+
+        // Runs opened before a restart have no clock; the reporter falls
+        // back to the timestamps for those.
+        let duration_ms = self
+            .run_clocks
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&run_id)
+            .map(|started| started.elapsed().as_millis() as i64);
+
+        sqlx::query("UPDATE runs SET stopped_at = ?, duration_ms = ? WHERE run_id = ?")
+            .bind(stopped_at)
+            .bind(duration_ms)
+            .bind(&run_id)
+            .execute(&self.pool)
+            .await?;
 
         Ok(())
     }
@@ -95,6 +188,167 @@ impl Collector for SqliteDatabase {
 
         Ok(())
     }
+
+    async fn lines_dropped(&self, run_id: &RunId, count: u64) -> Result<()> {
+        sqlx::query("UPDATE runs SET dropped_lines = dropped_lines + ? WHERE run_id = ?")
+            .bind(count as i64)
+            .bind(&run_id.0)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn usage_sampled(&self, run_id: &RunId, sample: &UsageSample) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE runs SET
+                usage_samples = usage_samples + 1,
+                memory_bytes_sum = memory_bytes_sum + ?1,
+                peak_memory_bytes = MAX(COALESCE(peak_memory_bytes, 0), ?1),
+                cpu_percent_sum = cpu_percent_sum + ?2,
+                peak_cpu_percent = MAX(COALESCE(peak_cpu_percent, 0), ?2)
+            WHERE run_id = ?3
+            "#,
+        )
+        .bind(sample.memory_bytes as i64)
+        .bind(sample.cpu_percent)
+        .bind(&run_id.0)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn requests_completed(&self, host: &Host, counts: &RequestCounts) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO request_counts (host, requests, errors, duration_ms, timestamp) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(&host.0)
+        .bind(counts.requests as i64)
+        .bind(counts.errors as i64)
+        .bind(counts.duration_ms as i64)
+        .bind(Timestamp::now().as_millisecond())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn monitor_checked(
+        &self,
+        host: &Host,
+        status: Option<u16>,
+        latency_ms: u64,
+        error: Option<&str>,
+        asleep: bool,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO monitor_checks (host, status, latency_ms, error, asleep, timestamp) VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&host.0)
+        .bind(status.map(i64::from))
+        .bind(latency_ms as i64)
+        .bind(error)
+        .bind(asleep)
+        .bind(Timestamp::now().as_millisecond())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn wait_decided(&self, host: &Host, decision: &WaitDecision) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO wait_decisions (host, wait_period_ms, short_rate, long_rate, adaptive, quiet_hours, timestamp) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&host.0)
+        .bind(decision.wait_period_ms as i64)
+        .bind(decision.short_rate)
+        .bind(decision.long_rate)
+        .bind(decision.adaptive)
+        .bind(decision.quiet_hours)
+        .bind(Timestamp::now().as_millisecond())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn proxy_failed(
+        &self,
+        host: &Host,
+        path: &str,
+        failure: ProxyFailure,
+        detail: &str,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO proxy_errors (host, path, kind, detail, timestamp) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(&host.0)
+        .bind(path)
+        .bind(failure.as_str())
+        .bind(detail)
+        .bind(Timestamp::now().as_millisecond())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn certificate_event(
+        &self,
+        domain: &str,
+        event: CertificateEvent,
+        detail: Option<&str>,
+    ) -> Result<()> {
+        let timestamp = Timestamp::now().as_millisecond();
+
+        sqlx::query(
+            "INSERT INTO certificate_events (domain, event, detail, timestamp) VALUES (?, ?, ?, ?)",
+        )
+        .bind(domain)
+        .bind(event.as_str())
+        .bind(detail)
+        .bind(timestamp)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn claim_start(&self, node: &str, host: &Host, lease: Duration) -> Result<bool> {
+        let expires_at = Timestamp::now().as_millisecond() + lease.as_millis() as i64;
+        self.acquire_lease(&start_lease(host), node, expires_at)
+            .await
+    }
+
+    async fn release_start(&self, node: &str, host: &Host) -> Result<()> {
+        self.release_lease(&start_lease(host), node).await
+    }
+
+    async fn app_seen(&self, node: &str, host: &Host, last_request_at: Option<i64>) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO cluster_activity (host, node, last_request_at, seen_at) VALUES (?, ?, ?, ?)
+            ON CONFLICT (host, node) DO UPDATE SET
+                last_request_at = COALESCE(excluded.last_request_at, cluster_activity.last_request_at),
+                seen_at = excluded.seen_at
+            "#,
+        )
+        .bind(&host.0)
+        .bind(node)
+        .bind(last_request_at)
+        .bind(Timestamp::now().as_millisecond())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn last_request_elsewhere(&self, node: &str, host: &Host) -> Result<Option<i64>> {
+        let (last_request_at,): (Option<i64>,) = sqlx::query_as(
+            "SELECT MAX(last_request_at) FROM cluster_activity WHERE host = ? AND node != ?",
+        )
+        .bind(&host.0)
+        .bind(node)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(last_request_at)
+    }
 }
 
 #[cfg(test)]
@@ -136,6 +390,23 @@ mod tests {
         assert_eq!(overview.total_runs, 1);
     }
 
+    #[tokio::test]
+    async fn awake_time_ignores_wall_clock_jumps() {
+        let db = create_test_db().await;
+        let host = Host("test-app.local".to_string());
+
+        db.app_started(&host).await.unwrap();
+        // As if the clock jumped forward an hour while the app ran.
+        sqlx::query("UPDATE runs SET started_at = started_at - 3600000")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        db.app_stopped(&host).await.unwrap();
+
+        let overview = db.app_overview(&host, None).await.unwrap();
+        assert!(overview.total_awake_time_ms < 60_000);
+    }
+
     #[tokio::test]
     async fn app_start_failed_sets_flag() {
         let db = create_test_db().await;
@@ -186,6 +457,26 @@ mod tests {
         assert_eq!(logs.stderr[1].line, "Stack trace here");
     }
 
+    #[tokio::test]
+    async fn certificate_event_recorded() {
+        let db = create_test_db().await;
+
+        db.certificate_event("app.local", CertificateEvent::Issued, None)
+            .await
+            .unwrap();
+        db.certificate_event("app.local", CertificateEvent::RenewalFailed, Some("boom"))
+            .await
+            .unwrap();
+        db.certificate_event("other.local", CertificateEvent::Issued, None)
+            .await
+            .unwrap();
+
+        let events = db.certificate_events(Some("app.local"), 10).await;
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event, "renewal_failed");
+        assert_eq!(events[0].detail.as_deref(), Some("boom"));
+    }
+
     #[tokio::test]
     async fn multiple_hosts_tracked_separately() {
         let db = create_test_db().await;
@@ -1,7 +1,7 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::fmt::Debug;
 use std::net::SocketAddr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use std::str::FromStr;
 use std::sync::Arc;
@@ -10,49 +10,81 @@ use std::time::Duration;
 use jiff::SignedDuration;
 use serde::{Deserialize, Serialize};
 use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::sync::RwLock;
+use tokio::sync::{RwLock, RwLockWriteGuard};
 use tracing::{debug, error, info, instrument, warn};
 
+use crate::capture::{CapturePolicy, LineLimiter, OutputTail};
+use crate::client_ip::Cidr;
+use crate::cluster::{CLAIM_LEASE, ClusterConfig};
 use crate::collector::Collector;
 use crate::db::SqliteDatabase;
+use crate::idle::{IdleDeadline, KeepAlive, LastWaitDecision};
+use crate::loading_page::{Branding, ColdStartPage};
+use crate::metrics;
+use crate::monitor::MonitorConfig;
+use crate::outbound::OutboundProxy;
+use crate::preview::Preview;
 use crate::proxy::ProxyContext;
-use crate::types::{Host, RunId};
+use crate::quiet::QuietHours;
+use crate::redact::Redactor;
+use crate::types::{Host, RequestCounts, RunId, UsageSample, WaitDecision, WakeReason};
 
-const SHORT_WINDOW_MINUTES: u64 = 5;
-const LONG_WINDOW_MINUTES: u64 = 30;
+/// Windows request rates are measured over, in seconds. Buckets are per
+/// second, so the windows slide smoothly and wait periods under a minute
+/// follow traffic as closely as long ones.
+const SHORT_WINDOW_SECS: u64 = 5 * 60;
+const LONG_WINDOW_SECS: u64 = 30 * 60;
+
+/// Buckets saved by versions that counted per minute are keyed by minutes
+/// since the epoch, far below any current second.
+const MINUTE_KEYED_BELOW: u64 = 1_000_000_000;
 
 #[derive(Debug, Default)]
 pub struct RequestTracker {
-    /// Request counts bucketed by minute (minute_epoch, count)
-    buckets: VecDeque<(u64, u64)>,
+    /// Request counts bucketed by second (unix_seconds, count). Behind a
+    /// mutex so requests can be recorded under the app's read lock.
+    buckets: std::sync::Mutex<VecDeque<(u64, u64)>>,
+    /// Unix milliseconds of the latest request, or 0 before the first.
+    last_request_at: std::sync::atomic::AtomicI64,
 }
 
 impl RequestTracker {
-    fn current_minute() -> u64 {
+    fn current_second() -> u64 {
         std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap_or_default()
             .as_secs()
-            / 60
     }
 
-    pub fn record_request(&mut self) {
-        let now = Self::current_minute();
+    fn buckets(&self) -> std::sync::MutexGuard<'_, VecDeque<(u64, u64)>> {
+        self.buckets.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn record_request(&self) {
+        self.last_request_at.store(
+            jiff::Timestamp::now().as_millisecond(),
+            std::sync::atomic::Ordering::Relaxed,
+        );
+        self.record_request_at(Self::current_second());
+    }
+
+    fn record_request_at(&self, now: u64) {
+        let mut buckets = self.buckets();
 
-        if let Some(last) = self.buckets.back_mut()
+        if let Some(last) = buckets.back_mut()
             && last.0 == now
         {
             last.1 += 1;
             return;
         }
 
-        self.buckets.push_back((now, 1));
+        buckets.push_back((now, 1));
 
         // Prune buckets older than the long window
-        let cutoff = now.saturating_sub(LONG_WINDOW_MINUTES);
-        while let Some(front) = self.buckets.front() {
+        let cutoff = now.saturating_sub(LONG_WINDOW_SECS);
+        while let Some(front) = buckets.front() {
             if front.0 < cutoff {
-                self.buckets.pop_front();
+                buckets.pop_front();
             } else {
                 break;
             }
@@ -61,47 +93,319 @@ impl RequestTracker {
 
     /// Returns (short_rate, long_rate) in requests per minute.
     pub fn request_rates(&self) -> (f64, f64) {
-        let now = Self::current_minute();
-        let short_cutoff = now.saturating_sub(SHORT_WINDOW_MINUTES);
-        let long_cutoff = now.saturating_sub(LONG_WINDOW_MINUTES);
+        self.request_rates_at(Self::current_second())
+    }
+
+    fn request_rates_at(&self, now: u64) -> (f64, f64) {
+        let short_cutoff = now.saturating_sub(SHORT_WINDOW_SECS);
+        let long_cutoff = now.saturating_sub(LONG_WINDOW_SECS);
 
         let mut short_total: u64 = 0;
         let mut long_total: u64 = 0;
 
-        for &(minute, count) in &self.buckets {
-            if minute >= long_cutoff {
+        for &(second, count) in self.buckets().iter() {
+            if second > long_cutoff {
                 long_total += count;
-                if minute >= short_cutoff {
+                if second > short_cutoff {
                     short_total += count;
                 }
             }
         }
 
-        let short_rate = short_total as f64 / SHORT_WINDOW_MINUTES as f64;
-        let long_rate = long_total as f64 / LONG_WINDOW_MINUTES as f64;
+        let short_rate = short_total as f64 * 60.0 / SHORT_WINDOW_SECS as f64;
+        let long_rate = long_total as f64 * 60.0 / LONG_WINDOW_SECS as f64;
 
         (short_rate, long_rate)
     }
 
+    /// Unix milliseconds of the latest request, if any.
+    pub fn last_request_at(&self) -> Option<i64> {
+        let at = self
+            .last_request_at
+            .load(std::sync::atomic::Ordering::Relaxed);
+        (at != 0).then_some(at)
+    }
+
+    /// The buckets and last request time, for persisting across restarts.
+    pub fn snapshot(&self) -> (Vec<(u64, u64)>, Option<i64>) {
+        (
+            self.buckets().iter().copied().collect(),
+            self.last_request_at(),
+        )
+    }
+
+    /// Replaces the state with a snapshot taken by [`RequestTracker::snapshot`],
+    /// including per-minute ones saved by older versions.
+    pub fn restore(&self, buckets: Vec<(u64, u64)>, last_request_at: Option<i64>) {
+        *self.buckets() = buckets
+            .into_iter()
+            .map(|(key, count)| {
+                if key < MINUTE_KEYED_BELOW {
+                    (key * 60, count)
+                } else {
+                    (key, count)
+                }
+            })
+            .collect();
+        self.last_request_at.store(
+            last_request_at.unwrap_or_default(),
+            std::sync::atomic::Ordering::Relaxed,
+        );
+    }
+
     /// Total requests within the long window, for logging.
     pub fn total_recent_requests(&self) -> u64 {
-        let now = Self::current_minute();
-        let cutoff = now.saturating_sub(LONG_WINDOW_MINUTES);
+        let cutoff = Self::current_second().saturating_sub(LONG_WINDOW_SECS);
 
-        self.buckets
+        self.buckets()
             .iter()
-            .filter(|(minute, _)| *minute >= cutoff)
+            .filter(|(second, _)| *second > cutoff)
             .map(|(_, count)| count)
             .sum()
     }
 }
 
+/// How long a resolved hostname `address` is used before resolving again.
+const ADDRESS_REFRESH: Duration = Duration::from_secs(30);
+
+/// Longest a single `address_command` run may take before it is killed and
+/// tried again.
+const ADDRESS_COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An app's `address`: a socket address, or a `hostname:port` that is
+/// resolved when needed and again every [`ADDRESS_REFRESH`], so upstreams
+/// whose IP changes (Docker DNS, Tailscale) keep working.
+#[derive(Debug)]
+pub enum UpstreamAddress {
+    Fixed(SocketAddr),
+    Hostname {
+        name: String,
+        resolved: std::sync::Mutex<Option<(SocketAddr, std::time::Instant)>>,
+    },
+}
+
+impl Clone for UpstreamAddress {
+    fn clone(&self) -> Self {
+        match self {
+            UpstreamAddress::Fixed(address) => UpstreamAddress::Fixed(*address),
+            UpstreamAddress::Hostname { name, resolved } => UpstreamAddress::Hostname {
+                name: name.clone(),
+                resolved: std::sync::Mutex::new(
+                    *resolved.lock().unwrap_or_else(|e| e.into_inner()),
+                ),
+            },
+        }
+    }
+}
+
+impl UpstreamAddress {
+    pub async fn resolve(&self) -> std::io::Result<SocketAddr> {
+        let (name, resolved) = match self {
+            UpstreamAddress::Fixed(address) => return Ok(*address),
+            UpstreamAddress::Hostname { name, resolved } => (name, resolved),
+        };
+
+        let cached = *resolved.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((address, at)) = cached
+            && at.elapsed() < ADDRESS_REFRESH
+        {
+            return Ok(address);
+        }
+
+        let lookup = tokio::net::lookup_host(name.as_str())
+            .await
+            .and_then(|mut addresses| {
+                addresses.next().ok_or_else(|| {
+                    std::io::Error::new(std::io::ErrorKind::NotFound, "no addresses found")
+                })
+            });
+        match (lookup, cached) {
+            (Ok(address), _) => {
+                if cached.is_none_or(|(previous, _)| previous != address) {
+                    info!(%name, %address, "resolved upstream address");
+                }
+                *resolved.lock().unwrap_or_else(|e| e.into_inner()) =
+                    Some((address, std::time::Instant::now()));
+                Ok(address)
+            }
+            (Err(e), Some((address, _))) => {
+                warn!(%name, %address, "failed to resolve upstream address, keeping the previous one: {e}");
+                Ok(address)
+            }
+            (Err(e), None) => Err(e),
+        }
+    }
+}
+
+impl UpstreamAddress {
+    /// Reads the address printed by an `address_command`: the first
+    /// non-empty line, e.g. `0.0.0.0:49153` from `docker port`. An
+    /// unspecified IP means the port is published on every interface, so
+    /// loopback is used instead.
+    fn from_command_output(output: &str) -> Option<Self> {
+        let line = output
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())?;
+        match line.parse().ok()? {
+            UpstreamAddress::Fixed(mut address) if address.ip().is_unspecified() => {
+                address.set_ip(match address {
+                    SocketAddr::V4(_) => std::net::Ipv4Addr::LOCALHOST.into(),
+                    SocketAddr::V6(_) => std::net::Ipv6Addr::LOCALHOST.into(),
+                });
+                Some(UpstreamAddress::Fixed(address))
+            }
+            address => Some(address),
+        }
+    }
+}
+
+impl std::fmt::Display for UpstreamAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpstreamAddress::Fixed(address) => address.fmt(f),
+            UpstreamAddress::Hostname { name, .. } => f.write_str(name),
+        }
+    }
+}
+
+impl FromStr for UpstreamAddress {
+    type Err = String;
+
+    fn from_str(address: &str) -> Result<Self, Self::Err> {
+        if let Ok(address) = address.parse() {
+            return Ok(UpstreamAddress::Fixed(address));
+        }
+
+        match address.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {
+                Ok(UpstreamAddress::Hostname {
+                    name: address.to_owned(),
+                    resolved: std::sync::Mutex::new(None),
+                })
+            }
+            _ => Err(format!(
+                "invalid address '{address}', expected ip:port or hostname:port"
+            )),
+        }
+    }
+}
+
+impl Serialize for UpstreamAddress {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for UpstreamAddress {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let address = String::deserialize(deserializer)?;
+        address.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// An inclusive range of ports like `4000-4010`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortRange {
+    start: u16,
+    end: u16,
+}
+
+impl PortRange {
+    pub fn start(&self) -> u16 {
+        self.start
+    }
+
+    pub fn iter(&self) -> std::ops::RangeInclusive<u16> {
+        self.start..=self.end
+    }
+
+    /// Whether the range holds a port other than `port`.
+    fn has_other_than(&self, port: u16) -> bool {
+        self.start != self.end || self.start != port
+    }
+
+    /// The first port in the range other than `current` that nothing
+    /// listens on at `ip`.
+    fn free_port(&self, ip: std::net::IpAddr, current: u16) -> Option<u16> {
+        (self.start..=self.end)
+            .filter(|&port| port != current)
+            .find(|&port| std::net::TcpListener::bind((ip, port)).is_ok())
+    }
+}
+
+impl std::fmt::Display for PortRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+impl FromStr for PortRange {
+    type Err = String;
+
+    fn from_str(range: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid port range '{range}', expected e.g. '4000-4010'");
+        let (start, end) = range.split_once('-').ok_or_else(invalid)?;
+        let port = |port: &str| {
+            port.trim()
+                .parse::<u16>()
+                .ok()
+                .filter(|&port| port != 0)
+                .ok_or_else(invalid)
+        };
+        let (start, end) = (port(start)?, port(end)?);
+        if start > end {
+            return Err(invalid());
+        }
+        Ok(Self { start, end })
+    }
+}
+
+impl Serialize for PortRange {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for PortRange {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let range = String::deserialize(deserializer)?;
+        range.parse().map_err(serde::de::Error::custom)
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct App {
-    pub address: SocketAddr,
+    pub address: UpstreamAddress,
     pub health_check: String,
+    /// Where requests go while `address` is not up after `fallback_after`,
+    /// e.g. a static "degraded" page. Penny keeps starting the app
+    /// meanwhile.
+    #[serde(default)]
+    pub fallback_address: Option<UpstreamAddress>,
+    #[serde(default = "default_fallback_after")]
+    pub fallback_after: SignedDuration,
+    /// Required unless `managed = false`.
+    #[serde(default)]
     pub command: AppCommand,
 
+    /// Set to false for services penny does not run: it proxies to them
+    /// and health-checks them, but never starts or stops them.
+    #[serde(default = "default_managed")]
+    pub managed: bool,
+
     #[serde(default = "default_wait_period")]
     pub wait_period: SignedDuration,
     #[serde(default = "default_start_timeout")]
@@ -109,10 +413,34 @@ pub struct App {
     #[serde(default = "default_stop_timeout")]
     pub stop_timeout: SignedDuration,
 
+    /// Environment variables for the app's commands, on top of penny's own.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+
+    /// Token the app sends with `POST /.penny/sleep` to be stopped before
+    /// its wait period is up. Passed to its commands as `PENNY_SLEEP_TOKEN`.
+    #[serde(default)]
+    pub sleep_token: Option<String>,
+
+    /// HTTP method of health checks.
+    #[serde(default = "default_health_check_method")]
+    pub health_check_method: String,
+    /// Extra headers sent with health checks, e.g. an `Authorization` token.
+    #[serde(default)]
+    pub health_check_headers: BTreeMap<String, String>,
+    /// `Host` header of health checks, for apps that route on it. Defaults
+    /// to the upstream address.
+    #[serde(default)]
+    pub health_check_host: Option<String>,
+
     #[serde(default = "default_health_check_initial_backoff_ms")]
     pub health_check_initial_backoff_ms: u64,
     #[serde(default = "default_health_check_max_backoff_secs")]
     pub health_check_max_backoff_secs: u64,
+    /// How long a passing health check is trusted when deciding whether an
+    /// app penny did not start is already up. 0 checks on every request.
+    #[serde(default = "default_health_check_cache_ms")]
+    pub health_check_cache_ms: u64,
 
     #[serde(default)]
     pub cold_start_page: bool,
@@ -123,6 +451,58 @@ pub struct App {
     #[serde(skip)]
     pub cold_start_page_html: Option<String>,
 
+    /// How many of the latest stdout lines to show on the cold start page
+    /// and in `/api/live` while the app starts, e.g. to follow migrations.
+    /// 0 shows none.
+    #[serde(default)]
+    pub cold_start_output_lines: usize,
+
+    #[serde(skip)]
+    pub start_output: OutputTail,
+
+    /// Overrides the global `cold_start_lang` for this app.
+    #[serde(default)]
+    pub cold_start_lang: Option<String>,
+
+    /// Overrides fields of the global `cold_start_branding` for this app.
+    #[serde(default)]
+    pub cold_start_branding: Branding,
+
+    /// Served by penny as `/robots.txt` while the app sleeps, instead of
+    /// waking it. Overrides the global `asleep_robots_txt`.
+    #[serde(default)]
+    pub asleep_robots_txt: Option<PathBuf>,
+
+    /// Served by penny as `/favicon.ico` while the app sleeps. Overrides the
+    /// global `asleep_favicon`.
+    #[serde(default)]
+    pub asleep_favicon: Option<PathBuf>,
+
+    /// The files above by request path, read at load.
+    #[serde(skip)]
+    pub asleep_files: HashMap<&'static str, AsleepFile>,
+
+    /// Paths whose requests are proxied without keeping the app awake or
+    /// counting toward adaptive wait rates and request metrics, e.g. uptime
+    /// monitor endpoints. A trailing `*` matches any path with that prefix.
+    #[serde(default)]
+    pub ignore_paths: Vec<String>,
+
+    /// Like `ignore_paths`, for requests whose `User-Agent` contains one of
+    /// these, ignoring case, e.g. `"UptimeRobot"`.
+    #[serde(default)]
+    pub ignore_user_agents: Vec<String>,
+
+    /// What requests that are not browser navigations (fetch/XHR, API
+    /// clients) get while the app is starting.
+    #[serde(default)]
+    pub non_navigation: NonNavigationPolicy,
+
+    /// How long queued requests wait for the app with
+    /// `non_navigation = "queue"` before getting a 503.
+    #[serde(default = "default_non_navigation_queue_timeout")]
+    pub non_navigation_queue_timeout: SignedDuration,
+
     #[serde(default)]
     pub adaptive_wait: bool,
 
@@ -138,44 +518,225 @@ pub struct App {
     #[serde(default)]
     pub high_req_per_hour: Option<f64>,
 
+    /// Daily window, e.g. `"23:00-07:00 Europe/Berlin"`, during which the
+    /// app goes to sleep after `quiet_wait_period` instead.
+    #[serde(default)]
+    pub quiet_hours: Option<QuietHours>,
+
+    /// Wait period during `quiet_hours`, when shorter than the usual one.
+    /// Pending stops are brought forward to it as quiet hours begin, so
+    /// `"0s"` stops the app then.
+    #[serde(default = "default_quiet_wait_period")]
+    pub quiet_wait_period: SignedDuration,
+
+    /// Estimated power draw while awake, in watts, to report the energy
+    /// saved while the app sleeps.
+    #[serde(default)]
+    pub watts: Option<f64>,
+
     #[serde(default)]
     pub cwd: Option<PathBuf>,
 
     #[serde(default)]
     pub also_warm: Vec<String>,
 
+    /// Apps stopped after this one when it goes idle, in order, e.g. a
+    /// database after the frontend using it. While this app runs, their
+    /// own idle stops are left to it.
+    #[serde(default)]
+    pub stop_after: Vec<StopStep>,
+
+    /// How long an unused connection to the app is kept open for reuse.
+    /// Unset keeps it until the connection pool needs room.
+    #[serde(default)]
+    pub keepalive_timeout: Option<SignedDuration>,
+
+    /// How many connections to the app are kept open for reuse. Requests
+    /// beyond this many at once close their connection when done; 0 never
+    /// reuses connections.
+    #[serde(default)]
+    pub keepalive_max_connections: Option<usize>,
+
+    /// Requests to the app being proxied right now. Idle stops and reloads
+    /// wait for them to finish.
+    #[serde(skip)]
+    pub in_flight: Arc<std::sync::atomic::AtomicUsize>,
+
+    /// Overrides the global `[tls.hsts]` policy for this app.
+    #[serde(default)]
+    pub hsts: Option<HstsConfig>,
+
+    /// Set to false to leave this host out of certificate provisioning
+    /// while TLS stays enabled for other apps.
+    #[serde(default = "default_tls")]
+    pub tls: bool,
+
+    /// Start and stop this app through a systemd unit (see
+    /// `penny systemd install-apps`) instead of spawning `command` directly.
+    #[serde(default)]
+    pub systemd_unit: Option<String>,
+
+    /// Rebuilds the app between the stop and start of a redeploy (see
+    /// `POST /api/apps/{host}/redeploy`). Requests to the app wait while it
+    /// runs, for up to `redeploy_timeout`.
+    #[serde(default)]
+    pub redeploy_command: Option<CommandSpec>,
+    #[serde(default = "default_redeploy_timeout")]
+    pub redeploy_timeout: SignedDuration,
+
+    /// Prints the app's current address after each start, e.g.
+    /// `docker port myapp 80`, for platforms that pick a new port on every
+    /// deploy. Replaces `address` until the next start.
+    #[serde(default)]
+    pub address_command: Option<CommandSpec>,
+
+    /// Ports a rolling restart (see `POST /api/apps/{host}/rolling-restart`)
+    /// may start the new instance on, e.g. `"4000-4010"`. The start command
+    /// gets the port to listen on in `PORT`.
+    #[serde(default)]
+    pub rolling_ports: Option<PortRange>,
+
+    /// Runs this app's commands through `$SHELL -c`, as if each had an
+    /// `sh:` prefix.
+    #[serde(default)]
+    pub shell: bool,
+
+    /// Runs the start command under bubblewrap with restricted filesystem
+    /// and network access. Ignored with `systemd_unit`.
+    #[serde(default)]
+    pub sandbox: Option<SandboxConfig>,
+
+    /// Regexes whose matches are masked in stored stdout/stderr. A
+    /// `secret` group masks only that part of the match.
+    #[serde(default)]
+    pub redact_patterns: Vec<String>,
+
+    /// Also mask common secrets such as `password=...`, bearer tokens and
+    /// credentials in URLs.
+    #[serde(default = "default_redact_defaults")]
+    pub redact_defaults: bool,
+
+    #[serde(skip)]
+    pub redactor: Redactor,
+
+    /// Which output streams to store: `all`, `stderr-only` or `none`.
+    #[serde(default)]
+    pub capture: CapturePolicy,
+
+    /// Most lines a second to store per run, so a chatty app does not
+    /// flood the database. Lines over it are dropped and counted.
+    #[serde(default)]
+    pub capture_rate_limit: Option<u32>,
+
     #[serde(skip)]
     pub request_tracker: RequestTracker,
 
+    /// Requests served since they were last stored (see
+    /// [`crate::request_stats`]). Behind a mutex so requests can be counted
+    /// under the app's read lock.
+    #[serde(skip)]
+    pub request_counts: std::sync::Mutex<RequestCounts>,
+
     #[serde(skip)]
     pub confirmed_healthy: bool,
 
+    /// When the last cacheable health check passed.
+    #[serde(skip)]
+    pub last_healthy_at: Option<std::time::Instant>,
+
+    /// The run the app's process records its output in.
+    #[serde(skip)]
+    pub current_run: Option<RunId>,
+
+    /// The latest resource usage sample of the app's processes, while they
+    /// run.
+    #[serde(skip)]
+    pub usage: Option<UsageSample>,
+
+    /// Resolves to the outcome of the most recent start's health check.
+    #[serde(skip)]
+    pub startup: Option<tokio::sync::watch::Receiver<Option<bool>>>,
+
+    /// Milliseconds from spawning the process to its first passing health
+    /// check, for the most recent cold start.
+    #[serde(skip)]
+    pub last_cold_start_ms: Option<u64>,
+
+    /// How the start in progress is going, for the cold start page and
+    /// `/api/live`. Health checks record into it without the app's lock.
+    #[serde(skip)]
+    pub start_progress: Arc<std::sync::Mutex<Option<StartProgress>>>,
+
+    #[serde(skip)]
+    pub idle_deadline: IdleDeadline,
+
+    #[serde(skip)]
+    pub keep_alive: KeepAlive,
+
+    #[serde(skip)]
+    pub last_wait_decision: LastWaitDecision,
+
+    /// Shared by every app when `max_concurrent_starts` is set.
+    #[serde(skip)]
+    pub start_slots: Option<Arc<tokio::sync::Semaphore>>,
+
+    /// This node's name when penny runs in a `[cluster]`.
     #[serde(skip)]
-    pub kill_task: Option<KillTask>,
+    pub cluster_node: Option<String>,
 
     #[serde(skip, default = "default_health_checker")]
-    pub health_checker: Box<dyn HealthChecker>,
+    pub health_checker: Arc<dyn HealthChecker>,
+
+    /// The domain of the preview this app was spawned for, if it was.
+    #[serde(skip)]
+    pub preview: Option<String>,
+
+    /// Set when a reload replaced the app with changed settings, until the
+    /// old app has finished its requests and stopped. Its process may
+    /// still answer on the same address, so this app must not start or
+    /// count as running before then.
+    #[serde(skip)]
+    pub replacing: Option<tokio::sync::watch::Receiver<bool>>,
+}
+
+/// One app in a `stop_after` sequence.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct StopStep {
+    pub host: String,
+    /// How long to wait for the app to stop before moving on to the next
+    /// one. Defaults to the app's `stop_timeout`.
+    #[serde(default)]
+    pub timeout: Option<SignedDuration>,
 }
 
-/// Handle for a scheduled kill task. Dropping the `cancel` sender
-/// cancels only the sleep phase; the stop/cleanup phase runs to completion.
-pub struct KillTask {
-    // Dropped to signal cancellation — never read directly.
-    #[allow(dead_code)]
-    cancel: tokio::sync::oneshot::Sender<()>,
-    _handle: tokio::task::JoinHandle<()>,
+/// How requests that are not browser navigations are handled while an app
+/// is starting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NonNavigationPolicy {
+    /// Hold the request until the app is healthy or `start_timeout` passes.
+    #[default]
+    Block,
+    /// Answer `503 Service Unavailable` with a `Retry-After` header at once.
+    #[serde(rename = "503_retry_after")]
+    RetryAfter,
+    /// Hold the request for up to `non_navigation_queue_timeout`, then
+    /// answer like `503_retry_after`.
+    Queue,
 }
 
-impl std::fmt::Debug for KillTask {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("KillTask").finish()
-    }
+fn default_non_navigation_queue_timeout() -> SignedDuration {
+    SignedDuration::from_secs(10)
 }
 
 pub fn default_wait_period() -> SignedDuration {
     SignedDuration::from_mins(10)
 }
 
+fn default_fallback_after() -> SignedDuration {
+    SignedDuration::from_secs(5)
+}
+
 pub fn default_start_timeout() -> SignedDuration {
     SignedDuration::from_secs(30)
 }
@@ -184,6 +745,26 @@ pub fn default_stop_timeout() -> SignedDuration {
     SignedDuration::from_secs(30)
 }
 
+fn default_redeploy_timeout() -> SignedDuration {
+    SignedDuration::from_mins(10)
+}
+
+fn default_tls() -> bool {
+    true
+}
+
+fn default_managed() -> bool {
+    true
+}
+
+fn default_redact_defaults() -> bool {
+    true
+}
+
+fn default_quiet_wait_period() -> SignedDuration {
+    SignedDuration::from_mins(1)
+}
+
 fn default_min_wait_period() -> SignedDuration {
     SignedDuration::from_mins(5)
 }
@@ -192,10 +773,18 @@ fn default_max_wait_period() -> SignedDuration {
     SignedDuration::from_mins(30)
 }
 
+fn default_health_check_method() -> String {
+    "GET".to_owned()
+}
+
 fn default_health_check_initial_backoff_ms() -> u64 {
     10
 }
 
+fn default_health_check_cache_ms() -> u64 {
+    1000
+}
+
 fn default_health_check_max_backoff_secs() -> u64 {
     2
 }
@@ -210,44 +799,163 @@ pub enum AppCommand {
     },
 }
 
+/// No command, for apps with `managed = false`.
+impl Default for AppCommand {
+    fn default() -> Self {
+        AppCommand::Start(Box::new(CommandSpec::from_parts(
+            String::new(),
+            Vec::new(),
+            "",
+        )))
+    }
+}
+
 #[derive(Debug)]
 pub struct CommandSpec {
     program: String,
     args: Vec<String>,
 
+    /// The command as written, without any `sh:` prefix.
+    source: String,
+    /// Runs `source` through `$SHELL -c` instead of splitting it into words.
+    shell: bool,
+    /// A program and arguments the command is run under, such as a
+    /// sandbox. Empty to run it directly.
+    wrapper: Vec<String>,
+
     collect_stdout: Option<tokio::task::JoinHandle<()>>,
     collect_stderr: Option<tokio::task::JoinHandle<()>>,
 
     child: Option<tokio::process::Child>,
+
+    /// When set, the command runs as this systemd unit instead of as a child.
+    unit: Option<SystemdUnit>,
+
+    /// Passed to the process as `PORT`.
+    port: Option<u16>,
+
+    /// Extra environment variables for the process.
+    env: Vec<(String, String)>,
 }
 
-impl Serialize for CommandSpec {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        let program = &self.program;
-        let args = shell_words::join(self.args.as_slice());
-        let command = format!("{program} {args}");
+/// A process taken out of its command by [`AppCommand::detach`], to run
+/// alongside a newer one until it is put back or killed.
+#[derive(Debug)]
+pub struct Detached {
+    child: Option<tokio::process::Child>,
+    collect_stdout: Option<tokio::task::JoinHandle<()>>,
+    collect_stderr: Option<tokio::task::JoinHandle<()>>,
+}
 
-        serializer.serialize_str(&command)
+impl Detached {
+    pub async fn kill(mut self) {
+        if let Some(child) = self.child.as_mut() {
+            info!("killing detached process");
+            if let Err(err) = child.kill().await {
+                error!("failed to kill process: {err}");
+            }
+        }
+        for collector in [self.collect_stdout, self.collect_stderr]
+            .into_iter()
+            .flatten()
+        {
+            collector.abort();
+        }
     }
 }
 
-impl FromStr for CommandSpec {
-    type Err = shell_words::ParseError;
+/// A systemd unit that runs an app's start command on penny's behalf.
+#[derive(Debug)]
+struct SystemdUnit {
+    name: String,
+    /// Talk to the user manager; penny uses the system manager only when it
+    /// runs as root, matching where `penny systemd install-apps` puts units.
+    user: bool,
+    active: bool,
+}
 
-    fn from_str(command: &str) -> Result<Self, Self::Err> {
-        let mut words = shell_words::split(command)?.into_iter();
-        let program = words.next().unwrap_or_else(|| command.to_owned());
+impl SystemdUnit {
+    fn new(name: String) -> Self {
+        use std::os::unix::fs::MetadataExt;
+        let root = std::fs::metadata("/proc/self").is_ok_and(|m| m.uid() == 0);
 
-        Ok(Self {
-            program,
-            args: words.collect(),
-            collect_stdout: None,
-            collect_stderr: None,
-            child: None,
-        })
+        Self {
+            name,
+            user: !root,
+            active: false,
+        }
+    }
+
+    fn systemctl_args<'a>(&'a self, action: &'a str) -> Vec<&'a str> {
+        let mut args = Vec::new();
+        if self.user {
+            args.push("--user");
+        }
+        args.extend([action, self.name.as_str()]);
+        args
+    }
+
+    async fn start(&mut self) {
+        if self.active {
+            debug!(unit = %self.name, "unit already started, skipping");
+            return;
+        }
+
+        info!(unit = %self.name, "starting systemd unit");
+        let mut args = self.systemctl_args("start");
+        args.insert(args.len() - 1, "--no-block");
+        let status = tokio::process::Command::new("systemctl")
+            .args(&args)
+            .status()
+            .await;
+        match status {
+            Ok(status) if status.success() => self.active = true,
+            Ok(status) => error!(unit = %self.name, "systemctl start exited with {status}"),
+            Err(err) => error!(unit = %self.name, "failed to run systemctl: {err}"),
+        }
+    }
+
+    async fn stop(&mut self) {
+        info!(unit = %self.name, "stopping systemd unit");
+        let status = tokio::process::Command::new("systemctl")
+            .args(self.systemctl_args("stop"))
+            .status()
+            .await;
+        match status {
+            Ok(status) if status.success() => {}
+            Ok(status) => error!(unit = %self.name, "systemctl stop exited with {status}"),
+            Err(err) => error!(unit = %self.name, "failed to run systemctl: {err}"),
+        }
+        self.active = false;
+    }
+}
+
+impl Serialize for CommandSpec {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        if self.shell {
+            serializer.serialize_str(&format!("sh:{}", self.source))
+        } else {
+            serializer.serialize_str(&self.command_line())
+        }
+    }
+}
+
+impl FromStr for CommandSpec {
+    type Err = shell_words::ParseError;
+
+    fn from_str(command: &str) -> Result<Self, Self::Err> {
+        if let Some(script) = command.strip_prefix("sh:") {
+            let mut spec = Self::from_parts(String::new(), Vec::new(), script.trim());
+            spec.use_shell();
+            return Ok(spec);
+        }
+
+        let mut words = shell_words::split(command)?.into_iter();
+        let program = words.next().unwrap_or_else(|| command.to_owned());
+        Ok(Self::from_parts(program, words.collect(), command))
     }
 }
 
@@ -265,24 +973,258 @@ impl<'de> Deserialize<'de> for CommandSpec {
 pub struct RunOptions<C: Collector> {
     pub run_id: RunId,
     pub collector: C,
+    pub redactor: Redactor,
+    pub capture: CapturePolicy,
+    pub limiter: LineLimiter,
+    /// Where stdout lines go for the cold start page.
+    pub start_output: OutputTail,
 }
 
 impl<C: Collector> RunOptions<C> {
+    /// Options for a new run of `app`, with its own line limit.
+    pub fn new(run_id: RunId, collector: C, app: &App) -> Self {
+        Self {
+            run_id,
+            collector,
+            redactor: app.redactor.clone(),
+            capture: app.capture,
+            limiter: LineLimiter::new(app.capture_rate_limit),
+            start_output: app.start_output.clone(),
+        }
+    }
+
     pub async fn append_stdout(&self, line: String) {
-        if let Err(e) = self.collector.append_stdout(&self.run_id, line).await {
+        let shown = self.start_output.is_enabled();
+        let stored = self.capture.stores_stdout() && self.allow().await;
+        if !shown && !stored {
+            return;
+        }
+        let line = self.redactor.redact(line);
+        if shown {
+            self.start_output.push(&line);
+        }
+        if stored
+            && let Err(e) =
+                metrics::store_output(self.collector.append_stdout(&self.run_id, line)).await
+        {
             error!("failed to append stdout: {e}");
         }
     }
 
     pub async fn append_stderr(&self, line: String) {
-        if let Err(e) = self.collector.append_stderr(&self.run_id, line).await {
+        if !self.capture.stores_stderr() || !self.allow().await {
+            return;
+        }
+        let line = self.redactor.redact(line);
+        if let Err(e) =
+            metrics::store_output(self.collector.append_stderr(&self.run_id, line)).await
+        {
             error!("failed to append stderr: {e}");
         }
     }
+
+    /// Applies the line limit, recording lines dropped since the last
+    /// stored one.
+    async fn allow(&self) -> bool {
+        if !self.limiter.allow() {
+            return false;
+        }
+        self.flush_dropped().await;
+        true
+    }
+
+    /// Records lines dropped over the line limit not yet recorded. Called
+    /// once output ends.
+    pub async fn flush_dropped(&self) {
+        let dropped = self.limiter.take_dropped();
+        if dropped == 0 {
+            return;
+        }
+        debug!(dropped, "dropped output lines over capture_rate_limit");
+        metrics::OUTPUT_LINES_DROPPED.add(dropped);
+        if let Err(e) = self.collector.lines_dropped(&self.run_id, dropped).await {
+            error!("failed to record dropped lines: {e}");
+        }
+    }
 }
 
 impl CommandSpec {
+    fn from_parts(program: String, args: Vec<String>, source: &str) -> Self {
+        Self {
+            program,
+            args,
+            source: source.to_owned(),
+            shell: false,
+            wrapper: Vec::new(),
+            collect_stdout: None,
+            collect_stderr: None,
+            child: None,
+            unit: None,
+            port: None,
+            env: Vec::new(),
+        }
+    }
+
+    /// A copy of the command that has started nothing, to run one-off
+    /// without holding the app's lock.
+    pub fn detached(&self) -> Self {
+        Self {
+            program: self.program.clone(),
+            args: self.args.clone(),
+            source: self.source.clone(),
+            shell: self.shell,
+            wrapper: self.wrapper.clone(),
+            collect_stdout: None,
+            collect_stderr: None,
+            child: None,
+            unit: None,
+            port: self.port,
+            env: self.env.clone(),
+        }
+    }
+
+    /// Runs the command through `$SHELL -c` from now on, so pipes, `&&`
+    /// and redirects work.
+    pub fn use_shell(&mut self) {
+        self.program = crate::service::login_shell();
+        self.args = vec!["-c".to_owned(), self.source.clone()];
+        self.shell = true;
+    }
+
+    /// Whether the command runs through the shell.
+    pub fn is_shell(&self) -> bool {
+        self.shell
+    }
+
+    /// Runs the command under `wrapper` (a program and its arguments) from
+    /// now on.
+    pub fn use_wrapper(&mut self, wrapper: Vec<String>) {
+        self.wrapper = wrapper;
+    }
+
+    /// The process to spawn, including any wrapper.
+    fn command(&self) -> tokio::process::Command {
+        let mut cmd = match self.wrapper.split_first() {
+            Some((wrapper, wrapper_args)) => {
+                let mut cmd = tokio::process::Command::new(wrapper);
+                cmd.args(wrapper_args).arg(&self.program);
+                cmd
+            }
+            None => tokio::process::Command::new(&self.program),
+        };
+        cmd.args(&self.args);
+        cmd.envs(self.env.iter().map(|(key, value)| (key, value)));
+        cmd
+    }
+
+    /// The command as written in the config file.
+    pub fn command_line(&self) -> String {
+        if self.shell {
+            return self.source.clone();
+        }
+        let program = &self.program;
+        let args = shell_words::join(self.args.as_slice());
+        format!("{program} {args}")
+    }
+
+    /// Runs the command to completion, recording its output in the run. It
+    /// is killed if it takes longer than `timeout`.
+    #[instrument(skip(self, opts), fields(program = %self.program))]
+    pub async fn run_to_completion<C: Collector>(
+        &self,
+        cwd: Option<&PathBuf>,
+        opts: &RunOptions<C>,
+        timeout: Duration,
+    ) -> std::io::Result<std::process::ExitStatus> {
+        info!(args = ?self.args, ?cwd, "running command to completion");
+        let mut cmd = self.command();
+        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+        if let Some(cwd) = cwd {
+            cmd.current_dir(cwd);
+        }
+        let mut child = cmd.spawn()?;
+
+        let mut collectors = Vec::new();
+        if let Some(stdout) = child.stdout.take() {
+            let mut reader = BufReader::new(stdout).lines();
+            let opts = opts.clone();
+            collectors.push(tokio::task::spawn(async move {
+                while let Ok(Some(line)) = reader.next_line().await {
+                    opts.append_stdout(line).await;
+                }
+                opts.flush_dropped().await;
+            }));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            let mut reader = BufReader::new(stderr).lines();
+            let opts = opts.clone();
+            collectors.push(tokio::task::spawn(async move {
+                while let Ok(Some(line)) = reader.next_line().await {
+                    opts.append_stderr(line).await;
+                }
+                opts.flush_dropped().await;
+            }));
+        }
+
+        let Ok(status) = tokio::time::timeout(timeout, child.wait()).await else {
+            let _ = child.kill().await;
+            // Whatever the command started may still hold its output open.
+            for collector in collectors {
+                collector.abort();
+            }
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "timed out",
+            ));
+        };
+        for collector in collectors {
+            let _ = collector.await;
+        }
+        status
+    }
+
+    /// Runs the command to completion and returns its stdout, failing if it
+    /// exits unsuccessfully. Dropping the future kills the command.
+    #[instrument(skip(self), fields(program = %self.program))]
+    pub async fn output(&self, cwd: Option<&PathBuf>) -> std::io::Result<String> {
+        let mut cmd = self.command();
+        cmd.stdin(Stdio::null()).kill_on_drop(true);
+        if let Some(cwd) = cwd {
+            cmd.current_dir(cwd);
+        }
+        let output = cmd.output().await?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(std::io::Error::other(format!(
+                "exited with {}: {}",
+                output.status,
+                stderr.trim()
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// Process id of the running child. Commands run as systemd units have
+    /// none.
+    pub fn child_pid(&self) -> Option<u32> {
+        self.child.as_ref().and_then(tokio::process::Child::id)
+    }
+
+    /// Like [`Self::is_child_running`], but only reads, so it works under a
+    /// read lock. A child that exited since it was last checked still
+    /// counts as running until then.
+    pub fn is_running(&self) -> bool {
+        if let Some(unit) = &self.unit {
+            return unit.active;
+        }
+        self.child_pid().is_some()
+    }
+
     pub fn is_child_running(&mut self) -> bool {
+        if let Some(unit) = &self.unit {
+            return unit.active;
+        }
+
         match self.child.as_mut() {
             Some(child) => match child.try_wait() {
                 Ok(Some(_)) => false,
@@ -294,7 +1236,13 @@ impl CommandSpec {
     }
 
     #[instrument(skip(self), fields(program = %self.program))]
-    pub fn run<C: Collector>(&mut self, cwd: Option<&PathBuf>, opts: Option<RunOptions<C>>) {
+    pub async fn run<C: Collector>(&mut self, cwd: Option<&PathBuf>, opts: Option<RunOptions<C>>) {
+        if let Some(unit) = self.unit.as_mut() {
+            // Output goes to the journal rather than the run logs.
+            unit.start().await;
+            return;
+        }
+
         let should_spawn = match self.child.as_mut() {
             Some(child) => match child.try_wait() {
                 Ok(Some(exit)) => {
@@ -318,13 +1266,23 @@ impl CommandSpec {
         };
 
         info!(args = ?self.args, ?cwd, "spawning command");
-        let mut cmd = tokio::process::Command::new(&self.program);
-        cmd.args(&self.args)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
+        // Output is only piped when something reads it; an unread pipe would
+        // eventually fill up and block the child.
+        let output = || {
+            if opts.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            }
+        };
+        let mut cmd = self.command();
+        cmd.stdout(output()).stderr(output());
         if let Some(cwd) = cwd {
             cmd.current_dir(cwd);
         }
+        if let Some(port) = self.port {
+            cmd.env("PORT", port.to_string());
+        }
         match cmd.spawn() {
             Ok(mut child) => {
                 if let Some(opts) = opts {
@@ -336,6 +1294,7 @@ impl CommandSpec {
                             while let Ok(Some(line)) = reader.next_line().await {
                                 opts.append_stdout(line).await;
                             }
+                            opts.flush_dropped().await;
                         }));
                     }
 
@@ -347,6 +1306,7 @@ impl CommandSpec {
                             while let Ok(Some(line)) = reader.next_line().await {
                                 opts.append_stderr(line).await;
                             }
+                            opts.flush_dropped().await;
                         }));
                     }
                 }
@@ -362,6 +1322,11 @@ impl CommandSpec {
 
     #[instrument(skip(self), fields(program = %self.program))]
     pub async fn kill(&mut self) {
+        if let Some(unit) = self.unit.as_mut() {
+            unit.stop().await;
+            return;
+        }
+
         if let Some(mut child) = self.child.take() {
             info!("killing process");
 
@@ -388,6 +1353,124 @@ impl CommandSpec {
 }
 
 impl AppCommand {
+    /// Runs the start command as the given systemd unit from now on.
+    pub fn use_systemd_unit(&mut self, name: String) {
+        let start = match self {
+            AppCommand::Start(start) => start,
+            AppCommand::StartEnd { start, .. } => start,
+        };
+        start.unit = Some(SystemdUnit::new(name));
+    }
+
+    /// Runs the start command under `wrapper` from now on. The end command
+    /// acts on the app from outside and runs as is.
+    pub fn use_wrapper(&mut self, wrapper: Vec<String>) {
+        let start = match self {
+            AppCommand::Start(start) => start,
+            AppCommand::StartEnd { start, .. } => start,
+        };
+        start.use_wrapper(wrapper);
+    }
+
+    /// Runs the start and end commands through the shell from now on.
+    pub fn use_shell(&mut self) {
+        match self {
+            AppCommand::Start(start) => start.use_shell(),
+            AppCommand::StartEnd { start, end } => {
+                start.use_shell();
+                end.use_shell();
+            }
+        }
+    }
+
+    /// Sets an environment variable for the start and end commands.
+    pub fn set_env(&mut self, key: &str, value: &str) {
+        let specs = match self {
+            AppCommand::Start(start) => vec![start],
+            AppCommand::StartEnd { start, end } => vec![start, end],
+        };
+        for spec in specs {
+            spec.env.push((key.to_owned(), value.to_owned()));
+        }
+    }
+
+    /// Passes `port` to the start command as `PORT` from now on.
+    pub fn set_port(&mut self, port: Option<u16>) {
+        let start = match self {
+            AppCommand::Start(start) => start,
+            AppCommand::StartEnd { start, .. } => start,
+        };
+        start.port = port;
+    }
+
+    /// Takes the running process out, so [`AppCommand::start`] spawns a
+    /// new one beside it.
+    pub fn detach(&mut self) -> Detached {
+        let start = match self {
+            AppCommand::Start(start) => start,
+            AppCommand::StartEnd { start, .. } => start,
+        };
+        Detached {
+            child: start.child.take(),
+            collect_stdout: start.collect_stdout.take(),
+            collect_stderr: start.collect_stderr.take(),
+        }
+    }
+
+    /// Puts a detached process back, returning the one it replaces.
+    pub fn reattach(&mut self, process: Detached) -> Detached {
+        let replaced = self.detach();
+        let start = match self {
+            AppCommand::Start(start) => start,
+            AppCommand::StartEnd { start, .. } => start,
+        };
+        start.child = process.child;
+        start.collect_stdout = process.collect_stdout;
+        start.collect_stderr = process.collect_stderr;
+        replaced
+    }
+
+    /// Extra environment variables of the start command, in the order they
+    /// are set; later ones win.
+    pub fn env(&self) -> &[(String, String)] {
+        match self {
+            AppCommand::Start(start) => &start.env,
+            AppCommand::StartEnd { start, .. } => &start.env,
+        }
+    }
+
+    /// Whether the start command runs through the shell.
+    pub fn start_in_shell(&self) -> bool {
+        match self {
+            AppCommand::Start(start) => start.is_shell(),
+            AppCommand::StartEnd { start, .. } => start.is_shell(),
+        }
+    }
+
+    /// The start command and, if configured, the end command.
+    pub fn command_lines(&self) -> (String, Option<String>) {
+        match self {
+            AppCommand::Start(start) => (start.command_line(), None),
+            AppCommand::StartEnd { start, end } => (start.command_line(), Some(end.command_line())),
+        }
+    }
+
+    /// Whether `stop` also stops a process penny did not spawn, through an
+    /// end command or a systemd unit.
+    pub fn can_stop_untracked(&self) -> bool {
+        match self {
+            AppCommand::Start(start) => start.unit.is_some(),
+            AppCommand::StartEnd { .. } => true,
+        }
+    }
+
+    pub fn child_pid(&self) -> Option<u32> {
+        match self {
+            AppCommand::Start(start) => start.child_pid(),
+            AppCommand::StartEnd { start, .. } => start.child_pid(),
+        }
+    }
+
     pub fn is_child_running(&mut self) -> bool {
         match self {
             AppCommand::Start(start) => start.is_child_running(),
@@ -395,15 +1478,27 @@ impl AppCommand {
         }
     }
 
+    /// See [`CommandSpec::is_running`].
+    pub fn is_running(&self) -> bool {
+        match self {
+            AppCommand::Start(start) => start.is_running(),
+            AppCommand::StartEnd { start, .. } => start.is_running(),
+        }
+    }
+
     #[instrument(skip(self))]
-    pub fn start<C: Collector>(&mut self, cwd: Option<&PathBuf>, opts: Option<RunOptions<C>>) {
+    pub async fn start<C: Collector>(
+        &mut self,
+        cwd: Option<&PathBuf>,
+        opts: Option<RunOptions<C>>,
+    ) {
         debug!("starting app command");
         let start = match self {
             AppCommand::Start(start) => start.as_mut(),
             AppCommand::StartEnd { start, .. } => start.as_mut(),
         };
 
-        start.run(cwd, opts);
+        start.run(cwd, opts).await;
     }
 
     #[instrument(skip(self))]
@@ -413,48 +1508,219 @@ impl AppCommand {
             AppCommand::Start(start) => start.kill().await,
             AppCommand::StartEnd { start, end } => {
                 start.kill().await;
-                end.run::<SqliteDatabase>(cwd, None)
+                // A unit runs the end command itself as `ExecStop=`.
+                if start.unit.is_none() {
+                    end.run::<SqliteDatabase>(cwd, None).await
+                }
             }
         };
     }
 }
 
-static HTTP: std::sync::LazyLock<reqwest::Client> = std::sync::LazyLock::new(reqwest::Client::new);
+static HTTP: std::sync::LazyLock<reqwest::Client> =
+    std::sync::LazyLock::new(crate::outbound::client);
+
+/// What an app sets of its cold start page, from
+/// [`App::own_cold_start_page`].
+#[derive(Debug, Clone)]
+pub struct AppColdStartPage {
+    html: Option<String>,
+    lang: Option<String>,
+    branding: Branding,
+}
+
+/// How [`App::launch`] handed a start to [`App::watch_startup`].
+enum Launch<C: Collector> {
+    /// The process was spawned, holding a start slot if they are limited.
+    Started(Option<tokio::sync::OwnedSemaphorePermit>),
+    /// Waiting for a start slot before spawning.
+    Queued(Arc<tokio::sync::Semaphore>, RunOptions<C>),
+    /// Running `redeploy_command` before spawning.
+    Redeploy(Rebuild<C>),
+}
+
+/// A `redeploy_command` run by [`App::watch_startup`] before the app starts
+/// again.
+struct Rebuild<C: Collector> {
+    command: CommandSpec,
+    timeout: Duration,
+    opts: RunOptions<C>,
+    /// Told whether the command succeeded, or why not.
+    done: tokio::sync::oneshot::Sender<Result<(), String>>,
+}
+
+/// Elapsed time and health check results of a start in progress.
+#[derive(Debug, Clone)]
+pub struct StartProgress {
+    pub since: std::time::Instant,
+    /// Health checks made so far.
+    pub checks: u32,
+    /// Why the latest health check failed, or `None` if none has yet.
+    pub last_error: Option<String>,
+}
 
 #[async_trait::async_trait]
 pub trait HealthChecker: Send + Sync + Debug {
-    async fn check(&self, address: SocketAddr, path: &str) -> bool;
+    /// Probes the app, returning why it is not healthy on failure.
+    async fn check(&self, address: SocketAddr, path: &str) -> Result<(), String>;
 }
 
-#[derive(Debug)]
-pub struct HttpHealthChecker;
+#[derive(Debug, Default)]
+pub struct HttpHealthChecker {
+    method: http::Method,
+    headers: http::HeaderMap,
+}
+
+impl HttpHealthChecker {
+    /// Builds a checker from an app's `health_check_method`,
+    /// `health_check_headers` and `health_check_host`.
+    pub fn new(
+        method: &str,
+        headers: &BTreeMap<String, String>,
+        host: Option<&str>,
+    ) -> Result<Self, String> {
+        let method = http::Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+            .map_err(|_| format!("invalid health_check_method '{method}'"))?;
+
+        let mut header_map = http::HeaderMap::new();
+        let host = host.map(|host| (http::header::HOST.as_str(), host));
+        for (name, value) in headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .chain(host)
+        {
+            let name = http::HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| format!("invalid health check header name '{name}'"))?;
+            let value = http::HeaderValue::from_str(value)
+                .map_err(|_| format!("invalid value for health check header '{name}'"))?;
+            header_map.insert(name, value);
+        }
+
+        Ok(Self {
+            method,
+            headers: header_map,
+        })
+    }
+}
 
 #[async_trait::async_trait]
 impl HealthChecker for HttpHealthChecker {
-    async fn check(&self, address: SocketAddr, path: &str) -> bool {
+    async fn check(&self, address: SocketAddr, path: &str) -> Result<(), String> {
         let url = format!("http://{address}{path}");
-        debug!(url = %url, "performing health check");
+        debug!(url = %url, method = %self.method, "performing health check");
+
+        let request = HTTP
+            .request(self.method.clone(), &url)
+            .headers(self.headers.clone());
+        let result = match request.send().await {
+            Ok(resp) if resp.status() == http::StatusCode::OK => Ok(()),
+            Ok(resp) => Err(format!("responded with {}", resp.status())),
+            Err(e) if e.is_connect() => Err("could not connect".to_owned()),
+            Err(e) if e.is_timeout() => Err("timed out".to_owned()),
+            Err(_) => Err("request failed".to_owned()),
+        };
+        debug!(?result, "health check result");
+        result
+    }
+}
 
-        let resp = HTTP
-            .get(&url)
-            .send()
-            .await
-            .ok()
-            .map(|r| r.status())
-            .unwrap_or_else(|| http::StatusCode::SERVICE_UNAVAILABLE);
+fn default_health_checker() -> Arc<dyn HealthChecker> {
+    Arc::new(HttpHealthChecker::default())
+}
 
-        let is_ok = resp == http::StatusCode::OK;
-        debug!(status = %resp, is_running = is_ok, "health check result");
-        is_ok
-    }
+/// Delays between health checks while waiting for an app.
+fn backoff(initial_ms: u64, max_secs: u64) -> impl Iterator<Item = Duration> {
+    tokio_retry::strategy::ExponentialBackoff::from_millis(initial_ms)
+        .max_delay(Duration::from_secs(max_secs))
+        .map(tokio_retry::strategy::jitter)
 }
 
-fn default_health_checker() -> Box<dyn HealthChecker> {
-    Box::new(HttpHealthChecker)
+/// What health checks need from an app, taken out of it so that waiting for
+/// a start does not hold the app's lock: a start or stop queued for the
+/// write lock would otherwise keep every request out until the app is up.
+#[derive(Debug, Clone)]
+pub struct HealthProbe {
+    address: UpstreamAddress,
+    health_check: String,
+    checker: Arc<dyn HealthChecker>,
+    progress: Arc<std::sync::Mutex<Option<StartProgress>>>,
+    initial_backoff_ms: u64,
+    max_backoff_secs: u64,
+    start_timeout: SignedDuration,
+}
+
+impl HealthProbe {
+    async fn probe(&self) -> Result<(), String> {
+        match self.address.resolve().await {
+            Ok(address) => self.checker.check(address, &self.health_check).await,
+            Err(e) => {
+                debug!("failed to resolve address: {e}");
+                Err(format!("could not resolve {}", self.address))
+            }
+        }
+    }
+
+    fn record_check(&self, result: &Result<(), String>) {
+        let mut progress = self.progress.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(progress) = progress.as_mut() {
+            progress.checks += 1;
+            progress.last_error = result.as_ref().err().cloned();
+        }
+    }
+
+    /// Probes the app until it passes its health check or `start_timeout`
+    /// passes, recording each check in the start's progress.
+    #[instrument(skip(self), fields(address = %self.address, timeout = ?self.start_timeout))]
+    pub async fn wait_for_running(&self) -> Result<(), pingora::time::Elapsed> {
+        debug!("waiting for app to become ready");
+        let strategy = backoff(self.initial_backoff_ms, self.max_backoff_secs);
+        let wait_for_running = tokio_retry::Retry::spawn(strategy, async || {
+            let result = self.probe().await;
+            self.record_check(&result);
+            result
+        });
+
+        let result = pingora::time::timeout(self.start_timeout.unsigned_abs(), wait_for_running)
+            .await
+            .map(|_| ());
+        if result.is_ok() {
+            info!("app is now running");
+        } else {
+            warn!("timed out waiting for app to start");
+        }
+
+        result
+    }
 }
 
 impl App {
     pub fn effective_wait_period(&self) -> Duration {
+        let wait_period = self.usual_wait_period();
+        match &self.quiet_hours {
+            Some(quiet_hours) if quiet_hours.is_now() => {
+                wait_period.min(self.quiet_wait_period.unsigned_abs())
+            }
+            _ => wait_period,
+        }
+    }
+
+    /// The wait period [`Self::effective_wait_period`] picks, with the
+    /// request rates and settings behind it.
+    pub fn wait_decision(&self) -> WaitDecision {
+        let usual = self.usual_wait_period();
+        let wait_period = self.effective_wait_period();
+        let (short_rate, long_rate) = self.request_tracker.request_rates();
+        WaitDecision {
+            wait_period_ms: wait_period.as_millis() as u64,
+            short_rate,
+            long_rate,
+            adaptive: self.adaptive_wait,
+            quiet_hours: wait_period < usual,
+        }
+    }
+
+    /// The wait period outside `quiet_hours`.
+    fn usual_wait_period(&self) -> Duration {
         if !self.adaptive_wait {
             return self.wait_period.unsigned_abs();
         }
@@ -485,42 +1751,123 @@ impl App {
         Duration::from_secs_f64(min_secs + (max_secs - min_secs) * factor)
     }
 
+    /// Counts a served request for the app's overview.
+    pub fn count_request(&self, status: u16, duration_ms: u64) {
+        self.request_counts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .add(status, duration_ms);
+    }
+
+    /// Takes the requests counted since the last call.
+    pub fn take_request_counts(&self) -> RequestCounts {
+        std::mem::take(
+            &mut *self
+                .request_counts
+                .lock()
+                .unwrap_or_else(|e| e.into_inner()),
+        )
+    }
+
+    /// The app's own cold start page settings, to take out of its lock
+    /// before [`Config::cold_start_page`] fills in the global ones.
+    pub fn own_cold_start_page(&self) -> AppColdStartPage {
+        AppColdStartPage {
+            html: self.cold_start_page_html.clone(),
+            lang: self.cold_start_lang.clone(),
+            branding: self.cold_start_branding.clone(),
+        }
+    }
+
+    /// Whether requests to `path` are exempt from idle tracking.
+    pub fn ignores_path(&self, path: &str) -> bool {
+        self.ignore_paths
+            .iter()
+            .any(|ignored| match ignored.strip_suffix('*') {
+                Some(prefix) => path.starts_with(prefix),
+                None => path == ignored,
+            })
+    }
+
+    pub fn ignores_user_agent(&self, agent: &str) -> bool {
+        let agent = agent.to_ascii_lowercase();
+        self.ignore_user_agents
+            .iter()
+            .any(|ignored| agent.contains(&ignored.to_ascii_lowercase()))
+    }
+
     #[instrument(skip(self), fields(address = %self.address, health_check = %self.health_check))]
     pub async fn is_running(&self) -> bool {
-        self.health_checker
-            .check(self.address, &self.health_check)
-            .await
+        self.probe().await.is_ok()
     }
 
-    fn retry_strategy(&self) -> impl Iterator<Item = Duration> {
-        tokio_retry::strategy::ExponentialBackoff::from_millis(self.health_check_initial_backoff_ms)
-            .max_delay(Duration::from_secs(self.health_check_max_backoff_secs))
-            .map(tokio_retry::strategy::jitter)
+    async fn probe(&self) -> Result<(), String> {
+        self.health_probe().probe().await
     }
 
-    #[instrument(skip(self), fields(timeout = ?self.start_timeout))]
-    pub async fn wait_for_running(&self) -> Result<(), pingora::time::Elapsed> {
-        let strategy = self.retry_strategy();
+    /// A copy of what health checks need, to probe without the app's lock.
+    pub fn health_probe(&self) -> HealthProbe {
+        HealthProbe {
+            address: self.address.clone(),
+            health_check: self.health_check.clone(),
+            checker: self.health_checker.clone(),
+            progress: self.start_progress.clone(),
+            initial_backoff_ms: self.health_check_initial_backoff_ms,
+            max_backoff_secs: self.health_check_max_backoff_secs,
+            start_timeout: self.start_timeout,
+        }
+    }
 
-        debug!("waiting for app to become ready");
-        let wait_for_running = tokio_retry::Retry::spawn(strategy, async || -> Result<(), ()> {
-            if self.is_running().await {
-                Ok(())
-            } else {
-                Err(())
-            }
+    /// Marks a start as begun, resetting its progress.
+    fn begin_progress(&mut self) {
+        *self
+            .start_progress
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = Some(StartProgress {
+            since: std::time::Instant::now(),
+            checks: 0,
+            last_error: None,
         });
+    }
 
-        let result = pingora::time::timeout(self.start_timeout.unsigned_abs(), wait_for_running)
-            .await
-            .map(|_| ());
-        if result.is_ok() {
-            info!("app is now running");
-        } else {
-            warn!("timed out waiting for app to start");
+    /// Progress of the most recent start.
+    pub fn start_progress(&self) -> Option<StartProgress> {
+        self.start_progress
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Like [`App::is_running`], but trusts a check that passed within
+    /// `health_check_cache_ms`. Requests queue on the app's write lock, so
+    /// concurrent ones share a single probe.
+    pub async fn is_running_cached(&mut self) -> bool {
+        let ttl = Duration::from_millis(self.health_check_cache_ms);
+        if self
+            .last_healthy_at
+            .is_some_and(|checked| checked.elapsed() < ttl)
+        {
+            debug!("using cached health check result");
+            return true;
         }
 
-        result
+        let healthy = self.is_running().await;
+        self.last_healthy_at = healthy.then(std::time::Instant::now);
+        healthy
+    }
+
+    fn retry_strategy(&self) -> impl Iterator<Item = Duration> + use<> {
+        backoff(
+            self.health_check_initial_backoff_ms,
+            self.health_check_max_backoff_secs,
+        )
+    }
+
+    /// Probes the app until it is up or `start_timeout` passes. Callers
+    /// holding the app's lock keep it all along; take a [`App::health_probe`]
+    /// to wait without it.
+    pub async fn wait_for_running(&self) -> Result<(), pingora::time::Elapsed> {
+        self.health_probe().wait_for_running().await
     }
 
     #[instrument(skip(self), fields(timeout = ?self.start_timeout))]
@@ -548,8 +1895,68 @@ impl App {
         result
     }
 
-    async fn wait_for_healthy(app: &Arc<RwLock<App>>) -> pingora::Result<()> {
-        if app.read().await.wait_for_running().await.is_err() {
+    /// Runs `address_command` until it prints a usable address or
+    /// `start_timeout` passes, since the port may only be published once
+    /// the start command has gone some way. The command runs without the
+    /// app's lock, and each run is killed after [`ADDRESS_COMMAND_TIMEOUT`].
+    async fn discover_address(app: &Arc<RwLock<App>>) -> Option<UpstreamAddress> {
+        let guard = app.read().await;
+        let command = guard.address_command.as_ref()?.detached();
+        let cwd = guard.cwd.clone();
+        let timeout = guard.start_timeout.unsigned_abs();
+        let strategy = guard.retry_strategy();
+        drop(guard);
+
+        let discover =
+            tokio_retry::Retry::spawn(strategy, async || -> Result<UpstreamAddress, ()> {
+                let output =
+                    pingora::time::timeout(ADDRESS_COMMAND_TIMEOUT, command.output(cwd.as_ref()));
+                match output.await {
+                    Ok(Ok(output)) => {
+                        UpstreamAddress::from_command_output(&output).ok_or_else(|| {
+                            debug!(%output, "address command printed no address");
+                        })
+                    }
+                    Ok(Err(e)) => {
+                        debug!("address command failed: {e}");
+                        Err(())
+                    }
+                    Err(_) => {
+                        debug!("address command timed out");
+                        Err(())
+                    }
+                }
+            });
+
+        match pingora::time::timeout(timeout, discover).await {
+            Ok(Ok(address)) => Some(address),
+            _ => {
+                warn!("address command found no address, keeping the current one");
+                None
+            }
+        }
+    }
+
+    /// Waits for the in-flight start to pass its health check. Every waiter
+    /// shares the probe loop of the start's [`App::watch_startup`] task.
+    pub async fn wait_for_healthy(app: &Arc<RwLock<App>>) -> pingora::Result<()> {
+        let startup = app.read().await.startup.clone();
+        // A failed start says nothing about a process that is still coming
+        // up, so probe again rather than failing straight away.
+        let startup = startup.filter(|startup| *startup.borrow() != Some(false));
+        let healthy = match startup {
+            Some(mut startup) => startup
+                .wait_for(Option::is_some)
+                .await
+                .is_ok_and(|healthy| *healthy == Some(true)),
+            // Nothing in flight to wait on; probe directly.
+            None => {
+                let probe = app.read().await.health_probe();
+                probe.wait_for_running().await.is_ok()
+            }
+        };
+
+        if !healthy {
             error!("failed to start app within timeout");
             return Err(pingora::Error::explain(
                 pingora::ErrorType::ConnectError,
@@ -565,52 +1972,67 @@ impl App {
         host: &Host,
         app: &Arc<RwLock<App>>,
         collector: impl Collector,
+        reason: WakeReason,
     ) -> pingora::Result<()> {
-        let mut guard = app.write().await;
+        let mut guard = metrics::write_app(app).await;
+
+        if !guard.managed {
+            drop(guard);
+            return Self::check_unmanaged(host, app, collector)
+                .await
+                .map_err(app_down);
+        }
 
         // Fast path: if child process is already running, skip health check
         if guard.command.is_child_running() {
-            if !guard.cold_start_page || guard.confirmed_healthy {
+            if guard.confirmed_healthy {
                 debug!("child process already running, skipping health check");
                 return Ok(());
             }
-            // cold_start_page app started by loading page flow, not yet confirmed healthy
+            // Started without waiting (cold start page, non-navigation
+            // policy, also_warm), not yet confirmed healthy
+            drop(guard);
+            return Self::wait_for_healthy(app).await;
+        }
+
+        if guard.is_start_queued() {
             drop(guard);
             return Self::wait_for_healthy(app).await;
         }
 
+        if let Some(mut retired) = guard.replacing.clone().filter(|_| guard.is_replacing()) {
+            drop(guard);
+            info!("waiting for the app this one replaced to stop");
+            let _ = retired.wait_for(|retired| *retired).await;
+            return Box::pin(Self::start_app(host, app, collector, reason)).await;
+        }
+
         // Slow path: no running child, do health check to confirm app state
-        let needs_start = !guard.is_running().await;
+        let needs_start = !guard.is_running_cached().await;
 
         if needs_start {
-            let address = guard.address;
+            if !guard.claim_start(host, &collector).await {
+                info!("another penny node is starting the app, waiting for it");
+                drop(guard);
+                return Self::wait_for_healthy(app).await;
+            }
+            let address = guard.address.to_string();
             let run_id = collector.app_started(host).await.map_err(|e| {
                 pingora::Error::explain(
                     pingora::ErrorType::ConnectError,
                     format!("failed to record app start: {e}"),
                 )
             })?;
+            record_wake(&collector, &run_id, reason).await;
 
             info!(%address, "app not running, starting it");
-            let cwd = guard.cwd.clone();
-            guard.command.start(
-                cwd.as_ref(),
-                Some(RunOptions {
-                    run_id,
-                    collector: collector.clone(),
-                }),
-            );
+            let opts = RunOptions::new(run_id, collector.clone(), &guard);
+            Self::launch(host, app, &mut guard, opts, collector).await;
 
             drop(guard);
-            if let Err(e) = Self::wait_for_healthy(app).await {
-                if let Err(e) = collector.app_start_failed(host).await {
-                    error!("failed to record app start failure: {e}");
-                }
-                return Err(e);
-            }
+            Self::wait_for_healthy(app).await?;
         } else {
-            let address = guard.address;
-            debug!(%address, "app already running");
+            debug!(address = %guard.address, "app already running");
         }
 
         Ok(())
@@ -621,8 +2043,17 @@ impl App {
         host: &Host,
         app: &Arc<RwLock<App>>,
         collector: impl Collector,
+        reason: WakeReason,
     ) -> pingora::Result<bool> {
-        let mut guard = app.write().await;
+        let mut guard = metrics::write_app(app).await;
+
+        if !guard.managed {
+            drop(guard);
+            Self::check_unmanaged(host, app, collector)
+                .await
+                .map_err(app_down)?;
+            return Ok(true);
+        }
 
         // Fast path: child running and confirmed healthy
         if guard.command.is_child_running() && guard.confirmed_healthy {
@@ -636,13 +2067,28 @@ impl App {
             return Ok(false);
         }
 
+        if guard.is_start_queued() {
+            debug!("start queued for a free start slot");
+            return Ok(false);
+        }
+
+        if guard.is_replacing() {
+            debug!("waiting for the app this one replaced to stop");
+            return Ok(false);
+        }
+
         // No child running, check if externally managed process is healthy
-        if guard.is_running().await {
+        if guard.is_running_cached().await {
             debug!("externally managed process is healthy");
             guard.confirmed_healthy = true;
             return Ok(true);
         }
 
+        if !guard.claim_start(host, &collector).await {
+            debug!("another penny node is starting the app");
+            return Ok(false);
+        }
+
         // Need to start the app
         let run_id = collector.app_started(host).await.map_err(|e| {
             pingora::Error::explain(
@@ -650,358 +2096,2526 @@ impl App {
                 format!("failed to record app start: {e}"),
             )
         })?;
-        let address = guard.address;
+        record_wake(&collector, &run_id, reason).await;
+        info!(address = %guard.address, "app not running, starting it (non-blocking)");
+        let opts = RunOptions::new(run_id, collector.clone(), &guard);
+        Self::launch(host, app, &mut guard, opts, collector).await;
 
-        info!(%address, "app not running, starting it (non-blocking)");
-        let cwd = guard.cwd.clone();
-        guard.command.start(
-            cwd.as_ref(),
-            Some(RunOptions {
-                run_id,
-                collector: collector.clone(),
-            }),
-        );
+        Ok(false)
+    }
 
-        drop(guard);
+    /// Health-checks an app penny does not manage. It coming up or going
+    /// down is recorded as the start or end of a run, so its uptime shows
+    /// like that of other apps. Returns why the check failed.
+    pub async fn check_unmanaged(
+        host: &Host,
+        app: &Arc<RwLock<App>>,
+        collector: impl Collector,
+    ) -> Result<(), String> {
+        let mut guard = app.write().await;
+        let ttl = Duration::from_millis(guard.health_check_cache_ms);
+        if guard
+            .last_healthy_at
+            .is_some_and(|checked| checked.elapsed() < ttl)
+        {
+            return Ok(());
+        }
 
-        // Spawn background task to wait for health and set confirmed_healthy
-        let app = app.clone();
-        let host = host.clone();
-        tokio::spawn(async move {
-            if app.read().await.wait_for_running().await.is_ok() {
-                app.write().await.confirmed_healthy = true;
-                info!(host = %host, "app confirmed healthy in background");
+        let result = guard.probe().await;
+        let up = result.is_ok();
+        guard.last_healthy_at = up.then(std::time::Instant::now);
+        if up != guard.confirmed_healthy {
+            guard.confirmed_healthy = up;
+            drop(guard);
+            let recorded = if up {
+                info!(host = %host, "unmanaged app is up");
+                collector.app_started(host).await.map(|_| ())
             } else {
-                error!(host = %host, "app failed to start in background");
-                if let Err(e) = collector.app_start_failed(&host).await {
-                    error!(host = %host, "failed to record app start failure: {e}");
-                }
+                warn!(host = %host, error = ?result.as_ref().err(), "unmanaged app is down");
+                collector.app_stopped(host).await
+            };
+            if let Err(e) = recorded {
+                error!(host = %host, "failed to record unmanaged app state: {e}");
             }
-        });
+        }
+        result
+    }
 
-        Ok(false)
+    /// Whether the app this one replaced in a reload is still stopping.
+    fn is_replacing(&self) -> bool {
+        self.replacing
+            .as_ref()
+            .is_some_and(|retired| !*retired.borrow() && retired.has_changed().is_ok())
     }
 
-    #[instrument(skip(app))]
-    pub async fn schedule_kill(host: &Host, app: &Arc<RwLock<App>>, collector: impl Collector) {
-        let mut app_guard = app.write().await;
-
-        if let Some(prev) = app_guard.kill_task.take() {
-            debug!("cancelling previous kill task");
-            drop(prev);
-        }
-
-        app_guard.request_tracker.record_request();
-        let wait_period = app_guard.effective_wait_period();
-        let (short_rate, long_rate) = app_guard.request_tracker.request_rates();
-        let total_reqs = app_guard.request_tracker.total_recent_requests();
-        info!(
-            ?wait_period,
-            short_rate = format!("{short_rate:.2}"),
-            long_rate = format!("{long_rate:.2}"),
-            total_reqs,
-            adaptive = app_guard.adaptive_wait,
-            "scheduling app shutdown"
-        );
+    /// Whether a start is waiting for a free slot or a rebuild, so no
+    /// process is running yet but one is on its way.
+    fn is_start_queued(&mut self) -> bool {
+        !self.command.is_child_running()
+            && self
+                .startup
+                .as_ref()
+                .is_some_and(|startup| startup.borrow().is_none())
+    }
 
-        let (cancel_tx, cancel_rx) = tokio::sync::oneshot::channel::<()>();
+    /// Whether this node may start the app. Outside a cluster it always
+    /// may; in one, unless another node claimed the start and is still up.
+    async fn claim_start(&self, host: &Host, collector: &impl Collector) -> bool {
+        let Some(node) = &self.cluster_node else {
+            return true;
+        };
+        match collector.claim_start(node, host, CLAIM_LEASE).await {
+            Ok(claimed) => claimed,
+            Err(e) => {
+                warn!(error = %e, "failed to claim the app's start, starting it anyway");
+                true
+            }
+        }
+    }
 
-        let handle = {
-            let app = app.clone();
-            let host = host.clone();
-            tokio::spawn(async move {
-                let wait_period = app.read().await.effective_wait_period();
-
-                // CANCELLABLE: sleep races against cancellation
-                tokio::select! {
-                    _ = pingora::time::sleep(wait_period) => {}
-                    _ = cancel_rx => {
-                        debug!("kill task cancelled during sleep");
+    /// Spawns the app's process and watches its startup. With
+    /// `max_concurrent_starts` and no free slot, the spawn is queued in the
+    /// watching task instead.
+    async fn launch<C: Collector>(
+        host: &Host,
+        app: &Arc<RwLock<App>>,
+        guard: &mut App,
+        opts: RunOptions<C>,
+        collector: C,
+    ) {
+        guard.start_output.clear();
+        let launch = match guard
+            .start_slots
+            .clone()
+            .map(|slots| slots.try_acquire_owned())
+        {
+            Some(Err(tokio::sync::TryAcquireError::NoPermits)) => {
+                let slots = guard.start_slots.clone().expect("checked above");
+                info!("too many apps starting, queueing start");
+                Launch::Queued(slots, opts)
+            }
+            permit => {
+                let cwd = guard.cwd.clone();
+                guard.current_run = Some(opts.run_id.clone());
+                guard.command.start(cwd.as_ref(), Some(opts)).await;
+                Launch::Started(permit.and_then(Result::ok))
+            }
+        };
+        guard.begin_progress();
+        guard.startup = Some(Self::watch_startup(host, app, collector, launch));
+    }
+
+    /// Spawns the one task that waits for a just-started app to pass its
+    /// health check, sets `confirmed_healthy`, and records a failed start.
+    /// Requests wait on the returned receiver, kept in `startup`, rather
+    /// than each polling the app. A start slot is held until then.
+    fn watch_startup<C: Collector>(
+        host: &Host,
+        app: &Arc<RwLock<App>>,
+        collector: C,
+        launch: Launch<C>,
+    ) -> tokio::sync::watch::Receiver<Option<bool>> {
+        let (tx, rx) = tokio::sync::watch::channel(None);
+        let app = app.clone();
+        let host = host.clone();
+        tokio::spawn(async move {
+            let _permit = match launch {
+                Launch::Started(permit) => permit,
+                Launch::Queued(slots, opts) => {
+                    let permit = slots.acquire_owned().await.ok();
+                    info!(host = %host, "start slot free, starting app");
+                    Self::spawn_process(&app, opts).await;
+                    permit
+                }
+                Launch::Redeploy(rebuild) => {
+                    let Some(permit) = Self::rebuild(&host, &app, &collector, rebuild).await else {
+                        tx.send_replace(Some(false));
                         return;
-                    }
+                    };
+                    permit
                 }
+            };
+            let started = std::time::Instant::now();
 
-                // CRITICAL SECTION: runs to completion, never aborted
-                info!("wait period elapsed, stopping app");
+            let discovered = Self::discover_address(&app).await;
+            if let Some(address) = discovered {
+                info!(host = %host, %address, "discovered app address");
+                app.write().await.address = address;
+            }
 
+            let probe = app.read().await.health_probe();
+            let healthy = probe.wait_for_running().await.is_ok();
+            if healthy {
                 let mut guard = app.write().await;
-                let cwd = guard.cwd.clone();
-                guard.command.stop(cwd.as_ref()).await;
-                guard.confirmed_healthy = false;
+                guard.confirmed_healthy = true;
+                guard.last_cold_start_ms = Some(started.elapsed().as_millis() as u64);
                 drop(guard);
-                if let Err(e) = collector.app_stopped(&host).await {
-                    error!("failed to record app stop: {e}");
+                info!(host = %host, "app confirmed healthy");
+            } else {
+                error!(host = %host, "app failed to start");
+                if let Err(e) = collector.app_start_failed(&host).await {
+                    error!(host = %host, "failed to record app start failure: {e}");
                 }
+            }
+            tx.send_replace(Some(healthy));
+        });
+        rx
+    }
 
-                if app.read().await.wait_for_stopped().await.is_err() {
-                    error!("failed to stop app within timeout");
-                    if let Err(e) = collector.app_stop_failed(&host).await {
-                        error!("failed to record app stop failure: {e}");
-                    }
-                }
-            })
+    /// Spawns the app's process for a start that had to wait.
+    async fn spawn_process<C: Collector>(app: &Arc<RwLock<App>>, opts: RunOptions<C>) {
+        let mut guard = app.write().await;
+        let cwd = guard.cwd.clone();
+        guard.current_run = Some(opts.run_id.clone());
+        guard.command.start(cwd.as_ref(), Some(opts)).await;
+    }
+
+    /// Runs a redeploy's `redeploy_command` without holding the app's lock,
+    /// then spawns the app once a start slot is free, and only then tells
+    /// the redeploy it is done. Returns `None` if the command failed, after
+    /// recording the failed start.
+    async fn rebuild<C: Collector>(
+        host: &Host,
+        app: &Arc<RwLock<App>>,
+        collector: &C,
+        rebuild: Rebuild<C>,
+    ) -> Option<Option<tokio::sync::OwnedSemaphorePermit>> {
+        let Rebuild {
+            command,
+            timeout,
+            opts,
+            done,
+        } = rebuild;
+        let cwd = app.read().await.cwd.clone();
+
+        info!(host = %host, "running redeploy command");
+        let failure = match command
+            .run_to_completion(cwd.as_ref(), &opts, timeout)
+            .await
+        {
+            Ok(status) if status.success() => None,
+            Ok(status) => Some(format!("redeploy command exited with {status}")),
+            Err(e) => Some(format!("failed to run redeploy command: {e}")),
         };
+        if let Some(failure) = failure {
+            error!(host = %host, "{failure}");
+            opts.append_stderr(failure.clone()).await;
+            if let Err(e) = collector.app_start_failed(host).await {
+                error!(host = %host, "failed to record app start failure: {e}");
+            }
+            if let Err(e) = collector.app_stopped(host).await {
+                error!(host = %host, "failed to record app stop: {e}");
+            }
+            let _ = done.send(Err(failure));
+            return None;
+        }
 
-        app_guard.kill_task = Some(KillTask {
-            cancel: cancel_tx,
-            _handle: handle,
-        });
+        info!(host = %host, "redeploy command finished, starting app");
+        let slots = app.read().await.start_slots.clone();
+        let permit = match slots {
+            Some(slots) => slots.acquire_owned().await.ok(),
+            None => None,
+        };
+        Self::spawn_process(app, opts).await;
+        let _ = done.send(Ok(()));
+        Some(permit)
     }
-}
 
-fn deserialize_apps<'de, D>(deserializer: D) -> Result<HashMap<String, Arc<RwLock<App>>>, D::Error>
-where
-    D: serde::Deserializer<'de>,
-{
-    let raw = HashMap::<String, App>::deserialize(deserializer)?;
+    /// Stops the app, runs its `redeploy_command`, and starts it again. The
+    /// rebuild's output opens the new run's logs. Returns whether the app is
+    /// ready, like [`App::begin_start_app`]. Requests wait for the rebuild
+    /// as they do for a queued start, without holding the app's lock, and
+    /// it is stopped after `redeploy_timeout`.
+    #[instrument(skip(app))]
+    pub async fn redeploy(
+        host: &Host,
+        app: &Arc<RwLock<App>>,
+        collector: impl Collector,
+    ) -> color_eyre::Result<bool> {
+        let mut guard = app.write().await;
+        if !guard.managed {
+            return Err(color_eyre::eyre::eyre!("'{host}' is not managed by penny"));
+        }
+        if guard.redeploy_command.is_none() {
+            return Err(color_eyre::eyre::eyre!(
+                "no redeploy_command configured for host '{host}'"
+            ));
+        }
+        if guard.is_start_queued() {
+            return Err(color_eyre::eyre::eyre!(
+                "'{host}' is already starting or redeploying"
+            ));
+        }
+        let awake = guard.idle_deadline.cancel() || guard.command.is_child_running();
+        drop(guard);
 
-    Ok(raw
-        .into_iter()
-        .map(|(k, v)| (k, Arc::new(RwLock::new(v))))
-        .collect())
-}
+        if awake {
+            Self::stop_app(host, app, collector.clone()).await;
+        }
 
-/// TLS configuration for automatic certificate provisioning.
-#[derive(Debug, Clone, Deserialize)]
-pub struct TlsConfig {
-    /// Enable automatic TLS certificate provisioning.
-    #[serde(default)]
-    pub enabled: bool,
+        let mut guard = app.write().await;
+        let cwd = guard.cwd.clone();
+        if guard.command.is_child_running() {
+            guard.command.stop(cwd.as_ref()).await;
+            if let Err(e) = collector.app_stopped(host).await {
+                error!("failed to record app stop: {e}");
+            }
+        }
+        guard.confirmed_healthy = false;
+        guard.last_healthy_at = None;
+
+        let run_id = collector.app_started(host).await?;
+        let (done, built) = tokio::sync::oneshot::channel();
+        let rebuild = Rebuild {
+            command: guard
+                .redeploy_command
+                .as_ref()
+                .expect("checked above")
+                .detached(),
+            timeout: guard.redeploy_timeout.unsigned_abs(),
+            opts: RunOptions::new(run_id, collector.clone(), &guard),
+            done,
+        };
+        // Requests from now on wait on the startup, like for a queued start,
+        // instead of waking the old version.
+        guard.start_output.clear();
+        guard.begin_progress();
+        guard.startup = Some(Self::watch_startup(
+            host,
+            app,
+            collector,
+            Launch::Redeploy(rebuild),
+        ));
+        drop(guard);
 
-    /// Contact email for ACME account registration.
-    pub acme_email: String,
+        match built.await {
+            Ok(Ok(())) => Ok(false),
+            Ok(Err(failure)) => Err(color_eyre::eyre::eyre!(failure)),
+            Err(_) => Err(color_eyre::eyre::eyre!(
+                "redeploy of '{host}' was abandoned"
+            )),
+        }
+    }
 
-    /// Use Let's Encrypt staging environment (for testing).
-    #[serde(default)]
-    pub staging: bool,
+    /// Starts a second instance of the app on a free port from
+    /// `rolling_ports`, switches requests over once it passes its health
+    /// check, then stops the old instance. Requests keep going to the old
+    /// instance until then, and the new one's output joins the current run.
+    /// Returns the new address.
+    #[instrument(skip(app))]
+    pub async fn rolling_restart(
+        host: &Host,
+        app: &Arc<RwLock<App>>,
+        collector: impl Collector,
+    ) -> color_eyre::Result<SocketAddr> {
+        use color_eyre::eyre::eyre;
 
-    /// Directory to store certificates.
-    #[serde(default = "default_certs_dir")]
-    pub certs_dir: PathBuf,
+        let mut guard = app.write().await;
+        let Some(ports) = guard.rolling_ports else {
+            return Err(eyre!("no rolling_ports configured for host '{host}'"));
+        };
+        let UpstreamAddress::Fixed(current) = guard.address else {
+            return Err(eyre!("'{host}' does not have a fixed ip:port address"));
+        };
+        if !guard.command.is_child_running() {
+            return Err(eyre!("'{host}' is not running, start it instead"));
+        }
+        let port = ports
+            .free_port(current.ip(), current.port())
+            .ok_or_else(|| eyre!("no free port in rolling_ports {ports} for '{host}'"))?;
+        let next = SocketAddr::new(current.ip(), port);
+        let run_id = match guard.current_run.clone() {
+            Some(run_id) => run_id,
+            None => collector.app_started(host).await?,
+        };
 
-    /// Days before expiry to renew certificates.
-    #[serde(default = "default_renewal_days")]
-    pub renewal_days: u32,
+        info!(%next, "starting new instance");
+        let cwd = guard.cwd.clone();
+        let opts = RunOptions::new(run_id, collector, &guard);
+        let old = guard.command.detach();
+        guard.command.set_port(Some(port));
+        guard.command.start(cwd.as_ref(), Some(opts)).await;
+        let new = guard.command.reattach(old);
+        guard.command.set_port(Some(current.port()));
+        drop(guard);
 
-    /// Hours between certificate renewal checks.
-    #[serde(default = "default_renewal_check_interval_hours")]
-    pub renewal_check_interval_hours: u64,
+        if !Self::wait_for_instance(app, next).await {
+            new.kill().await;
+            return Err(eyre!(
+                "new instance on {next} did not pass its health check, keeping {current}"
+            ));
+        }
 
-    /// Seconds between order status poll attempts.
-    #[serde(default = "default_order_poll_interval_secs")]
-    pub order_poll_interval_secs: u64,
+        let mut guard = app.write().await;
+        // Stopped for being idle while the new instance came up.
+        if !guard.command.is_child_running() {
+            drop(guard);
+            new.kill().await;
+            return Err(eyre!("'{host}' was stopped during the rolling restart"));
+        }
+        let old = guard.command.reattach(new);
+        guard.command.set_port(Some(port));
+        guard.address = UpstreamAddress::Fixed(next);
+        guard.last_healthy_at = None;
+        drop(guard);
 
-    /// Maximum number of order status poll retries.
-    #[serde(default = "default_order_poll_max_retries")]
-    pub order_poll_max_retries: u32,
+        info!(%current, %next, "switched to new instance, stopping the old one");
+        old.kill().await;
+        Ok(next)
+    }
 
-    /// Seconds between certificate readiness poll attempts.
-    #[serde(default = "default_cert_poll_interval_secs")]
-    pub cert_poll_interval_secs: u64,
+    /// Waits up to `start_timeout` for an instance at `address` to pass the
+    /// health check. The lock is only held for each probe, so requests go on
+    /// meanwhile.
+    async fn wait_for_instance(app: &Arc<RwLock<App>>, address: SocketAddr) -> bool {
+        let guard = app.read().await;
+        let timeout = guard.start_timeout.unsigned_abs();
+        let strategy = guard.retry_strategy();
+        drop(guard);
 
-    /// Maximum number of certificate readiness poll retries.
-    #[serde(default = "default_cert_poll_max_retries")]
-    pub cert_poll_max_retries: u32,
-}
+        let probe = tokio_retry::Retry::spawn(strategy, async || {
+            let guard = app.read().await;
+            guard
+                .health_checker
+                .check(address, &guard.health_check)
+                .await
+        });
+        pingora::time::timeout(timeout, probe)
+            .await
+            .is_ok_and(|result| result.is_ok())
+    }
 
-fn default_certs_dir() -> PathBuf {
-    PathBuf::from("./certs")
-}
+    /// Stops the app now and records the stop.
+    pub async fn stop_app(host: &Host, app: &Arc<RwLock<App>>, collector: impl Collector) {
+        let guard = app.write().await;
+        Self::stop_locked(host, app, guard, collector).await;
+    }
 
-fn default_renewal_days() -> u32 {
-    30
-}
+    /// Stops the app with its write lock already held, so nothing the caller
+    /// checked under it can change before the stop.
+    pub async fn stop_locked(
+        host: &Host,
+        app: &Arc<RwLock<App>>,
+        mut guard: RwLockWriteGuard<'_, App>,
+        collector: impl Collector,
+    ) {
+        if !guard.managed {
+            debug!("app is not managed by penny, leaving it running");
+            return;
+        }
+        let cwd = guard.cwd.clone();
+        guard.command.stop(cwd.as_ref()).await;
+        guard.confirmed_healthy = false;
+        guard.last_healthy_at = None;
+        let node = guard.cluster_node.clone();
+        drop(guard);
+        if let Err(e) = collector.app_stopped(host).await {
+            error!("failed to record app stop: {e}");
+        }
+        if let Some(node) = node
+            && let Err(e) = collector.release_start(&node, host).await
+        {
+            warn!(error = %e, "failed to release the app's cluster claim");
+        }
 
-fn default_renewal_check_interval_hours() -> u64 {
-    12
+        if app.read().await.wait_for_stopped().await.is_err() {
+            error!("failed to stop app within timeout");
+            if let Err(e) = collector.app_stop_failed(host).await {
+                error!("failed to record app stop failure: {e}");
+            }
+        }
+    }
 }
 
-fn default_order_poll_interval_secs() -> u64 {
-    2
+/// Records what woke the app; failing to is not worth failing the start.
+async fn record_wake(collector: &impl Collector, run_id: &RunId, reason: WakeReason) {
+    if let Err(e) = collector.app_woken(run_id, reason).await {
+        warn!(error = %e, "failed to record wake reason");
+    }
 }
 
-fn default_order_poll_max_retries() -> u32 {
-    20
+fn app_down(reason: String) -> Box<pingora::Error> {
+    pingora::Error::explain(
+        pingora::ErrorType::ConnectError,
+        format!("app is down: {reason}"),
+    )
+}
+
+fn deserialize_apps<'de, D>(deserializer: D) -> Result<HashMap<String, Arc<RwLock<App>>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error;
+
+    let tables = HashMap::<String, toml::Table>::deserialize(deserializer)?;
+    let mut resolved = HashMap::with_capacity(tables.len());
+    for name in tables.keys() {
+        resolve_template(name, &tables, &mut resolved, &mut Vec::new())
+            .map_err(D::Error::custom)?;
+    }
+
+    let mut apps = HashMap::with_capacity(resolved.len());
+    for (name, table) in resolved {
+        let host = normalize_hostname(&name)
+            .ok_or_else(|| D::Error::custom(format!("invalid hostname '{name}'")))?;
+        let v = build_app(&host, table).map_err(D::Error::custom)?;
+        if apps
+            .insert(host.clone(), Arc::new(RwLock::new(v)))
+            .is_some()
+        {
+            return Err(D::Error::custom(format!(
+                "hostname '{name}' is configured more than once as '{host}'"
+            )));
+        }
+    }
+    Ok(apps)
+}
+
+/// Most stdout lines a cold start page shows.
+const MAX_COLD_START_OUTPUT_LINES: usize = 50;
+
+/// Deserializes and checks one app's settings, and sets up its commands.
+pub fn build_app(host: &str, table: toml::Table) -> Result<App, String> {
+    let mut v = App::deserialize(toml::Value::Table(table))
+        .map_err(|e| format!("{} for '{host}'", e.message()))?;
+    if v.managed && v.command.command_lines().0.is_empty() {
+        return Err(format!(
+            "missing command for '{host}' (set `managed = false` for apps penny does not run)"
+        ));
+    }
+    v.also_warm = v
+        .also_warm
+        .into_iter()
+        .map(|other| normalize_hostname(&other).unwrap_or(other))
+        .collect();
+    for step in &mut v.stop_after {
+        if let Some(other) = normalize_hostname(&step.host) {
+            step.host = other;
+        }
+    }
+    v.redactor = Redactor::new(&v.redact_patterns, v.redact_defaults)
+        .map_err(|e| format!("invalid redact_patterns for '{host}': {e}"))?;
+    if v.watts
+        .is_some_and(|watts| !watts.is_finite() || watts < 0.0)
+    {
+        return Err(format!("watts for '{host}' must be a positive number"));
+    }
+    if v.ignore_user_agents.iter().any(|agent| agent.is_empty()) {
+        return Err(format!(
+            "ignore_user_agents for '{host}' must not contain an empty string"
+        ));
+    }
+    for (key, value) in &v.env {
+        if key.is_empty() || key.contains(['=', '\0']) || value.contains('\0') {
+            return Err(format!("invalid env variable '{key}' for '{host}'"));
+        }
+        v.command.set_env(key, value);
+    }
+    if v.fallback_after.is_negative() {
+        return Err(format!("fallback_after for '{host}' must not be negative"));
+    }
+    if !v.redeploy_timeout.is_positive() {
+        return Err(format!("redeploy_timeout for '{host}' must be positive"));
+    }
+    if v.cold_start_output_lines > MAX_COLD_START_OUTPUT_LINES {
+        return Err(format!(
+            "cold_start_output_lines for '{host}' must be at most {MAX_COLD_START_OUTPUT_LINES}"
+        ));
+    }
+    v.start_output = OutputTail::new(v.cold_start_output_lines);
+    if let Some(token) = &v.sleep_token {
+        if token.is_empty() {
+            return Err(format!("sleep_token for '{host}' must not be empty"));
+        }
+        v.command.set_env(SLEEP_TOKEN_ENV, token);
+    }
+    if v.capture_rate_limit == Some(0) {
+        return Err(format!(
+            "capture_rate_limit for '{host}' must be at least 1 (use capture = \"none\" to store nothing)"
+        ));
+    }
+    v.health_checker = Arc::new(
+        HttpHealthChecker::new(
+            &v.health_check_method,
+            &v.health_check_headers,
+            v.health_check_host.as_deref(),
+        )
+        .map_err(|e| format!("{e} for '{host}'"))?,
+    );
+    if v.shell {
+        v.command.use_shell();
+        for command in [&mut v.redeploy_command, &mut v.address_command]
+            .into_iter()
+            .flatten()
+        {
+            command.use_shell();
+        }
+    }
+    if let Some(sandbox) = &v.sandbox {
+        let cwd = v.cwd.clone().or_else(|| std::env::current_dir().ok());
+        v.command.use_wrapper(sandbox.wrapper(cwd.as_ref()));
+    }
+    if let Some(unit) = &v.systemd_unit {
+        v.command.use_systemd_unit(unit.clone());
+    }
+    if let Some(ports) = v.rolling_ports {
+        let UpstreamAddress::Fixed(address) = v.address else {
+            return Err(format!(
+                "rolling_ports for '{host}' needs an ip:port address"
+            ));
+        };
+        if v.systemd_unit.is_some()
+            || v.address_command.is_some()
+            || v.command.command_lines().1.is_some()
+            || !v.managed
+        {
+            return Err(format!(
+                "rolling_ports for '{host}' needs a single start command penny runs itself, without systemd_unit, address_command or an end command"
+            ));
+        }
+        if !ports.has_other_than(address.port()) {
+            return Err(format!(
+                "rolling_ports for '{host}' must hold a port other than {}",
+                address.port()
+            ));
+        }
+        v.command.set_port(Some(address.port()));
+    }
+    Ok(v)
+}
+
+/// An app's settings with those of its `template` filled in, following
+/// templates of templates. Settings on the app replace the template's.
+/// `chain` holds the apps whose templates are being resolved, to catch
+/// loops.
+fn resolve_template(
+    name: &str,
+    tables: &HashMap<String, toml::Table>,
+    resolved: &mut HashMap<String, toml::Table>,
+    chain: &mut Vec<String>,
+) -> Result<toml::Table, String> {
+    if let Some(table) = resolved.get(name) {
+        return Ok(table.clone());
+    }
+
+    let mut table = tables[name].clone();
+    if let Some(template) = table.remove("template") {
+        let toml::Value::String(template) = template else {
+            return Err(format!("template for '{name}' must be a hostname"));
+        };
+        let wanted = normalize_hostname(&template);
+        let base = tables
+            .keys()
+            .find(|other| {
+                **other == template || (wanted.is_some() && normalize_hostname(other) == wanted)
+            })
+            .ok_or_else(|| format!("template '{template}' for '{name}' is not a configured app"))?;
+        if base == name || chain.iter().any(|other| other == base) {
+            return Err(format!(
+                "template '{template}' for '{name}' leads back to '{name}'"
+            ));
+        }
+
+        chain.push(name.to_owned());
+        let mut merged = resolve_template(base, tables, resolved, chain)?;
+        chain.pop();
+        for (key, value) in table {
+            merged.insert(key, value);
+        }
+        table = merged;
+    }
+
+    resolved.insert(name.to_owned(), table.clone());
+    Ok(table)
+}
+
+/// Canonical form of a hostname for routing: lowercase, without a trailing
+/// dot, and with internationalized labels in punycode. `None` if it is not
+/// a valid hostname.
+pub fn normalize_hostname(name: &str) -> Option<String> {
+    let name = name.strip_suffix('.').unwrap_or(name);
+    idna::domain_to_ascii(name)
+        .ok()
+        .filter(|name| !name.is_empty())
+}
+
+/// A file penny answers with itself while an app sleeps, so fetching it does
+/// not wake the app.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsleepFile {
+    pub body: bytes::Bytes,
+    pub content_type: String,
+}
+
+impl AsleepFile {
+    fn read(path: &std::path::Path, used_for: &str) -> color_eyre::Result<Self> {
+        let body = std::fs::read(path).map_err(|e| {
+            color_eyre::eyre::eyre!(
+                "failed to read file served while asleep for {used_for} at {}: {e}",
+                path.display()
+            )
+        })?;
+        let content_type = mime_guess::from_path(path)
+            .first_or_octet_stream()
+            .to_string();
+        Ok(Self {
+            body: body.into(),
+            content_type,
+        })
+    }
+}
+
+/// Reads a cold start page template and checks that it parses.
+fn read_cold_start_page(path: &std::path::Path, used_for: &str) -> color_eyre::Result<String> {
+    let html = std::fs::read_to_string(path).map_err(|e| {
+        color_eyre::eyre::eyre!(
+            "failed to read cold start page for {used_for} at {}: {e}",
+            path.display()
+        )
+    })?;
+    crate::loading_page::validate(&html).map_err(|e| {
+        color_eyre::eyre::eyre!(
+            "invalid cold start page template at {}: {e}",
+            path.display()
+        )
+    })?;
+    Ok(html)
+}
+
+fn deserialize_hostname<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error;
+
+    Option::<String>::deserialize(deserializer)?
+        .map(|name| {
+            normalize_hostname(&name)
+                .ok_or_else(|| D::Error::custom(format!("invalid hostname '{name}'")))
+        })
+        .transpose()
+}
+
+/// TLS configuration for automatic certificate provisioning.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TlsConfig {
+    /// Enable automatic TLS certificate provisioning.
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Contact email for ACME account registration.
+    pub acme_email: String,
+
+    /// Use Let's Encrypt staging environment (for testing).
+    #[serde(default)]
+    pub staging: bool,
+
+    /// ACME directory URL of a non-Let's Encrypt CA (e.g. ZeroSSL).
+    /// Overrides `staging` when set.
+    #[serde(default)]
+    pub acme_directory_url: Option<String>,
+
+    /// Key identifier for External Account Binding, issued by the CA.
+    #[serde(default)]
+    pub eab_kid: Option<String>,
+
+    /// Base64url-encoded HMAC key for External Account Binding, issued by the CA.
+    #[serde(default)]
+    pub eab_hmac_key: Option<String>,
+
+    /// Directory to store certificates.
+    #[serde(default = "default_certs_dir")]
+    pub certs_dir: PathBuf,
+
+    /// Extra listener that serves only HTTP-01 challenges, for setups where
+    /// an edge proxy or port-forward sends challenge traffic to penny.
+    #[serde(default)]
+    pub challenge_address: Option<SocketAddr>,
+
+    /// Path prefix the challenge listener answers on.
+    #[serde(default = "default_challenge_path")]
+    pub challenge_path: String,
+
+    /// Fetch each HTTP-01 challenge over the public address before asking
+    /// the CA to validate it.
+    #[serde(default = "default_verify_challenges")]
+    pub verify_challenges: bool,
+
+    /// Keep active HTTP-01 challenges in the database, so they are still
+    /// answered after a restart mid-order or by another penny sharing it.
+    #[serde(default)]
+    pub persist_challenges: bool,
+
+    /// Whether each domain gets its own certificate or all domains share
+    /// one SAN certificate.
+    #[serde(default)]
+    pub cert_strategy: CertStrategy,
+
+    /// Private key algorithm for new certificates.
+    #[serde(default)]
+    pub key_type: KeyType,
+
+    /// Keep the existing private key when renewing a certificate.
+    #[serde(default)]
+    pub reuse_private_key: bool,
+
+    /// Where certificates and private keys are stored.
+    #[serde(default)]
+    pub storage: CertStorage,
+
+    /// Base64-encoded 32-byte key that encrypts private keys when
+    /// `storage = "database"`. Falls back to the `PENNY_CERT_KEY` env var.
+    #[serde(default)]
+    pub storage_key: Option<String>,
+
+    /// Several penny instances share the database: only one of them issues
+    /// each certificate, and all answer challenges from the database.
+    /// Requires `storage = "database"`.
+    #[serde(default)]
+    pub shared_storage: bool,
+
+    /// Days before expiry to renew certificates.
+    #[serde(default = "default_renewal_days")]
+    pub renewal_days: u32,
+
+    /// Hours between certificate renewal checks.
+    #[serde(default = "default_renewal_check_interval_hours")]
+    pub renewal_check_interval_hours: u64,
+
+    /// Days before expiry at which an `expiring` event is emitted.
+    #[serde(default = "default_expiry_warning_days")]
+    pub expiry_warning_days: u32,
+
+    /// URL that receives a JSON POST for every certificate event.
+    #[serde(default)]
+    pub notify_webhook: Option<String>,
+
+    /// Seconds between order status poll attempts.
+    #[serde(default = "default_order_poll_interval_secs")]
+    pub order_poll_interval_secs: u64,
+
+    /// Maximum number of order status poll retries.
+    #[serde(default = "default_order_poll_max_retries")]
+    pub order_poll_max_retries: u32,
+
+    /// Seconds between certificate readiness poll attempts.
+    #[serde(default = "default_cert_poll_interval_secs")]
+    pub cert_poll_interval_secs: u64,
+
+    /// Maximum number of certificate readiness poll retries.
+    #[serde(default = "default_cert_poll_max_retries")]
+    pub cert_poll_max_retries: u32,
+
+    /// `Strict-Transport-Security` policy added to HTTPS responses.
+    #[serde(default)]
+    pub hsts: Option<HstsConfig>,
+
+    /// Minimum TLS protocol version accepted on the HTTPS listener.
+    #[serde(default)]
+    pub min_tls_version: Option<TlsVersion>,
+
+    /// OpenSSL cipher list for TLS 1.2 and below.
+    #[serde(default)]
+    pub ciphers: Option<String>,
+
+    /// OpenSSL ciphersuites for TLS 1.3.
+    #[serde(default)]
+    pub ciphersuites: Option<String>,
+
+    /// Issue certificates on the first TLS handshake instead of at startup.
+    #[serde(default)]
+    pub on_demand: bool,
+
+    /// Seconds to wait before retrying a failed on-demand issuance.
+    #[serde(default = "default_on_demand_retry_secs")]
+    pub on_demand_retry_secs: u64,
+}
+
+/// HTTP Strict Transport Security policy.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HstsConfig {
+    /// How long browsers should only use HTTPS, in seconds.
+    #[serde(default = "default_hsts_max_age_secs")]
+    pub max_age_secs: u64,
+
+    /// Apply the policy to all subdomains.
+    #[serde(default)]
+    pub include_subdomains: bool,
+
+    /// Request inclusion in browser preload lists.
+    #[serde(default)]
+    pub preload: bool,
+}
+
+impl HstsConfig {
+    pub fn header_value(&self) -> String {
+        let mut value = format!("max-age={}", self.max_age_secs);
+        if self.include_subdomains {
+            value.push_str("; includeSubDomains");
+        }
+        if self.preload {
+            value.push_str("; preload");
+        }
+        value
+    }
+}
+
+fn default_hsts_max_age_secs() -> u64 {
+    31_536_000
+}
+
+/// Directories every sandbox can read, so programs and their libraries
+/// load. Missing ones are skipped.
+const SANDBOX_SYSTEM_PATHS: &[&str] = &["/usr", "/bin", "/sbin", "/lib", "/lib64", "/etc"];
+
+/// Runs an app's start command under bubblewrap (`bwrap`), which sees only
+/// the system directories, its `cwd` and the paths listed here.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SandboxConfig {
+    /// Extra paths the app can read.
+    #[serde(default)]
+    pub ro_binds: Vec<PathBuf>,
+
+    /// Paths the app can write to besides its `cwd`.
+    #[serde(default)]
+    pub rw_binds: Vec<PathBuf>,
+
+    /// Share the host network. Penny proxies to the app over it, so only
+    /// turn this off for apps that accept no connections.
+    #[serde(default = "default_sandbox_net")]
+    pub net: bool,
+}
+
+fn default_sandbox_net() -> bool {
+    true
+}
+
+impl SandboxConfig {
+    /// The `bwrap` invocation a command is appended to.
+    fn wrapper(&self, cwd: Option<&PathBuf>) -> Vec<String> {
+        let mut args: Vec<String> = [
+            "bwrap",
+            "--unshare-all",
+            "--die-with-parent",
+            "--new-session",
+        ]
+        .map(String::from)
+        .into();
+        if self.net {
+            args.push("--share-net".to_owned());
+        }
+        args.extend(["--proc", "/proc", "--dev", "/dev", "--tmpfs", "/tmp"].map(String::from));
+
+        let mut bind = |flag: &str, path: &std::path::Path| {
+            let path = path.to_string_lossy().into_owned();
+            args.extend([flag.to_owned(), path.clone(), path]);
+        };
+        for path in SANDBOX_SYSTEM_PATHS {
+            bind("--ro-bind-try", std::path::Path::new(path));
+        }
+        for path in &self.ro_binds {
+            bind("--ro-bind", path);
+        }
+        for path in cwd.into_iter().chain(&self.rw_binds) {
+            bind("--bind", path);
+        }
+
+        if let Some(cwd) = cwd {
+            args.extend(["--chdir".to_owned(), cwd.to_string_lossy().into_owned()]);
+        }
+        args.push("--".to_owned());
+        args
+    }
+}
+
+/// Minimum TLS protocol version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum TlsVersion {
+    #[serde(rename = "1.2")]
+    Tls12,
+    #[serde(rename = "1.3")]
+    Tls13,
+}
+
+/// How domains are grouped into certificates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CertStrategy {
+    /// One certificate per domain.
+    #[default]
+    PerDomain,
+    /// A single certificate listing every domain as a SAN.
+    Combined,
+}
+
+/// Private key algorithm used in certificate signing requests.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum KeyType {
+    #[default]
+    #[serde(rename = "ecdsa-p256")]
+    EcdsaP256,
+    #[serde(rename = "rsa-2048")]
+    Rsa2048,
+}
+
+/// Backend used to persist certificates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CertStorage {
+    /// PEM files in `certs_dir`.
+    #[default]
+    Files,
+    /// Rows in the penny database, with private keys encrypted.
+    Database,
+}
+
+impl TlsConfig {
+    /// Whether active challenges are kept in the database.
+    pub fn persists_challenges(&self) -> bool {
+        self.persist_challenges || self.shared_storage
+    }
+}
+
+fn default_challenge_path() -> String {
+    crate::challenge::ACME_CHALLENGE_PATH.to_owned()
+}
+
+fn default_verify_challenges() -> bool {
+    true
+}
+
+fn default_certs_dir() -> PathBuf {
+    PathBuf::from("./certs")
+}
+
+fn default_renewal_days() -> u32 {
+    30
+}
+
+fn default_renewal_check_interval_hours() -> u64 {
+    12
+}
+
+fn default_expiry_warning_days() -> u32 {
+    7
+}
+
+fn default_order_poll_interval_secs() -> u64 {
+    2
+}
+
+fn default_order_poll_max_retries() -> u32 {
+    20
 }
 
 fn default_cert_poll_interval_secs() -> u64 {
     1
 }
 
-fn default_cert_poll_max_retries() -> u32 {
-    10
-}
+fn default_cert_poll_max_retries() -> u32 {
+    10
+}
+
+fn default_on_demand_retry_secs() -> u64 {
+    600
+}
+
+/// Where `api_path` serves the dashboard on app hosts.
+pub const API_PATH_PREFIX: &str = "/.penny";
+
+/// Where an app with a `sleep_token` asks to be stopped, even with
+/// `api_path`. Apps without one get requests to it like any other.
+pub const SLEEP_PATH: &str = "/.penny/sleep";
+
+/// Tells an app its `sleep_token`.
+const SLEEP_TOKEN_ENV: &str = "PENNY_SLEEP_TOKEN";
+
+fn is_api_path(path: &str) -> bool {
+    path != SLEEP_PATH
+        && path
+            .strip_prefix(API_PATH_PREFIX)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// The running configuration, swapped out in place by `penny reload`.
+pub type SharedConfig = Arc<RwLock<Config>>;
+
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub api_address: Option<SocketAddr>,
+
+    #[serde(default, deserialize_with = "deserialize_hostname")]
+    pub api_domain: Option<String>,
+
+    /// Also serve the dashboard under `/.penny/` on every app's host, for
+    /// setups without a spare domain for `api_domain`.
+    #[serde(default)]
+    pub api_path: bool,
+
+    #[serde(default = "default_database_url")]
+    pub database_url: String,
+
+    /// Use `X-Forwarded-Host` and `X-Forwarded-Proto` from incoming requests,
+    /// for when penny runs behind another proxy.
+    #[serde(default)]
+    pub trust_forwarded_headers: bool,
+
+    /// Proxies whose `X-Forwarded-For` is honored, as networks like
+    /// `10.0.0.0/8`. From any other peer the header is replaced.
+    #[serde(default)]
+    pub trusted_proxies: Vec<Cidr>,
+
+    /// Template replacing the built-in cold start page for every app
+    /// without its own `cold_start_page_path`.
+    #[serde(default)]
+    pub cold_start_page_path: Option<PathBuf>,
+
+    #[serde(skip)]
+    pub cold_start_page_html: Option<String>,
+
+    /// Language of the built-in cold start page's text, e.g. `de`.
+    #[serde(default = "default_cold_start_lang")]
+    pub cold_start_lang: String,
+
+    /// Name, logo and colors for the built-in cold start page.
+    #[serde(default)]
+    pub cold_start_branding: Branding,
+
+    /// `robots.txt` served for every sleeping app without its own.
+    #[serde(default)]
+    pub asleep_robots_txt: Option<PathBuf>,
+
+    /// `favicon.ico` served for every sleeping app without its own.
+    #[serde(default)]
+    pub asleep_favicon: Option<PathBuf>,
+
+    /// How many apps may be starting at once. Further starts wait until an
+    /// earlier one passes or fails its health check.
+    #[serde(default)]
+    pub max_concurrent_starts: Option<std::num::NonZeroUsize>,
+
+    #[serde(skip)]
+    pub start_slots: Option<Arc<tokio::sync::Semaphore>>,
+
+    /// Unprivileged user to switch to after binding the proxy listeners,
+    /// so penny and its apps do not keep running as root.
+    #[serde(default)]
+    pub user: Option<String>,
+
+    /// Group to switch to along with `user`. Defaults to the user's
+    /// primary group.
+    #[serde(default)]
+    pub group: Option<String>,
+
+    /// TLS configuration for automatic certificate provisioning.
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+
+    /// Periodic end-to-end checks of every app, off unless set.
+    #[serde(default)]
+    pub monitor: Option<MonitorConfig>,
+
+    /// Proxy for penny's own outbound requests: ACME, health checks and
+    /// webhooks. Defaults to `HTTPS_PROXY` and `NO_PROXY` from the environment.
+    #[serde(default)]
+    pub outbound_proxy: Option<OutboundProxy>,
+
+    /// App settings for wildcard hosts like `*.preview.example.com`. Each
+    /// subdomain gets its own app on its first request.
+    #[serde(default, deserialize_with = "crate::preview::deserialize_previews")]
+    pub previews: Vec<Preview>,
+
+    /// Share app state with other penny nodes using the same database
+    /// (experimental).
+    #[serde(default)]
+    pub cluster: Option<ClusterConfig>,
+
+    /// Size the database may grow to, in megabytes. Beyond it, the oldest
+    /// logs, request metrics and other history are deleted.
+    #[serde(default)]
+    pub max_database_mb: Option<u64>,
+
+    /// Default page size for paginated API responses.
+    #[serde(default = "default_page_limit")]
+    pub default_page_limit: u32,
+
+    /// Maximum allowed page size for paginated API responses.
+    #[serde(default = "default_max_page_limit")]
+    pub max_page_limit: u32,
+
+    #[serde(flatten, deserialize_with = "deserialize_apps")]
+    pub apps: HashMap<String, Arc<RwLock<App>>>,
+}
+
+pub fn default_database_url() -> String {
+    "sqlite://penny.db".to_owned()
+}
+
+fn default_cold_start_lang() -> String {
+    "en".to_owned()
+}
+
+/// `default_page_limit` when it is not set.
+pub const DEFAULT_PAGE_LIMIT: u32 = 20;
+
+fn default_page_limit() -> u32 {
+    DEFAULT_PAGE_LIMIT
+}
+
+fn default_max_page_limit() -> u32 {
+    100
+}
+
+/// Languages a config file can be written in, told apart by its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// YAML for `.yaml` and `.yml` files, JSON for `.json`, TOML otherwise.
+    pub fn of(path: impl AsRef<Path>) -> Self {
+        let extension = path
+            .as_ref()
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("yaml" | "yml") => Self::Yaml,
+            Some("json") => Self::Json,
+            _ => Self::Toml,
+        }
+    }
+}
+
+impl Config {
+    /// Parses a config file's contents. YAML and JSON are read into the
+    /// same tables as TOML, so every setting is written the same way in all
+    /// three.
+    pub fn parse(content: &str, format: ConfigFormat) -> color_eyre::Result<Self> {
+        let table: toml::Table = match format {
+            ConfigFormat::Toml => return Ok(toml::from_str(content)?),
+            ConfigFormat::Yaml => serde_norway::from_str(content)?,
+            ConfigFormat::Json => serde_json::from_str(content)?,
+        };
+        Ok(toml::Value::Table(table).try_into()?)
+    }
+
+    /// Reads and parses a config file.
+    pub fn load(path: &str) -> color_eyre::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| color_eyre::eyre::eyre!("failed to read config file '{path}': {e}"))?;
+        let mut config = Self::parse(&content, ConfigFormat::of(path))?;
+        if config.default_page_limit == 0 || config.default_page_limit > config.max_page_limit {
+            color_eyre::eyre::bail!(
+                "default_page_limit must be between 1 and max_page_limit ({})",
+                config.max_page_limit
+            );
+        }
+        crate::outbound::init(config.outbound_proxy.as_ref());
+        config.share_start_slots();
+        config.share_cluster_node();
+        Ok(config)
+    }
+
+    /// Tells every app this node's name in `[cluster]`. Apps must not be
+    /// shared yet.
+    fn share_cluster_node(&mut self) {
+        let node = self.cluster.as_ref().map(|cluster| cluster.node.clone());
+        for app in self.apps.values_mut() {
+            if let Some(app) = Arc::get_mut(app) {
+                app.get_mut().cluster_node = node.clone();
+            }
+        }
+    }
+
+    /// Hands every app the start limiter for `max_concurrent_starts`. Apps
+    /// must not be shared yet.
+    fn share_start_slots(&mut self) {
+        self.start_slots = self
+            .max_concurrent_starts
+            .map(|max| Arc::new(tokio::sync::Semaphore::new(max.get())));
+        for app in self.apps.values_mut() {
+            if let Some(app) = Arc::get_mut(app) {
+                app.get_mut().start_slots = self.start_slots.clone();
+            }
+        }
+    }
+
+    /// Domains that need a certificate: every app not opted out with
+    /// `tls = false`, plus the API domain.
+    pub async fn tls_domains(&self) -> Vec<String> {
+        let mut domains = Vec::new();
+        for (host, app) in &self.apps {
+            if app.read().await.tls {
+                domains.push(host.clone());
+            }
+        }
+        if let Some(api_domain) = &self.api_domain
+            && self.api_address.is_some()
+        {
+            domains.push(api_domain.clone());
+        }
+        domains
+    }
+
+    /// Reads the files penny answers with while apps sleep or start: cold
+    /// start pages, their logos, and `asleep_robots_txt`/`asleep_favicon`.
+    pub fn load_cold_start_pages(&mut self) -> color_eyre::Result<()> {
+        if let Some(path) = &self.cold_start_page_path {
+            self.cold_start_page_html = Some(read_cold_start_page(path, "all apps")?);
+        }
+        self.cold_start_branding.load_logo("all apps")?;
+        let robots_txt = self
+            .asleep_robots_txt
+            .as_deref()
+            .map(|path| AsleepFile::read(path, "all apps"))
+            .transpose()?;
+        let favicon = self
+            .asleep_favicon
+            .as_deref()
+            .map(|path| AsleepFile::read(path, "all apps"))
+            .transpose()?;
+
+        for (host, app) in &self.apps {
+            let mut guard = app.blocking_write();
+            guard.cold_start_branding.load_logo(host)?;
+
+            let own_robots_txt = guard
+                .asleep_robots_txt
+                .as_deref()
+                .map(|path| AsleepFile::read(path, host))
+                .transpose()?;
+            let own_favicon = guard
+                .asleep_favicon
+                .as_deref()
+                .map(|path| AsleepFile::read(path, host))
+                .transpose()?;
+            guard.asleep_files.clear();
+            if let Some(file) = own_robots_txt.or_else(|| robots_txt.clone()) {
+                guard.asleep_files.insert("/robots.txt", file);
+            }
+            if let Some(file) = own_favicon.or_else(|| favicon.clone()) {
+                guard.asleep_files.insert("/favicon.ico", file);
+            }
+            if let Some(path) = &guard.cold_start_page_path {
+                let html = read_cold_start_page(path, host)?;
+
+                if !html.contains("<meta http-equiv=\"refresh\"") {
+                    warn!(
+                        host = %host,
+                        path = %path.display(),
+                        "custom cold start page is missing <meta http-equiv=\"refresh\" ...> tag; page won't auto-refresh"
+                    );
+                }
+
+                guard.cold_start_page = true;
+                guard.cold_start_page_html = Some(html);
+            }
+        }
+        Ok(())
+    }
+
+    /// The cold start page template, language and branding for an app,
+    /// from its own settings and the global ones.
+    pub fn cold_start_page(&self, app: AppColdStartPage) -> ColdStartPage {
+        ColdStartPage {
+            template: app.html.or_else(|| self.cold_start_page_html.clone()),
+            lang: app.lang.unwrap_or_else(|| self.cold_start_lang.clone()),
+            branding: app.branding.or(&self.cold_start_branding),
+        }
+    }
+
+    /// What requests for `host` are routed to: an app, the dashboard on
+    /// `api_domain`, or nothing.
+    fn route(&self, host: &str) -> Option<String> {
+        let host = normalize_hostname(host)?;
+        if self.apps.contains_key(&host)
+            || (self.api_domain.as_ref() == Some(&host) && self.api_address.is_some())
+        {
+            Some(host)
+        } else {
+            None
+        }
+    }
+
+    /// Whether a TLS connection opened for `sni` may carry a request for
+    /// `host`, i.e. both are routed to the same place.
+    pub fn same_route(&self, sni: &str, host: &str) -> bool {
+        self.route(sni) == self.route(host)
+    }
+
+    pub fn get_proxy_context(&self, host: &str, path: &str) -> Option<ProxyContext> {
+        let default_hsts = self.tls.as_ref().and_then(|tls| tls.hsts.as_ref());
+        let host = normalize_hostname(host)?;
+        let host = host.as_str();
+
+        // Only with a dashboard password: these paths are on public hosts.
+        if self.api_path
+            && crate::auth::is_auth_required()
+            && is_api_path(path)
+            && let Some(api_address) = self.api_address
+            && self.apps.contains_key(host)
+        {
+            let mut ctx = ProxyContext::new_api(host, api_address, default_hsts);
+            ctx.api_prefix = Some(API_PATH_PREFIX);
+            return Some(ctx);
+        }
+
+        if let Some(app) = self.apps.get(host) {
+            return Some(ProxyContext::new(host, app.clone(), default_hsts));
+        }
+
+        if let Some(api_domain) = &self.api_domain
+            && host == api_domain
+            && let Some(api_address) = self.api_address
+        {
+            return Some(ProxyContext::new_api(host, api_address, default_hsts));
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn yaml_and_json_configs_read_like_toml() {
+        let yaml = r#"
+api_address: 127.0.0.1:3031
+myapp.example.com:
+  address: 127.0.0.1:3001
+  command: node server.js
+  health_check: /
+  wait_period: 5m
+"#;
+        let json = r#"{
+            "api_address": "127.0.0.1:3031",
+            "myapp.example.com": {
+                "address": "127.0.0.1:3001",
+                "command": "node server.js",
+                "health_check": "/",
+                "wait_period": "5m"
+            }
+        }"#;
+
+        for (content, format) in [(yaml, ConfigFormat::Yaml), (json, ConfigFormat::Json)] {
+            let config = Config::parse(content, format).unwrap();
+            assert_eq!(config.api_address, Some("127.0.0.1:3031".parse().unwrap()));
+            let app = config.apps["myapp.example.com"].read().await;
+            assert_eq!(app.address.to_string(), "127.0.0.1:3001");
+            assert_eq!(app.wait_period, SignedDuration::from_mins(5));
+        }
+
+        assert_eq!(ConfigFormat::of("penny.yml"), ConfigFormat::Yaml);
+        assert_eq!(
+            ConfigFormat::of("/etc/penny/penny.JSON"),
+            ConfigFormat::Json
+        );
+        assert_eq!(ConfigFormat::of("penny.toml"), ConfigFormat::Toml);
+        assert!(Config::parse("{", ConfigFormat::Json).is_err());
+    }
+
+    #[test]
+    fn parse_app_with_cwd() {
+        let toml_str = r#"
+            ["myapp.example.com"]
+            address = "127.0.0.1:3001"
+            command = "node server.js"
+            health_check = "/"
+            cwd = "/opt/apps/myapp"
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let app = config.apps.get("myapp.example.com").unwrap();
+        let guard = app.blocking_read();
+        assert_eq!(guard.cwd, Some(PathBuf::from("/opt/apps/myapp")));
+    }
+
+    #[test]
+    fn parse_app_without_cwd() {
+        let toml_str = r#"
+            ["myapp.example.com"]
+            address = "127.0.0.1:3001"
+            command = "node server.js"
+            health_check = "/"
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let app = config.apps.get("myapp.example.com").unwrap();
+        let guard = app.blocking_read();
+        assert_eq!(guard.cwd, None);
+    }
+
+    #[test]
+    fn parse_app_with_start_end_command_and_cwd() {
+        let toml_str = r#"
+            ["myapp.example.com"]
+            address = "127.0.0.1:3001"
+            health_check = "/"
+            cwd = "/opt/apps/myapp"
+
+            ["myapp.example.com".command]
+            start = "docker start myapp"
+            end = "docker stop myapp"
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let app = config.apps.get("myapp.example.com").unwrap();
+        let guard = app.blocking_read();
+        assert_eq!(guard.cwd, Some(PathBuf::from("/opt/apps/myapp")));
+        assert!(matches!(guard.command, AppCommand::StartEnd { .. }));
+    }
+
+    #[test]
+    fn parse_hsts_policy() {
+        let toml_str = r#"
+            [tls]
+            enabled = true
+            acme_email = "me@example.com"
+            min_tls_version = "1.3"
+
+            [tls.hsts]
+            include_subdomains = true
+
+            ["myapp.example.com"]
+            address = "127.0.0.1:3001"
+            command = "node server.js"
+            health_check = "/"
+            hsts = { max_age_secs = 600, preload = true }
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let tls = config.tls.as_ref().unwrap();
+        assert_eq!(tls.min_tls_version, Some(TlsVersion::Tls13));
+        assert_eq!(
+            tls.hsts.as_ref().unwrap().header_value(),
+            "max-age=31536000; includeSubDomains"
+        );
+
+        let app = config.apps.get("myapp.example.com").unwrap();
+        let guard = app.blocking_read();
+        assert_eq!(
+            guard.hsts.as_ref().unwrap().header_value(),
+            "max-age=600; preload"
+        );
+    }
+
+    #[test]
+    fn hosts_are_normalized() {
+        let toml_str = r#"
+            api_domain = "Penny.Example.com."
+            api_address = "127.0.0.1:3030"
+
+            ["MyApp.Example.COM"]
+            address = "127.0.0.1:3001"
+            command = "node server.js"
+            health_check = "/"
+
+            ["bücher.example"]
+            address = "127.0.0.1:3002"
+            command = "node server.js"
+            health_check = "/"
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert!(config.apps.contains_key("myapp.example.com"));
+        assert!(config.apps.contains_key("xn--bcher-kva.example"));
+        assert_eq!(config.api_domain.as_deref(), Some("penny.example.com"));
+
+        for host in ["myapp.example.com", "MYAPP.example.com.", "BÜCHER.example"] {
+            assert!(config.get_proxy_context(host, "/").is_some(), "{host}");
+        }
+        let ctx = config.get_proxy_context("Penny.example.com", "/").unwrap();
+        assert_eq!(ctx.host.0, "penny.example.com");
+        assert!(ctx.api_address.is_some());
+        assert!(config.get_proxy_context("other.example.com", "/").is_none());
+    }
+
+    #[test]
+    fn sni_and_host_must_route_to_the_same_place() {
+        let toml_str = r#"
+            api_domain = "penny.example.com"
+            api_address = "127.0.0.1:3030"
+
+            ["a.example.com"]
+            address = "127.0.0.1:3001"
+            command = "node server.js"
+            health_check = "/"
+
+            ["b.example.com"]
+            address = "127.0.0.1:3002"
+            command = "node server.js"
+            health_check = "/"
+        "#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+
+        assert!(config.same_route("a.example.com", "A.example.com."));
+        assert!(config.same_route("x.example.com", "y.example.com"));
+        assert!(!config.same_route("a.example.com", "b.example.com"));
+        assert!(!config.same_route("a.example.com", "penny.example.com"));
+        assert!(!config.same_route("x.example.com", "b.example.com"));
+    }
+
+    #[test]
+    fn api_path_matches_only_the_reserved_prefix() {
+        assert!(is_api_path("/.penny"));
+        assert!(is_api_path("/.penny/"));
+        assert!(is_api_path("/.penny/api/version"));
+        assert!(!is_api_path("/.pennyfoo"));
+        assert!(!is_api_path("/.penny/sleep"));
+        assert!(!is_api_path("/api/version"));
+    }
+
+    #[test]
+    fn duplicate_and_invalid_hosts_are_rejected() {
+        let duplicate = r#"
+            ["myapp.example.com"]
+            address = "127.0.0.1:3001"
+            command = "node server.js"
+            health_check = "/"
+
+            ["MyApp.Example.com"]
+            address = "127.0.0.1:3002"
+            command = "node server.js"
+            health_check = "/"
+        "#;
+        assert!(toml::from_str::<Config>(duplicate).is_err());
+
+        let invalid = r#"
+            ["my app/example"]
+            address = "127.0.0.1:3001"
+            command = "node server.js"
+            health_check = "/"
+        "#;
+        assert!(toml::from_str::<Config>(invalid).is_err());
+    }
+
+    #[test]
+    fn parse_shell_commands() {
+        let toml_str = r#"
+            ["prefixed.example.com"]
+            address = "127.0.0.1:3001"
+            command = "sh: cd /srv/app && ./run.sh | tee log"
+            health_check = "/"
+
+            ["shell.example.com"]
+            address = "127.0.0.1:3002"
+            command = "./run.sh > out.log 2>&1"
+            health_check = "/"
+            shell = true
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let shell = crate::service::login_shell();
+
+        let app = config.apps["prefixed.example.com"].blocking_read();
+        let AppCommand::Start(start) = &app.command else {
+            panic!("expected a start command");
+        };
+        assert_eq!(start.program, shell);
+        assert_eq!(start.args, ["-c", "cd /srv/app && ./run.sh | tee log"]);
+        assert_eq!(start.command_line(), "cd /srv/app && ./run.sh | tee log");
+
+        let app = config.apps["shell.example.com"].blocking_read();
+        let AppCommand::Start(start) = &app.command else {
+            panic!("expected a start command");
+        };
+        assert_eq!(start.args, ["-c", "./run.sh > out.log 2>&1"]);
+        assert!(app.command.start_in_shell());
+    }
+
+    #[tokio::test]
+    async fn shell_command_runs_pipelines() {
+        let command: CommandSpec = "sh:echo hello | tr h j".parse().unwrap();
+        assert_eq!(command.output(None).await.unwrap(), "jello\n");
+    }
+
+    #[test]
+    fn sandbox_wraps_start_command() {
+        let toml_str = r#"
+            ["myapp.example.com"]
+            address = "127.0.0.1:3001"
+            command = "node server.js"
+            health_check = "/"
+            cwd = "/srv/myapp"
+            sandbox = { ro_binds = ["/opt/node"], rw_binds = ["/var/cache/myapp"], net = false }
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let app = config.apps["myapp.example.com"].blocking_read();
+        let AppCommand::Start(start) = &app.command else {
+            panic!("expected a start command");
+        };
+        let wrapper = start.wrapper.join(" ");
+        assert!(wrapper.starts_with("bwrap --unshare-all"));
+        assert!(!wrapper.contains("--share-net"));
+        assert!(wrapper.contains("--ro-bind /opt/node /opt/node"));
+        assert!(
+            wrapper
+                .contains("--bind /srv/myapp /srv/myapp --bind /var/cache/myapp /var/cache/myapp")
+        );
+        assert!(wrapper.ends_with("--chdir /srv/myapp --"));
+    }
+
+    #[test]
+    fn parse_non_navigation_policy() {
+        let toml_str = r#"
+            ["a.example.com"]
+            address = "127.0.0.1:3001"
+            command = "node server.js"
+            health_check = "/"
+
+            ["b.example.com"]
+            address = "127.0.0.1:3002"
+            command = "node server.js"
+            health_check = "/"
+            non_navigation = "503_retry_after"
+
+            ["c.example.com"]
+            address = "127.0.0.1:3003"
+            command = "node server.js"
+            health_check = "/"
+            non_navigation = "queue"
+            non_navigation_queue_timeout = "5s"
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let policy = |host: &str| {
+            let app = config.apps[host].blocking_read();
+            (app.non_navigation, app.non_navigation_queue_timeout)
+        };
+        assert_eq!(
+            policy("a.example.com"),
+            (NonNavigationPolicy::Block, SignedDuration::from_secs(10))
+        );
+        assert_eq!(policy("b.example.com").0, NonNavigationPolicy::RetryAfter);
+        assert_eq!(
+            policy("c.example.com"),
+            (NonNavigationPolicy::Queue, SignedDuration::from_secs(5))
+        );
+
+        let invalid = toml_str.replace("\"queue\"", "\"drop\"");
+        assert!(toml::from_str::<Config>(&invalid).is_err());
+    }
+
+    #[test]
+    fn ignore_paths_match_exactly_or_by_prefix() {
+        let toml_str = r#"
+            ["myapp.example.com"]
+            address = "127.0.0.1:3001"
+            command = "node server.js"
+            health_check = "/"
+            ignore_paths = ["/healthz", "/metrics/*"]
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let app = config.apps["myapp.example.com"].blocking_read();
+        assert!(app.ignores_path("/healthz"));
+        assert!(!app.ignores_path("/healthz/deep"));
+        assert!(app.ignores_path("/metrics/"));
+        assert!(app.ignores_path("/metrics/node"));
+        assert!(!app.ignores_path("/"));
+    }
+
+    #[test]
+    fn ignore_user_agents_match_anywhere_ignoring_case() {
+        let app = |agents: &str| {
+            toml::from_str::<Config>(&format!(
+                r#"
+                ["myapp.example.com"]
+                address = "127.0.0.1:3001"
+                command = "node server.js"
+                health_check = "/"
+                ignore_user_agents = {agents}
+                "#
+            ))
+        };
+
+        let config = app(r#"["UptimeRobot", "kube-probe/"]"#).unwrap();
+        let app_ref = config.apps["myapp.example.com"].blocking_read();
+        assert!(app_ref.ignores_user_agent("Mozilla/5.0+(compatible; UptimeRobot/2.0)"));
+        assert!(app_ref.ignores_user_agent("kube-probe/1.29"));
+        assert!(!app_ref.ignores_user_agent("Mozilla/5.0 (X11; Linux x86_64)"));
+        drop(app_ref);
+
+        assert!(app(r#"[""]"#).is_err());
+    }
+
+    #[test]
+    fn env_is_passed_to_the_commands() {
+        let config: Config = toml::from_str(
+            r#"
+            ["myapp.example.com"]
+            address = "127.0.0.1:3001"
+            command = "node server.js"
+            health_check = "/"
+            env = { DATABASE_URL = "postgres://localhost/myapp", NODE_ENV = "production" }
+            "#,
+        )
+        .unwrap();
+        let guard = config.apps["myapp.example.com"].blocking_read();
+        assert_eq!(
+            guard.command.env(),
+            [
+                (
+                    "DATABASE_URL".to_owned(),
+                    "postgres://localhost/myapp".to_owned()
+                ),
+                ("NODE_ENV".to_owned(), "production".to_owned()),
+            ]
+        );
+        drop(guard);
+
+        let invalid = r#"
+            ["myapp.example.com"]
+            address = "127.0.0.1:3001"
+            command = "node server.js"
+            health_check = "/"
+            env = { "A=B" = "c" }
+        "#;
+        assert!(toml::from_str::<Config>(invalid).is_err());
+    }
+
+    #[test]
+    fn sleep_token_is_passed_to_the_commands() {
+        let app = |token: &str| {
+            toml::from_str::<Config>(&format!(
+                r#"
+                ["myapp.example.com"]
+                address = "127.0.0.1:3001"
+                command = {{ start = "node server.js", end = "node drain.js" }}
+                health_check = "/"
+                sleep_token = "{token}"
+                "#
+            ))
+        };
+
+        let config = app("s3cret").unwrap();
+        let guard = config.apps["myapp.example.com"].blocking_read();
+        let AppCommand::StartEnd { start, end } = &guard.command else {
+            panic!("expected start and end commands");
+        };
+        for spec in [start, end] {
+            assert!(
+                spec.env
+                    .contains(&(SLEEP_TOKEN_ENV.to_owned(), "s3cret".to_owned()))
+            );
+        }
+        drop(guard);
+
+        assert!(app("").is_err());
+    }
+
+    #[test]
+    fn health_check_request_is_customizable() {
+        let toml_str = r#"
+            ["myapp.example.com"]
+            address = "127.0.0.1:3001"
+            command = "node server.js"
+            health_check = "/health"
+            health_check_method = "head"
+            health_check_host = "myapp.internal"
+            health_check_headers = { Authorization = "Bearer abc" }
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let app = config.apps["myapp.example.com"].blocking_read();
+        let checker = HttpHealthChecker::new(
+            &app.health_check_method,
+            &app.health_check_headers,
+            app.health_check_host.as_deref(),
+        )
+        .unwrap();
+        assert_eq!(checker.method, http::Method::HEAD);
+        assert_eq!(checker.headers["host"], "myapp.internal");
+        assert_eq!(checker.headers["authorization"], "Bearer abc");
+
+        let invalid = toml_str.replace("Authorization", "\"Bad Header\"");
+        assert!(toml::from_str::<Config>(&invalid).is_err());
+    }
+
+    #[test]
+    fn parse_upstream_addresses() {
+        assert!(matches!(
+            "127.0.0.1:3001".parse(),
+            Ok(UpstreamAddress::Fixed(_))
+        ));
+        assert!(matches!(
+            "[::1]:3001".parse(),
+            Ok(UpstreamAddress::Fixed(_))
+        ));
+
+        let hostname: UpstreamAddress = "myapp.internal:3000".parse().unwrap();
+        assert!(matches!(hostname, UpstreamAddress::Hostname { .. }));
+        assert_eq!(hostname.to_string(), "myapp.internal:3000");
+
+        assert!("myapp.internal".parse::<UpstreamAddress>().is_err());
+        assert!("myapp.internal:http".parse::<UpstreamAddress>().is_err());
+        assert!(":3000".parse::<UpstreamAddress>().is_err());
+    }
+
+    #[test]
+    fn parse_address_command_output() {
+        let address = UpstreamAddress::from_command_output("0.0.0.0:49153\n[::]:49153\n");
+        assert_eq!(address.unwrap().to_string(), "127.0.0.1:49153");
+
+        let address = UpstreamAddress::from_command_output("\n  myapp.internal:3000  \n");
+        assert_eq!(address.unwrap().to_string(), "myapp.internal:3000");
+
+        assert!(UpstreamAddress::from_command_output("").is_none());
+        assert!(UpstreamAddress::from_command_output("Error: no such container").is_none());
+    }
+
+    #[tokio::test]
+    async fn hostname_address_resolves() {
+        let address: UpstreamAddress = "localhost:3000".parse().unwrap();
+        let resolved = address.resolve().await.unwrap();
+        assert!(resolved.ip().is_loopback());
+        assert_eq!(resolved.port(), 3000);
+    }
+
+    #[tokio::test]
+    async fn command_runs_in_cwd() {
+        let mut spec = CommandSpec::from_str("pwd").unwrap();
+        let cwd = std::env::temp_dir();
+        spec.run::<crate::db::SqliteDatabase>(Some(&cwd), None)
+            .await;
+
+        let child = spec.child.take().unwrap();
+        let output = child.wait_with_output().await.unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let canonical_tmp = std::fs::canonicalize(&cwd).unwrap();
+        assert_eq!(stdout.trim(), canonical_tmp.to_str().unwrap(),);
+    }
+
+    #[tokio::test]
+    async fn command_runs_without_cwd() {
+        let mut spec = CommandSpec::from_str("pwd").unwrap();
+        spec.run::<crate::db::SqliteDatabase>(None, None).await;
+
+        let child = spec.child.take().unwrap();
+        let output = child.wait_with_output().await.unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        // Without cwd, should inherit the current working directory
+        let current_dir = std::env::current_dir().unwrap();
+        assert_eq!(stdout.trim(), current_dir.to_str().unwrap());
+    }
+
+    #[tokio::test]
+    async fn running_checks_under_a_read_lock_see_the_last_reaped_state() {
+        let mut spec = CommandSpec::from_str("true").unwrap();
+        assert!(!spec.is_running());
+        spec.run::<crate::db::SqliteDatabase>(None, None).await;
+        assert!(spec.is_running());
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!spec.is_child_running());
+        assert!(!spec.is_running());
+    }
+
+    #[derive(Debug, Default)]
+    struct CountingHealthChecker(std::sync::Arc<std::sync::atomic::AtomicUsize>);
+
+    #[async_trait::async_trait]
+    impl HealthChecker for CountingHealthChecker {
+        async fn check(&self, _address: SocketAddr, _path: &str) -> Result<(), String> {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn passing_health_check_is_cached() {
+        let toml_str = r#"
+            ["myapp.example.com"]
+            address = "127.0.0.1:3001"
+            command = "node server.js"
+            health_check = "/"
+        "#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let app = config.apps.get("myapp.example.com").unwrap();
+        let mut guard = app.write().await;
+        let checks = CountingHealthChecker::default();
+        let count = checks.0.clone();
+        guard.health_checker = Arc::new(checks);
+
+        assert!(guard.is_running_cached().await);
+        assert!(guard.is_running_cached().await);
+        assert_eq!(count.load(std::sync::atomic::Ordering::SeqCst), 1);
 
-#[derive(Debug, Deserialize)]
-pub struct Config {
-    #[serde(default)]
-    pub api_address: Option<SocketAddr>,
+        guard.health_check_cache_ms = 0;
+        assert!(guard.is_running_cached().await);
+        assert_eq!(count.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
 
-    #[serde(default)]
-    pub api_domain: Option<String>,
+    #[tokio::test]
+    async fn concurrent_waiters_share_one_probe() {
+        let toml_str = r#"
+            ["myapp.example.com"]
+            address = "127.0.0.1:3001"
+            command = "node server.js"
+            health_check = "/"
+        "#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let app = config.apps.get("myapp.example.com").unwrap();
+        let db = crate::db::SqliteDatabase::new("sqlite::memory:")
+            .await
+            .unwrap();
+        let checks = CountingHealthChecker::default();
+        let count = checks.0.clone();
+        {
+            let mut guard = app.write().await;
+            guard.health_checker = Arc::new(checks);
+            let host = Host("myapp.example.com".to_owned());
+            guard.startup = Some(App::watch_startup(&host, app, db, Launch::Started(None)));
+        }
 
-    #[serde(default = "default_database_url")]
-    pub database_url: String,
+        let mut waiters = tokio::task::JoinSet::new();
+        for _ in 0..10 {
+            let app = app.clone();
+            waiters.spawn(async move { App::wait_for_healthy(&app).await.is_ok() });
+        }
+        while let Some(healthy) = waiters.join_next().await {
+            assert!(healthy.unwrap());
+        }
+        assert_eq!(count.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert!(app.read().await.confirmed_healthy);
+    }
 
-    /// TLS configuration for automatic certificate provisioning.
-    #[serde(default)]
-    pub tls: Option<TlsConfig>,
+    #[derive(Debug)]
+    struct FailingHealthChecker;
 
-    /// Default page size for paginated API responses.
-    #[serde(default = "default_page_limit")]
-    pub default_page_limit: u32,
+    #[async_trait::async_trait]
+    impl HealthChecker for FailingHealthChecker {
+        async fn check(&self, _address: SocketAddr, _path: &str) -> Result<(), String> {
+            Err("responded with 503 Service Unavailable".to_owned())
+        }
+    }
 
-    /// Maximum allowed page size for paginated API responses.
-    #[serde(default = "default_max_page_limit")]
-    pub max_page_limit: u32,
+    #[tokio::test]
+    async fn unmanaged_apps_are_health_checked_but_never_run() {
+        use crate::reporter::{PaginationParams, Reporter};
+
+        assert!(
+            toml::from_str::<Config>(
+                r#"
+                ["myapp.example.com"]
+                address = "127.0.0.1:3001"
+                health_check = "/"
+            "#
+            )
+            .is_err()
+        );
 
-    #[serde(flatten, deserialize_with = "deserialize_apps")]
-    pub apps: HashMap<String, Arc<RwLock<App>>>,
-}
+        let toml_str = r#"
+            ["myapp.example.com"]
+            address = "127.0.0.1:3001"
+            health_check = "/"
+            managed = false
+        "#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let app = config.apps.get("myapp.example.com").unwrap();
+        let host = Host("myapp.example.com".to_owned());
+        let db = crate::db::SqliteDatabase::new("sqlite::memory:")
+            .await
+            .unwrap();
+        app.write().await.health_checker = Arc::new(CountingHealthChecker::default());
 
-pub fn default_database_url() -> String {
-    "sqlite://penny.db".to_owned()
-}
+        assert!(
+            App::begin_start_app(&host, app, db.clone(), WakeReason::Browser)
+                .await
+                .unwrap()
+        );
+        assert!(app.write().await.confirmed_healthy);
+        assert!(!app.write().await.command.is_child_running());
 
-fn default_page_limit() -> u32 {
-    20
-}
+        App::stop_app(&host, app, db.clone()).await;
+        assert!(app.read().await.confirmed_healthy);
 
-fn default_max_page_limit() -> u32 {
-    100
-}
+        {
+            let mut guard = app.write().await;
+            guard.health_checker = Arc::new(FailingHealthChecker);
+            guard.health_check_cache_ms = 0;
+        }
+        assert!(
+            App::start_app(&host, app, db.clone(), WakeReason::Browser)
+                .await
+                .is_err()
+        );
+        assert!(!app.read().await.confirmed_healthy);
 
-impl Config {
-    pub fn tls_domains(&self) -> Vec<String> {
-        let mut domains: Vec<String> = self.apps.keys().cloned().collect();
-        if let Some(api_domain) = &self.api_domain
-            && self.api_address.is_some()
+        let runs = db.app_runs(&host, None, PaginationParams::default()).await;
+        assert_eq!(runs.items.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn start_progress_records_health_checks() {
+        let toml_str = r#"
+            ["myapp.example.com"]
+            address = "127.0.0.1:3001"
+            command = "node server.js"
+            health_check = "/"
+            health_check_initial_backoff_ms = 10
+        "#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let app = config.apps.get("myapp.example.com").unwrap();
+        let mut guard = app.write().await;
+        guard.health_checker = Arc::new(FailingHealthChecker);
+        guard.start_timeout = SignedDuration::from_millis(200);
+
+        assert!(guard.wait_for_running().await.is_err());
+        assert!(guard.start_progress().is_none());
+
+        guard.begin_progress();
+        assert!(guard.wait_for_running().await.is_err());
+        let progress = guard.start_progress().unwrap();
+        assert!(progress.checks >= 2);
+        assert_eq!(
+            progress.last_error.as_deref(),
+            Some("responded with 503 Service Unavailable")
+        );
+        assert!(progress.since.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn waiting_for_a_start_leaves_the_app_unlocked() {
+        let toml_str = r#"
+            ["myapp.example.com"]
+            address = "127.0.0.1:3001"
+            command = "sleep 30"
+            health_check = "/"
+            health_check_initial_backoff_ms = 10
+        "#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let db = crate::db::SqliteDatabase::new("sqlite::memory:")
+            .await
+            .unwrap();
+        let app = &config.apps["myapp.example.com"];
+        let host = Host("myapp.example.com".to_owned());
         {
-            domains.push(api_domain.clone());
+            let mut guard = app.write().await;
+            guard.health_checker = Arc::new(FailingHealthChecker);
+            guard.start_timeout = SignedDuration::from_secs(5);
         }
-        domains
+
+        assert!(
+            !App::begin_start_app(&host, app, db.clone(), WakeReason::Browser)
+                .await
+                .unwrap()
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let guard = tokio::time::timeout(Duration::from_millis(500), app.write())
+            .await
+            .expect("the start's health checks hold the app lock");
+        drop(guard);
+        let progress = app.read().await.start_progress().unwrap();
+        assert!(progress.checks >= 1);
+
+        App::stop_app(&host, app, db).await;
     }
 
-    pub fn load_cold_start_pages(&mut self) -> color_eyre::Result<()> {
-        for (host, app) in &self.apps {
-            let mut guard = app.blocking_write();
-            if let Some(path) = &guard.cold_start_page_path {
-                let html = std::fs::read_to_string(path).map_err(|e| {
-                    color_eyre::eyre::eyre!(
-                        "failed to read cold start page for {host} at {}: {e}",
-                        path.display()
-                    )
-                })?;
+    #[tokio::test]
+    async fn requests_wait_for_a_redeploy_without_the_app_lock() {
+        let toml_str = r#"
+            ["myapp.example.com"]
+            address = "127.0.0.1:3001"
+            command = "sleep 30"
+            redeploy_command = "sleep 0.5"
+            health_check = "/"
+            health_check_initial_backoff_ms = 10
+        "#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let db = crate::db::SqliteDatabase::new("sqlite::memory:")
+            .await
+            .unwrap();
+        let app = config.apps["myapp.example.com"].clone();
+        let host = Host("myapp.example.com".to_owned());
+        app.write().await.health_checker = Arc::new(FailingHealthChecker);
+
+        let redeploy = tokio::spawn({
+            let (host, app, db) = (host.clone(), app.clone(), db.clone());
+            async move { App::redeploy(&host, &app, db).await }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let mut guard = tokio::time::timeout(Duration::from_millis(100), app.write())
+            .await
+            .expect("the rebuild holds the app lock");
+        assert!(guard.is_start_queued());
+        drop(guard);
+        assert!(
+            !App::begin_start_app(&host, &app, db.clone(), WakeReason::Browser)
+                .await
+                .unwrap()
+        );
+        assert!(!app.write().await.command.is_child_running());
 
-                if !html.contains("<meta http-equiv=\"refresh\"") {
-                    warn!(
-                        host = %host,
-                        path = %path.display(),
-                        "custom cold start page is missing <meta http-equiv=\"refresh\" ...> tag; page won't auto-refresh"
-                    );
-                }
+        assert!(!redeploy.await.unwrap().unwrap());
+        assert!(app.write().await.command.is_child_running());
 
-                guard.cold_start_page = true;
-                guard.cold_start_page_html = Some(html);
-            }
-        }
-        Ok(())
+        App::stop_app(&host, &app, db).await;
     }
 
-    pub async fn get_proxy_context(&self, host: &str) -> Option<ProxyContext> {
-        if let Some(app) = self.apps.get(host) {
-            return Some(ProxyContext::new(host, app.clone()).await);
-        }
+    #[tokio::test]
+    async fn starts_beyond_the_limit_are_queued() {
+        let toml_str = r#"
+            max_concurrent_starts = 1
 
-        if let Some(api_domain) = &self.api_domain
-            && host == api_domain
-            && let Some(api_address) = self.api_address
-        {
-            return Some(ProxyContext::new_api(host, api_address));
+            ["a.example.com"]
+            address = "127.0.0.1:3001"
+            command = "sleep 30"
+            health_check = "/"
+            health_check_initial_backoff_ms = 10
+
+            ["b.example.com"]
+            address = "127.0.0.1:3002"
+            command = "sleep 30"
+            health_check = "/"
+            health_check_initial_backoff_ms = 10
+        "#;
+        let mut config: Config = toml::from_str(toml_str).unwrap();
+        config.share_start_slots();
+        let db = crate::db::SqliteDatabase::new("sqlite::memory:")
+            .await
+            .unwrap();
+        for app in config.apps.values() {
+            let mut guard = app.write().await;
+            guard.health_checker = Arc::new(FailingHealthChecker);
+            guard.start_timeout = SignedDuration::from_millis(300);
         }
 
-        None
+        let (a, b) = (&config.apps["a.example.com"], &config.apps["b.example.com"]);
+        let (host_a, host_b) = (
+            Host("a.example.com".to_owned()),
+            Host("b.example.com".to_owned()),
+        );
+        assert!(
+            !App::begin_start_app(&host_a, a, db.clone(), WakeReason::Browser)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !App::begin_start_app(&host_b, b, db.clone(), WakeReason::Browser)
+                .await
+                .unwrap()
+        );
+        assert!(a.write().await.command.is_child_running());
+        assert!(b.write().await.is_start_queued());
+
+        // A failed start frees its slot for the queued one.
+        let mut startup = a.read().await.startup.clone().unwrap();
+        startup.wait_for(Option::is_some).await.unwrap();
+        let mut startup = b.read().await.startup.clone().unwrap();
+        startup.wait_for(Option::is_some).await.unwrap();
+        assert!(b.write().await.command.is_child_running());
+
+        App::stop_app(&host_a, a, db.clone()).await;
+        App::stop_app(&host_b, b, db).await;
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[tokio::test]
+    async fn run_to_completion_records_output() {
+        use crate::reporter::Reporter;
 
-    #[test]
-    fn parse_app_with_cwd() {
+        let db = crate::db::SqliteDatabase::new("sqlite::memory:")
+            .await
+            .unwrap();
+        let run_id = db.app_started(&Host("app.local".to_owned())).await.unwrap();
+        let opts = RunOptions {
+            run_id: run_id.clone(),
+            collector: db.clone(),
+            redactor: Redactor::default(),
+            capture: CapturePolicy::All,
+            limiter: LineLimiter::default(),
+            start_output: OutputTail::default(),
+        };
+
+        let spec = CommandSpec::from_str("sh -c 'echo built; echo warning >&2; exit 3'").unwrap();
+        let status = spec
+            .run_to_completion(None, &opts, Duration::from_secs(10))
+            .await
+            .unwrap();
+        assert_eq!(status.code(), Some(3));
+
+        let logs = db.run_logs(&run_id).await.unwrap();
+        assert_eq!(logs.stdout[0].line, "built");
+        assert_eq!(logs.stderr[0].line, "warning");
+
+        let hung = CommandSpec::from_str("sleep 10").unwrap();
+        let err = hung
+            .run_to_completion(None, &opts, Duration::from_millis(100))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+    }
+
+    #[tokio::test]
+    async fn capture_policy_and_rate_limit_apply_to_output() {
+        use crate::reporter::Reporter;
+
+        let db = crate::db::SqliteDatabase::new("sqlite::memory:")
+            .await
+            .unwrap();
+        let host = Host("app.local".to_owned());
+        let run_id = db.app_started(&host).await.unwrap();
+        let opts = RunOptions {
+            run_id: run_id.clone(),
+            collector: db.clone(),
+            redactor: Redactor::default(),
+            capture: CapturePolicy::StderrOnly,
+            limiter: LineLimiter::new(Some(2)),
+            start_output: OutputTail::new(5),
+        };
+
+        let spec =
+            CommandSpec::from_str("sh -c 'echo out; for i in 1 2 3 4 5; do echo err$i >&2; done'")
+                .unwrap();
+        spec.run_to_completion(None, &opts, Duration::from_secs(10))
+            .await
+            .unwrap();
+
+        let logs = db.run_logs(&run_id).await.unwrap();
+        assert!(logs.stdout.is_empty());
+        let stderr: Vec<_> = logs
+            .stderr
+            .iter()
+            .map(|entry| entry.line.as_str())
+            .collect();
+        assert_eq!(stderr, ["err1", "err2"]);
+
+        let runs = db
+            .app_runs(&host, None, crate::reporter::PaginationParams::default())
+            .await;
+        assert_eq!(runs.items[0].dropped_lines, 3);
+        // The cold start page shows stdout even when it is not stored.
+        assert_eq!(opts.start_output.lines(), ["out"]);
+    }
+
+    #[tokio::test]
+    async fn tls_opt_out_excluded_from_tls_domains() {
         let toml_str = r#"
-            ["myapp.example.com"]
+            ["public.example.com"]
             address = "127.0.0.1:3001"
             command = "node server.js"
             health_check = "/"
-            cwd = "/opt/apps/myapp"
+
+            ["nas.lan"]
+            address = "127.0.0.1:3002"
+            command = "node server.js"
+            health_check = "/"
+            tls = false
         "#;
 
         let config: Config = toml::from_str(toml_str).unwrap();
-        let app = config.apps.get("myapp.example.com").unwrap();
-        let guard = app.blocking_read();
-        assert_eq!(guard.cwd, Some(PathBuf::from("/opt/apps/myapp")));
+        assert_eq!(config.tls_domains().await, vec!["public.example.com"]);
     }
 
     #[test]
-    fn parse_app_without_cwd() {
+    fn request_rates_slide_by_the_second() {
+        let tracker = RequestTracker::default();
+        let now = 1_800_000_000;
+        for _ in 0..5 {
+            tracker.record_request_at(now - SHORT_WINDOW_SECS);
+        }
+        tracker.record_request_at(now - 10);
+        tracker.record_request_at(now);
+
+        // Requests exactly one short window ago fall out of it.
+        let (short_rate, long_rate) = tracker.request_rates_at(now);
+        assert_eq!(short_rate, 2.0 * 60.0 / SHORT_WINDOW_SECS as f64);
+        assert_eq!(long_rate, 7.0 * 60.0 / LONG_WINDOW_SECS as f64);
+
+        // Per-minute buckets from older versions are converted.
+        tracker.restore(vec![(now / 60, 3)], None);
+        let (_, long_rate) = tracker.request_rates_at(now);
+        assert_eq!(long_rate, 3.0 * 60.0 / LONG_WINDOW_SECS as f64);
+    }
+
+    #[test]
+    fn sub_minute_wait_periods() {
         let toml_str = r#"
-            ["myapp.example.com"]
+            ["dev.example.com"]
             address = "127.0.0.1:3001"
             command = "node server.js"
             health_check = "/"
-        "#;
+            wait_period = "20s"
 
+            ["adaptive.example.com"]
+            address = "127.0.0.1:3002"
+            command = "node server.js"
+            health_check = "/"
+            adaptive_wait = true
+            min_wait_period = "5s"
+            max_wait_period = "45s"
+        "#;
         let config: Config = toml::from_str(toml_str).unwrap();
-        let app = config.apps.get("myapp.example.com").unwrap();
-        let guard = app.blocking_read();
-        assert_eq!(guard.cwd, None);
+
+        let dev = config.apps["dev.example.com"].blocking_read();
+        assert_eq!(dev.effective_wait_period(), Duration::from_secs(20));
+
+        let adaptive = config.apps["adaptive.example.com"].blocking_read();
+        assert_eq!(adaptive.effective_wait_period(), Duration::from_secs(5));
+        for _ in 0..100 {
+            adaptive.request_tracker.record_request();
+        }
+        assert_eq!(adaptive.effective_wait_period(), Duration::from_secs(45));
     }
 
     #[test]
-    fn parse_app_with_start_end_command_and_cwd() {
-        let toml_str = r#"
-            ["myapp.example.com"]
+    fn watts_must_be_a_positive_number() {
+        let app = |watts: &str| {
+            toml::from_str::<Config>(&format!(
+                r#"
+                ["myapp.example.com"]
+                address = "127.0.0.1:3001"
+                command = "node server.js"
+                health_check = "/"
+                watts = {watts}
+            "#
+            ))
+        };
+
+        let config = app("12.5").unwrap();
+        assert_eq!(
+            config.apps["myapp.example.com"].blocking_read().watts,
+            Some(12.5)
+        );
+        assert!(app("-3").is_err());
+        assert!(app("nan").is_err());
+    }
+
+    #[test]
+    fn rolling_ports_need_a_fixed_address_and_another_port() {
+        let app = |address: &str, ports: &str| {
+            toml::from_str::<Config>(&format!(
+                r#"
+                ["myapp.example.com"]
+                address = "{address}"
+                command = "node server.js"
+                health_check = "/"
+                rolling_ports = "{ports}"
+            "#
+            ))
+        };
+
+        let config = app("127.0.0.1:4000", "4000-4001").unwrap();
+        assert_eq!(
+            config.apps["myapp.example.com"]
+                .blocking_read()
+                .rolling_ports
+                .map(|ports| ports.to_string()),
+            Some("4000-4001".to_owned())
+        );
+        assert!(app("127.0.0.1:4000", "4000-4000").is_err());
+        assert!(app("localhost:4000", "4001-4002").is_err());
+        assert!(app("127.0.0.1:4000", "4002-4001").is_err());
+        assert!(app("127.0.0.1:4000", "4001").is_err());
+
+        let ports: PortRange = "4000-4002".parse().unwrap();
+        assert!(ports.has_other_than(4000));
+        assert!(
+            !"4000-4000"
+                .parse::<PortRange>()
+                .unwrap()
+                .has_other_than(4000)
+        );
+    }
+
+    #[test]
+    fn templates_fill_in_settings_the_app_does_not_set() {
+        let config: Config = toml::from_str(
+            r#"
+            ["base.example.com"]
+            address = "127.0.0.1:3001"
+            command = "node server.js"
+            health_check = "/health"
+            wait_period = "5m"
+
+            ["pr-1.example.com"]
+            template = "Base.Example.com"
+            address = "127.0.0.1:3002"
+
+            ["pr-2.example.com"]
+            template = "pr-1.example.com"
+            wait_period = "1m"
+            "#,
+        )
+        .unwrap();
+
+        let pr1 = config.apps["pr-1.example.com"].blocking_read();
+        assert_eq!(pr1.address.to_string(), "127.0.0.1:3002");
+        assert_eq!(pr1.health_check, "/health");
+        assert_eq!(pr1.wait_period, SignedDuration::from_mins(5));
+
+        let pr2 = config.apps["pr-2.example.com"].blocking_read();
+        assert_eq!(pr2.address.to_string(), "127.0.0.1:3002");
+        assert_eq!(pr2.wait_period, SignedDuration::from_mins(1));
+
+        let looped = r#"
+            ["a.example.com"]
+            template = "b.example.com"
             address = "127.0.0.1:3001"
+            command = "node server.js"
             health_check = "/"
-            cwd = "/opt/apps/myapp"
 
-            ["myapp.example.com".command]
-            start = "docker start myapp"
-            end = "docker stop myapp"
+            ["b.example.com"]
+            template = "a.example.com"
         "#;
+        assert!(toml::from_str::<Config>(looped).is_err());
 
-        let config: Config = toml::from_str(toml_str).unwrap();
-        let app = config.apps.get("myapp.example.com").unwrap();
-        let guard = app.blocking_read();
-        assert_eq!(guard.cwd, Some(PathBuf::from("/opt/apps/myapp")));
-        assert!(matches!(guard.command, AppCommand::StartEnd { .. }));
+        let missing = r#"
+            ["a.example.com"]
+            template = "nowhere.example.com"
+            address = "127.0.0.1:3001"
+            command = "node server.js"
+            health_check = "/"
+        "#;
+        assert!(toml::from_str::<Config>(missing).is_err());
     }
 
-    #[tokio::test]
-    async fn command_runs_in_cwd() {
-        let mut spec = CommandSpec::from_str("pwd").unwrap();
-        let cwd = std::env::temp_dir();
-        spec.run::<crate::db::SqliteDatabase>(Some(&cwd), None);
+    #[test]
+    fn asleep_files_fall_back_to_the_global_ones() {
+        let dir = std::env::temp_dir().join(format!("penny-asleep-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("robots.txt"), "User-agent: *\nDisallow: /\n").unwrap();
+        std::fs::write(dir.join("app.png"), [0x89, b'P', b'N', b'G']).unwrap();
 
-        let child = spec.child.take().unwrap();
-        let output = child.wait_with_output().await.unwrap();
-        let stdout = String::from_utf8(output.stdout).unwrap();
-        let canonical_tmp = std::fs::canonicalize(&cwd).unwrap();
-        assert_eq!(stdout.trim(), canonical_tmp.to_str().unwrap(),);
-    }
+        let mut config: Config = toml::from_str(&format!(
+            r#"
+            asleep_robots_txt = "{dir}/robots.txt"
 
-    #[tokio::test]
-    async fn command_runs_without_cwd() {
-        let mut spec = CommandSpec::from_str("pwd").unwrap();
-        spec.run::<crate::db::SqliteDatabase>(None, None);
+            ["a.example.com"]
+            address = "127.0.0.1:3001"
+            command = "node server.js"
+            health_check = "/"
+            asleep_favicon = "{dir}/app.png"
 
-        let child = spec.child.take().unwrap();
-        let output = child.wait_with_output().await.unwrap();
-        let stdout = String::from_utf8(output.stdout).unwrap();
-        // Without cwd, should inherit the current working directory
-        let current_dir = std::env::current_dir().unwrap();
-        assert_eq!(stdout.trim(), current_dir.to_str().unwrap());
+            ["b.example.com"]
+            address = "127.0.0.1:3002"
+            command = "node server.js"
+            health_check = "/"
+            "#,
+            dir = dir.display()
+        ))
+        .unwrap();
+        config.load_cold_start_pages().unwrap();
+
+        let a = config.apps["a.example.com"].blocking_read();
+        assert_eq!(a.asleep_files["/robots.txt"].content_type, "text/plain");
+        assert_eq!(a.asleep_files["/favicon.ico"].content_type, "image/png");
+        let b = config.apps["b.example.com"].blocking_read();
+        assert_eq!(
+            &b.asleep_files["/robots.txt"].body[..],
+            b"User-agent: *\nDisallow: /\n"
+        );
+        assert!(!b.asleep_files.contains_key("/favicon.ico"));
+        drop((a, b));
+
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
@@ -0,0 +1,333 @@
+use std::io::{BufRead, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{Context, eyre};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+use tracing::{debug, error, info};
+
+use crate::api;
+use crate::config::SharedConfig;
+use crate::db::SqliteDatabase;
+use crate::idle::IdleScheduler;
+use crate::types::WakeReason;
+
+/// JSON-RPC error codes.
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_ERROR: i64 = -32000;
+
+/// Where `penny serve` listens for `penny ctl` by default:
+/// `$XDG_RUNTIME_DIR/penny.sock`, or `/run/penny.sock` when running as root.
+pub fn socket_path() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("penny.sock"),
+        None if nix::unistd::Uid::effective().is_root() => PathBuf::from("/run/penny.sock"),
+        None => std::env::temp_dir().join("penny.sock"),
+    }
+}
+
+/// A JSON-RPC 2.0 request, one per line.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Request {
+    pub jsonrpc: String,
+    pub id: Value,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Response {
+    pub jsonrpc: String,
+    pub id: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct HostParams {
+    host: String,
+}
+
+/// What the control socket acts on.
+#[derive(Debug, Clone)]
+pub struct Control {
+    pub config: SharedConfig,
+    pub config_path: String,
+    pub collector: SqliteDatabase,
+    pub idle: IdleScheduler,
+}
+
+impl Control {
+    async fn handle(&self, request: Request) -> Result<Value, RpcError> {
+        debug!(method = %request.method, "control request");
+        let host = || {
+            serde_json::from_value::<HostParams>(request.params.clone())
+                .map(|params| params.host)
+                .map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))
+        };
+
+        let result = match request.method.as_str() {
+            "status" => serde_json::to_value(api::live_statuses(&self.config).await),
+            "reload" => {
+                crate::reload::reload(&self.config, &self.config_path, &self.collector)
+                    .await
+                    .map_err(|e| RpcError::new(SERVER_ERROR, format!("{e:#}")))?;
+                Ok(Value::Bool(true))
+            }
+            "start" => {
+                let response = api::start_app(
+                    &self.config,
+                    &self.idle,
+                    self.collector.clone(),
+                    host()?,
+                    WakeReason::Schedule,
+                )
+                .await
+                .map_err(|(_, message)| RpcError::new(SERVER_ERROR, message))?;
+                serde_json::to_value(response)
+            }
+            "stop" => {
+                let response = api::stop_app(&self.config, self.collector.clone(), host()?)
+                    .await
+                    .map_err(|(_, message)| RpcError::new(SERVER_ERROR, message))?;
+                serde_json::to_value(response)
+            }
+            method => {
+                return Err(RpcError::new(
+                    METHOD_NOT_FOUND,
+                    format!("unknown method '{method}'"),
+                ));
+            }
+        };
+        result.map_err(|e| RpcError::new(SERVER_ERROR, e.to_string()))
+    }
+
+    async fn serve_connection(self, stream: UnixStream) {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+
+        while let Ok(Some(line)) = lines.next_line().await {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let response = match serde_json::from_str::<Request>(&line) {
+                Ok(request) => {
+                    let id = request.id.clone();
+                    let (result, error) = match self.handle(request).await {
+                        Ok(result) => (Some(result), None),
+                        Err(e) => (None, Some(e)),
+                    };
+                    Response {
+                        jsonrpc: "2.0".to_owned(),
+                        id,
+                        result,
+                        error,
+                    }
+                }
+                Err(e) => Response {
+                    jsonrpc: "2.0".to_owned(),
+                    id: Value::Null,
+                    result: None,
+                    error: Some(RpcError::new(PARSE_ERROR, e.to_string())),
+                },
+            };
+
+            let mut body = serde_json::to_vec(&response).expect("responses serialize");
+            body.push(b'\n');
+            if writer.write_all(&body).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Binds the control socket, replacing one left behind by an earlier run.
+/// Only the owner may connect. Call this while holding the PID file lock,
+/// before dropping privileges.
+pub fn bind(path: &Path) -> color_eyre::Result<std::os::unix::net::UnixListener> {
+    match std::fs::remove_file(path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => {
+            return Err(e)
+                .wrap_err_with(|| format!("failed to remove stale socket {}", path.display()));
+        }
+    }
+
+    let listener = std::os::unix::net::UnixListener::bind(path)
+        .wrap_err_with(|| format!("failed to bind control socket {}", path.display()))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+        .wrap_err_with(|| format!("failed to restrict control socket {}", path.display()))?;
+    listener.set_nonblocking(true)?;
+    info!(path = %path.display(), "control socket listening");
+    Ok(listener)
+}
+
+/// Answers control requests on `listener`. Must run inside the runtime.
+pub fn spawn(listener: std::os::unix::net::UnixListener, control: Control) {
+    let listener = match tokio::net::UnixListener::from_std(listener) {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!(error = %e, "failed to use control socket");
+            return;
+        }
+    };
+
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(control.clone().serve_connection(stream));
+                }
+                Err(e) => error!(error = %e, "failed to accept control connection"),
+            }
+        }
+    });
+}
+
+/// Calls `method` on the running `penny serve` through its control socket.
+pub fn call<T: DeserializeOwned>(
+    path: &Path,
+    method: &str,
+    params: Value,
+) -> color_eyre::Result<T> {
+    let mut stream = std::os::unix::net::UnixStream::connect(path).wrap_err_with(|| {
+        format!(
+            "no running penny serve found (could not connect to {})",
+            path.display()
+        )
+    })?;
+
+    let request = Request {
+        jsonrpc: "2.0".to_owned(),
+        id: Value::from(1),
+        method: method.to_owned(),
+        params,
+    };
+    let mut body = serde_json::to_vec(&request)?;
+    body.push(b'\n');
+    stream
+        .write_all(&body)
+        .wrap_err("failed to send control request")?;
+
+    let mut line = String::new();
+    std::io::BufReader::new(&stream)
+        .read_line(&mut line)
+        .wrap_err("failed to read control response")?;
+    let response: Response =
+        serde_json::from_str(&line).wrap_err("unexpected response from the control socket")?;
+
+    match (response.result, response.error) {
+        (_, Some(error)) => Err(eyre!("{method}: {}", error.message)),
+        (Some(result), None) => serde_json::from_value(result)
+            .wrap_err_with(|| format!("unexpected result for {method}")),
+        (None, None) => Err(eyre!("{method}: empty response")),
+    }
+}
+
+/// Prints the state of every app.
+pub fn status(path: &Path) -> color_eyre::Result<()> {
+    let statuses: Vec<api::LiveAppStatus> = call(path, "status", Value::Null)?;
+    for status in statuses {
+        println!("{:<40} {}", status.host, status.state);
+    }
+    Ok(())
+}
+
+/// Reloads the config file of the running `penny serve`.
+pub fn reload(path: &Path) -> color_eyre::Result<()> {
+    call::<Value>(path, "reload", Value::Null)?;
+    println!("configuration reloaded");
+    Ok(())
+}
+
+/// Runs an app action (`start` or `stop`) and reports the resulting state.
+pub fn app_action(path: &Path, host: &str, action: &str) -> color_eyre::Result<()> {
+    let response: api::AppActionResponse = call(path, action, serde_json::json!({ "host": host }))?;
+    println!("{}: {}", response.host, response.state);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{AppActionResponse, LiveAppStatus};
+
+    #[tokio::test]
+    async fn answers_requests_over_the_socket() {
+        let toml_str = r#"
+            ["myapp.example.com"]
+            address = "127.0.0.1:1"
+            command = "node server.js"
+            health_check = "/"
+        "#;
+        let config: SharedConfig =
+            std::sync::Arc::new(tokio::sync::RwLock::new(toml::from_str(toml_str).unwrap()));
+        let collector = SqliteDatabase::new("sqlite::memory:").await.unwrap();
+        let idle = IdleScheduler::spawn(collector.clone(), config.clone());
+
+        let path = std::env::temp_dir().join(format!("penny-test-{}.sock", std::process::id()));
+        spawn(
+            bind(&path).unwrap(),
+            Control {
+                config,
+                config_path: "penny.toml".to_owned(),
+                collector,
+                idle,
+            },
+        );
+
+        let (statuses, stopped, unknown_host, unknown_method) =
+            tokio::task::spawn_blocking(move || {
+                let statuses: Vec<LiveAppStatus> = call(&path, "status", Value::Null).unwrap();
+                let stopped: AppActionResponse = call(
+                    &path,
+                    "stop",
+                    serde_json::json!({ "host": "MyApp.example.com" }),
+                )
+                .unwrap();
+                let unknown_host = call::<Value>(
+                    &path,
+                    "start",
+                    serde_json::json!({ "host": "nope.example.com" }),
+                )
+                .unwrap_err();
+                let unknown_method = call::<Value>(&path, "restart", Value::Null).unwrap_err();
+                std::fs::remove_file(&path).unwrap();
+                (statuses, stopped, unknown_host, unknown_method)
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(statuses[0].state, "sleeping");
+        assert_eq!(stopped.host, "myapp.example.com");
+        assert_eq!(stopped.state, "sleeping");
+        assert!(unknown_host.to_string().contains("no app configured"));
+        assert!(unknown_method.to_string().contains("unknown method"));
+    }
+}
@@ -1,9 +1,50 @@
 use sqlx::sqlite::SqliteConnectOptions;
+use std::collections::HashMap;
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Tables of history that can be pruned oldest-first to stay under
+/// `max_database_mb`, each with a `timestamp` column. Runs themselves are
+/// kept, so overviews stay complete.
+pub const PRUNABLE_TABLES: [&str; 7] = [
+    "stdout",
+    "stderr",
+    "request_counts",
+    "monitor_checks",
+    "wait_decisions",
+    "proxy_errors",
+    "certificate_events",
+];
 
 #[derive(Debug, Clone)]
 pub struct SqliteDatabase {
     pub(crate) pool: sqlx::SqlitePool,
+    /// When each open run started, on the monotonic clock, so its duration
+    /// survives wall-clock jumps.
+    pub(crate) run_clocks: Arc<Mutex<HashMap<String, Instant>>>,
+}
+
+async fn add_column_if_missing(
+    pool: &sqlx::SqlitePool,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> color_eyre::Result<()> {
+    let (exists,): (bool,) =
+        sqlx::query_as("SELECT COUNT(*) > 0 FROM pragma_table_info(?) WHERE name = ?")
+            .bind(table)
+            .bind(column)
+            .fetch_one(pool)
+            .await?;
+    if !exists {
+        sqlx::query(&format!(
+            "ALTER TABLE {table} ADD COLUMN {column} {definition}"
+        ))
+        .execute(pool)
+        .await?;
+    }
+    Ok(())
 }
 
 impl SqliteDatabase {
@@ -19,13 +60,39 @@ impl SqliteDatabase {
                 started_at INTEGER NOT NULL,
                 stopped_at INTEGER,
                 start_failed INTEGER NOT NULL DEFAULT 0,
-                stop_failed INTEGER NOT NULL DEFAULT 0
+                stop_failed INTEGER NOT NULL DEFAULT 0,
+                interrupted INTEGER NOT NULL DEFAULT 0,
+                duration_ms INTEGER,
+                wake_reason TEXT,
+                dropped_lines INTEGER NOT NULL DEFAULT 0,
+                usage_samples INTEGER NOT NULL DEFAULT 0,
+                memory_bytes_sum INTEGER NOT NULL DEFAULT 0,
+                peak_memory_bytes INTEGER,
+                cpu_percent_sum REAL NOT NULL DEFAULT 0,
+                peak_cpu_percent REAL
             )
             "#,
         )
         .execute(&pool)
         .await?;
 
+        // Databases created by older versions lack the later columns.
+        add_column_if_missing(&pool, "runs", "interrupted", "INTEGER NOT NULL DEFAULT 0").await?;
+        add_column_if_missing(&pool, "runs", "duration_ms", "INTEGER").await?;
+        add_column_if_missing(&pool, "runs", "wake_reason", "TEXT").await?;
+        add_column_if_missing(&pool, "runs", "dropped_lines", "INTEGER NOT NULL DEFAULT 0").await?;
+        add_column_if_missing(&pool, "runs", "usage_samples", "INTEGER NOT NULL DEFAULT 0").await?;
+        add_column_if_missing(
+            &pool,
+            "runs",
+            "memory_bytes_sum",
+            "INTEGER NOT NULL DEFAULT 0",
+        )
+        .await?;
+        add_column_if_missing(&pool, "runs", "peak_memory_bytes", "INTEGER").await?;
+        add_column_if_missing(&pool, "runs", "cpu_percent_sum", "REAL NOT NULL DEFAULT 0").await?;
+        add_column_if_missing(&pool, "runs", "peak_cpu_percent", "REAL").await?;
+
         sqlx::query(
             r#"
             CREATE TABLE IF NOT EXISTS stdout (
@@ -66,7 +133,190 @@ impl SqliteDatabase {
         .execute(&pool)
         .await?;
 
-        Ok(Self { pool })
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS certificates (
+                domain TEXT PRIMARY KEY,
+                cert_pem TEXT NOT NULL,
+                encrypted_key BLOB NOT NULL,
+                updated_at INTEGER NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS certificate_events (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                domain TEXT NOT NULL,
+                event TEXT NOT NULL,
+                detail TEXT,
+                timestamp INTEGER NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS certificate_failures (
+                domain TEXT PRIMARY KEY,
+                failures INTEGER NOT NULL,
+                last_error TEXT NOT NULL,
+                rate_limited INTEGER NOT NULL,
+                last_failure_at INTEGER NOT NULL,
+                retry_at INTEGER NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS request_rates (
+                host TEXT PRIMARY KEY,
+                buckets TEXT NOT NULL,
+                last_request_at INTEGER,
+                updated_at INTEGER NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS request_counts (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                host TEXT NOT NULL,
+                requests INTEGER NOT NULL,
+                errors INTEGER NOT NULL,
+                duration_ms INTEGER NOT NULL,
+                timestamp INTEGER NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS request_counts_host_timestamp ON request_counts (host, timestamp)",
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS monitor_checks (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                host TEXT NOT NULL,
+                status INTEGER,
+                latency_ms INTEGER NOT NULL,
+                error TEXT,
+                asleep INTEGER NOT NULL DEFAULT 0,
+                timestamp INTEGER NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS monitor_checks_host_timestamp ON monitor_checks (host, timestamp)",
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS wait_decisions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                host TEXT NOT NULL,
+                wait_period_ms INTEGER NOT NULL,
+                short_rate REAL NOT NULL,
+                long_rate REAL NOT NULL,
+                adaptive INTEGER NOT NULL,
+                quiet_hours INTEGER NOT NULL,
+                timestamp INTEGER NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS wait_decisions_host_timestamp ON wait_decisions (host, timestamp)",
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS proxy_errors (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                host TEXT NOT NULL,
+                path TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                detail TEXT NOT NULL,
+                timestamp INTEGER NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS proxy_errors_timestamp ON proxy_errors (timestamp)",
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS acme_challenges (
+                token TEXT PRIMARY KEY,
+                key_authorization TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS cluster_activity (
+                host TEXT NOT NULL,
+                node TEXT NOT NULL,
+                last_request_at INTEGER,
+                seen_at INTEGER NOT NULL,
+                PRIMARY KEY (host, node)
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS leases (
+                name TEXT PRIMARY KEY,
+                holder TEXT NOT NULL,
+                expires_at INTEGER NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        Ok(Self {
+            pool,
+            run_clocks: Arc::default(),
+        })
     }
 
     /// Gets the stored ACME account private key PEM if it exists.
@@ -96,4 +346,328 @@ impl SqliteDatabase {
 
         Ok(())
     }
+
+    /// Gets the certificate PEM and encrypted private key for a domain.
+    pub async fn get_certificate(
+        &self,
+        domain: &str,
+    ) -> color_eyre::Result<Option<(String, Vec<u8>)>> {
+        let result: Option<(String, Vec<u8>)> =
+            sqlx::query_as(r#"SELECT cert_pem, encrypted_key FROM certificates WHERE domain = ?"#)
+                .bind(domain)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(result)
+    }
+
+    /// Saves the certificate PEM and encrypted private key for a domain.
+    pub async fn save_certificate(
+        &self,
+        domain: &str,
+        cert_pem: &str,
+        encrypted_key: &[u8],
+    ) -> color_eyre::Result<()> {
+        let now = jiff::Timestamp::now().as_millisecond();
+
+        sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO certificates (domain, cert_pem, encrypted_key, updated_at)
+            VALUES (?, ?, ?, ?)
+            "#,
+        )
+        .bind(domain)
+        .bind(cert_pem)
+        .bind(encrypted_key)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Deletes the stored certificate for a domain. Returns false if none existed.
+    pub async fn delete_certificate(&self, domain: &str) -> color_eyre::Result<bool> {
+        let result = sqlx::query(r#"DELETE FROM certificates WHERE domain = ?"#)
+            .bind(domain)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Lists the domains that have a stored certificate.
+    pub async fn list_certificate_domains(&self) -> color_eyre::Result<Vec<String>> {
+        let rows: Vec<(String,)> = sqlx::query_as(r#"SELECT domain FROM certificates"#)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().map(|(domain,)| domain).collect())
+    }
+
+    /// Gets the consecutive failure count and retry time (ms) recorded for a domain.
+    pub async fn get_certificate_failure(
+        &self,
+        domain: &str,
+    ) -> color_eyre::Result<Option<(u32, i64)>> {
+        let result: Option<(i64, i64)> = sqlx::query_as(
+            r#"SELECT failures, retry_at FROM certificate_failures WHERE domain = ?"#,
+        )
+        .bind(domain)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(result.map(|(failures, retry_at)| (failures as u32, retry_at)))
+    }
+
+    /// Records a failed issuance attempt and when it may next be retried.
+    pub async fn save_certificate_failure(
+        &self,
+        domain: &str,
+        failures: u32,
+        last_error: &str,
+        rate_limited: bool,
+        retry_at: i64,
+    ) -> color_eyre::Result<()> {
+        let now = jiff::Timestamp::now().as_millisecond();
+
+        sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO certificate_failures
+                (domain, failures, last_error, rate_limited, last_failure_at, retry_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(domain)
+        .bind(failures as i64)
+        .bind(last_error)
+        .bind(rate_limited)
+        .bind(now)
+        .bind(retry_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Clears the failure state for a domain after a successful issuance.
+    pub async fn clear_certificate_failure(&self, domain: &str) -> color_eyre::Result<()> {
+        sqlx::query(r#"DELETE FROM certificate_failures WHERE domain = ?"#)
+            .bind(domain)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Stores an HTTP-01 challenge, so any penny using this database can
+    /// answer it.
+    pub async fn save_acme_challenge(
+        &self,
+        token: &str,
+        key_authorization: &str,
+    ) -> color_eyre::Result<()> {
+        let now = jiff::Timestamp::now().as_millisecond();
+
+        sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO acme_challenges (token, key_authorization, created_at)
+            VALUES (?, ?, ?)
+            "#,
+        )
+        .bind(token)
+        .bind(key_authorization)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Gets the key authorization for a challenge stored after `since` (ms).
+    pub async fn get_acme_challenge(
+        &self,
+        token: &str,
+        since: i64,
+    ) -> color_eyre::Result<Option<String>> {
+        let result: Option<(String,)> = sqlx::query_as(
+            r#"SELECT key_authorization FROM acme_challenges WHERE token = ? AND created_at > ?"#,
+        )
+        .bind(token)
+        .bind(since)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(result.map(|(key_authorization,)| key_authorization))
+    }
+
+    /// Removes a challenge once its order is done.
+    pub async fn remove_acme_challenge(&self, token: &str) -> color_eyre::Result<()> {
+        sqlx::query(r#"DELETE FROM acme_challenges WHERE token = ?"#)
+            .bind(token)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Removes challenges stored before `before` (ms), left behind by
+    /// orders that never finished. Returns how many were removed.
+    pub async fn remove_stale_acme_challenges(&self, before: i64) -> color_eyre::Result<u64> {
+        let result = sqlx::query(r#"DELETE FROM acme_challenges WHERE created_at <= ?"#)
+            .bind(before)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Takes or extends the lease `name` for `holder` until `expires_at`
+    /// (ms). Fails if another holder's lease has not expired yet.
+    pub async fn acquire_lease(
+        &self,
+        name: &str,
+        holder: &str,
+        expires_at: i64,
+    ) -> color_eyre::Result<bool> {
+        let now = jiff::Timestamp::now().as_millisecond();
+
+        let result = sqlx::query(
+            r#"
+            INSERT INTO leases (name, holder, expires_at) VALUES (?, ?, ?)
+            ON CONFLICT (name) DO UPDATE SET
+                holder = excluded.holder,
+                expires_at = excluded.expires_at
+            WHERE leases.holder = excluded.holder OR leases.expires_at <= ?
+            "#,
+        )
+        .bind(name)
+        .bind(holder)
+        .bind(expires_at)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Gives up the lease `name` if `holder` still holds it.
+    pub async fn release_lease(&self, name: &str, holder: &str) -> color_eyre::Result<()> {
+        sqlx::query(r#"DELETE FROM leases WHERE name = ? AND holder = ?"#)
+            .bind(name)
+            .bind(holder)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Closes runs left open by a previous penny process that exited
+    /// without recording their stop, marking them `interrupted`. The real
+    /// stop time is unknown, so they end at their last log line, or where
+    /// they started if they logged nothing. Returns how many were closed.
+    /// Runs are not tied to a node, so this must not be called when nodes
+    /// of a cluster share the database.
+    pub async fn close_dangling_runs(&self) -> color_eyre::Result<u64> {
+        let result = sqlx::query(
+            r#"
+            UPDATE runs SET
+                interrupted = 1,
+                stopped_at = MAX(
+                    started_at,
+                    COALESCE((SELECT MAX(timestamp) FROM stdout WHERE stdout.run_id = runs.run_id), 0),
+                    COALESCE((SELECT MAX(timestamp) FROM stderr WHERE stderr.run_id = runs.run_id), 0)
+                )
+            WHERE stopped_at IS NULL
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Gets the persisted per-second request buckets (JSON) and last request
+    /// time (ms) for an app. Rows saved by older versions hold per-minute
+    /// buckets instead.
+    pub async fn get_request_rates(
+        &self,
+        host: &str,
+    ) -> color_eyre::Result<Option<(String, Option<i64>)>> {
+        let result: Option<(String, Option<i64>)> =
+            sqlx::query_as(r#"SELECT buckets, last_request_at FROM request_rates WHERE host = ?"#)
+                .bind(host)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(result)
+    }
+
+    /// Saves an app's per-second request buckets (JSON) and last request time.
+    pub async fn save_request_rates(
+        &self,
+        host: &str,
+        buckets: &str,
+        last_request_at: Option<i64>,
+    ) -> color_eyre::Result<()> {
+        let now = jiff::Timestamp::now().as_millisecond();
+
+        sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO request_rates (host, buckets, last_request_at, updated_at)
+            VALUES (?, ?, ?, ?)
+            "#,
+        )
+        .bind(host)
+        .bind(buckets)
+        .bind(last_request_at)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Bytes of the database file holding data. Pages freed by deleting
+    /// rows are reused before the file grows, so they are not counted.
+    pub async fn used_bytes(&self) -> color_eyre::Result<u64> {
+        let (used,): (i64,) = sqlx::query_as(
+            r#"
+            SELECT (page_count - freelist_count) * page_size
+            FROM pragma_page_count(), pragma_freelist_count(), pragma_page_size()
+            "#,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(used.max(0) as u64)
+    }
+
+    /// Deletes history older than `cutoff` (Unix ms) from every table in
+    /// [`PRUNABLE_TABLES`]. Returns how many rows were deleted.
+    pub async fn prune_older_than(&self, cutoff: i64) -> color_eyre::Result<u64> {
+        let mut deleted = 0;
+        for table in PRUNABLE_TABLES {
+            let result = sqlx::query(&format!("DELETE FROM {table} WHERE timestamp < ?"))
+                .bind(cutoff)
+                .execute(&self.pool)
+                .await?;
+            deleted += result.rows_affected();
+        }
+
+        Ok(deleted)
+    }
+
+    /// Timestamp of the oldest row in [`PRUNABLE_TABLES`], if any.
+    pub async fn oldest_prunable(&self) -> color_eyre::Result<Option<i64>> {
+        let union = PRUNABLE_TABLES
+            .map(|table| format!("SELECT MIN(timestamp) AS timestamp FROM {table}"))
+            .join(" UNION ALL ");
+        let (oldest,): (Option<i64>,) =
+            sqlx::query_as(&format!("SELECT MIN(timestamp) FROM ({union})"))
+                .fetch_one(&self.pool)
+                .await?;
+
+        Ok(oldest)
+    }
 }
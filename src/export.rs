@@ -0,0 +1,302 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+use color_eyre::eyre::{Context, bail, eyre};
+use sqlx::sqlite::SqliteConnectOptions;
+
+use crate::config::{Config, ConfigFormat};
+
+/// Names of the parts of an export inside the archive. The config comes
+/// first, so an import knows where the rest goes before unpacking it. It
+/// keeps its extension, which tells its format.
+const CONFIG_ENTRY: &str = "penny";
+const DATABASE_ENTRY: &str = "penny.db";
+const CERTS_ENTRY: &str = "certs";
+
+/// The file behind a `sqlite://` database URL.
+fn database_path(database_url: &str) -> color_eyre::Result<PathBuf> {
+    let options = SqliteConnectOptions::from_str(database_url)
+        .wrap_err_with(|| format!("invalid database_url '{database_url}'"))?;
+    Ok(options.get_filename().to_path_buf())
+}
+
+/// Copies the database to `snapshot` in one consistent read, so it can be
+/// exported while `penny serve` is writing to it.
+async fn snapshot_database(database: &Path, snapshot: &Path) -> color_eyre::Result<()> {
+    let options = SqliteConnectOptions::new()
+        .filename(database)
+        .read_only(true);
+    let pool = sqlx::SqlitePool::connect_with(options)
+        .await
+        .wrap_err_with(|| format!("failed to open database '{}'", database.display()))?;
+    sqlx::query("VACUUM INTO ?")
+        .bind(snapshot.to_string_lossy().into_owned())
+        .execute(&pool)
+        .await
+        .wrap_err("failed to snapshot the database")?;
+    pool.close().await;
+    Ok(())
+}
+
+/// Bundles the config file, the database (with the ACME account and any
+/// certificates stored in it) and `certs_dir` into a zstd-compressed tar
+/// archive at `output`.
+pub async fn export(config_path: &str, output: &Path) -> color_eyre::Result<()> {
+    let config = Config::load(config_path)?;
+    let database = database_path(&config.database_url)?;
+    let certs_dir = config.tls.as_ref().map(|tls| tls.certs_dir.clone());
+
+    let snapshot = std::env::temp_dir().join(format!("penny-export-{}.db", std::process::id()));
+    let _ = fs::remove_file(&snapshot);
+    let has_database = database.exists();
+    if has_database {
+        snapshot_database(&database, &snapshot).await?;
+    }
+
+    let written = write_archive(
+        config_path,
+        has_database.then_some(snapshot.as_path()),
+        certs_dir.as_deref().filter(|dir| dir.is_dir()),
+        output,
+    );
+    let _ = fs::remove_file(&snapshot);
+    written?;
+
+    println!("exported penny to {}", output.display());
+    if !has_database {
+        println!(
+            "no database at {}, exported the config only",
+            database.display()
+        );
+    }
+    Ok(())
+}
+
+fn write_archive(
+    config_path: &str,
+    database: Option<&Path>,
+    certs_dir: Option<&Path>,
+    output: &Path,
+) -> color_eyre::Result<()> {
+    let file = File::create(output)
+        .wrap_err_with(|| format!("failed to create '{}'", output.display()))?;
+    let mut archive = tar::Builder::new(zstd::Encoder::new(file, 0)?);
+
+    let mut config_entry = PathBuf::from(CONFIG_ENTRY);
+    if let Some(extension) = Path::new(config_path).extension() {
+        config_entry.set_extension(extension);
+    }
+    archive
+        .append_path_with_name(config_path, config_entry)
+        .wrap_err_with(|| format!("failed to add config file '{config_path}'"))?;
+    if let Some(database) = database {
+        archive
+            .append_path_with_name(database, DATABASE_ENTRY)
+            .wrap_err("failed to add the database")?;
+    }
+    if let Some(certs_dir) = certs_dir {
+        archive
+            .append_dir_all(CERTS_ENTRY, certs_dir)
+            .wrap_err_with(|| format!("failed to add certs_dir '{}'", certs_dir.display()))?;
+    }
+
+    archive
+        .into_inner()
+        .wrap_err("failed to write the archive")?
+        .finish()
+        .wrap_err("failed to write the archive")?;
+    Ok(())
+}
+
+/// Where an entry of the archive is unpacked to, or `None` for entries
+/// penny does not know.
+fn destination(entry: &Path, database: &Path, certs_dir: &Path) -> Option<PathBuf> {
+    if entry == Path::new(DATABASE_ENTRY) {
+        return Some(database.to_path_buf());
+    }
+    let inside = entry.strip_prefix(CERTS_ENTRY).ok()?;
+    // Never write outside certs_dir, whatever the archive says.
+    if !inside
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return None;
+    }
+    Some(certs_dir.join(inside))
+}
+
+/// Unpacks an archive made by [`export`]: the config goes to `config_path`
+/// (by default its exported name, e.g. `penny.toml`), the database and
+/// certificates to where that config expects them. Existing files are only
+/// replaced with `force`.
+pub fn import(archive: &Path, config_path: Option<&str>, force: bool) -> color_eyre::Result<()> {
+    let file =
+        File::open(archive).wrap_err_with(|| format!("failed to open '{}'", archive.display()))?;
+    let mut archive = tar::Archive::new(zstd::Decoder::new(file)?);
+    let mut entries = archive.entries().wrap_err("failed to read the archive")?;
+
+    let mut first = entries
+        .next()
+        .ok_or_else(|| eyre!("the archive is empty"))?
+        .wrap_err("failed to read the archive")?;
+    let exported = first.path()?.into_owned();
+    if exported.file_stem() != Some(CONFIG_ENTRY.as_ref())
+        || exported.parent() != Some(Path::new(""))
+    {
+        bail!("not a penny export: it does not start with a config file");
+    }
+    let config_path = match config_path {
+        Some(path) => path.to_owned(),
+        None => exported.to_string_lossy().into_owned(),
+    };
+    let config_path = config_path.as_str();
+    if ConfigFormat::of(config_path) != ConfigFormat::of(&exported) {
+        bail!(
+            "the exported config is {}; give a config path with the same extension",
+            exported.display()
+        );
+    }
+    let mut content = String::new();
+    first
+        .read_to_string(&mut content)
+        .wrap_err("failed to read the exported config")?;
+    let config = Config::parse(&content, ConfigFormat::of(&exported))
+        .wrap_err("the exported config is invalid")?;
+    let database = database_path(&config.database_url)?;
+    let certs_dir = config
+        .tls
+        .as_ref()
+        .map(|tls| tls.certs_dir.clone())
+        .unwrap_or_else(|| PathBuf::from(CERTS_ENTRY));
+
+    if !force {
+        for path in [Path::new(config_path), &database] {
+            if path.exists() {
+                bail!(
+                    "'{}' already exists; pass --force to replace it",
+                    path.display()
+                );
+            }
+        }
+    }
+
+    fs::write(config_path, &content)
+        .wrap_err_with(|| format!("failed to write config file '{config_path}'"))?;
+    println!("config: {config_path}");
+
+    for entry in entries {
+        let mut entry = entry.wrap_err("failed to read the archive")?;
+        let path = entry.path()?.into_owned();
+        let Some(target) = destination(&path, &database, &certs_dir) else {
+            println!("skipped unknown entry {}", path.display());
+            continue;
+        };
+
+        if entry.header().entry_type().is_dir() {
+            fs::create_dir_all(&target)
+                .wrap_err_with(|| format!("failed to create '{}'", target.display()))?;
+            continue;
+        }
+        if let Some(parent) = target
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)
+                .wrap_err_with(|| format!("failed to create '{}'", parent.display()))?;
+        }
+        if target == database {
+            // A journal left by the replaced database would be replayed
+            // into the imported one.
+            for suffix in ["-wal", "-shm"] {
+                let mut journal = database.clone().into_os_string();
+                journal.push(suffix);
+                let _ = fs::remove_file(journal);
+            }
+            println!("database: {}", target.display());
+        }
+        entry
+            .unpack(&target)
+            .wrap_err_with(|| format!("failed to write '{}'", target.display()))?;
+    }
+
+    println!("imported; start it with `penny serve {config_path}`");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::SqliteDatabase;
+
+    #[tokio::test]
+    async fn an_export_restores_config_database_and_certificates() {
+        let dir = std::env::temp_dir().join(format!("penny-export-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("certs")).unwrap();
+
+        let config_path = dir.join("penny.toml").to_string_lossy().into_owned();
+        let database = dir.join("penny.db");
+        fs::write(
+            &config_path,
+            format!(
+                "database_url = \"sqlite://{}\"\n\n[tls]\nenabled = true\nacme_email = \"admin@example.com\"\ncerts_dir = \"{}\"\n",
+                database.display(),
+                dir.join("certs").display()
+            ),
+        )
+        .unwrap();
+        let db = SqliteDatabase::new(&format!("sqlite://{}", database.display()))
+            .await
+            .unwrap();
+        db.save_acme_account("account key").await.unwrap();
+        db.pool.close().await;
+        fs::write(dir.join("certs/example.com.pem"), "certificate").unwrap();
+
+        let output = dir.join("state.tar.zst");
+        export(&config_path, &output).await.unwrap();
+
+        assert!(import(&output, Some(&config_path), false).is_err());
+        assert!(import(&output, Some("penny.json"), true).is_err());
+
+        fs::remove_file(&config_path).unwrap();
+        fs::remove_file(&database).unwrap();
+        fs::remove_dir_all(dir.join("certs")).unwrap();
+        import(&output, Some(&config_path), false).unwrap();
+
+        assert!(fs::read_to_string(&config_path).unwrap().contains("[tls]"));
+        assert_eq!(
+            fs::read_to_string(dir.join("certs/example.com.pem")).unwrap(),
+            "certificate"
+        );
+        let db = SqliteDatabase::new(&format!("sqlite://{}", database.display()))
+            .await
+            .unwrap();
+        assert_eq!(
+            db.get_acme_account().await.unwrap().as_deref(),
+            Some("account key")
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn entries_are_never_unpacked_outside_their_place() {
+        let database = Path::new("/var/lib/penny/penny.db");
+        let certs = Path::new("/var/lib/penny/certs");
+        assert_eq!(
+            destination(Path::new("penny.db"), database, certs).as_deref(),
+            Some(database)
+        );
+        assert_eq!(
+            destination(Path::new("certs/example.com/cert.pem"), database, certs),
+            Some(certs.join("example.com/cert.pem"))
+        );
+        assert_eq!(
+            destination(Path::new("certs/../../etc/passwd"), database, certs),
+            None
+        );
+        assert_eq!(destination(Path::new("etc/passwd"), database, certs), None);
+    }
+}
@@ -0,0 +1,709 @@
+use std::collections::BinaryHeap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::{RwLock, mpsc};
+use tracing::{debug, info, instrument, warn};
+
+use crate::collector::Collector;
+use crate::config::{App, SharedConfig};
+use crate::metrics;
+use crate::types::{Host, WaitDecision};
+
+/// Least time between two recorded wait decisions of an app.
+const DECISION_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How long the stop of an idle app still serving requests, like a
+/// download or a websocket, is put off before it is checked again.
+const IN_FLIGHT_RECHECK: Duration = Duration::from_secs(10);
+
+/// Reference point for deadlines, so they fit in an `AtomicU64`.
+static EPOCH: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+fn now_ms() -> u64 {
+    EPOCH.elapsed().as_millis() as u64
+}
+
+/// When an app should be stopped for being idle, in milliseconds since
+/// [`EPOCH`], or 0 when no stop is scheduled. Requests push it forward
+/// without taking the app's write lock.
+#[derive(Debug, Clone, Default)]
+pub struct IdleDeadline(Arc<AtomicU64>);
+
+#[derive(Debug, PartialEq, Eq)]
+enum Expiry {
+    Cancelled,
+    Later(u64),
+    Due,
+}
+
+impl IdleDeadline {
+    /// Moves the deadline to `at`. Returns true if no stop was scheduled,
+    /// meaning the scheduler does not know about this app yet.
+    fn set(&self, at: u64) -> bool {
+        self.0.swap(at.max(1), Ordering::AcqRel) == 0
+    }
+
+    /// Sets the deadline to `at` only if no stop is scheduled. Returns true
+    /// if it did.
+    fn set_if_unset(&self, at: u64) -> bool {
+        self.0
+            .compare_exchange(0, at.max(1), Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
+    /// Pushes the deadline out to `at`, unless it is later already. Returns
+    /// true if no stop was scheduled.
+    fn raise_to(&self, at: u64) -> bool {
+        self.0.fetch_max(at.max(1), Ordering::AcqRel) == 0
+    }
+
+    /// Moves a scheduled stop forward to `at`. Returns true if it did.
+    fn lower_to(&self, at: u64) -> bool {
+        let at = at.max(1);
+        let mut current = self.0.load(Ordering::Acquire);
+        loop {
+            if current == 0 || current <= at {
+                return false;
+            }
+            match self
+                .0
+                .compare_exchange(current, at, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => return true,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    /// Whether a stop is scheduled.
+    pub fn is_pending(&self) -> bool {
+        self.0.load(Ordering::Acquire) != 0
+    }
+
+    /// Cancels the pending stop. Returns true if one was scheduled, which
+    /// means the app was awake.
+    pub fn cancel(&self) -> bool {
+        self.0.swap(0, Ordering::AcqRel) != 0
+    }
+
+    /// Claims the stop if the deadline has passed by `now`.
+    fn expire(&self, now: u64) -> Expiry {
+        let mut at = self.0.load(Ordering::Acquire);
+        loop {
+            if at == 0 {
+                return Expiry::Cancelled;
+            }
+            if at > now {
+                return Expiry::Later(at);
+            }
+            match self
+                .0
+                .compare_exchange(at, 0, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => return Expiry::Due,
+                Err(current) => at = current,
+            }
+        }
+    }
+}
+
+/// Until when an app asked to be kept up with `X-Penny-Keep-Alive`, in
+/// milliseconds since [`EPOCH`], or 0. No request brings its stop before
+/// that.
+#[derive(Debug, Default)]
+pub struct KeepAlive(AtomicU64);
+
+impl KeepAlive {
+    /// Keeps the app up until at least `until`.
+    fn extend_to(&self, until: u64) {
+        self.0.fetch_max(until, Ordering::AcqRel);
+    }
+
+    /// Drops the request to be kept up, for `X-Penny-Sleep-Now`.
+    pub fn clear(&self) {
+        self.0.store(0, Ordering::Release);
+    }
+
+    /// When a stop `wait` from now may be due at the earliest.
+    fn stop_at(&self, wait: Duration) -> u64 {
+        (now_ms() + wait.as_millis() as u64).max(self.0.load(Ordering::Acquire))
+    }
+}
+
+/// The wait period last recorded for an app and when, so a decision is only
+/// recorded when it changes rather than on every request.
+#[derive(Debug, Default)]
+pub struct LastWaitDecision(Mutex<Option<(Instant, u64)>>);
+
+impl LastWaitDecision {
+    /// Whether a decision for `wait_period_ms` should be recorded at `now`:
+    /// the first one is, later ones once the wait period has changed by a
+    /// second or more and [`DECISION_INTERVAL`] has passed.
+    fn due(&self, wait_period_ms: u64, now: Instant) -> bool {
+        let mut last = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((at, recorded_ms)) = *last
+            && (recorded_ms.abs_diff(wait_period_ms) < 1_000
+                || now.duration_since(at) < DECISION_INTERVAL)
+        {
+            return false;
+        }
+        *last = Some((now, wait_period_ms));
+        true
+    }
+}
+
+/// An app waiting in the scheduler's queue.
+struct Pending {
+    at: u64,
+    host: Host,
+    app: Arc<RwLock<App>>,
+    deadline: IdleDeadline,
+}
+
+impl PartialEq for Pending {
+    fn eq(&self, other: &Self) -> bool {
+        self.at == other.at
+    }
+}
+
+impl Eq for Pending {}
+
+impl PartialOrd for Pending {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Pending {
+    // Reversed so the heap pops the earliest deadline first.
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        other.at.cmp(&self.at)
+    }
+}
+
+/// Stops apps once their wait period passes without requests. A single task
+/// owns a queue of deadlines; requests only update an app's
+/// [`IdleDeadline`], and the task re-queues apps whose deadline moved.
+#[derive(Debug, Clone)]
+pub struct IdleScheduler {
+    tx: mpsc::UnboundedSender<Pending>,
+    decisions: mpsc::UnboundedSender<(Host, WaitDecision)>,
+}
+
+impl IdleScheduler {
+    /// Spawns the scheduler task on the current runtime. `config` is used
+    /// to follow apps' `stop_after` sequences.
+    pub fn spawn(collector: impl Collector, config: SharedConfig) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (decisions, decisions_rx) = mpsc::unbounded_channel();
+        tokio::spawn(record_decisions(decisions_rx, collector.clone()));
+        tokio::spawn(run(rx, tx.downgrade(), collector, config));
+        Self { tx, decisions }
+    }
+
+    /// Records a request to the app and pushes its idle stop back by its
+    /// wait period.
+    #[instrument(skip(self, app))]
+    pub async fn schedule_stop(&self, host: &Host, app: &Arc<RwLock<App>>) {
+        let guard = metrics::read_app(app).await;
+
+        guard.request_tracker.record_request();
+        let decision = guard.wait_decision();
+        let wait_period = Duration::from_millis(decision.wait_period_ms);
+        let total_reqs = guard.request_tracker.total_recent_requests();
+        info!(
+            ?wait_period,
+            short_rate = format!("{:.2}", decision.short_rate),
+            long_rate = format!("{:.2}", decision.long_rate),
+            total_reqs,
+            adaptive = decision.adaptive,
+            "scheduling app shutdown"
+        );
+
+        self.set_deadline(host, app, &guard, wait_period);
+        if guard
+            .last_wait_decision
+            .due(decision.wait_period_ms, Instant::now())
+            && self.decisions.send((host.clone(), decision)).is_err()
+        {
+            debug!("idle scheduler has shut down");
+        }
+    }
+
+    /// Starts the idle timer without counting a request, for apps penny
+    /// finds already running.
+    pub async fn arm(&self, host: &Host, app: &Arc<RwLock<App>>) {
+        let guard = app.read().await;
+        let wait_period = guard.effective_wait_period();
+        self.set_deadline(host, app, &guard, wait_period);
+    }
+
+    /// Makes sure a stop is scheduled without pushing back a pending one,
+    /// for requests to an app's `ignore_paths`.
+    pub async fn ensure_scheduled(&self, host: &Host, app: &Arc<RwLock<App>>) {
+        let guard = app.read().await;
+        let at = guard.keep_alive.stop_at(guard.effective_wait_period());
+        if guard.idle_deadline.set_if_unset(at) {
+            self.enqueue(host, app, &guard.idle_deadline, at);
+        }
+    }
+
+    /// Brings a pending stop forward to `wait` from now, if it is due
+    /// later, but not before the app's keep-alive. Apps without one are
+    /// left alone.
+    pub async fn shorten(&self, host: &Host, app: &Arc<RwLock<App>>, wait: Duration) {
+        let guard = app.read().await;
+        let at = guard.keep_alive.stop_at(wait);
+        if guard.idle_deadline.lower_to(at) {
+            self.enqueue(host, app, &guard.idle_deadline, at);
+        }
+    }
+
+    /// Keeps the app up for at least `wait` from now, whatever its wait
+    /// period, for `X-Penny-Keep-Alive`.
+    pub async fn keep_alive(&self, host: &Host, app: &Arc<RwLock<App>>, wait: Duration) {
+        let guard = app.read().await;
+        let until = now_ms() + wait.as_millis() as u64;
+        guard.keep_alive.extend_to(until);
+        if guard.idle_deadline.raise_to(until) {
+            self.enqueue(host, app, &guard.idle_deadline, until);
+        }
+    }
+
+    /// Stops the app as soon as its requests in progress finish, when it
+    /// asks to with `X-Penny-Sleep-Now` or `POST /.penny/sleep`. Drops any
+    /// keep-alive it asked for before.
+    pub async fn sleep_now(&self, host: &Host, app: &Arc<RwLock<App>>) {
+        let guard = app.read().await;
+        guard.keep_alive.clear();
+        let at = now_ms();
+        if guard.idle_deadline.set_if_unset(at) || guard.idle_deadline.lower_to(at) {
+            self.enqueue(host, app, &guard.idle_deadline, at);
+        }
+    }
+
+    fn set_deadline(
+        &self,
+        host: &Host,
+        app: &Arc<RwLock<App>>,
+        guard: &App,
+        wait_period: Duration,
+    ) {
+        let at = guard.keep_alive.stop_at(wait_period);
+        if guard.idle_deadline.set(at) {
+            self.enqueue(host, app, &guard.idle_deadline, at);
+        }
+    }
+
+    fn enqueue(&self, host: &Host, app: &Arc<RwLock<App>>, deadline: &IdleDeadline, at: u64) {
+        let pending = Pending {
+            at,
+            host: host.clone(),
+            app: app.clone(),
+            deadline: deadline.clone(),
+        };
+        if self.tx.send(pending).is_err() {
+            debug!("idle scheduler has shut down");
+        }
+    }
+}
+
+/// How much longer an app should stay up for requests other `[cluster]`
+/// nodes sent it, if its wait period has not passed since the last one.
+async fn busy_elsewhere(
+    host: &Host,
+    app: &Arc<RwLock<App>>,
+    collector: &impl Collector,
+) -> Option<Duration> {
+    let guard = app.read().await;
+    let node = guard.cluster_node.clone()?;
+    let wait_period = guard.effective_wait_period();
+    drop(guard);
+
+    let last_request_at = match collector.last_request_elsewhere(&node, host).await {
+        Ok(last_request_at) => last_request_at?,
+        Err(e) => {
+            warn!(host = %host, error = %e, "failed to read the app's activity on other nodes");
+            return None;
+        }
+    };
+    let idle_for = jiff::Timestamp::now().as_millisecond() - last_request_at;
+    let remaining = wait_period.as_millis() as i64 - idle_for.max(0);
+    (remaining > 0).then(|| Duration::from_millis(remaining as u64))
+}
+
+/// How long to put off stopping an app whose wait period ran out: while it
+/// is still serving requests, or other nodes still send it some.
+async fn postponement(
+    host: &Host,
+    app: &Arc<RwLock<App>>,
+    collector: &impl Collector,
+) -> Option<Duration> {
+    let in_flight = app.read().await.in_flight.load(Ordering::Acquire);
+    if in_flight > 0 {
+        info!(host = %host, in_flight, "app is still serving requests, postponing stop");
+        return Some(IN_FLIGHT_RECHECK);
+    }
+    let wait = busy_elsewhere(host, app, collector).await?;
+    info!(host = %host, ?wait, "app still gets requests on another node, postponing stop");
+    Some(wait)
+}
+
+/// Stops an idle app, then its `stop_after` apps in order. An app that an
+/// awake app stops after itself is left running for that app to stop.
+async fn stop_idle(
+    config: &SharedConfig,
+    host: &Host,
+    app: &Arc<RwLock<App>>,
+    collector: impl Collector,
+) {
+    let stop_after = app.read().await.stop_after.clone();
+    // Taken out of the config first, so a reload does not wait on the
+    // apps' locks.
+    let others: Vec<_> = config
+        .read()
+        .await
+        .apps
+        .iter()
+        // Two apps that stop after each other must not wait on each other.
+        .filter(|(other_host, _)| {
+            **other_host != host.0 && !stop_after.iter().any(|step| step.host == **other_host)
+        })
+        .map(|(other_host, other)| (other_host.clone(), other.clone()))
+        .collect();
+    let mut leader = None;
+    for (other_host, other) in others {
+        let other = other.read().await;
+        if other.stop_after.iter().any(|step| step.host == host.0)
+            && (other.command.is_running() || other.idle_deadline.is_pending())
+        {
+            leader = Some(other_host);
+            break;
+        }
+    }
+    if let Some(leader) = leader {
+        info!(host = %host, %leader, "wait period elapsed, leaving stop to the app it is stopped after");
+        return;
+    }
+
+    let guard = app.write().await;
+    // A request since the wait period ran out schedules a new stop.
+    if guard.idle_deadline.is_pending() || guard.in_flight.load(Ordering::Acquire) > 0 {
+        info!(host = %host, "app got a request after its wait period, not stopping it");
+        return;
+    }
+    info!(host = %host, "wait period elapsed, stopping app");
+    metrics::IDLE_STOPS.add(1);
+    App::stop_locked(host, app, guard, collector.clone()).await;
+
+    for step in stop_after {
+        let Some(next) = config.read().await.apps.get(&step.host).cloned() else {
+            warn!(host = %host, next = %step.host, "stop_after target not found in config");
+            continue;
+        };
+        let mut guard = next.write().await;
+        let awake = guard.idle_deadline.cancel() || guard.command.is_child_running();
+        let timeout = step.timeout.unwrap_or(guard.stop_timeout).unsigned_abs();
+        drop(guard);
+        if !awake {
+            continue;
+        }
+
+        let next_host = Host(step.host);
+        info!(host = %next_host, after = %host, "stopping app after the one before it");
+        let stop = App::stop_app(&next_host, &next, collector.clone());
+        if tokio::time::timeout(timeout, stop).await.is_err() {
+            warn!(host = %next_host, ?timeout, "app did not stop in time, moving on");
+        }
+    }
+}
+
+/// Stores wait decisions as they come, off the request path.
+async fn record_decisions(
+    mut rx: mpsc::UnboundedReceiver<(Host, WaitDecision)>,
+    collector: impl Collector,
+) {
+    while let Some((host, decision)) = rx.recv().await {
+        if let Err(e) = collector.wait_decided(&host, &decision).await {
+            warn!(host = %host, error = %e, "failed to record wait decision");
+        }
+    }
+}
+
+async fn run(
+    mut rx: mpsc::UnboundedReceiver<Pending>,
+    tx: mpsc::WeakUnboundedSender<Pending>,
+    collector: impl Collector,
+    config: SharedConfig,
+) {
+    let mut queue = BinaryHeap::new();
+
+    loop {
+        metrics::IDLE_QUEUE_DEPTH.set(queue.len() as u64);
+        let next = queue.peek().map(|pending: &Pending| pending.at);
+        let wake_at = tokio::time::Instant::from_std(
+            *EPOCH + Duration::from_millis(next.unwrap_or_default()),
+        );
+
+        tokio::select! {
+            pending = rx.recv() => match pending {
+                Some(pending) => queue.push(pending),
+                None => return,
+            },
+            _ = tokio::time::sleep_until(wake_at), if next.is_some() => {
+                let now = now_ms();
+                while queue.peek().is_some_and(|pending| pending.at <= now) {
+                    let mut pending = queue.pop().expect("peeked above");
+                    match pending.deadline.expire(now) {
+                        Expiry::Cancelled => {}
+                        Expiry::Later(at) => {
+                            pending.at = at;
+                            queue.push(pending);
+                        }
+                        Expiry::Due => {
+                            metrics::IDLE_STOP_LAG
+                                .observe(Duration::from_millis(now.saturating_sub(pending.at)));
+                            let collector = collector.clone();
+                            let config = config.clone();
+                            let tx = tx.clone();
+                            tokio::spawn(async move {
+                                if let Some(wait) =
+                                    postponement(&pending.host, &pending.app, &collector).await
+                                {
+                                    metrics::IDLE_POSTPONEMENTS.add(1);
+                                    let at = now_ms() + wait.as_millis() as u64;
+                                    // A request here may have rescheduled it already.
+                                    if pending.deadline.set_if_unset(at)
+                                        && let Some(tx) = tx.upgrade()
+                                    {
+                                        pending.at = at;
+                                        let _ = tx.send(pending);
+                                    }
+                                    return;
+                                }
+                                stop_idle(&config, &pending.host, &pending.app, collector).await;
+                            });
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::WakeReason;
+
+    #[test]
+    fn wait_decisions_are_recorded_when_they_change() {
+        let last = LastWaitDecision::default();
+        let start = Instant::now();
+        let later = start + DECISION_INTERVAL;
+
+        assert!(last.due(60_000, start));
+        assert!(!last.due(60_000, later));
+        assert!(!last.due(90_000, start + Duration::from_secs(1)));
+        assert!(last.due(90_000, later));
+        assert!(!last.due(90_400, later + DECISION_INTERVAL));
+    }
+
+    #[test]
+    fn deadline_expires_only_once_due() {
+        let deadline = IdleDeadline::default();
+        assert_eq!(deadline.expire(100), Expiry::Cancelled);
+
+        assert!(deadline.set(200));
+        assert!(!deadline.set(300));
+        assert_eq!(deadline.expire(250), Expiry::Later(300));
+        assert_eq!(deadline.expire(300), Expiry::Due);
+        assert_eq!(deadline.expire(400), Expiry::Cancelled);
+    }
+
+    #[test]
+    fn set_if_unset_keeps_a_pending_stop() {
+        let deadline = IdleDeadline::default();
+        assert!(deadline.set_if_unset(200));
+        assert!(!deadline.set_if_unset(500));
+        assert_eq!(deadline.expire(300), Expiry::Due);
+    }
+
+    #[tokio::test]
+    async fn dependent_apps_stop_after_the_app_using_them() {
+        let toml_str = r#"
+            ["app.example.com"]
+            address = "127.0.0.1:1"
+            command = "sleep 30"
+            health_check = "/"
+            stop_after = [{ host = "db.example.com", timeout = "5s" }]
+
+            ["db.example.com"]
+            address = "127.0.0.1:1"
+            command = "sleep 30"
+            health_check = "/"
+        "#;
+        let config: SharedConfig = Arc::new(RwLock::new(toml::from_str(toml_str).unwrap()));
+        let db = crate::db::SqliteDatabase::new("sqlite::memory:")
+            .await
+            .unwrap();
+        let (app_host, db_host) = (
+            Host("app.example.com".to_owned()),
+            Host("db.example.com".to_owned()),
+        );
+        let (app, database) = {
+            let config = config.read().await;
+            (
+                config.apps["app.example.com"].clone(),
+                config.apps["db.example.com"].clone(),
+            )
+        };
+        App::begin_start_app(&app_host, &app, db.clone(), WakeReason::Browser)
+            .await
+            .unwrap();
+        App::begin_start_app(&db_host, &database, db.clone(), WakeReason::Prewarm)
+            .await
+            .unwrap();
+
+        stop_idle(&config, &db_host, &database, db.clone()).await;
+        assert!(database.write().await.command.is_child_running());
+
+        stop_idle(&config, &app_host, &app, db).await;
+        assert!(!app.write().await.command.is_child_running());
+        assert!(!database.write().await.command.is_child_running());
+    }
+
+    #[tokio::test]
+    async fn stops_wait_for_requests_in_flight() {
+        let config: crate::config::Config = toml::from_str(
+            r#"
+            ["app.example.com"]
+            address = "127.0.0.1:1"
+            command = "sleep 30"
+            health_check = "/"
+            "#,
+        )
+        .unwrap();
+        let db = crate::db::SqliteDatabase::new("sqlite::memory:")
+            .await
+            .unwrap();
+        let host = Host("app.example.com".to_owned());
+        let app = config.apps["app.example.com"].clone();
+        assert_eq!(postponement(&host, &app, &db).await, None);
+
+        let in_flight = app.read().await.in_flight.clone();
+        in_flight.fetch_add(1, Ordering::AcqRel);
+        assert_eq!(
+            postponement(&host, &app, &db).await,
+            Some(IN_FLIGHT_RECHECK)
+        );
+        in_flight.fetch_sub(1, Ordering::AcqRel);
+        assert_eq!(postponement(&host, &app, &db).await, None);
+    }
+
+    #[tokio::test]
+    async fn apps_that_got_a_request_after_their_wait_period_are_not_stopped() {
+        let config: SharedConfig = Arc::new(RwLock::new(
+            toml::from_str(
+                r#"
+                ["app.example.com"]
+                address = "127.0.0.1:1"
+                command = "sleep 30"
+                health_check = "/"
+                "#,
+            )
+            .unwrap(),
+        ));
+        let db = crate::db::SqliteDatabase::new("sqlite::memory:")
+            .await
+            .unwrap();
+        let host = Host("app.example.com".to_owned());
+        let app = config.read().await.apps["app.example.com"].clone();
+        App::begin_start_app(&host, &app, db.clone(), WakeReason::Browser)
+            .await
+            .unwrap();
+
+        app.read().await.idle_deadline.set(now_ms() + 60_000);
+        stop_idle(&config, &host, &app, db.clone()).await;
+        assert!(app.write().await.command.is_child_running());
+
+        app.read().await.idle_deadline.cancel();
+        let in_flight = app.read().await.in_flight.clone();
+        in_flight.fetch_add(1, Ordering::AcqRel);
+        stop_idle(&config, &host, &app, db.clone()).await;
+        assert!(app.write().await.command.is_child_running());
+
+        in_flight.fetch_sub(1, Ordering::AcqRel);
+        stop_idle(&config, &host, &app, db).await;
+        assert!(!app.write().await.command.is_child_running());
+    }
+
+    #[tokio::test]
+    async fn keep_alive_outlasts_shorter_wait_periods() {
+        let config: SharedConfig = Arc::new(RwLock::new(
+            toml::from_str(
+                r#"
+                ["app.example.com"]
+                address = "127.0.0.1:1"
+                command = "sleep 30"
+                health_check = "/"
+                wait_period = "1m"
+                "#,
+            )
+            .unwrap(),
+        ));
+        let db = crate::db::SqliteDatabase::new("sqlite::memory:")
+            .await
+            .unwrap();
+        let scheduler = IdleScheduler::spawn(db, config.clone());
+        let host = Host("app.example.com".to_owned());
+        let app = config.read().await.apps["app.example.com"].clone();
+        let deadline = || {
+            app.try_read()
+                .unwrap()
+                .idle_deadline
+                .0
+                .load(Ordering::Acquire)
+        };
+
+        scheduler.schedule_stop(&host, &app).await;
+        let one_minute = deadline();
+        scheduler
+            .keep_alive(&host, &app, Duration::from_secs(3600))
+            .await;
+        let one_hour = deadline();
+        assert!(one_hour >= one_minute + 3_540_000);
+
+        // Neither another request nor quiet hours bring the stop back.
+        scheduler.schedule_stop(&host, &app).await;
+        scheduler.shorten(&host, &app, Duration::ZERO).await;
+        assert!(deadline() >= one_hour);
+
+        app.read().await.keep_alive.clear();
+        scheduler.shorten(&host, &app, Duration::ZERO).await;
+        assert!(deadline() < one_minute);
+    }
+
+    #[test]
+    fn lower_to_only_moves_a_pending_stop_forward() {
+        let deadline = IdleDeadline::default();
+        assert!(!deadline.lower_to(100));
+
+        deadline.set(500);
+        assert!(!deadline.lower_to(600));
+        assert!(deadline.lower_to(200));
+        assert_eq!(deadline.expire(200), Expiry::Due);
+    }
+
+    #[test]
+    fn cancel_reports_whether_a_stop_was_pending() {
+        let deadline = IdleDeadline::default();
+        assert!(!deadline.cancel());
+
+        deadline.set(100);
+        assert!(deadline.cancel());
+        assert_eq!(deadline.expire(100), Expiry::Cancelled);
+    }
+}
@@ -0,0 +1,511 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use minijinja::{AutoEscape, Environment, Value, context};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// The built-in cold start page. Custom pages are templates too and get the
+/// same variables, see [`render`].
+const DEFAULT_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="{{ lang }}">
+<head>
+    <meta charset="utf-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <meta http-equiv="refresh" content="2">
+    <title>{{ title }}...{% if branding.name %} · {{ branding.name }}{% endif %}</title>
+    <style>
+        * { margin: 0; padding: 0; box-sizing: border-box; }
+        body {
+            font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", Roboto, sans-serif;
+            display: flex;
+            justify-content: center;
+            align-items: center;
+            min-height: 100vh;
+            background: #fafafa;
+            color: #0a0a0a;
+        }
+        @media (prefers-color-scheme: dark) {
+            body { background: #0a0a0a; color: #fafafa; }
+            .github-link { color: #a1a1a1; }
+            .github-link:hover { color: #f97316; }
+        }
+        .container { text-align: center; padding: 2rem; }
+        .logo {
+            animation: pulse 1s ease-in-out infinite;
+        }
+        @keyframes pulse {
+            0%, 100% { opacity: 1; transform: scale(1); }
+            50% { opacity: 0.6; transform: scale(0.95); }
+        }
+        h1 {
+            font-size: 1.25rem;
+            font-weight: 500;
+            margin-top: 1.5rem;
+            margin-bottom: 0.5rem;
+        }
+        .subtitle {
+            font-size: 0.875rem;
+            color: #888;
+            margin-bottom: 0.5rem;
+        }
+        .status {
+            font-size: 0.75rem;
+            color: #888;
+            margin-bottom: 0.25rem;
+        }
+        .last-check {
+            font-size: 0.75rem;
+            color: #888;
+        }
+        .output {
+            max-width: 40rem;
+            margin: 1rem auto 0;
+            font-size: 0.75rem;
+            color: #888;
+            text-align: left;
+            white-space: pre-wrap;
+            overflow-wrap: anywhere;
+        }
+        .github-link {
+            display: inline-block;
+            margin-top: 1.5rem;
+            font-size: 0.75rem;
+            color: #888;
+            text-decoration: none;
+        }
+        .github-link:hover { color: #f97316; }
+        {%- if branding.background_color %}
+        body { background: {{ branding.background_color }}; }
+        {%- endif %}
+        {%- if branding.text_color %}
+        body { color: {{ branding.text_color }}; }
+        {%- endif %}
+        {%- if branding.accent_color %}
+        .logo { color: {{ branding.accent_color }}; }
+        .github-link:hover { color: {{ branding.accent_color }}; }
+        {%- endif %}
+    </style>
+</head>
+<body>
+    <div class="container">
+        {% if branding.logo %}<div class="logo">{{ branding.logo }}</div>{% else %}
+        <svg class="logo" width="80" height="80" viewBox="0 0 100 100" fill="none"
+             xmlns="http://www.w3.org/2000/svg" role="img">
+            <title>Penny Logo</title>
+            <circle cx="50" cy="50" r="45" stroke="currentColor" stroke-width="6" fill="none" />
+            <circle cx="50" cy="50" r="35" stroke="currentColor" stroke-width="3" fill="none" />
+            <text x="50" y="58" text-anchor="middle" fill="currentColor"
+                  font-size="36" font-weight="bold" font-family="system-ui, sans-serif">P</text>
+        </svg>{% endif %}
+        <h1>{{ title }}</h1>
+        <p class="subtitle">{{ message }}</p>
+        <p class="status">{{ status }}</p>
+        {% if last_check %}<p class="last-check">{{ last_check }}</p>{% endif %}
+        {% if output %}<pre class="output">{% for line in output %}{{ line }}
+{% endfor %}</pre>{% endif %}
+        {% if branding.name %}<p class="github-link">{{ branding.name }}</p>{% else %}
+        <a class="github-link" href="https://github.com/frectonz/penny"
+           target="_blank" rel="noopener noreferrer">github.com/frectonz/penny</a>{% endif %}
+    </div>
+</body>
+</html>"#;
+
+/// Look of the built-in page, so it can match a product without a custom
+/// template. Each field left out of an app's `cold_start_branding` falls
+/// back to the global one.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct Branding {
+    /// Shown instead of penny's link, and in the page title.
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// SVG file shown instead of penny's logo.
+    #[serde(default)]
+    pub logo_path: Option<PathBuf>,
+
+    #[serde(skip)]
+    pub logo_svg: Option<String>,
+
+    /// CSS colors, e.g. `#2563eb`. Background and text colors replace both
+    /// the light and the dark scheme's.
+    #[serde(default, deserialize_with = "deserialize_color")]
+    pub background_color: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_color")]
+    pub text_color: Option<String>,
+    /// Colors the logo and the footer link on hover.
+    #[serde(default, deserialize_with = "deserialize_color")]
+    pub accent_color: Option<String>,
+}
+
+impl Branding {
+    /// This branding, with fields it leaves out taken from `fallback`.
+    pub fn or(&self, fallback: &Branding) -> Branding {
+        let logo = if self.logo_svg.is_some() {
+            self
+        } else {
+            fallback
+        };
+        Branding {
+            name: self.name.clone().or_else(|| fallback.name.clone()),
+            logo_path: logo.logo_path.clone(),
+            logo_svg: logo.logo_svg.clone(),
+            background_color: self
+                .background_color
+                .clone()
+                .or_else(|| fallback.background_color.clone()),
+            text_color: self
+                .text_color
+                .clone()
+                .or_else(|| fallback.text_color.clone()),
+            accent_color: self
+                .accent_color
+                .clone()
+                .or_else(|| fallback.accent_color.clone()),
+        }
+    }
+
+    /// Reads the logo from `logo_path`, if set.
+    pub fn load_logo(&mut self, used_for: &str) -> color_eyre::Result<()> {
+        let Some(path) = &self.logo_path else {
+            return Ok(());
+        };
+        self.logo_svg = Some(read_logo(path).map_err(|e| {
+            color_eyre::eyre::eyre!(
+                "failed to read cold start logo for {used_for} at {}: {e}",
+                path.display()
+            )
+        })?);
+        Ok(())
+    }
+
+    fn context(&self) -> Value {
+        context! {
+            name => self.name,
+            logo => self.logo_svg.clone().map(Value::from_safe_string),
+            background_color => self.background_color,
+            text_color => self.text_color,
+            accent_color => self.accent_color,
+        }
+    }
+}
+
+fn read_logo(path: &Path) -> Result<String, String> {
+    let svg = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    if !svg.contains("<svg") {
+        return Err("not an SVG image".to_owned());
+    }
+    Ok(svg)
+}
+
+/// Whether `color` is safe to put in the page's stylesheet: a hex color, a
+/// name, or a function like `rgb(37, 99, 235)`.
+fn is_css_color(color: &str) -> bool {
+    !color.is_empty()
+        && color
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "#(),.% -".contains(c))
+}
+
+fn deserialize_color<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error;
+
+    Option::<String>::deserialize(deserializer)?
+        .map(|color| {
+            let trimmed = color.trim();
+            if is_css_color(trimmed) {
+                Ok(trimmed.to_owned())
+            } else {
+                Err(D::Error::custom(format!("invalid color '{color}'")))
+            }
+        })
+        .transpose()
+}
+
+/// The built-in page's text in one language. `{host}`, `{secs}` and
+/// `{error}` are filled in before rendering.
+struct Messages {
+    title: &'static str,
+    unavailable: &'static str,
+    message: &'static str,
+    elapsed: &'static str,
+    timeout: &'static str,
+    eta: &'static str,
+    last_check: &'static str,
+}
+
+fn messages(lang: &str) -> Messages {
+    let primary = lang.split(['-', '_']).next().unwrap_or_default();
+    match primary.to_ascii_lowercase().as_str() {
+        "de" => Messages {
+            title: "{host} wird gestartet",
+            unavailable: "{host} ist nicht erreichbar",
+            message: "Diese Seite aktualisiert sich automatisch.",
+            elapsed: "Wartet seit {secs}s",
+            timeout: "Zeitlimit {secs}s",
+            eta: "meist in etwa {secs}s bereit",
+            last_check: "Letzte Prüfung: {error}",
+        },
+        "es" => Messages {
+            title: "Iniciando {host}",
+            unavailable: "{host} no está disponible",
+            message: "Esta página se actualizará automáticamente.",
+            elapsed: "Esperando desde hace {secs}s",
+            timeout: "límite {secs}s",
+            eta: "suele estar listo en unos {secs}s",
+            last_check: "Última comprobación: {error}",
+        },
+        "fr" => Messages {
+            title: "Démarrage de {host}",
+            unavailable: "{host} est indisponible",
+            message: "Cette page se rafraîchira automatiquement.",
+            elapsed: "En attente depuis {secs}s",
+            timeout: "délai {secs}s",
+            eta: "prêt d'habitude en {secs}s environ",
+            last_check: "Dernière vérification : {error}",
+        },
+        "it" => Messages {
+            title: "Avvio di {host}",
+            unavailable: "{host} non è disponibile",
+            message: "Questa pagina si aggiornerà automaticamente.",
+            elapsed: "In attesa da {secs}s",
+            timeout: "limite {secs}s",
+            eta: "di solito pronto in circa {secs}s",
+            last_check: "Ultimo controllo: {error}",
+        },
+        "pt" => Messages {
+            title: "Iniciando {host}",
+            unavailable: "{host} está indisponível",
+            message: "Esta página será atualizada automaticamente.",
+            elapsed: "Aguardando há {secs}s",
+            timeout: "limite {secs}s",
+            eta: "geralmente pronto em cerca de {secs}s",
+            last_check: "Última verificação: {error}",
+        },
+        _ => Messages {
+            title: "Starting {host}",
+            unavailable: "{host} is unavailable",
+            message: "This page will refresh automatically.",
+            elapsed: "Waiting for {secs}s",
+            timeout: "timeout {secs}s",
+            eta: "usually ready in about {secs}s",
+            last_check: "Last health check: {error}",
+        },
+    }
+}
+
+/// What an app's cold start page is rendered from.
+pub struct ColdStartPage {
+    /// Custom template, if any.
+    pub template: Option<String>,
+    pub lang: String,
+    pub branding: Branding,
+}
+
+/// What the cold start page knows about the start in progress.
+pub struct PageInfo<'a> {
+    pub host: &'a str,
+    pub lang: &'a str,
+    /// Time since the app was started.
+    pub elapsed: Duration,
+    /// How long penny waits for the app before giving up.
+    pub timeout: Duration,
+    /// Expected time left, from the app's previous cold start.
+    pub eta: Option<Duration>,
+    /// Health checks made so far.
+    pub checks: u32,
+    /// Why the latest health check failed.
+    pub last_error: Option<&'a str>,
+    /// The app's latest stdout lines, with `cold_start_output_lines`.
+    pub output: &'a [String],
+    pub branding: &'a Branding,
+}
+
+fn environment() -> Environment<'static> {
+    let mut env = Environment::new();
+    env.set_auto_escape_callback(|_| AutoEscape::Html);
+    env
+}
+
+/// Checks that a custom page parses as a template.
+pub fn validate(template: &str) -> Result<(), minijinja::Error> {
+    environment().template_from_str(template).map(|_| ())
+}
+
+/// Renders `template`, or the built-in page when there is none. Templates
+/// can use `host`, `lang`, `elapsed`, `timeout` and `eta` (whole seconds,
+/// `eta` may be none), `checks` and `last_error` (may be none), `output`
+/// (a list of lines, may be empty), the translated `title`, `message`,
+/// `status` and `last_check` lines, and `branding` (`name`, `logo`, `background_color`, `text_color` and
+/// `accent_color`, each may be none).
+pub fn render(template: Option<&str>, info: &PageInfo) -> String {
+    let text = messages(info.lang);
+    let elapsed = info.elapsed.as_secs();
+    let timeout = info.timeout.as_secs();
+    let eta = info.eta.map(|eta| eta.as_secs());
+
+    let mut status = text.elapsed.replace("{secs}", &elapsed.to_string());
+    status.push_str(" / ");
+    status.push_str(&text.timeout.replace("{secs}", &timeout.to_string()));
+    if let Some(eta) = eta {
+        status.push_str(", ");
+        status.push_str(&text.eta.replace("{secs}", &eta.to_string()));
+    }
+    let last_check = info
+        .last_error
+        .map(|error| text.last_check.replace("{error}", error));
+
+    let ctx = context! {
+        host => info.host,
+        lang => info.lang,
+        elapsed,
+        timeout,
+        eta,
+        checks => info.checks,
+        last_error => info.last_error,
+        output => info.output,
+        title => text.title.replace("{host}", info.host),
+        message => text.message,
+        status,
+        last_check,
+        branding => info.branding.context(),
+    };
+
+    let env = environment();
+    match env.render_str(template.unwrap_or(DEFAULT_TEMPLATE), &ctx) {
+        Ok(html) => html,
+        Err(e) => {
+            warn!(host = %info.host, error = %e, "failed to render cold start page, using the built-in one");
+            env.render_str(DEFAULT_TEMPLATE, ctx)
+                .expect("built-in cold start page renders")
+        }
+    }
+}
+
+/// Renders the built-in page for an app penny does not manage that is
+/// down, with why its health check failed.
+pub fn render_unavailable(host: &str, lang: &str, error: &str, branding: &Branding) -> String {
+    let text = messages(lang);
+    let ctx = context! {
+        host,
+        lang,
+        title => text.unavailable.replace("{host}", host),
+        message => text.message,
+        status => text.last_check.replace("{error}", error),
+        branding => branding.context(),
+    };
+    environment()
+        .render_str(DEFAULT_TEMPLATE, ctx)
+        .expect("built-in cold start page renders")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(lang: &str) -> PageInfo<'_> {
+        static BRANDING: Branding = Branding {
+            name: None,
+            logo_path: None,
+            logo_svg: None,
+            background_color: None,
+            text_color: None,
+            accent_color: None,
+        };
+        PageInfo {
+            host: "<b>app</b>.example.com",
+            lang,
+            elapsed: Duration::from_secs(3),
+            timeout: Duration::from_secs(30),
+            eta: Some(Duration::from_secs(5)),
+            checks: 2,
+            last_error: Some("could not connect"),
+            output: &[],
+            branding: &BRANDING,
+        }
+    }
+
+    #[test]
+    fn built_in_page_is_translated_and_escaped() {
+        let html = render(None, &info("de-AT"));
+        assert!(html.contains(r#"<html lang="de-AT">"#));
+        assert!(html.contains("&lt;b&gt;app&lt;&#x2f;b&gt;.example.com wird gestartet"));
+        assert!(html.contains("Wartet seit 3s / Zeitlimit 30s, meist in etwa 5s bereit"));
+        assert!(html.contains("Letzte Prüfung: could not connect"));
+
+        let html = render(None, &info("xx"));
+        assert!(html.contains("Waiting for 3s / timeout 30s, usually ready in about 5s"));
+        assert!(!html.contains(r#"class="output""#));
+
+        let output = ["Running migrations".to_owned(), "<applied> 3".to_owned()];
+        let html = render(
+            None,
+            &PageInfo {
+                output: &output,
+                ..info("en")
+            },
+        );
+        assert!(
+            html.contains("<pre class=\"output\">Running migrations\n&lt;applied&gt; 3\n</pre>")
+        );
+
+        let html = render_unavailable(
+            "app.example.com",
+            "fr",
+            "connection refused",
+            &Branding::default(),
+        );
+        assert!(html.contains("app.example.com est indisponible"));
+        assert!(html.contains("Dernière vérification : connection refused"));
+    }
+
+    #[test]
+    fn custom_template_gets_variables() {
+        let template = "{{ host }} {{ elapsed }}/{{ timeout }}/{% if eta is not none %}{{ eta }}{% endif %} {{ checks }}: {{ last_error }}";
+        assert_eq!(
+            render(Some(template), &info("en")),
+            "&lt;b&gt;app&lt;&#x2f;b&gt;.example.com 3/30/5 2: could not connect"
+        );
+
+        assert!(validate("{% if %}").is_err());
+        assert!(
+            render(Some("{{ host | no_such_filter }}"), &info("en")).contains("<!DOCTYPE html>")
+        );
+    }
+
+    #[test]
+    fn branding_replaces_penny_on_the_built_in_page() {
+        let global: Branding = toml::from_str(
+            r##"
+            name = "Acme"
+            accent_color = "#2563eb"
+            text_color = "#111"
+            "##,
+        )
+        .unwrap();
+        let mut app: Branding = toml::from_str(r##"text_color = "rgb(1, 2, 3)""##).unwrap();
+        app.logo_svg = Some("<svg><circle r=\"1\"/></svg>".to_owned());
+        app.logo_path = Some("logo.svg".into());
+        let branding = app.or(&global);
+
+        let html = render(
+            None,
+            &PageInfo {
+                branding: &branding,
+                ..info("en")
+            },
+        );
+        assert!(html.contains("· Acme</title>"));
+        assert!(html.contains(r#"<p class="github-link">Acme</p>"#));
+        assert!(!html.contains("github.com/frectonz/penny"));
+        assert!(html.contains(r#"<div class="logo"><svg><circle r="1"/></svg></div>"#));
+        assert!(html.contains("body { color: rgb(1, 2, 3); }"));
+        assert!(html.contains(".logo { color: #2563eb; }"));
+        assert!(!html.contains("Penny Logo"));
+
+        assert!(toml::from_str::<Branding>(r#"accent_color = "red; } body { x: y""#).is_err());
+    }
+}
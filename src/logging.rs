@@ -0,0 +1,73 @@
+use std::path::Path;
+
+use color_eyre::eyre::eyre;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+
+/// How penny's own log lines are written.
+#[derive(Debug, Clone, Copy, Default, clap::ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line, for Loki, Elasticsearch and the like.
+    Json,
+}
+
+/// How often `--log-file` starts a new file.
+#[derive(Debug, Clone, Copy, Default, clap::ValueEnum)]
+pub enum LogRotation {
+    Hourly,
+    #[default]
+    Daily,
+    Never,
+}
+
+impl From<LogRotation> for Rotation {
+    fn from(rotation: LogRotation) -> Self {
+        match rotation {
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Daily => Rotation::DAILY,
+            LogRotation::Never => Rotation::NEVER,
+        }
+    }
+}
+
+/// Installs the tracing subscriber, writing to stderr or to `file`. Rotated
+/// files get the period's date appended to their name. Keep the returned
+/// guard until exit so buffered lines are flushed.
+pub fn init(
+    format: LogFormat,
+    file: Option<&Path>,
+    rotation: LogRotation,
+) -> color_eyre::Result<Option<WorkerGuard>> {
+    let filter = std::env::var("RUST_LOG").unwrap_or_else(|_| "tracing=info,penny=info".to_owned());
+
+    let (writer, guard) = match file {
+        Some(path) => {
+            let name = path
+                .file_name()
+                .ok_or_else(|| eyre!("log file '{}' has no file name", path.display()))?;
+            let dir = path
+                .parent()
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .unwrap_or(Path::new("."));
+            let appender = RollingFileAppender::new(rotation.into(), dir, name);
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            (BoxMakeWriter::new(writer), Some(guard))
+        }
+        None => (BoxMakeWriter::new(std::io::stderr), None),
+    };
+
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_writer(writer);
+    match format {
+        LogFormat::Text => builder.with_ansi(file.is_none()).init(),
+        LogFormat::Json => builder.json().init(),
+    }
+    Ok(guard)
+}
@@ -1,28 +1,70 @@
 mod acme;
+mod activation;
 mod api;
 mod auth;
+mod capture;
+mod cert;
 mod challenge;
 mod check;
+mod client;
+mod client_ip;
+mod cluster;
 mod collector;
 mod config;
+mod control;
 mod db;
+mod export;
+mod idle;
+mod loading_page;
+mod logging;
+mod metrics;
+mod monitor;
+mod notify;
+mod on_demand;
+mod openrc;
+mod outbound;
+mod preview;
+mod privileges;
+mod procfs;
 mod proxy;
+mod quiet;
+mod rates;
+mod rcd;
+mod redact;
+mod reload;
 mod reporter;
+mod request_stats;
+mod service;
+mod storage;
 mod systemd;
 mod tls;
+mod top;
 mod types;
+mod usage;
+
+use std::sync::Arc;
 
 use clap::{Parser, Subcommand};
 use color_eyre::eyre::Context;
-use tracing::{error, info, warn};
+use tokio::sync::RwLock;
+use tracing::{debug, error, info, warn};
 
 use acme::AcmeClient;
 use api::{PaginationConfig, create_api_router};
-use challenge::{ChallengeStore, create_challenge_store};
-use config::{Config, TlsConfig};
+use challenge::{ChallengeStore, challenge_router, create_challenge_store};
+use collector::Collector;
+use config::{CertStrategy, Config, SharedConfig, TlsConfig};
 use db::SqliteDatabase;
+use idle::IdleScheduler;
+use notify::CertificateNotifier;
+use on_demand::OnDemandIssuer;
+use openrc::OpenRc;
 use proxy::YarpProxy;
+use rcd::RcD;
+use service::{ServeOpts, ServiceManager};
+use systemd::Systemd;
 use tls::{CertificateStore, DynamicCertificates};
+use types::Host;
 
 const DEFAULT_CONFIG: &str = "penny.toml";
 
@@ -36,7 +78,8 @@ fn resolve_config_path(config: Option<String>) -> color_eyre::Result<String> {
             } else {
                 Err(color_eyre::eyre::eyre!(
                     "no config file specified and '{}' not found in the current directory.\n\
-                     Provide a config path explicitly: penny <command> <path>",
+                     Provide a config path explicitly: penny <command> <path>, \
+                     or create one with `penny init`",
                     DEFAULT_CONFIG
                 ))
             }
@@ -44,27 +87,69 @@ fn resolve_config_path(config: Option<String>) -> color_eyre::Result<String> {
     }
 }
 
+/// Written by `penny init`, so penny can start without apps and have them
+/// added from the dashboard.
+const STARTER_CONFIG: &str = "\
+# Apps can be added here or from the dashboard.
+api_address = \"127.0.0.1:3031\"
+";
+
+/// Creates a config file with [`STARTER_CONFIG`], refusing to replace one
+/// that already exists.
+fn init_config(path: &str) -> color_eyre::Result<()> {
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .wrap_err_with(|| format!("failed to create '{path}'"))?;
+    std::io::Write::write_all(&mut file, STARTER_CONFIG.as_bytes())
+        .wrap_err_with(|| format!("failed to write '{path}'"))?;
+    println!("wrote {path} with the dashboard on 127.0.0.1:3031");
+    println!(
+        "start it with `penny serve {path} --password <PASSWORD>` and add apps from the dashboard"
+    );
+    Ok(())
+}
+
 #[derive(Debug, Parser)]
 #[command(version, about)]
 struct Args {
     #[clap(subcommand)]
     command: Command,
+
+    /// Format of penny's own logs.
+    #[arg(long, global = true, value_enum, default_value_t)]
+    log_format: logging::LogFormat,
+
+    /// Write logs to this file instead of stderr.
+    #[arg(long, global = true)]
+    log_file: Option<std::path::PathBuf>,
+
+    /// How often `--log-file` is rotated.
+    #[arg(long, global = true, value_enum, default_value_t)]
+    log_rotation: logging::LogRotation,
 }
 
 #[derive(Debug, Subcommand)]
 enum Command {
+    /// Create a config file with only the dashboard, to add apps from there.
+    Init {
+        /// Where to write the config file. [default: penny.toml]
+        config: Option<String>,
+    },
     /// Start the reverse proxy.
     Serve {
         /// Path to the config file. [default: penny.toml]
         config: Option<String>,
 
-        /// The HTTP address to bind to.
-        #[arg(short, long, default_value = "0.0.0.0:80")]
-        address: String,
+        /// The HTTP addresses to bind to. Repeat or comma-separate to listen
+        /// on several, e.g. `0.0.0.0:80,[::]:80`.
+        #[arg(short, long, default_value = "0.0.0.0:80", value_delimiter = ',')]
+        address: Vec<String>,
 
-        /// The HTTPS address to bind to.
-        #[arg(long, default_value = "0.0.0.0:443")]
-        https_address: String,
+        /// The HTTPS addresses to bind to, like `--address`.
+        #[arg(long, default_value = "0.0.0.0:443", value_delimiter = ',')]
+        https_address: Vec<String>,
 
         /// Disable TLS even if configured.
         #[arg(long)]
@@ -73,6 +158,18 @@ enum Command {
         /// Password for dashboard access (can also use PENNY_PASSWORD env var)
         #[arg(long, env = "PENNY_PASSWORD")]
         password: Option<String>,
+
+        /// Where to record the PID; also locked so only one instance runs. [default: $XDG_RUNTIME_DIR/penny.pid]
+        #[arg(long)]
+        pid_file: Option<std::path::PathBuf>,
+
+        /// Unix socket for `penny ctl`. [default: $XDG_RUNTIME_DIR/penny.sock, or /run/penny.sock as root]
+        #[arg(long)]
+        control_socket: Option<std::path::PathBuf>,
+
+        /// Reload the config file whenever it changes, as on SIGHUP.
+        #[arg(long)]
+        watch: bool,
     },
     /// Check app start/stop commands by running them.
     Check {
@@ -82,12 +179,256 @@ enum Command {
         /// Optional list of specific apps to check (by hostname).
         #[arg(long, value_delimiter = ',')]
         apps: Option<Vec<String>>,
+
+        /// Output format for the results.
+        #[arg(long, value_enum, default_value_t = check::CheckFormat::Text)]
+        format: check::CheckFormat,
+
+        /// Leave apps running after they pass the health check.
+        #[arg(long)]
+        keep_running: bool,
+
+        /// Health-check apps that are already running instead of failing.
+        #[arg(long)]
+        allow_running: bool,
+
+        /// Base URL of a running penny's API. Each app is put to sleep and
+        /// woken by a request through the live proxy instead of being run
+        /// locally.
+        #[arg(long)]
+        remote: Option<String>,
+
+        /// Dashboard password for --remote (can also use PENNY_PASSWORD env var)
+        #[arg(long, env = "PENNY_PASSWORD")]
+        password: Option<String>,
     },
     /// Manage penny as a systemd user service.
     Systemd {
         #[clap(subcommand)]
         action: SystemdAction,
     },
+    /// Manage penny as an OpenRC service (Alpine, Gentoo).
+    Openrc {
+        #[clap(subcommand)]
+        action: OpenrcAction,
+    },
+    /// Manage penny as a FreeBSD rc.d service.
+    Rc {
+        #[clap(subcommand)]
+        action: RcAction,
+    },
+    /// Manage TLS certificates.
+    Cert {
+        #[clap(subcommand)]
+        action: CertAction,
+    },
+    /// Bundle the config, database and certificates into one archive, to
+    /// move penny to another server.
+    Export {
+        /// Path to the config file. [default: penny.toml]
+        config: Option<String>,
+
+        /// Where to write the archive.
+        #[arg(short, long, default_value = "penny-state.tar.zst")]
+        output: std::path::PathBuf,
+    },
+    /// Restore an archive made by `penny export`.
+    Import {
+        /// The archive to restore.
+        archive: std::path::PathBuf,
+
+        /// Where to write the config file. The database and certificates go
+        /// where it says. [default: the exported name, e.g. penny.toml]
+        config: Option<String>,
+
+        /// Replace an existing config file and database.
+        #[arg(long)]
+        force: bool,
+    },
+    /// Tell the running `penny serve` to reload its config file.
+    Reload {
+        /// PID file written by `penny serve`. [default: $XDG_RUNTIME_DIR/penny.pid]
+        #[arg(long)]
+        pid_file: Option<std::path::PathBuf>,
+    },
+    /// Show a live terminal dashboard of app state.
+    Top {
+        /// Path to the config file, used to find `api_address`. [default: penny.toml]
+        config: Option<String>,
+
+        /// Base URL of the penny API, instead of reading it from the config.
+        #[arg(long)]
+        url: Option<String>,
+
+        /// Dashboard password (can also use PENNY_PASSWORD env var)
+        #[arg(long, env = "PENNY_PASSWORD")]
+        password: Option<String>,
+
+        /// Seconds between refreshes.
+        #[arg(short, long, default_value_t = 2)]
+        interval: u64,
+    },
+    /// List the hostname and path rules of the running `penny serve`, with
+    /// where each one goes and whether its app is awake.
+    Routes {
+        /// Path to the config file, used to find `api_address`. [default: penny.toml]
+        config: Option<String>,
+
+        /// Base URL of the penny API, instead of reading it from the config.
+        #[arg(long)]
+        url: Option<String>,
+
+        /// Dashboard password (can also use PENNY_PASSWORD env var)
+        #[arg(long, env = "PENNY_PASSWORD")]
+        password: Option<String>,
+    },
+    /// Start, stop, redeploy, or rolling-restart an app on the running `penny serve`.
+    App {
+        #[clap(subcommand)]
+        action: AppAction,
+    },
+    /// Administer the running `penny serve` over its control socket, without
+    /// the HTTP API.
+    Ctl {
+        #[clap(subcommand)]
+        action: CtlAction,
+
+        /// Control socket of `penny serve`. [default: $XDG_RUNTIME_DIR/penny.sock, or /run/penny.sock as root]
+        #[arg(long, global = true)]
+        socket: Option<std::path::PathBuf>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum CtlAction {
+    /// Show the state of every app.
+    Status,
+    /// Reload the config file.
+    Reload,
+    /// Wake an app now, as if it had received a request.
+    Start {
+        /// The app's hostname.
+        host: String,
+    },
+    /// Put an app to sleep now instead of waiting for its idle timeout.
+    Stop {
+        /// The app's hostname.
+        host: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum AppAction {
+    /// Wake an app now, as if it had received a request.
+    Start {
+        /// The app's hostname.
+        host: String,
+
+        /// Path to the config file, used to find `api_address`. [default: penny.toml]
+        config: Option<String>,
+
+        /// Base URL of the penny API, instead of reading it from the config.
+        #[arg(long)]
+        url: Option<String>,
+
+        /// Dashboard password (can also use PENNY_PASSWORD env var)
+        #[arg(long, env = "PENNY_PASSWORD")]
+        password: Option<String>,
+    },
+    /// Put an app to sleep now instead of waiting for its idle timeout.
+    Stop {
+        /// The app's hostname.
+        host: String,
+
+        /// Path to the config file, used to find `api_address`. [default: penny.toml]
+        config: Option<String>,
+
+        /// Base URL of the penny API, instead of reading it from the config.
+        #[arg(long)]
+        url: Option<String>,
+
+        /// Dashboard password (can also use PENNY_PASSWORD env var)
+        #[arg(long, env = "PENNY_PASSWORD")]
+        password: Option<String>,
+    },
+    /// Stop an app, run its redeploy_command, and start it again.
+    Redeploy {
+        /// The app's hostname.
+        host: String,
+
+        /// Path to the config file, used to find `api_address`. [default: penny.toml]
+        config: Option<String>,
+
+        /// Base URL of the penny API, instead of reading it from the config.
+        #[arg(long)]
+        url: Option<String>,
+
+        /// Dashboard password (can also use PENNY_PASSWORD env var)
+        #[arg(long, env = "PENNY_PASSWORD")]
+        password: Option<String>,
+    },
+    /// Start a new instance on a port from rolling_ports, switch to it once
+    /// healthy, and stop the old one.
+    RollingRestart {
+        /// The app's hostname.
+        host: String,
+
+        /// Path to the config file, used to find `api_address`. [default: penny.toml]
+        config: Option<String>,
+
+        /// Base URL of the penny API, instead of reading it from the config.
+        #[arg(long)]
+        url: Option<String>,
+
+        /// Dashboard password (can also use PENNY_PASSWORD env var)
+        #[arg(long, env = "PENNY_PASSWORD")]
+        password: Option<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum CertAction {
+    /// List certificates and their expiry dates.
+    List {
+        /// Path to the config file. [default: penny.toml]
+        config: Option<String>,
+    },
+    /// Obtain a new certificate for a domain now.
+    Renew {
+        /// The domain to renew.
+        domain: String,
+
+        /// Path to the config file. [default: penny.toml]
+        config: Option<String>,
+
+        /// The HTTP address to serve the ACME challenge on.
+        #[arg(short, long, default_value = "0.0.0.0:80")]
+        address: String,
+    },
+    /// Delete the stored certificate for a domain.
+    Delete {
+        /// The domain whose certificate to delete.
+        domain: String,
+
+        /// Path to the config file. [default: penny.toml]
+        config: Option<String>,
+    },
+    /// Import an existing certificate and private key for a domain.
+    Import {
+        /// The domain the certificate belongs to.
+        domain: String,
+
+        /// Path to the config file. [default: penny.toml]
+        config: Option<String>,
+
+        /// Path to the PEM certificate chain.
+        #[arg(long)]
+        cert: std::path::PathBuf,
+
+        /// Path to the PEM private key.
+        #[arg(long)]
+        key: std::path::PathBuf,
+    },
 }
 
 #[derive(Debug, Subcommand)]
@@ -97,13 +438,14 @@ enum SystemdAction {
         /// Path to the config file. [default: penny.toml]
         config: Option<String>,
 
-        /// The HTTP address to bind to.
-        #[arg(short, long, default_value = "0.0.0.0:80")]
-        address: String,
+        /// The HTTP addresses to bind to. Repeat or comma-separate to listen
+        /// on several, e.g. `0.0.0.0:80,[::]:80`.
+        #[arg(short, long, default_value = "0.0.0.0:80", value_delimiter = ',')]
+        address: Vec<String>,
 
-        /// The HTTPS address to bind to.
-        #[arg(long, default_value = "0.0.0.0:443")]
-        https_address: String,
+        /// The HTTPS addresses to bind to, like `--address`.
+        #[arg(long, default_value = "0.0.0.0:443", value_delimiter = ',')]
+        https_address: Vec<String>,
 
         /// Disable TLS even if configured.
         #[arg(long)]
@@ -116,6 +458,26 @@ enum SystemdAction {
         /// Install as a system-level service instead of a user service.
         #[arg(long)]
         system: bool,
+
+        /// Generate `.socket` units so systemd binds the listen addresses.
+        #[arg(long)]
+        socket_activation: bool,
+
+        /// Reference this EnvironmentFile instead of writing the password into the unit.
+        #[arg(long)]
+        env_file: Option<std::path::PathBuf>,
+
+        #[command(flatten)]
+        hardening: systemd::Hardening,
+    },
+    /// Generate a systemd unit for each configured app.
+    InstallApps {
+        /// Path to the config file. [default: penny.toml]
+        config: Option<String>,
+
+        /// Install system-level units instead of user units.
+        #[arg(long)]
+        system: bool,
     },
     /// Stop and remove the penny systemd service.
     Uninstall {
@@ -145,15 +507,113 @@ enum SystemdAction {
         #[arg(long)]
         system: bool,
     },
+    /// Reload the config of the penny systemd service without restarting it.
+    Reload {
+        /// Reload the system-level service instead of a user service.
+        #[arg(long)]
+        system: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum OpenrcAction {
+    /// Install and start the penny OpenRC service.
+    Install {
+        /// Path to the config file. [default: penny.toml]
+        config: Option<String>,
+
+        /// The HTTP addresses to bind to. Repeat or comma-separate to listen
+        /// on several, e.g. `0.0.0.0:80,[::]:80`.
+        #[arg(short, long, default_value = "0.0.0.0:80", value_delimiter = ',')]
+        address: Vec<String>,
+
+        /// The HTTPS addresses to bind to, like `--address`.
+        #[arg(long, default_value = "0.0.0.0:443", value_delimiter = ',')]
+        https_address: Vec<String>,
+
+        /// Disable TLS even if configured.
+        #[arg(long)]
+        no_tls: bool,
+
+        /// Password for dashboard access (can also use PENNY_PASSWORD env var)
+        #[arg(long, env = "PENNY_PASSWORD")]
+        password: Option<String>,
+    },
+    /// Stop and remove the penny OpenRC service.
+    Uninstall,
+    /// Show the status of the penny OpenRC service.
+    Status,
+    /// Show logs from the penny OpenRC service.
+    Logs {
+        /// Follow log output.
+        #[arg(short, long)]
+        follow: bool,
+    },
+    /// Restart the penny OpenRC service.
+    Restart,
+    /// Reload the config of the penny OpenRC service without restarting it.
+    Reload,
+}
+
+#[derive(Debug, Subcommand)]
+enum RcAction {
+    /// Install and start the penny rc.d service.
+    Install {
+        /// Path to the config file. [default: penny.toml]
+        config: Option<String>,
+
+        /// The HTTP addresses to bind to. Repeat or comma-separate to listen
+        /// on several, e.g. `0.0.0.0:80,[::]:80`.
+        #[arg(short, long, default_value = "0.0.0.0:80", value_delimiter = ',')]
+        address: Vec<String>,
+
+        /// The HTTPS addresses to bind to, like `--address`.
+        #[arg(long, default_value = "0.0.0.0:443", value_delimiter = ',')]
+        https_address: Vec<String>,
+
+        /// Disable TLS even if configured.
+        #[arg(long)]
+        no_tls: bool,
+
+        /// Password for dashboard access (can also use PENNY_PASSWORD env var)
+        #[arg(long, env = "PENNY_PASSWORD")]
+        password: Option<String>,
+    },
+    /// Stop and remove the penny rc.d service.
+    Uninstall,
+    /// Show the status of the penny rc.d service.
+    Status,
+    /// Show logs from the penny rc.d service.
+    Logs {
+        /// Follow log output.
+        #[arg(short, long)]
+        follow: bool,
+    },
+    /// Restart the penny rc.d service.
+    Restart,
+    /// Reload the config of the penny rc.d service without restarting it.
+    Reload,
 }
 
 async fn setup_api_server(
     api_address: Option<std::net::SocketAddr>,
     collector: SqliteDatabase,
     pagination_config: PaginationConfig,
+    config: SharedConfig,
+    idle: IdleScheduler,
+    config_path: &str,
+    certificates: Option<CertificateStore>,
 ) -> color_eyre::Result<()> {
     if let Some(api_address) = api_address {
-        let router = create_api_router(collector, pagination_config);
+        let config_file = reload::ConfigFile::new(config_path.to_owned(), collector.clone());
+        let router = create_api_router(
+            collector,
+            pagination_config,
+            config,
+            idle,
+            config_file,
+            certificates,
+        );
         let listener = tokio::net::TcpListener::bind(api_address)
             .await
             .context("failed to bind API server address")?;
@@ -167,6 +627,25 @@ async fn setup_api_server(
     Ok(())
 }
 
+async fn setup_challenge_server(
+    tls_config: &TlsConfig,
+    challenge_store: ChallengeStore,
+) -> color_eyre::Result<()> {
+    if let Some(address) = tls_config.challenge_address {
+        let router = challenge_router(challenge_store, &tls_config.challenge_path);
+        let listener = tokio::net::TcpListener::bind(address)
+            .await
+            .context("failed to bind ACME challenge address")?;
+        info!(address = %address, path = %tls_config.challenge_path, "ACME challenge server listening");
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, router).await {
+                error!("ACME challenge server error: {e}");
+            }
+        });
+    }
+    Ok(())
+}
+
 fn setup_tls(
     domains: Vec<String>,
     collector: SqliteDatabase,
@@ -178,34 +657,160 @@ fn setup_tls(
         return;
     }
 
+    let notifier = CertificateNotifier::new(collector.clone(), tls_config.notify_webhook.clone());
+
     tokio::spawn(async move {
-        if let Err(e) =
-            provision_certificates(&domains, &collector, &challenge_store, &tls_config).await
+        if let Err(e) = provision_certificates(
+            &domains,
+            &collector,
+            &challenge_store,
+            &tls_config,
+            &notifier,
+        )
+        .await
         {
             error!(error = %e, "initial certificate provisioning failed");
         }
 
-        renewal_loop(domains, collector, challenge_store, tls_config).await;
+        renewal_loop(domains, collector, challenge_store, tls_config, notifier).await;
+    });
+}
+
+/// Finds apps that are already up when penny starts, so they are tracked
+/// and put to sleep like apps penny started itself. Probes run in parallel.
+fn probe_initial_state(config: &SharedConfig, collector: SqliteDatabase, idle: IdleScheduler) {
+    let config = config.clone();
+    tokio::spawn(async move {
+        let mut probes = tokio::task::JoinSet::new();
+        for (host, app) in &config.read().await.apps {
+            let host = Host(host.clone());
+            let app = app.clone();
+            let collector = collector.clone();
+            let idle = idle.clone();
+            probes.spawn(async move {
+                let mut guard = app.write().await;
+                // Apps penny does not manage are watched separately.
+                if !guard.managed {
+                    return false;
+                }
+                // A request may have started it already.
+                if guard.command.is_child_running() || !guard.is_running_cached().await {
+                    return false;
+                }
+                guard.confirmed_healthy = true;
+                // Without an end command or unit there is no way to put it
+                // to sleep, so leave it be.
+                if !guard.command.can_stop_untracked() {
+                    info!(host = %host, "app already running");
+                    return true;
+                }
+                drop(guard);
+
+                info!(host = %host, "app already running, starting its idle timer");
+                if let Err(e) = collector.app_started(&host).await {
+                    error!(host = %host, "failed to record app start: {e}");
+                }
+                idle.arm(&host, &app).await;
+                true
+            });
+        }
+
+        let mut running = 0;
+        while let Some(result) = probes.join_next().await {
+            if result.unwrap_or(false) {
+                running += 1;
+            }
+        }
+        info!(running, "probed app states");
+    });
+}
+
+/// How often apps with `managed = false` are health-checked between requests.
+const UNMANAGED_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Health-checks apps penny does not manage on an interval, so their uptime
+/// is recorded without waiting for requests.
+fn watch_unmanaged_apps(config: &SharedConfig, collector: SqliteDatabase) {
+    let config = config.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(UNMANAGED_CHECK_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+            let apps: Vec<_> = config
+                .read()
+                .await
+                .apps
+                .iter()
+                .map(|(host, app)| (Host(host.clone()), app.clone()))
+                .collect();
+            for (host, app) in apps {
+                if !app.read().await.managed {
+                    let _ =
+                        crate::config::App::check_unmanaged(&host, &app, collector.clone()).await;
+                }
+            }
+        }
     });
 }
 
 async fn setup(
-    config: &Config,
+    shared_config: &SharedConfig,
+    config_path: &str,
     no_tls: bool,
-) -> color_eyre::Result<(SqliteDatabase, ChallengeStore)> {
+) -> color_eyre::Result<(SqliteDatabase, ChallengeStore, IdleScheduler)> {
+    let config = shared_config.read().await;
     let collector = SqliteDatabase::new(&config.database_url).await?;
-    let pagination_config = PaginationConfig {
-        default_limit: config.default_page_limit,
-        max_limit: config.max_page_limit,
-    };
-    setup_api_server(config.api_address, collector.clone(), pagination_config).await?;
-    let challenge_store = create_challenge_store();
+    // Other nodes of a cluster share the database, and their open runs are
+    // still running.
+    if config.cluster.is_none() {
+        match collector.close_dangling_runs().await {
+            Ok(0) => {}
+            Ok(closed) => warn!(closed, "closed runs left open by a previous penny process"),
+            Err(e) => error!(error = %e, "failed to close dangling runs"),
+        }
+    }
+    let idle = IdleScheduler::spawn(collector.clone(), shared_config.clone());
+    let pagination_config = PaginationConfig::from(&*config);
+    let certificates = config
+        .tls
+        .as_ref()
+        .filter(|tls_config| tls_config.enabled && !no_tls)
+        .and_then(
+            |tls_config| match CertificateStore::from_config(tls_config, &collector) {
+                Ok(store) => Some(store),
+                Err(e) => {
+                    warn!(error = %e, "failed to open certificate store for the API");
+                    None
+                }
+            },
+        );
+    setup_api_server(
+        config.api_address,
+        collector.clone(),
+        pagination_config,
+        shared_config.clone(),
+        idle.clone(),
+        config_path,
+        certificates,
+    )
+    .await?;
+    let challenge_store = create_challenge_store(
+        config
+            .tls
+            .as_ref()
+            .is_some_and(TlsConfig::persists_challenges)
+            .then(|| collector.clone()),
+    );
 
     if let Some(tls_config) = &config.tls
         && tls_config.enabled
         && !no_tls
     {
-        let domains = config.tls_domains();
+        setup_challenge_server(tls_config, challenge_store.clone()).await?;
+        challenge::spawn_cleanup(challenge_store.clone());
+        let domains = config.tls_domains().await;
         setup_tls(
             domains,
             collector.clone(),
@@ -213,31 +818,164 @@ async fn setup(
             tls_config.clone(),
         );
     }
+    drop(config);
+
+    rates::restore_all(&collector, shared_config).await;
+    rates::spawn_persist_loop(collector.clone(), shared_config.clone());
+    probe_initial_state(shared_config, collector.clone(), idle.clone());
+    watch_unmanaged_apps(shared_config, collector.clone());
+    quiet::spawn_watcher(shared_config.clone(), idle.clone());
+    monitor::spawn(shared_config.clone(), collector.clone());
+    usage::spawn(shared_config.clone(), collector.clone());
+    request_stats::spawn(shared_config.clone(), collector.clone());
+    storage::spawn(collector.clone(), shared_config.clone());
+    cluster::spawn(shared_config.clone(), collector.clone());
 
-    Ok((collector, challenge_store))
+    Ok((collector, challenge_store, idle))
 }
 
 fn main() -> color_eyre::Result<()> {
     color_eyre::install()?;
 
-    let filter = std::env::var("RUST_LOG").unwrap_or_else(|_| "tracing=info,penny=info".to_owned());
-    tracing_subscriber::fmt()
-        .with_env_filter(filter)
-        .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
-        .init();
-
     let args = Args::parse();
+    let _log_guard = logging::init(args.log_format, args.log_file.as_deref(), args.log_rotation)?;
 
     match args.command {
-        Command::Check { config, apps } => {
+        Command::Check {
+            config,
+            apps,
+            format,
+            keep_running,
+            allow_running,
+            remote,
+            password,
+        } => {
             let config = resolve_config_path(config)?;
-            let config_content = std::fs::read_to_string(&config).context("reading config file")?;
-            let mut config: Config = toml::from_str(&config_content)?;
+            let mut config = Config::load(&config)?;
             config.load_cold_start_pages()?;
             let runtime = tokio::runtime::Runtime::new().context("creating tokio runtime")?;
-            runtime.block_on(check::run_check(&config, apps))?;
+            let options = check::CheckOptions {
+                format,
+                keep_running,
+                allow_running,
+            };
+            let remote = remote.map(|url| client::ApiClient::new(url, password));
+            runtime.block_on(check::run_check(&config, apps, options, remote.as_ref()))?;
             Ok(())
         }
+        Command::Init { config } => init_config(config.as_deref().unwrap_or(DEFAULT_CONFIG)),
+        Command::Export { config, output } => {
+            let runtime = tokio::runtime::Runtime::new().context("creating tokio runtime")?;
+            runtime.block_on(export::export(&resolve_config_path(config)?, &output))
+        }
+        Command::Import {
+            archive,
+            config,
+            force,
+        } => export::import(&archive, config.as_deref(), force),
+        Command::Reload { pid_file } => reload::send_reload(pid_file),
+        Command::Ctl { action, socket } => {
+            let socket = socket.unwrap_or_else(control::socket_path);
+            match action {
+                CtlAction::Status => control::status(&socket),
+                CtlAction::Reload => control::reload(&socket),
+                CtlAction::Start { host } => control::app_action(&socket, &host, "start"),
+                CtlAction::Stop { host } => control::app_action(&socket, &host, "stop"),
+            }
+        }
+        Command::Top {
+            config,
+            url,
+            password,
+            interval,
+        } => {
+            let url = match url {
+                Some(url) => url,
+                None => client::api_url(&Config::load(&resolve_config_path(config)?)?)?,
+            };
+            let runtime = tokio::runtime::Runtime::new().context("creating tokio runtime")?;
+            runtime.block_on(top::run(
+                url,
+                password,
+                std::time::Duration::from_secs(interval.max(1)),
+            ))
+        }
+        Command::Routes {
+            config,
+            url,
+            password,
+        } => {
+            let url = match url {
+                Some(url) => url,
+                None => client::api_url(&Config::load(&resolve_config_path(config)?)?)?,
+            };
+            let runtime = tokio::runtime::Runtime::new().context("creating tokio runtime")?;
+            runtime.block_on(client::routes(url, password))
+        }
+        Command::App { action } => {
+            let (host, config, url, password, action) = match action {
+                AppAction::Start {
+                    host,
+                    config,
+                    url,
+                    password,
+                } => (host, config, url, password, "start"),
+                AppAction::Stop {
+                    host,
+                    config,
+                    url,
+                    password,
+                } => (host, config, url, password, "stop"),
+                AppAction::Redeploy {
+                    host,
+                    config,
+                    url,
+                    password,
+                } => (host, config, url, password, "redeploy"),
+                AppAction::RollingRestart {
+                    host,
+                    config,
+                    url,
+                    password,
+                } => (host, config, url, password, "rolling-restart"),
+            };
+            let url = match url {
+                Some(url) => url,
+                None => client::api_url(&Config::load(&resolve_config_path(config)?)?)?,
+            };
+            let runtime = tokio::runtime::Runtime::new().context("creating tokio runtime")?;
+            runtime.block_on(client::app_action(url, password, &host, action))
+        }
+        Command::Cert { action } => {
+            let runtime = tokio::runtime::Runtime::new().context("creating tokio runtime")?;
+            match action {
+                CertAction::List { config } => {
+                    let config = Config::load(&resolve_config_path(config)?)?;
+                    runtime.block_on(cert::list(&config))
+                }
+                CertAction::Renew {
+                    domain,
+                    config,
+                    address,
+                } => {
+                    let config = Config::load(&resolve_config_path(config)?)?;
+                    runtime.block_on(cert::renew(&config, &domain, &address))
+                }
+                CertAction::Delete { domain, config } => {
+                    let config = Config::load(&resolve_config_path(config)?)?;
+                    runtime.block_on(cert::delete(&config, &domain))
+                }
+                CertAction::Import {
+                    domain,
+                    config,
+                    cert,
+                    key,
+                } => {
+                    let config = Config::load(&resolve_config_path(config)?)?;
+                    runtime.block_on(cert::import(&config, &domain, &cert, &key))
+                }
+            }
+        }
         Command::Systemd { action } => match action {
             SystemdAction::Install {
                 config,
@@ -246,21 +984,80 @@ fn main() -> color_eyre::Result<()> {
                 no_tls,
                 password,
                 system,
+                socket_activation,
+                env_file,
+                hardening,
             } => {
                 let config = resolve_config_path(config)?;
-                systemd::install(systemd::InstallOpts {
+                let env_file = env_file.map(std::path::absolute).transpose()?;
+                let manager = Systemd {
+                    system,
+                    socket_activation,
+                    env_file,
+                    hardening,
+                };
+                manager.install(&ServeOpts {
                     config,
                     address,
                     https_address,
                     no_tls,
                     password,
-                    system,
                 })
             }
-            SystemdAction::Uninstall { system } => systemd::uninstall(system),
-            SystemdAction::Status { system } => systemd::status(system),
-            SystemdAction::Logs { follow, system } => systemd::logs(follow, system),
-            SystemdAction::Restart { system } => systemd::restart(system),
+            SystemdAction::InstallApps { config, system } => {
+                Systemd::new(system).install_apps(&resolve_config_path(config)?)
+            }
+            SystemdAction::Uninstall { system } => Systemd::new(system).uninstall(),
+            SystemdAction::Status { system } => Systemd::new(system).status(),
+            SystemdAction::Logs { follow, system } => Systemd::new(system).logs(follow),
+            SystemdAction::Restart { system } => Systemd::new(system).restart(),
+            SystemdAction::Reload { system } => Systemd::new(system).reload(),
+        },
+        Command::Openrc { action } => match action {
+            OpenrcAction::Install {
+                config,
+                address,
+                https_address,
+                no_tls,
+                password,
+            } => {
+                let config = resolve_config_path(config)?;
+                OpenRc.install(&ServeOpts {
+                    config,
+                    address,
+                    https_address,
+                    no_tls,
+                    password,
+                })
+            }
+            OpenrcAction::Uninstall => OpenRc.uninstall(),
+            OpenrcAction::Status => OpenRc.status(),
+            OpenrcAction::Logs { follow } => OpenRc.logs(follow),
+            OpenrcAction::Restart => OpenRc.restart(),
+            OpenrcAction::Reload => OpenRc.reload(),
+        },
+        Command::Rc { action } => match action {
+            RcAction::Install {
+                config,
+                address,
+                https_address,
+                no_tls,
+                password,
+            } => {
+                let config = resolve_config_path(config)?;
+                RcD.install(&ServeOpts {
+                    config,
+                    address,
+                    https_address,
+                    no_tls,
+                    password,
+                })
+            }
+            RcAction::Uninstall => RcD.uninstall(),
+            RcAction::Status => RcD.status(),
+            RcAction::Logs { follow } => RcD.logs(follow),
+            RcAction::Restart => RcD.restart(),
+            RcAction::Reload => RcD.reload(),
         },
         Command::Serve {
             config,
@@ -268,22 +1065,35 @@ fn main() -> color_eyre::Result<()> {
             https_address,
             no_tls,
             password,
+            pid_file,
+            control_socket,
+            watch,
         } => {
-            let config = resolve_config_path(config)?;
+            let activated = activation::take_listen_fds()?;
+            let config_path = resolve_config_path(config)?;
+            let pid_file = pid_file.unwrap_or_else(reload::pid_file_path);
+            let _pid_lock = reload::acquire_pid_file(&pid_file)?;
+            let control_listener =
+                control::bind(&control_socket.unwrap_or_else(control::socket_path))?;
             auth::init_password(password.clone())?;
             info!(
-                config = %config,
-                address = %address,
-                https_address = %https_address,
+                config = %config_path,
+                address = %address.join(","),
+                https_address = %https_address.join(","),
                 auth_enabled = password.is_some(),
                 "starting penny proxy"
             );
 
-            let config_content = std::fs::read_to_string(&config)?;
-            let mut config: Config = toml::from_str(&config_content)?;
+            let mut config = Config::load(&config_path)?;
             config.load_cold_start_pages()?;
 
             info!(apps_count = config.apps.len(), "loaded configuration");
+            if config.apps.is_empty() {
+                warn!(
+                    config = %config_path,
+                    "no apps configured yet; add them to the config file or from the dashboard"
+                );
+            }
             for (host, app) in &config.apps {
                 let app = app.blocking_read();
                 info!(
@@ -302,69 +1112,300 @@ fn main() -> color_eyre::Result<()> {
                 }
             }
 
+            if config.api_path {
+                if config.api_address.is_none() {
+                    warn!("api_path is set but api_address is not configured");
+                } else if password.is_none() {
+                    warn!(
+                        "api_path is set but no --password is configured, not serving the dashboard on app hosts"
+                    );
+                }
+            }
+
+            let tls_enabled = config.tls.as_ref().is_some_and(|t| t.enabled) && !no_tls;
+            // Listeners handed to pingora rather than bound by it: systemd
+            // sockets, or sockets bound before dropping root.
+            let mut http_fds: Vec<_> = activation::find_all(&activated, "http", &address)
+                .into_iter()
+                .map(|s| s.map(|s| s.fd))
+                .collect();
+            let mut https_fds: Vec<_> = activation::find_all(&activated, "https", &https_address)
+                .into_iter()
+                .map(|s| s.map(|s| s.fd))
+                .collect();
+            if !tls_enabled {
+                https_fds.clear();
+            }
+            let unbound = address
+                .iter()
+                .zip(&mut http_fds)
+                .chain(https_address.iter().zip(&mut https_fds))
+                .filter(|(_, fd)| fd.is_none());
+            if let Some(user) = &config.user {
+                for (address, fd) in unbound {
+                    *fd = Some(privileges::bind_early(address)?);
+                }
+                privileges::drop_privileges(user, config.group.as_deref())?;
+            } else {
+                for (address, _) in unbound {
+                    ensure_address_free(address)?;
+                }
+            }
+
             let mut server =
                 pingora::server::Server::new(None).context("creating pingora server")?;
             server.bootstrap();
 
             let runtime = tokio::runtime::Runtime::new().context("creating tokio runtime")?;
-            let (collector, challenge_store) = runtime.block_on(setup(&config, no_tls))?;
+            let config: SharedConfig = Arc::new(RwLock::new(config));
+            let (collector, challenge_store, idle) =
+                runtime.block_on(setup(&config, &config_path, no_tls))?;
 
-            let tls_enabled = config.tls.as_ref().is_some_and(|t| t.enabled) && !no_tls;
-            let tls_config = config.tls.clone();
-            let domains = config.tls_domains();
+            let (tls_config, domains) = runtime.block_on(async {
+                let config = config.read().await;
+                (config.tls.clone(), config.tls_domains().await)
+            });
 
-            let proxy = YarpProxy::new(config, collector, challenge_store);
+            {
+                let _guard = runtime.enter();
+                control::spawn(
+                    control_listener,
+                    control::Control {
+                        config: config.clone(),
+                        config_path: config_path.clone(),
+                        collector: collector.clone(),
+                        idle: idle.clone(),
+                    },
+                );
+                if watch {
+                    reload::spawn_config_watcher(
+                        config.clone(),
+                        config_path.clone(),
+                        collector.clone(),
+                    );
+                }
+                reload::spawn_reload_listener(config.clone(), config_path, collector.clone());
+            }
+
+            let proxy = YarpProxy::new(config, collector.clone(), challenge_store.clone(), idle);
             let mut proxy_service =
                 pingora::prelude::http_proxy_service(&server.configuration, proxy);
 
-            proxy_service.add_tcp(&address);
-            info!(address = %address, "HTTP proxy server listening");
+            let mut inherited_fds = Vec::new();
+            for (address, fd) in address.iter().zip(http_fds) {
+                proxy_service.add_tcp(address);
+                if let Some(fd) = fd {
+                    inherited_fds.push((address.clone(), fd));
+                }
+                info!(address = %address, "HTTP proxy server listening");
+            }
 
             if tls_enabled && !domains.is_empty() {
                 let tls_config = tls_config.as_ref().unwrap();
-                let cert_store = CertificateStore::new(&tls_config.certs_dir)?;
-                let dynamic_certs = DynamicCertificates::new(cert_store);
-                let tls_settings =
-                    pingora::listeners::tls::TlsSettings::with_callbacks(Box::new(dynamic_certs))?;
+                let cert_store = CertificateStore::from_config(tls_config, &collector)?;
+                let issuer = tls_config.on_demand.then(|| {
+                    let notifier = CertificateNotifier::new(
+                        collector.clone(),
+                        tls_config.notify_webhook.clone(),
+                    );
+                    info!("on-demand certificate issuance enabled");
+                    Arc::new(OnDemandIssuer::new(
+                        domains,
+                        cert_store.clone(),
+                        collector,
+                        challenge_store,
+                        tls_config.clone(),
+                        notifier,
+                    ))
+                });
+
+                // Each listener takes its own settings; they share the
+                // certificate store and on-demand issuer.
+                for (address, fd) in https_address.iter().zip(https_fds) {
+                    let mut dynamic_certs = DynamicCertificates::new(cert_store.clone())?;
+                    if let Some(issuer) = &issuer {
+                        dynamic_certs = dynamic_certs.with_on_demand(issuer.clone());
+                    }
+                    let mut tls_settings = pingora::listeners::tls::TlsSettings::with_callbacks(
+                        Box::new(dynamic_certs),
+                    )?;
+                    tls::apply_tls_policy(&mut tls_settings, tls_config)?;
 
-                proxy_service.add_tls_with_settings(&https_address, None, tls_settings);
-                info!(address = %https_address, "HTTPS proxy server listening");
+                    proxy_service.add_tls_with_settings(address, None, tls_settings);
+                    if let Some(fd) = fd {
+                        inherited_fds.push((address.clone(), fd));
+                    }
+                    info!(address = %address, "HTTPS proxy server listening");
+                }
             }
 
-            server.add_service(proxy_service);
+            server.add_service(activation::SocketActivated::new(
+                proxy_service,
+                inherited_fds,
+            ));
             server.run_forever()
         }
     }
 }
 
+/// Fails early if another process is already listening on `address`, rather
+/// than after apps and certificates have been set up.
+fn ensure_address_free(address: &str) -> color_eyre::Result<()> {
+    match std::net::TcpListener::bind(address) {
+        Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => Err(color_eyre::eyre::eyre!(
+            "{address} is already in use; is another penny serve running?"
+        )),
+        // Other errors (such as missing permissions) surface when the
+        // proxy binds for real.
+        _ => Ok(()),
+    }
+}
+
 /// Provisions certificates for all domains that need them.
 async fn provision_certificates(
     domains: &[String],
     db: &SqliteDatabase,
     challenge_store: &ChallengeStore,
     tls_config: &TlsConfig,
+    notifier: &CertificateNotifier<SqliteDatabase>,
 ) -> color_eyre::Result<()> {
-    let cert_store = CertificateStore::new(&tls_config.certs_dir)?;
+    let cert_store = CertificateStore::from_config(tls_config, db)?;
     let acme_client = AcmeClient::new(tls_config, db).await?;
 
+    let mut eligible = Vec::new();
     for domain in domains {
-        if cert_store.needs_renewal(domain, tls_config.renewal_days) {
-            info!(domain = %domain, "provisioning certificate");
+        if tls_config.on_demand && cert_store.get_certificate(domain).await.is_none() {
+            debug!(domain = %domain, "no certificate yet, leaving to on-demand issuance");
+            continue;
+        }
+        eligible.push(domain.clone());
+    }
 
-            match acme_client
-                .obtain_certificate(&[domain.as_str()], challenge_store)
-                .await
+    let groups: Vec<Vec<String>> = match tls_config.cert_strategy {
+        CertStrategy::PerDomain => eligible.into_iter().map(|d| vec![d]).collect(),
+        CertStrategy::Combined if eligible.is_empty() => Vec::new(),
+        CertStrategy::Combined => {
+            eligible.sort();
+            vec![eligible]
+        }
+    };
+
+    for group in &groups {
+        provision_group(
+            group,
+            db,
+            &cert_store,
+            &acme_client,
+            challenge_store,
+            tls_config,
+            notifier,
+        )
+        .await?;
+
+        for domain in group {
+            if let Ok(expiry) = cert_store.certificate_expiry(domain).await
+                && let Ok(warning_at) = expiry.checked_sub(
+                    jiff::Span::new().hours(tls_config.expiry_warning_days as i64 * 24),
+                )
+                && jiff::Timestamp::now() >= warning_at
             {
-                Ok((cert_pem, key_pem)) => {
-                    cert_store.store_certificate(domain, &cert_pem, &key_pem)?;
-                    info!(domain = %domain, "certificate provisioned successfully");
-                }
-                Err(e) => {
-                    error!(domain = %domain, error = %e, "failed to provision certificate");
-                }
+                notifier.expiring(domain, expiry).await;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Provisions the certificate for `group` unless another instance sharing
+/// the database is already doing so.
+async fn provision_group(
+    group: &[String],
+    db: &SqliteDatabase,
+    cert_store: &CertificateStore,
+    acme_client: &AcmeClient,
+    challenge_store: &ChallengeStore,
+    tls_config: &TlsConfig,
+    notifier: &CertificateNotifier<SqliteDatabase>,
+) -> color_eyre::Result<()> {
+    let primary = &group[0];
+    if !acme::claim_issuance(db, tls_config, primary).await {
+        info!(domains = ?group, "another instance is provisioning this certificate");
+        return Ok(());
+    }
+    let result = provision_claimed_group(
+        group,
+        db,
+        cert_store,
+        acme_client,
+        challenge_store,
+        tls_config,
+        notifier,
+    )
+    .await;
+    acme::release_issuance(db, tls_config, primary).await;
+    result
+}
+
+/// Obtains one certificate covering every domain in `group` if any of them
+/// needs renewal, and stores it under each domain. Backoff state and key
+/// reuse are tracked against the first domain of the group.
+async fn provision_claimed_group(
+    group: &[String],
+    db: &SqliteDatabase,
+    cert_store: &CertificateStore,
+    acme_client: &AcmeClient,
+    challenge_store: &ChallengeStore,
+    tls_config: &TlsConfig,
+    notifier: &CertificateNotifier<SqliteDatabase>,
+) -> color_eyre::Result<()> {
+    let primary = &group[0];
+
+    let mut needs_renewal = false;
+    for domain in group {
+        if cert_store
+            .needs_renewal(domain, tls_config.renewal_days)
+            .await
+        {
+            needs_renewal = true;
+            break;
+        }
+    }
+    if !needs_renewal {
+        info!(domains = ?group, "certificate valid, skipping provisioning");
+        return Ok(());
+    }
+
+    if let Some(retry_at) = acme::backoff_until(db, primary).await {
+        info!(domains = ?group, retry_at = %retry_at, "previous issuance failed, backing off");
+        return Ok(());
+    }
+
+    info!(domains = ?group, "provisioning certificate");
+    let reuse_key = cert_store.reusable_key(primary, tls_config).await;
+    let names: Vec<&str> = group.iter().map(String::as_str).collect();
+
+    match acme_client
+        .obtain_certificate(&names, challenge_store, reuse_key.as_deref())
+        .await
+    {
+        Ok((cert_pem, key_pem)) => {
+            for domain in group {
+                let renewing = cert_store.get_certificate(domain).await.is_some();
+                cert_store
+                    .store_certificate(domain, &cert_pem, &key_pem)
+                    .await?;
+                notifier.obtained(domain, renewing).await;
+            }
+            info!(domains = ?group, "certificate provisioned successfully");
+            acme::clear_failure(db, primary).await;
+        }
+        Err(e) => {
+            let retry_at = acme::record_failure(db, primary, &e).await;
+            error!(domains = ?group, error = %e, retry_at = %retry_at, "failed to provision certificate");
+            for domain in group {
+                notifier.failed(domain, &e.to_string()).await;
             }
-        } else {
-            info!(domain = %domain, "certificate valid, skipping provisioning");
         }
     }
 
@@ -377,6 +1418,7 @@ async fn renewal_loop(
     db: SqliteDatabase,
     challenge_store: ChallengeStore,
     tls_config: TlsConfig,
+    notifier: CertificateNotifier<SqliteDatabase>,
 ) {
     let check_interval =
         std::time::Duration::from_secs(tls_config.renewal_check_interval_hours * 60 * 60);
@@ -386,7 +1428,9 @@ async fn renewal_loop(
 
         info!("checking certificates for renewal");
 
-        if let Err(e) = provision_certificates(&domains, &db, &challenge_store, &tls_config).await {
+        if let Err(e) =
+            provision_certificates(&domains, &db, &challenge_store, &tls_config, &notifier).await
+        {
             error!(error = %e, "certificate renewal check failed");
         }
     }
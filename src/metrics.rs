@@ -0,0 +1,274 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::config::App;
+
+/// Upper bounds of histogram buckets, in seconds.
+const BUCKETS: [f64; 9] = [0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+
+/// A count that only goes up.
+#[derive(Debug)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    const fn new() -> Self {
+        Self(AtomicU64::new(0))
+    }
+
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A value that goes up and down.
+#[derive(Debug)]
+pub struct Gauge(AtomicU64);
+
+impl Gauge {
+    const fn new() -> Self {
+        Self(AtomicU64::new(0))
+    }
+
+    pub fn set(&self, value: u64) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dec(&self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// How long something took, counted into [`BUCKETS`].
+#[derive(Debug)]
+pub struct Histogram {
+    buckets: [AtomicU64; BUCKETS.len()],
+    count: AtomicU64,
+    sum_nanos: AtomicU64,
+}
+
+impl Histogram {
+    const fn new() -> Self {
+        Self {
+            buckets: [const { AtomicU64::new(0) }; BUCKETS.len()],
+            count: AtomicU64::new(0),
+            sum_nanos: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        if let Some(bucket) = BUCKETS.iter().position(|&bound| secs <= bound) {
+            self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_nanos
+            .fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Writes the histogram's series with `labels`, e.g. `mode="read"`.
+    fn write(&self, out: &mut String, name: &str, labels: &str) {
+        let separator = if labels.is_empty() { "" } else { "," };
+        let mut cumulative = 0;
+        for (bound, bucket) in BUCKETS.iter().zip(&self.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(
+                out,
+                "{name}_bucket{{{labels}{separator}le=\"{bound}\"}} {cumulative}"
+            );
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let _ = writeln!(
+            out,
+            "{name}_bucket{{{labels}{separator}le=\"+Inf\"}} {count}"
+        );
+        let sum = self.sum_nanos.load(Ordering::Relaxed) as f64 / 1e9;
+        let labels = if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{labels}}}")
+        };
+        let _ = writeln!(out, "{name}_sum{labels} {sum}");
+        let _ = writeln!(out, "{name}_count{labels} {count}");
+    }
+}
+
+/// Stops waiting in the idle scheduler's queue.
+pub static IDLE_QUEUE_DEPTH: Gauge = Gauge::new();
+/// Idle stops that came due and were carried out.
+pub static IDLE_STOPS: Counter = Counter::new();
+/// Idle stops put off for requests in flight or elsewhere in the cluster.
+pub static IDLE_POSTPONEMENTS: Counter = Counter::new();
+/// How late idle stops came due after their deadline.
+pub static IDLE_STOP_LAG: Histogram = Histogram::new();
+/// Time spent waiting for an app's lock on the request path.
+pub static APP_READ_LOCK_WAIT: Histogram = Histogram::new();
+pub static APP_WRITE_LOCK_WAIT: Histogram = Histogram::new();
+/// Output lines the collector is storing right now.
+pub static COLLECTOR_WRITES_IN_PROGRESS: Gauge = Gauge::new();
+/// Time the collector took to store a line of app output.
+pub static OUTPUT_WRITE: Histogram = Histogram::new();
+/// Output lines dropped over `capture_rate_limit`.
+pub static OUTPUT_LINES_DROPPED: Counter = Counter::new();
+
+/// Takes an app's read lock, recording how long that took.
+pub async fn read_app(app: &RwLock<App>) -> RwLockReadGuard<'_, App> {
+    let waiting = Instant::now();
+    let guard = app.read().await;
+    APP_READ_LOCK_WAIT.observe(waiting.elapsed());
+    guard
+}
+
+/// Takes an app's write lock, recording how long that took.
+pub async fn write_app(app: &RwLock<App>) -> RwLockWriteGuard<'_, App> {
+    let waiting = Instant::now();
+    let guard = app.write().await;
+    APP_WRITE_LOCK_WAIT.observe(waiting.elapsed());
+    guard
+}
+
+/// Runs a collector write of a line of output, counting it as in progress
+/// until it is stored and recording how long that took.
+pub async fn store_output<T>(write: impl Future<Output = T>) -> T {
+    /// Stops counting the write even if the output task is aborted
+    /// mid-write.
+    struct InProgress;
+
+    impl Drop for InProgress {
+        fn drop(&mut self) {
+            COLLECTOR_WRITES_IN_PROGRESS.dec();
+        }
+    }
+
+    COLLECTOR_WRITES_IN_PROGRESS.inc();
+    let _in_progress = InProgress;
+    let writing = Instant::now();
+    let result = write.await;
+    OUTPUT_WRITE.observe(writing.elapsed());
+    result
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+/// Every metric in the Prometheus text format.
+pub fn render() -> String {
+    let mut out = String::new();
+
+    header(
+        &mut out,
+        "penny_idle_queue_depth",
+        "gauge",
+        "Idle stops waiting in the scheduler's queue.",
+    );
+    let _ = writeln!(out, "penny_idle_queue_depth {}", IDLE_QUEUE_DEPTH.get());
+    header(
+        &mut out,
+        "penny_idle_stops_total",
+        "counter",
+        "Idle stops carried out.",
+    );
+    let _ = writeln!(out, "penny_idle_stops_total {}", IDLE_STOPS.get());
+    header(
+        &mut out,
+        "penny_idle_postponements_total",
+        "counter",
+        "Idle stops put off for requests still in flight, here or on another node.",
+    );
+    let _ = writeln!(
+        out,
+        "penny_idle_postponements_total {}",
+        IDLE_POSTPONEMENTS.get()
+    );
+    header(
+        &mut out,
+        "penny_idle_stop_lag_seconds",
+        "histogram",
+        "How late idle stops came due after their deadline.",
+    );
+    IDLE_STOP_LAG.write(&mut out, "penny_idle_stop_lag_seconds", "");
+
+    header(
+        &mut out,
+        "penny_app_lock_wait_seconds",
+        "histogram",
+        "Time requests waited for an app's lock.",
+    );
+    APP_READ_LOCK_WAIT.write(&mut out, "penny_app_lock_wait_seconds", "mode=\"read\"");
+    APP_WRITE_LOCK_WAIT.write(&mut out, "penny_app_lock_wait_seconds", "mode=\"write\"");
+
+    header(
+        &mut out,
+        "penny_collector_writes_in_progress",
+        "gauge",
+        "Output lines being stored by the collector right now.",
+    );
+    let _ = writeln!(
+        out,
+        "penny_collector_writes_in_progress {}",
+        COLLECTOR_WRITES_IN_PROGRESS.get()
+    );
+    header(
+        &mut out,
+        "penny_output_write_seconds",
+        "histogram",
+        "Time taken to store a line of app output.",
+    );
+    OUTPUT_WRITE.write(&mut out, "penny_output_write_seconds", "");
+    header(
+        &mut out,
+        "penny_output_lines_dropped_total",
+        "counter",
+        "Output lines dropped over capture_rate_limit.",
+    );
+    let _ = writeln!(
+        out,
+        "penny_output_lines_dropped_total {}",
+        OUTPUT_LINES_DROPPED.get()
+    );
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histograms_count_into_cumulative_buckets() {
+        let histogram = Histogram::new();
+        histogram.observe(Duration::from_micros(200));
+        histogram.observe(Duration::from_millis(30));
+        histogram.observe(Duration::from_secs(10));
+
+        let mut out = String::new();
+        histogram.write(&mut out, "wait_seconds", "mode=\"read\"");
+        assert!(out.contains("wait_seconds_bucket{mode=\"read\",le=\"0.0005\"} 1\n"));
+        assert!(out.contains("wait_seconds_bucket{mode=\"read\",le=\"0.05\"} 2\n"));
+        assert!(out.contains("wait_seconds_bucket{mode=\"read\",le=\"5\"} 2\n"));
+        assert!(out.contains("wait_seconds_bucket{mode=\"read\",le=\"+Inf\"} 3\n"));
+        assert!(out.contains("wait_seconds_sum{mode=\"read\"} 10.03"));
+        assert!(out.contains("wait_seconds_count{mode=\"read\"} 3\n"));
+
+        let mut out = String::new();
+        histogram.write(&mut out, "lag_seconds", "");
+        assert!(out.contains("lag_seconds_bucket{le=\"+Inf\"} 3\n"));
+        assert!(out.contains("lag_seconds_count 3\n"));
+    }
+}
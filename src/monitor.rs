@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+use jiff::SignedDuration;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use crate::collector::Collector;
+use crate::config::SharedConfig;
+use crate::types::Host;
+
+/// Header marking penny's own monitor requests. The proxy answers them
+/// without counting them as traffic or waking sleeping apps.
+pub const HEADER: &str = "x-penny-monitor";
+
+/// Set on the proxy's answer to monitor requests for sleeping apps.
+pub const ASLEEP_HEADER: &str = "x-penny-asleep";
+
+/// Sent in [`HEADER`], so clients cannot pass as the monitor.
+static TOKEN: LazyLock<String> = LazyLock::new(|| ulid::Ulid::new().to_string());
+
+/// Whether a request was sent by this penny's monitor.
+pub fn is_monitor_request(headers: &http::HeaderMap) -> bool {
+    headers
+        .get(HEADER)
+        .is_some_and(|value| value.as_bytes() == TOKEN.as_bytes())
+}
+
+/// Periodic end-to-end checks of every app through its public hostname.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MonitorConfig {
+    /// How often each app is checked.
+    #[serde(default = "default_interval")]
+    pub interval: SignedDuration,
+
+    /// How long a check may take before it fails.
+    #[serde(default = "default_timeout")]
+    pub timeout: SignedDuration,
+
+    /// Failed checks in a row before an app is reported down.
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+
+    /// URL that receives a JSON POST when an app goes down or recovers.
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+}
+
+fn default_interval() -> SignedDuration {
+    SignedDuration::from_mins(1)
+}
+
+fn default_timeout() -> SignedDuration {
+    SignedDuration::from_secs(10)
+}
+
+fn default_failure_threshold() -> u32 {
+    2
+}
+
+/// How long to wait before looking again while the monitor is disabled.
+const DISABLED_POLL: Duration = Duration::from_secs(30);
+
+/// The outcome of one check.
+#[derive(Debug, Clone, PartialEq)]
+struct Check {
+    status: Option<u16>,
+    latency_ms: u64,
+    error: Option<String>,
+    /// The app was asleep, so only penny answered.
+    asleep: bool,
+}
+
+impl Check {
+    fn is_up(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Alert {
+    Down,
+    Recovered,
+}
+
+#[derive(Debug, Serialize)]
+struct WebhookPayload<'a> {
+    event: Alert,
+    host: &'a str,
+    detail: Option<&'a str>,
+    timestamp: i64,
+}
+
+/// Consecutive failures of one app.
+#[derive(Debug, Default)]
+struct Health {
+    failures: u32,
+    down: bool,
+}
+
+impl Health {
+    /// Counts a check, returning the alert it triggers, if any.
+    fn record(&mut self, up: bool, threshold: u32) -> Option<Alert> {
+        if up {
+            self.failures = 0;
+            return std::mem::take(&mut self.down).then_some(Alert::Recovered);
+        }
+        self.failures += 1;
+        if !self.down && self.failures >= threshold.max(1) {
+            self.down = true;
+            return Some(Alert::Down);
+        }
+        None
+    }
+}
+
+/// The URL the monitor requests for an app.
+fn check_url(hostname: &str, health_check: &str, https: bool) -> String {
+    let scheme = if https { "https" } else { "http" };
+    format!("{scheme}://{hostname}{health_check}")
+}
+
+async fn check(client: &reqwest::Client, url: &str, timeout: Duration) -> Check {
+    let started = Instant::now();
+    let result = client
+        .get(url)
+        .header(HEADER, TOKEN.as_str())
+        .timeout(timeout)
+        .send()
+        .await;
+    let latency_ms = started.elapsed().as_millis() as u64;
+
+    match result {
+        Ok(response) => {
+            let status = response.status();
+            let asleep = response
+                .headers()
+                .get(ASLEEP_HEADER)
+                .is_some_and(|value| value == "1");
+            Check {
+                status: Some(status.as_u16()),
+                latency_ms,
+                error: (!status.is_success()).then(|| format!("responded with {status}")),
+                asleep,
+            }
+        }
+        Err(e) => Check {
+            status: None,
+            latency_ms,
+            error: Some(e.to_string()),
+            asleep: false,
+        },
+    }
+}
+
+async fn alert(
+    client: &reqwest::Client,
+    url: &str,
+    host: &str,
+    event: Alert,
+    detail: Option<&str>,
+) {
+    let payload = WebhookPayload {
+        event,
+        host,
+        detail,
+        timestamp: jiff::Timestamp::now().as_millisecond(),
+    };
+    let body = match serde_json::to_string(&payload) {
+        Ok(body) => body,
+        Err(e) => {
+            error!("failed to serialize monitor webhook payload: {e}");
+            return;
+        }
+    };
+    let result = client
+        .post(url)
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await
+        .and_then(|resp| resp.error_for_status());
+    if let Err(e) = result {
+        warn!(host = %host, url = %url, error = %e, "monitor webhook failed");
+    }
+}
+
+/// Checks every app each `monitor.interval`, records the results and
+/// alerts when an app goes down or recovers. Does nothing while the config
+/// has no `[monitor]` section.
+pub fn spawn<C: Collector>(config: SharedConfig, collector: C) {
+    tokio::spawn(async move {
+        let client = crate::outbound::client();
+        let mut health: HashMap<String, Health> = HashMap::new();
+
+        loop {
+            let guard = config.read().await;
+            let Some(monitor) = guard.monitor.clone() else {
+                drop(guard);
+                tokio::time::sleep(DISABLED_POLL).await;
+                continue;
+            };
+            let tls = guard.tls.as_ref().is_some_and(|tls| tls.enabled);
+            let mut targets = Vec::with_capacity(guard.apps.len());
+            for (host, app) in &guard.apps {
+                let app = app.read().await;
+                targets.push((
+                    host.clone(),
+                    check_url(host, &app.health_check, tls && app.tls),
+                ));
+            }
+            drop(guard);
+
+            let timeout = monitor.timeout.unsigned_abs();
+            let checks: Vec<_> = targets
+                .into_iter()
+                .map(|(host, url)| {
+                    let client = client.clone();
+                    tokio::spawn(async move {
+                        let result = check(&client, &url, timeout).await;
+                        (host, result)
+                    })
+                })
+                .collect();
+
+            for handle in checks {
+                let Ok((host, result)) = handle.await else {
+                    continue;
+                };
+                if let Err(e) = collector
+                    .monitor_checked(
+                        &Host(host.clone()),
+                        result.status,
+                        result.latency_ms,
+                        result.error.as_deref(),
+                        result.asleep,
+                    )
+                    .await
+                {
+                    error!(host = %host, error = %e, "failed to record monitor check");
+                }
+
+                let event = health
+                    .entry(host.clone())
+                    .or_default()
+                    .record(result.is_up(), monitor.failure_threshold);
+                match event {
+                    Some(Alert::Down) => {
+                        error!(host = %host, error = ?result.error, "app is down")
+                    }
+                    Some(Alert::Recovered) => info!(host = %host, "app recovered"),
+                    None => {}
+                }
+                if let Some(event) = event
+                    && let Some(url) = &monitor.webhook_url
+                {
+                    alert(&client, url, &host, event, result.error.as_deref()).await;
+                }
+            }
+
+            tokio::time::sleep(monitor.interval.unsigned_abs()).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alerts_once_after_the_threshold_and_on_recovery() {
+        let mut health = Health::default();
+        assert_eq!(health.record(false, 2), None);
+        assert_eq!(health.record(false, 2), Some(Alert::Down));
+        assert_eq!(health.record(false, 2), None);
+        assert_eq!(health.record(true, 2), Some(Alert::Recovered));
+        assert_eq!(health.record(true, 2), None);
+        assert_eq!(health.record(false, 2), None);
+    }
+
+    #[test]
+    fn only_requests_with_the_token_are_monitor_requests() {
+        let mut headers = http::HeaderMap::new();
+        assert!(!is_monitor_request(&headers));
+        headers.insert(HEADER, "guess".parse().unwrap());
+        assert!(!is_monitor_request(&headers));
+        headers.insert(HEADER, TOKEN.parse().unwrap());
+        assert!(is_monitor_request(&headers));
+
+        assert_eq!(
+            check_url("app.example.com", "/health", true),
+            "https://app.example.com/health"
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use serde::Serialize;
+use tracing::{error, info, warn};
+
+use crate::collector::Collector;
+use crate::types::CertificateEvent;
+
+#[derive(Debug, Serialize)]
+struct WebhookPayload<'a> {
+    event: CertificateEvent,
+    domain: &'a str,
+    detail: Option<&'a str>,
+    timestamp: i64,
+}
+
+/// Records certificate lifecycle events through the collector and, if
+/// configured, posts them to a webhook.
+#[derive(Debug)]
+pub struct CertificateNotifier<C> {
+    collector: C,
+    webhook_url: Option<String>,
+    http: reqwest::Client,
+    /// Domains already reported as expiring, so the warning fires once
+    /// per certificate rather than on every renewal check.
+    expiring_notified: Mutex<HashSet<String>>,
+}
+
+impl<C: Collector> CertificateNotifier<C> {
+    pub fn new(collector: C, webhook_url: Option<String>) -> Self {
+        Self {
+            collector,
+            webhook_url,
+            http: crate::outbound::client(),
+            expiring_notified: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Reports a new certificate for a domain.
+    pub async fn obtained(&self, domain: &str, renewed: bool) {
+        self.expiring_notified.lock().unwrap().remove(domain);
+        let event = if renewed {
+            CertificateEvent::Renewed
+        } else {
+            CertificateEvent::Issued
+        };
+        self.notify(domain, event, None).await;
+    }
+
+    /// Reports a failed issuance or renewal.
+    pub async fn failed(&self, domain: &str, error: &str) {
+        self.notify(domain, CertificateEvent::RenewalFailed, Some(error))
+            .await;
+    }
+
+    /// Reports a certificate inside its expiry warning window, once per certificate.
+    pub async fn expiring(&self, domain: &str, expiry: jiff::Timestamp) {
+        if !self
+            .expiring_notified
+            .lock()
+            .unwrap()
+            .insert(domain.to_owned())
+        {
+            return;
+        }
+
+        let detail = format!("certificate expires at {expiry}");
+        self.notify(domain, CertificateEvent::Expiring, Some(&detail))
+            .await;
+    }
+
+    async fn notify(&self, domain: &str, event: CertificateEvent, detail: Option<&str>) {
+        info!(domain = %domain, event = event.as_str(), "certificate event");
+
+        if let Err(e) = self
+            .collector
+            .certificate_event(domain, event, detail)
+            .await
+        {
+            error!(domain = %domain, "failed to record certificate event: {e}");
+        }
+
+        let Some(url) = &self.webhook_url else {
+            return;
+        };
+
+        let payload = WebhookPayload {
+            event,
+            domain,
+            detail,
+            timestamp: jiff::Timestamp::now().as_millisecond(),
+        };
+        let body = match serde_json::to_string(&payload) {
+            Ok(body) => body,
+            Err(e) => {
+                error!("failed to serialize webhook payload: {e}");
+                return;
+            }
+        };
+
+        let result = self
+            .http
+            .post(url)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await
+            .and_then(|resp| resp.error_for_status());
+        if let Err(e) = result {
+            warn!(domain = %domain, url = %url, error = %e, "certificate webhook failed");
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::{debug, error, info};
+
+use crate::acme::{self, AcmeClient};
+use crate::challenge::ChallengeStore;
+use crate::config::TlsConfig;
+use crate::db::SqliteDatabase;
+use crate::notify::CertificateNotifier;
+use crate::tls::CertificateStore;
+
+/// Issues certificates lazily, the first time a TLS handshake arrives for a
+/// configured domain that has no certificate yet.
+///
+/// Only one issuance runs per domain at a time, and failed domains are not
+/// retried until `on_demand_retry_secs` have passed.
+pub struct OnDemandIssuer {
+    domains: HashSet<String>,
+    in_flight: Mutex<HashSet<String>>,
+    failures: Mutex<HashMap<String, Instant>>,
+    retry_after: Duration,
+    cert_store: CertificateStore,
+    db: SqliteDatabase,
+    challenge_store: ChallengeStore,
+    tls_config: TlsConfig,
+    notifier: CertificateNotifier<SqliteDatabase>,
+}
+
+impl OnDemandIssuer {
+    pub fn new(
+        domains: Vec<String>,
+        cert_store: CertificateStore,
+        db: SqliteDatabase,
+        challenge_store: ChallengeStore,
+        tls_config: TlsConfig,
+        notifier: CertificateNotifier<SqliteDatabase>,
+    ) -> Self {
+        Self {
+            domains: domains.into_iter().collect(),
+            in_flight: Mutex::new(HashSet::new()),
+            failures: Mutex::new(HashMap::new()),
+            retry_after: Duration::from_secs(tls_config.on_demand_retry_secs),
+            cert_store,
+            db,
+            challenge_store,
+            tls_config,
+            notifier,
+        }
+    }
+
+    /// Starts issuance for `domain` in the background if it is configured,
+    /// not already being issued, and not in the negative cache.
+    pub fn request(self: &Arc<Self>, domain: &str) {
+        if !self.domains.contains(domain) {
+            debug!(domain = %domain, "ignoring on-demand request for unconfigured domain");
+            return;
+        }
+
+        if let Some(failed_at) = self.failures.lock().unwrap().get(domain)
+            && failed_at.elapsed() < self.retry_after
+        {
+            debug!(domain = %domain, "recent issuance failure, not retrying yet");
+            return;
+        }
+
+        if !self.in_flight.lock().unwrap().insert(domain.to_owned()) {
+            debug!(domain = %domain, "issuance already in progress");
+            return;
+        }
+
+        info!(domain = %domain, "starting on-demand certificate issuance");
+
+        let issuer = self.clone();
+        let domain = domain.to_owned();
+        tokio::spawn(async move {
+            if let Some(retry_at) = acme::backoff_until(&issuer.db, &domain).await {
+                debug!(domain = %domain, retry_at = %retry_at, "issuance backing off after failure");
+                issuer.in_flight.lock().unwrap().remove(&domain);
+                return;
+            }
+
+            if !acme::claim_issuance(&issuer.db, &issuer.tls_config, &domain).await {
+                debug!(domain = %domain, "another instance is issuing this certificate");
+                issuer.in_flight.lock().unwrap().remove(&domain);
+                return;
+            }
+            // Another instance may have stored it since the handshake.
+            if issuer.cert_store.get_certificate(&domain).await.is_some() {
+                acme::release_issuance(&issuer.db, &issuer.tls_config, &domain).await;
+                issuer.in_flight.lock().unwrap().remove(&domain);
+                return;
+            }
+            let result = issuer.issue(&domain).await;
+            acme::release_issuance(&issuer.db, &issuer.tls_config, &domain).await;
+
+            match result {
+                Ok(()) => {
+                    info!(domain = %domain, "on-demand certificate issued");
+                    issuer.failures.lock().unwrap().remove(&domain);
+                    acme::clear_failure(&issuer.db, &domain).await;
+                    issuer.notifier.obtained(&domain, false).await;
+                }
+                Err(e) => {
+                    let retry_at = acme::record_failure(&issuer.db, &domain, &e).await;
+                    error!(domain = %domain, error = %e, retry_at = %retry_at, "on-demand certificate issuance failed");
+                    issuer.notifier.failed(&domain, &e.to_string()).await;
+                    issuer
+                        .failures
+                        .lock()
+                        .unwrap()
+                        .insert(domain.clone(), Instant::now());
+                }
+            }
+            issuer.in_flight.lock().unwrap().remove(&domain);
+        });
+    }
+
+    async fn issue(&self, domain: &str) -> color_eyre::Result<()> {
+        let acme_client = AcmeClient::new(&self.tls_config, &self.db).await?;
+        let (cert_pem, key_pem) = acme_client
+            .obtain_certificate(&[domain], &self.challenge_store, None)
+            .await?;
+        self.cert_store
+            .store_certificate(domain, &cert_pem, &key_pem)
+            .await
+    }
+}
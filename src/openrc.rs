@@ -0,0 +1,173 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::service::{ServeOpts, ServiceManager, login_shell, run_cmd, run_status_cmd};
+
+const SERVICE_NAME: &str = "penny";
+const INIT_SCRIPT: &str = "/etc/init.d/penny";
+/// Sourced by the init script; holds the dashboard password, so it is only
+/// readable by root.
+const CONF_FILE: &str = "/etc/conf.d/penny";
+const LOG_FILE: &str = "/var/log/penny.log";
+const PID_FILE: &str = "/run/penny.pid";
+
+/// penny as an OpenRC service, for Alpine and Gentoo.
+pub struct OpenRc;
+
+fn generate_init_script(opts: &ServeOpts) -> color_eyre::Result<String> {
+    let serve = opts.serve_command()?;
+    let shell = login_shell();
+    let working_dir = serve.working_dir.display();
+    let command_line = format!("{} --pid-file {PID_FILE}", serve.command_line);
+
+    Ok(format!(
+        "\
+#!/sbin/openrc-run
+
+name=\"penny\"
+description=\"Penny reverse proxy\"
+command=\"{shell}\"
+command_args=\"-lc 'exec {command_line}'\"
+command_background=true
+pidfile=\"{PID_FILE}\"
+directory=\"{working_dir}\"
+output_log=\"{LOG_FILE}\"
+error_log=\"{LOG_FILE}\"
+extra_started_commands=\"reload\"
+
+depend() {{
+    need net
+    after firewall
+}}
+
+reload() {{
+    ebegin \"Reloading ${{RC_SVCNAME}}\"
+    start-stop-daemon --signal HUP --pidfile \"${{pidfile}}\"
+    eend $?
+}}
+"
+    ))
+}
+
+fn generate_conf_file(opts: &ServeOpts) -> String {
+    let mut conf = String::from("# Environment for the penny service.\n");
+    if let Some(password) = &opts.password {
+        conf.push_str(&format!("export PENNY_PASSWORD='{password}'\n"));
+    }
+    if let Ok(rust_log) = std::env::var("RUST_LOG") {
+        conf.push_str(&format!("export RUST_LOG='{rust_log}'\n"));
+    }
+    conf
+}
+
+impl ServiceManager for OpenRc {
+    fn hint(&self, action: &str) -> String {
+        format!("penny openrc {action}")
+    }
+
+    fn check_supported(&self) -> color_eyre::Result<()> {
+        if !std::path::Path::new("/sbin/openrc-run").exists() {
+            return Err(color_eyre::eyre::eyre!(
+                "OpenRC not found (no /sbin/openrc-run); the `openrc` command is for Alpine, Gentoo, and other OpenRC systems"
+            ));
+        }
+        Ok(())
+    }
+
+    fn service_path(&self) -> color_eyre::Result<PathBuf> {
+        Ok(PathBuf::from(INIT_SCRIPT))
+    }
+
+    fn install(&self, opts: &ServeOpts) -> color_eyre::Result<()> {
+        self.ensure_not_installed()?;
+
+        let script = generate_init_script(opts)?;
+        fs::write(INIT_SCRIPT, script)?;
+        fs::set_permissions(INIT_SCRIPT, fs::Permissions::from_mode(0o755))?;
+        println!("wrote init script to {INIT_SCRIPT}");
+
+        fs::write(CONF_FILE, generate_conf_file(opts))?;
+        fs::set_permissions(CONF_FILE, fs::Permissions::from_mode(0o600))?;
+        println!("wrote {CONF_FILE}");
+
+        run_cmd("rc-update", &["add", SERVICE_NAME, "default"])?;
+        println!("added {SERVICE_NAME} to the default runlevel");
+
+        run_cmd("rc-service", &[SERVICE_NAME, "start"])?;
+        println!("started {SERVICE_NAME}");
+
+        println!("\npenny service installed and running.");
+        println!("use `{}` to check status", self.hint("status"));
+        println!("use `{} --follow` to watch logs", self.hint("logs"));
+
+        Ok(())
+    }
+
+    fn uninstall(&self) -> color_eyre::Result<()> {
+        let script = self.installed_path()?;
+
+        // Ignore errors — the service might already be stopped.
+        let _ = run_cmd("rc-service", &[SERVICE_NAME, "stop"]);
+        println!("stopped {SERVICE_NAME}");
+
+        let _ = run_cmd("rc-update", &["del", SERVICE_NAME, "default"]);
+        println!("removed {SERVICE_NAME} from the default runlevel");
+
+        fs::remove_file(&script)?;
+        println!("removed {}", script.display());
+
+        if fs::remove_file(CONF_FILE).is_ok() {
+            println!("removed {CONF_FILE}");
+        }
+
+        println!("\npenny service uninstalled.");
+
+        Ok(())
+    }
+
+    fn status(&self) -> color_eyre::Result<()> {
+        self.installed_path()?;
+        run_status_cmd("rc-service", &[SERVICE_NAME, "status"])
+    }
+
+    fn restart(&self) -> color_eyre::Result<()> {
+        self.installed_path()?;
+
+        run_cmd("rc-service", &[SERVICE_NAME, "restart"])?;
+        println!("restarted {SERVICE_NAME}");
+
+        Ok(())
+    }
+
+    fn reload(&self) -> color_eyre::Result<()> {
+        self.installed_path()?;
+
+        run_cmd("rc-service", &[SERVICE_NAME, "reload"])?;
+        println!("reloaded {SERVICE_NAME}");
+
+        Ok(())
+    }
+
+    fn logs(&self, follow: bool) -> color_eyre::Result<()> {
+        self.installed_path()?;
+
+        let mut args = vec!["-n", "100"];
+        if follow {
+            args.push("-F");
+        }
+        args.push(LOG_FILE);
+
+        let status = Command::new("tail")
+            .args(&args)
+            .status()
+            .map_err(|e| color_eyre::eyre::eyre!("failed to run tail: {e}"))?;
+
+        if !status.success() {
+            return Err(color_eyre::eyre::eyre!("tail exited with {}", status));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,143 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::OnceLock;
+
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use instant_acme::{BodyWrapper, BytesResponse, HttpClient};
+use serde::Deserialize;
+use tracing::{info, warn};
+
+/// Hosts always reached directly, so health checks of local apps never go
+/// through the proxy.
+const LOOPBACK: [&str; 3] = ["localhost", "127.0.0.1", "::1"];
+
+/// Proxy for penny's own outbound HTTP: the ACME client, health checks and
+/// webhooks. Without it, `HTTPS_PROXY`, `HTTP_PROXY` and `NO_PROXY` from the
+/// environment are used.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct OutboundProxy {
+    /// Proxy URL, e.g. `http://proxy.internal:3128`.
+    pub url: String,
+
+    /// Hosts, domains (`.internal`) or networks (`10.0.0.0/8`) reached
+    /// directly. Loopback addresses always are.
+    #[serde(default)]
+    pub no_proxy: Vec<String>,
+}
+
+impl OutboundProxy {
+    /// `no_proxy` in the comma-separated form of `NO_PROXY`.
+    fn no_proxy_list(&self) -> String {
+        let mut hosts: Vec<&str> = self.no_proxy.iter().map(String::as_str).collect();
+        for host in LOOPBACK {
+            if !hosts.contains(&host) {
+                hosts.push(host);
+            }
+        }
+        hosts.join(",")
+    }
+}
+
+static PROXY: OnceLock<Option<OutboundProxy>> = OnceLock::new();
+
+/// Sets the proxy for every client built afterwards. Only the first call
+/// counts, so a changed `outbound_proxy` needs a restart.
+pub fn init(proxy: Option<&OutboundProxy>) {
+    if PROXY.set(proxy.cloned()).is_ok() {
+        if let Some(proxy) = proxy {
+            info!(url = %proxy.url, "sending outbound requests through proxy");
+        }
+    } else if PROXY.get().and_then(Option::as_ref) != proxy {
+        warn!("outbound_proxy changed; restart penny to apply it");
+    }
+}
+
+/// A client builder using the configured proxy, or the environment's.
+pub fn builder() -> reqwest::ClientBuilder {
+    let builder = reqwest::Client::builder();
+    let Some(proxy) = PROXY.get().and_then(Option::as_ref) else {
+        return builder;
+    };
+    match reqwest::Proxy::all(&proxy.url) {
+        Ok(all) => builder
+            .proxy(all.no_proxy(reqwest::NoProxy::from_string(&proxy.no_proxy_list())))
+            .no_proxy(),
+        Err(e) => {
+            warn!(url = %proxy.url, error = %e, "invalid outbound_proxy url, connecting directly");
+            builder.no_proxy()
+        }
+    }
+}
+
+/// A client using the configured proxy, or the environment's.
+pub fn client() -> reqwest::Client {
+    builder().build().unwrap_or_else(|e| {
+        warn!(error = %e, "failed to build outbound HTTP client");
+        reqwest::Client::new()
+    })
+}
+
+/// Sends the ACME client's requests through [`client`], since its own
+/// HTTP client knows nothing about proxies.
+pub struct AcmeHttp(reqwest::Client);
+
+impl Default for AcmeHttp {
+    fn default() -> Self {
+        Self(client())
+    }
+}
+
+fn acme_error(e: impl std::error::Error + Send + Sync + 'static) -> instant_acme::Error {
+    instant_acme::Error::Other(Box::new(e))
+}
+
+impl HttpClient for AcmeHttp {
+    fn request(
+        &self,
+        req: http::Request<BodyWrapper<Bytes>>,
+    ) -> Pin<Box<dyn Future<Output = Result<BytesResponse, instant_acme::Error>> + Send>> {
+        let client = self.0.clone();
+        Box::pin(async move {
+            let (parts, body) = req.into_parts();
+            let body = body.collect().await.map_err(acme_error)?.to_bytes();
+            let response = client
+                .request(parts.method, parts.uri.to_string())
+                .headers(parts.headers)
+                .body(body)
+                .send()
+                .await
+                .map_err(acme_error)?;
+
+            let mut builder = http::Response::builder()
+                .status(response.status())
+                .version(response.version());
+            if let Some(headers) = builder.headers_mut() {
+                *headers = response.headers().clone();
+            }
+            let body = response.bytes().await.map_err(acme_error)?;
+            let (parts, ()) = builder.body(()).map_err(acme_error)?.into_parts();
+            Ok(BytesResponse {
+                parts,
+                body: Box::new(Full::new(body)),
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loopback_always_bypasses_the_proxy() {
+        let proxy: OutboundProxy = toml::from_str(
+            r#"
+            url = "http://proxy.internal:3128"
+            no_proxy = [".internal", "localhost"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(proxy.no_proxy_list(), ".internal,localhost,127.0.0.1,::1");
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use serde::Deserialize;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+use crate::config::{Config, PortRange, UpstreamAddress, build_app, normalize_hostname};
+
+/// Passed to a preview instance's commands: the subdomain it serves.
+pub const SUBDOMAIN_ENV: &str = "PENNY_SUBDOMAIN";
+
+/// App settings for every subdomain of a wildcard like
+/// `*.preview.example.com`. Each subdomain gets its own app the first time
+/// it is requested.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preview {
+    /// The domain under the wildcard, e.g. `preview.example.com`.
+    pub domain: String,
+    /// Handed out to instances, one port each.
+    pub ports: PortRange,
+    /// App settings every instance starts from, without an address.
+    settings: toml::Table,
+}
+
+impl Preview {
+    /// The subdomain `host` is for, if it is a single label under this
+    /// preview's domain.
+    fn subdomain<'a>(&self, host: &'a str) -> Option<&'a str> {
+        host.strip_suffix(&self.domain)?
+            .strip_suffix('.')
+            .filter(|label| !label.is_empty() && !label.contains('.'))
+    }
+
+    /// The app for one subdomain, listening on `port`.
+    fn instance(
+        &self,
+        host: &str,
+        subdomain: &str,
+        port: u16,
+    ) -> Result<crate::config::App, String> {
+        let mut settings = self.settings.clone();
+        settings.insert(
+            "address".to_owned(),
+            toml::Value::String(format!("127.0.0.1:{port}")),
+        );
+        let mut app = build_app(host, settings)?;
+        app.command.set_port(Some(port));
+        app.command.set_env(SUBDOMAIN_ENV, subdomain);
+        app.preview = Some(self.domain.clone());
+        Ok(app)
+    }
+}
+
+pub fn deserialize_previews<'de, D>(deserializer: D) -> Result<Vec<Preview>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error;
+
+    let raw = HashMap::<String, toml::Table>::deserialize(deserializer)?;
+    let mut previews = Vec::with_capacity(raw.len());
+    for (pattern, mut settings) in raw {
+        let domain = pattern
+            .strip_prefix("*.")
+            .and_then(normalize_hostname)
+            .ok_or_else(|| {
+                D::Error::custom(format!(
+                    "preview '{pattern}' must be a wildcard like '*.preview.example.com'"
+                ))
+            })?;
+        if settings.contains_key("address") {
+            return Err(D::Error::custom(format!(
+                "preview '{pattern}' takes no address; its instances listen on a port from `ports`"
+            )));
+        }
+        let ports = settings
+            .remove("ports")
+            .ok_or_else(|| D::Error::custom(format!("missing ports for preview '{pattern}'")))?;
+        let ports = PortRange::deserialize(ports)
+            .map_err(|e| D::Error::custom(format!("{} for preview '{pattern}'", e.message())))?;
+
+        let preview = Preview {
+            domain,
+            ports,
+            settings,
+        };
+        let example = format!("example.{}", preview.domain);
+        preview
+            .instance(&example, "example", ports.start())
+            .map_err(|e| D::Error::custom(e.replace(&example, &pattern)))?;
+        previews.push(preview);
+    }
+    // The most specific wildcard wins.
+    previews.sort_by(|a, b| b.domain.len().cmp(&a.domain.len()));
+    Ok(previews)
+}
+
+impl Config {
+    /// The preview `host` would get an instance of, if it has no app yet.
+    fn preview_for(&self, host: &str) -> Option<(&Preview, String)> {
+        let host = normalize_hostname(host)?;
+        if self.apps.contains_key(&host) {
+            return None;
+        }
+        self.previews.iter().find_map(|preview| {
+            let subdomain = preview.subdomain(&host)?.to_owned();
+            Some((preview, subdomain))
+        })
+    }
+
+    /// Whether a request for `host` would spawn a preview instance.
+    pub fn spawns_preview(&self, host: &str) -> bool {
+        self.preview_for(host).is_some()
+    }
+
+    /// Adds the app for a preview subdomain, on a port no other instance of
+    /// the preview has. With every port taken, a sleeping instance is
+    /// dropped to make room. Returns whether an app was added.
+    pub async fn spawn_preview(&mut self, host: &str) -> bool {
+        let Some((preview, subdomain)) = self
+            .preview_for(host)
+            .map(|(preview, subdomain)| (preview.clone(), subdomain))
+        else {
+            return false;
+        };
+        let Some(host) = normalize_hostname(host) else {
+            return false;
+        };
+
+        let mut taken = HashSet::new();
+        let mut asleep = None;
+        for (other, app) in &self.apps {
+            let mut app = app.write().await;
+            if app.preview.as_ref() != Some(&preview.domain) {
+                continue;
+            }
+            let UpstreamAddress::Fixed(address) = app.address else {
+                continue;
+            };
+            taken.insert(address.port());
+            if asleep.is_none()
+                && !app.command.is_child_running()
+                && !app.idle_deadline.is_pending()
+            {
+                asleep = Some((other.clone(), address.port()));
+            }
+        }
+
+        let free = preview.ports.iter().find(|port| {
+            !taken.contains(port)
+                && std::net::TcpListener::bind((std::net::Ipv4Addr::LOCALHOST, *port)).is_ok()
+        });
+        let port = match (free, asleep) {
+            (Some(port), _) => port,
+            (None, Some((other, port))) => {
+                info!(host = %other, domain = %preview.domain, "dropping sleeping preview to free its port");
+                self.apps.remove(&other);
+                port
+            }
+            (None, None) => {
+                warn!(host = %host, ports = %preview.ports, "no free port for preview");
+                return false;
+            }
+        };
+
+        match preview.instance(&host, &subdomain, port) {
+            Ok(mut app) => {
+                app.start_slots = self.start_slots.clone();
+                app.cluster_node = self.cluster.as_ref().map(|cluster| cluster.node.clone());
+                info!(host = %host, port, "spawned preview");
+                self.apps.insert(host, Arc::new(RwLock::new(app)));
+                true
+            }
+            Err(e) => {
+                warn!(host = %host, error = %e, "failed to spawn preview");
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn each_subdomain_gets_its_own_app_and_port() {
+        let mut config: Config = toml::from_str(
+            r#"
+            ["app.preview.example.com"]
+            address = "127.0.0.1:3001"
+            command = "node server.js"
+            health_check = "/"
+
+            [previews."*.preview.example.com"]
+            ports = "47100-47101"
+            command = "./serve"
+            health_check = "/health"
+            "#,
+        )
+        .unwrap();
+
+        assert!(!config.spawns_preview("app.preview.example.com"));
+        assert!(!config.spawns_preview("a.b.preview.example.com"));
+        assert!(!config.spawns_preview("preview.example.com"));
+        assert!(config.spawns_preview("Feature-X.preview.example.com"));
+
+        assert!(config.spawn_preview("feature-x.preview.example.com").await);
+        assert!(config.spawn_preview("feature-y.preview.example.com").await);
+        assert!(!config.spawns_preview("feature-x.preview.example.com"));
+
+        let x = config.apps["feature-x.preview.example.com"].read().await;
+        let y = config.apps["feature-y.preview.example.com"].read().await;
+        assert_eq!(x.health_check, "/health");
+        assert_eq!(x.preview.as_deref(), Some("preview.example.com"));
+        assert_ne!(x.address.to_string(), y.address.to_string());
+        drop((x, y));
+
+        // Both ports are taken, so a sleeping instance makes room.
+        assert!(config.spawn_preview("feature-z.preview.example.com").await);
+        assert_eq!(config.apps.len(), 3);
+    }
+
+    #[test]
+    fn previews_need_a_wildcard_and_ports() {
+        let preview = |table: &str| {
+            toml::from_str::<Config>(&format!(
+                "[previews.{table}]\ncommand = \"./serve\"\nhealth_check = \"/\""
+            ))
+        };
+        assert!(preview("\"*.preview.example.com\"\nports = \"4100-4199\"").is_ok());
+        assert!(preview("\"preview.example.com\"\nports = \"4100-4199\"").is_err());
+        assert!(preview("\"*.preview.example.com\"").is_err());
+        assert!(
+            preview("\"*.preview.example.com\"\nports = \"4100-4199\"\naddress = \"127.0.0.1:1\"")
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use std::net::TcpListener;
+use std::os::fd::{IntoRawFd, RawFd};
+
+use color_eyre::eyre::{Context, eyre};
+use nix::unistd::{Group, User, setgid, setgroups, setuid};
+use tracing::info;
+
+/// Binds `address` while penny may still be root, for pingora to adopt
+/// once privileges are dropped.
+pub fn bind_early(address: &str) -> color_eyre::Result<RawFd> {
+    let listener =
+        TcpListener::bind(address).wrap_err_with(|| format!("failed to bind {address}"))?;
+    listener.set_nonblocking(true)?;
+    Ok(listener.into_raw_fd())
+}
+
+/// Switches the process to `user` and `group` (the user's primary group by
+/// default). Must run before penny spawns any threads.
+pub fn drop_privileges(user: &str, group: Option<&str>) -> color_eyre::Result<()> {
+    let account = User::from_name(user)
+        .wrap_err_with(|| format!("failed to look up user '{user}'"))?
+        .ok_or_else(|| eyre!("user '{user}' does not exist"))?;
+    let gid = match group {
+        Some(name) => {
+            Group::from_name(name)
+                .wrap_err_with(|| format!("failed to look up group '{name}'"))?
+                .ok_or_else(|| eyre!("group '{name}' does not exist"))?
+                .gid
+        }
+        None => account.gid,
+    };
+
+    setgroups(&[gid]).wrap_err("failed to drop supplementary groups; is penny running as root?")?;
+    setgid(gid).wrap_err_with(|| format!("failed to switch to group {gid}"))?;
+    setuid(account.uid).wrap_err_with(|| format!("failed to switch to user '{user}'"))?;
+
+    // Apps inherit penny's environment, so point it at the new user.
+    // SAFETY: called during startup, before penny spawns any threads.
+    unsafe {
+        std::env::set_var("HOME", &account.dir);
+        std::env::set_var("USER", user);
+        std::env::set_var("LOGNAME", user);
+    }
+
+    info!(user, uid = %account.uid, gid = %gid, "dropped root privileges");
+    Ok(())
+}
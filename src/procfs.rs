@@ -0,0 +1,138 @@
+use std::collections::{HashMap, HashSet};
+
+/// Linux's `USER_HZ`, the unit of CPU time in `/proc`. It is 100 on every
+/// architecture penny runs on.
+pub const CLOCK_TICKS_PER_SEC: u64 = 100;
+
+/// A process as `/proc/<pid>/status` and `/proc/<pid>/stat` describe it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Process {
+    pid: u32,
+    ppid: u32,
+    /// Resident memory in KiB.
+    rss_kb: u64,
+    /// User and system CPU time, in clock ticks.
+    cpu_ticks: u64,
+}
+
+/// Resources used by a process tree.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Usage {
+    /// Resident memory, in bytes.
+    pub memory_bytes: u64,
+    /// CPU time used so far, in [`CLOCK_TICKS_PER_SEC`] ticks. Time of
+    /// processes that have exited is not included.
+    pub cpu_ticks: u64,
+}
+
+fn read_process(pid: u32) -> Option<Process> {
+    let status = std::fs::read_to_string(format!("/proc/{pid}/status")).ok()?;
+    let field = |name: &str| {
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+            .and_then(|value| value.split_whitespace().next()?.parse::<u64>().ok())
+    };
+    // The command name in parentheses may contain spaces, so fields are
+    // counted from after it: utime and stime are the 12th and 13th.
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    let mut times = stat
+        .rsplit_once(')')?
+        .1
+        .split_whitespace()
+        .skip(11)
+        .map(|ticks| ticks.parse::<u64>().ok());
+    let cpu_ticks = times.next()?? + times.next()??;
+
+    Some(Process {
+        pid,
+        ppid: field("PPid")? as u32,
+        // Kernel threads have no memory of their own.
+        rss_kb: field("VmRSS").unwrap_or(0),
+        cpu_ticks,
+    })
+}
+
+/// Every process visible in `/proc`, or `None` where there is no `/proc`.
+fn processes() -> Option<Vec<Process>> {
+    let entries = std::fs::read_dir("/proc").ok()?;
+    Some(
+        entries
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+            .filter_map(read_process)
+            .collect(),
+    )
+}
+
+/// Resources used by `roots` and every process they started.
+fn tree_usage_of(processes: &[Process], roots: &[u32]) -> Usage {
+    let mut children: HashMap<u32, Vec<&Process>> = HashMap::new();
+    let mut by_pid = HashMap::new();
+    for process in processes {
+        children.entry(process.ppid).or_default().push(process);
+        by_pid.insert(process.pid, process);
+    }
+
+    let mut seen = HashSet::new();
+    let mut pending: Vec<&Process> = roots
+        .iter()
+        .filter_map(|pid| by_pid.get(pid).copied())
+        .collect();
+    let mut usage = Usage::default();
+    while let Some(process) = pending.pop() {
+        if !seen.insert(process.pid) {
+            continue;
+        }
+        usage.memory_bytes += process.rss_kb * 1024;
+        usage.cpu_ticks += process.cpu_ticks;
+        pending.extend(children.get(&process.pid).into_iter().flatten().copied());
+    }
+    usage
+}
+
+/// Resources used by the processes `roots` and everything they started,
+/// like the workers of `npm start`. `None` where `/proc` is not available.
+pub fn tree_usage(roots: &[u32]) -> Option<Usage> {
+    let processes = processes()?;
+    Some(tree_usage_of(&processes, roots))
+}
+
+/// Resident memory of the processes `roots` and everything they started,
+/// in bytes.
+pub fn tree_memory_bytes(roots: &[u32]) -> Option<u64> {
+    tree_usage(roots).map(|usage| usage.memory_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usage_of_descendants_is_counted_once() {
+        let process = |pid, ppid, rss_kb| Process {
+            pid,
+            ppid,
+            rss_kb,
+            cpu_ticks: 1,
+        };
+        let processes = [
+            process(1, 0, 1000),
+            process(10, 1, 100),
+            process(11, 10, 20),
+            process(12, 11, 3),
+            process(20, 1, 7),
+        ];
+        let usage = tree_usage_of(&processes, &[10]);
+        assert_eq!(usage.memory_bytes, 123 * 1024);
+        assert_eq!(usage.cpu_ticks, 3);
+        assert_eq!(
+            tree_usage_of(&processes, &[10, 11, 20]).memory_bytes,
+            130 * 1024
+        );
+        assert_eq!(tree_usage_of(&processes, &[99]), Usage::default());
+
+        if let Some(bytes) = tree_memory_bytes(&[std::process::id()]) {
+            assert!(bytes > 0);
+        }
+    }
+}
@@ -1,29 +1,44 @@
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
 
 use bytes::Bytes;
 use tokio::sync::RwLock;
 use tracing::{debug, error, info, warn};
 
 use crate::challenge::{ChallengeStore, get_challenge};
+use crate::client_ip::{self, ClientIp};
 use crate::collector::Collector;
-use crate::config::{App, Config};
-use crate::types::Host;
+use crate::config::{App, AsleepFile, HstsConfig, NonNavigationPolicy, SLEEP_PATH, SharedConfig};
+use crate::idle::IdleScheduler;
+use crate::loading_page::{self, ColdStartPage};
+use crate::metrics;
+use crate::monitor;
+use crate::tls::ServerName;
+use crate::types::{Host, ProxyFailure, WakeReason};
 
 pub struct YarpProxy<C> {
-    pub config: Config,
+    pub config: SharedConfig,
     pub collector: C,
     pub challenge_store: ChallengeStore,
+    pub idle: IdleScheduler,
 }
 
 impl<C> YarpProxy<C>
 where
     C: Collector,
 {
-    pub fn new(config: Config, collector: C, challenge_store: ChallengeStore) -> Self {
+    pub fn new(
+        config: SharedConfig,
+        collector: C,
+        challenge_store: ChallengeStore,
+        idle: IdleScheduler,
+    ) -> Self {
         Self {
             config,
             collector,
             challenge_store,
+            idle,
         }
     }
 
@@ -47,33 +62,220 @@ where
         session: &mut pingora::prelude::Session,
         proxy_ctx: &ProxyContext,
         app: &Arc<RwLock<App>>,
-        cold_start_page_html: Option<&str>,
+        page: ColdStartPage,
     ) -> pingora::Result<Option<bool>> {
-        let is_ready = App::begin_start_app(&proxy_ctx.host, app, self.collector.clone()).await?;
-        App::schedule_kill(&proxy_ctx.host, app, self.collector.clone()).await;
+        let is_ready = App::begin_start_app(
+            &proxy_ctx.host,
+            app,
+            self.collector.clone(),
+            proxy_ctx.wake_reason,
+        )
+        .await?;
+        self.record_activity(proxy_ctx, app).await;
         if !is_ready {
-            return Ok(Some(
-                respond_with_loading_page(session, &proxy_ctx.host.0, cold_start_page_html).await?,
-            ));
+            let body = loading_page_body(&proxy_ctx.host, app, &page).await;
+            return Ok(Some(respond_with_loading_page(session, body).await?));
         }
         Ok(None)
     }
 
-    fn warm_related_apps(&self, also_warm: Vec<String>) {
+    /// Applies the app's `non_navigation` policy, answering 503 if the app
+    /// is still starting (after queueing, for `queue`).
+    async fn handle_non_navigation(
+        &self,
+        session: &mut pingora::prelude::Session,
+        proxy_ctx: &ProxyContext,
+        app: &Arc<RwLock<App>>,
+        (policy, queue_timeout): (NonNavigationPolicy, std::time::Duration),
+    ) -> pingora::Result<Option<bool>> {
+        let mut is_ready = App::begin_start_app(
+            &proxy_ctx.host,
+            app,
+            self.collector.clone(),
+            proxy_ctx.wake_reason,
+        )
+        .await?;
+        if !is_ready && policy == NonNavigationPolicy::Queue {
+            debug!(host = %proxy_ctx.host, "queueing request until the app is ready");
+            is_ready = match tokio::time::timeout(queue_timeout, App::wait_for_healthy(app)).await {
+                Ok(result) => result.map(|_| true)?,
+                Err(_) => false,
+            };
+        }
+        if is_ready {
+            return Ok(None);
+        }
+
+        self.record_activity(proxy_ctx, app).await;
+        let guard = app.read().await;
+        let elapsed = guard
+            .start_progress()
+            .map(|progress| progress.since.elapsed())
+            .unwrap_or_default();
+        let retry_after = guard
+            .last_cold_start_ms
+            .map(|ms| std::time::Duration::from_millis(ms).saturating_sub(elapsed))
+            .map_or(DEFAULT_RETRY_AFTER_SECS, |eta| eta.as_secs().max(1));
+        drop(guard);
+
+        debug!(host = %proxy_ctx.host, retry_after, "app is starting, asking client to retry");
+        Ok(Some(
+            respond_starting(session, &proxy_ctx.host, retry_after).await?,
+        ))
+    }
+
+    /// Stores why a request could not be served, for `/api/errors`.
+    async fn record_failure(&self, host: &Host, path: &str, failure: ProxyFailure, detail: &str) {
+        if let Err(e) = self
+            .collector
+            .proxy_failed(host, path, failure, detail)
+            .await
+        {
+            error!(host = %host, error = %e, "failed to record proxy failure");
+        }
+    }
+
+    /// Pushes back the app's idle stop, unless the request is to one of its
+    /// `ignore_paths`; those only make sure a stop is scheduled.
+    async fn record_activity(&self, proxy_ctx: &ProxyContext, app: &Arc<RwLock<App>>) {
+        {
+            let guard = app.read().await;
+            // Nothing to put to sleep; only count the request.
+            if !guard.managed {
+                if !proxy_ctx.idle_exempt {
+                    guard.request_tracker.record_request();
+                }
+                return;
+            }
+        }
+
+        if proxy_ctx.idle_exempt {
+            self.idle.ensure_scheduled(&proxy_ctx.host, app).await;
+        } else {
+            self.idle.schedule_stop(&proxy_ctx.host, app).await;
+        }
+    }
+
+    /// Adjusts the app's idle stop as its response asks: `X-Penny-Keep-Alive`
+    /// keeps it up for at least the given duration, `X-Penny-Sleep-Now` stops
+    /// it once its requests finish. Both headers stay between penny and the
+    /// app.
+    async fn apply_idle_hints(
+        &self,
+        host: &Host,
+        app: &Arc<RwLock<App>>,
+        response: &mut pingora::http::ResponseHeader,
+    ) {
+        let keep_alive = response.remove_header(KEEP_ALIVE_HEADER);
+        let sleep_now = response.remove_header(SLEEP_NOW_HEADER).is_some();
+        if !app.read().await.managed {
+            return;
+        }
+
+        if sleep_now {
+            info!(host = %host, "app asked to sleep now");
+            self.idle.sleep_now(host, app).await;
+        } else if let Some(value) = keep_alive {
+            match value
+                .to_str()
+                .ok()
+                .and_then(|value| value.trim().parse::<jiff::SignedDuration>().ok())
+                .filter(|duration| duration.is_positive())
+            {
+                Some(duration) => {
+                    debug!(host = %host, keep_alive = %duration, "app asked to be kept up");
+                    self.idle
+                        .keep_alive(host, app, duration.unsigned_abs())
+                        .await;
+                }
+                None => {
+                    warn!(host = %host, value = ?value, "ignoring invalid X-Penny-Keep-Alive");
+                }
+            }
+        }
+    }
+
+    /// Answers `POST /.penny/sleep` for an app with a `sleep_token`,
+    /// stopping it once its requests in progress finish.
+    async fn respond_sleep(
+        &self,
+        session: &mut pingora::proxy::Session,
+        host: &Host,
+        app: &Arc<RwLock<App>>,
+        token: &str,
+    ) -> pingora::Result<bool> {
+        let status = if session.req_header().method != http::Method::POST {
+            405
+        } else if !bearer_matches(session, token) {
+            warn!(host = %host, "rejected sleep request with a wrong token");
+            401
+        } else {
+            info!(host = %host, "app asked to sleep");
+            self.idle.sleep_now(host, app).await;
+            202
+        };
+
+        let mut resp = pingora::http::ResponseHeader::build(status, None)?;
+        resp.insert_header(http::header::CACHE_CONTROL, "no-store")?;
+        if status == 405 {
+            resp.insert_header(http::header::ALLOW, "POST")?;
+        }
+        session.write_response_header(Box::new(resp), true).await?;
+
+        Ok(true)
+    }
+
+    /// Starts the app for a request. An app with a `fallback_address` only
+    /// gets its `fallback_after` to come up, or to pass its health check if
+    /// penny does not manage it. Returns true if the request should go to
+    /// the fallback instead, while the start carries on.
+    async fn start_or_fall_back(
+        &self,
+        ctx: &ProxyContext,
+        app: &Arc<RwLock<App>>,
+    ) -> pingora::Result<bool> {
+        let guard = app.read().await;
+        if guard.fallback_address.is_none() {
+            drop(guard);
+            App::start_app(&ctx.host, app, self.collector.clone(), ctx.wake_reason).await?;
+            return Ok(false);
+        }
+        let budget = guard.fallback_after.unsigned_abs();
+        drop(guard);
+
+        let up = match App::begin_start_app(&ctx.host, app, self.collector.clone(), ctx.wake_reason)
+            .await
+        {
+            Ok(true) => true,
+            Ok(false) => tokio::time::timeout(budget, App::wait_for_healthy(app))
+                .await
+                .is_ok_and(|healthy| healthy.is_ok()),
+            Err(e) => {
+                debug!(host = %ctx.host, error = %e, "app is down");
+                false
+            }
+        };
+        Ok(!up)
+    }
+
+    async fn warm_related_apps(&self, also_warm: Vec<String>) {
+        let config = self.config.read().await;
         for hostname in also_warm {
-            if let Some(related_app) = self.config.apps.get(&hostname) {
+            if let Some(related_app) = config.apps.get(&hostname) {
                 let related_app = related_app.clone();
                 let host = Host(hostname.clone());
                 let collector = self.collector.clone();
+                let idle = self.idle.clone();
                 tokio::spawn(async move {
                     info!(host = %host, "warming related app");
                     if let Err(e) =
-                        App::begin_start_app(&host, &related_app, collector.clone()).await
+                        App::begin_start_app(&host, &related_app, collector, WakeReason::Prewarm)
+                            .await
                     {
                         warn!(host = %host, error = %e, "failed to warm related app");
                         return;
                     }
-                    App::schedule_kill(&host, &related_app, collector).await;
+                    idle.schedule_stop(&host, &related_app).await;
                 });
             } else {
                 warn!(hostname = %hostname, "also_warm target not found in config");
@@ -99,14 +301,92 @@ async fn respond_to_acme_challenge(
     Ok(true)
 }
 
-pub fn get_host(session: &pingora::prelude::Session) -> Option<&str> {
+/// Returns the first value of a comma-separated `X-Forwarded-*` header.
+fn forwarded_header<'a>(session: &'a pingora::prelude::Session, name: &str) -> Option<&'a str> {
     session
-        .get_header(http::header::HOST)
+        .get_header(name)
         .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(',').next())
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+/// The address of the connection's peer, before any `X-Forwarded-For`.
+fn client_addr(session: &pingora::prelude::Session) -> Option<std::net::IpAddr> {
+    session
+        .client_addr()
+        .and_then(|addr| addr.as_inet())
+        .map(|addr| addr.ip())
+}
+
+/// Every `X-Forwarded-For` header of the request, joined into one chain.
+fn forwarded_for(session: &pingora::prelude::Session) -> Option<String> {
+    let hops: Vec<&str> = session
+        .req_header()
+        .headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect();
+    (!hops.is_empty()).then(|| hops.join(", "))
+}
+
+/// Returns the request host without port. With `trust_forwarded`, the
+/// `X-Forwarded-Host` set by a fronting proxy takes precedence.
+pub fn get_host(session: &pingora::prelude::Session, trust_forwarded: bool) -> Option<&str> {
+    trust_forwarded
+        .then(|| forwarded_header(session, "x-forwarded-host"))
+        .flatten()
+        .or_else(|| {
+            session
+                .get_header(http::header::HOST)
+                .and_then(|value| value.to_str().ok())
+        })
         .and_then(|host| host.split(':').next())
         .or(session.req_header().uri.host())
 }
 
+/// The SNI hostname the request's TLS connection was opened for.
+fn server_name(session: &pingora::prelude::Session) -> Option<&str> {
+    let digest = session.digest()?.ssl_digest.as_ref()?;
+    digest
+        .extension
+        .get::<ServerName>()
+        .map(|name| name.0.as_str())
+}
+
+/// Returns true if the downstream connection was accepted over TLS, or, with
+/// `trust_forwarded`, if the fronting proxy reports `X-Forwarded-Proto: https`.
+fn is_tls(session: &pingora::prelude::Session, trust_forwarded: bool) -> bool {
+    let forwarded_https = trust_forwarded
+        && forwarded_header(session, "x-forwarded-proto")
+            .is_some_and(|proto| proto.eq_ignore_ascii_case("https"));
+
+    forwarded_https
+        || session
+            .digest()
+            .is_some_and(|digest| digest.ssl_digest.is_some())
+}
+
+/// `User-Agent` fragments of crawlers and link previews.
+const BOT_USER_AGENTS: &[&str] = &[
+    "bot",
+    "crawl",
+    "spider",
+    "slurp",
+    "facebookexternalhit",
+    "embedly",
+    "preview",
+];
+
+fn is_bot(session: &pingora::prelude::Session) -> bool {
+    session
+        .get_header(http::header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .map(|agent| agent.to_ascii_lowercase())
+        .is_some_and(|agent| BOT_USER_AGENTS.iter().any(|bot| agent.contains(bot)))
+}
+
 fn is_browser_navigation(session: &pingora::prelude::Session) -> bool {
     // Must be GET
     if session.req_header().method != http::Method::GET {
@@ -150,104 +430,44 @@ fn is_browser_navigation(session: &pingora::prelude::Session) -> bool {
     true
 }
 
-fn escape_html(s: &str) -> String {
-    let mut escaped = String::with_capacity(s.len());
-    for c in s.chars() {
-        match c {
-            '&' => escaped.push_str("&amp;"),
-            '<' => escaped.push_str("&lt;"),
-            '>' => escaped.push_str("&gt;"),
-            '"' => escaped.push_str("&quot;"),
-            '\'' => escaped.push_str("&#x27;"),
-            _ => escaped.push(c),
-        }
-    }
-    escaped
-}
+/// Renders the cold start page with how the start is going. The app's lock
+/// is only held to read that, not while its health checks run.
+async fn loading_page_body(host: &Host, app: &Arc<RwLock<App>>, page: &ColdStartPage) -> String {
+    let guard = app.read().await;
+    let progress = guard.start_progress();
+    let elapsed = progress
+        .as_ref()
+        .map(|progress| progress.since.elapsed())
+        .unwrap_or_default();
+    let timeout = guard.start_timeout.unsigned_abs();
+    let eta = guard
+        .last_cold_start_ms
+        .map(|ms| std::time::Duration::from_millis(ms).saturating_sub(elapsed));
+    let output = guard.start_output.lines();
+    drop(guard);
 
-fn loading_page_html(host: &str) -> String {
-    let host = escape_html(host);
-    format!(
-        r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta charset="utf-8">
-    <meta name="viewport" content="width=device-width, initial-scale=1">
-    <meta http-equiv="refresh" content="2">
-    <title>Starting {host}...</title>
-    <style>
-        * {{ margin: 0; padding: 0; box-sizing: border-box; }}
-        body {{
-            font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", Roboto, sans-serif;
-            display: flex;
-            justify-content: center;
-            align-items: center;
-            min-height: 100vh;
-            background: #fafafa;
-            color: #0a0a0a;
-        }}
-        @media (prefers-color-scheme: dark) {{
-            body {{ background: #0a0a0a; color: #fafafa; }}
-            .github-link {{ color: #a1a1a1; }}
-            .github-link:hover {{ color: #f97316; }}
-        }}
-        .container {{ text-align: center; padding: 2rem; }}
-        .logo {{
-            animation: pulse 1s ease-in-out infinite;
-        }}
-        @keyframes pulse {{
-            0%, 100% {{ opacity: 1; transform: scale(1); }}
-            50% {{ opacity: 0.6; transform: scale(0.95); }}
-        }}
-        h1 {{
-            font-size: 1.25rem;
-            font-weight: 500;
-            margin-top: 1.5rem;
-            margin-bottom: 0.5rem;
-        }}
-        .subtitle {{
-            font-size: 0.875rem;
-            color: #888;
-            margin-bottom: 1.5rem;
-        }}
-        .github-link {{
-            font-size: 0.75rem;
-            color: #888;
-            text-decoration: none;
-        }}
-        .github-link:hover {{ color: #f97316; }}
-    </style>
-</head>
-<body>
-    <div class="container">
-        <svg class="logo" width="80" height="80" viewBox="0 0 100 100" fill="none"
-             xmlns="http://www.w3.org/2000/svg" role="img">
-            <title>Penny Logo</title>
-            <circle cx="50" cy="50" r="45" stroke="currentColor" stroke-width="6" fill="none" />
-            <circle cx="50" cy="50" r="35" stroke="currentColor" stroke-width="3" fill="none" />
-            <text x="50" y="58" text-anchor="middle" fill="currentColor"
-                  font-size="36" font-weight="bold" font-family="system-ui, sans-serif">P</text>
-        </svg>
-        <h1>Starting {host}</h1>
-        <p class="subtitle">This page will refresh automatically.</p>
-        <a class="github-link" href="https://github.com/frectonz/penny"
-           target="_blank" rel="noopener noreferrer">github.com/frectonz/penny</a>
-    </div>
-</body>
-</html>"#,
-        host = host
+    loading_page::render(
+        page.template.as_deref(),
+        &loading_page::PageInfo {
+            host: &host.0,
+            lang: &page.lang,
+            elapsed,
+            timeout,
+            eta,
+            checks: progress.as_ref().map_or(0, |progress| progress.checks),
+            last_error: progress
+                .as_ref()
+                .and_then(|progress| progress.last_error.as_deref()),
+            output: &output,
+            branding: &page.branding,
+        },
     )
 }
 
 async fn respond_with_loading_page(
     session: &mut pingora::proxy::Session,
-    host: &str,
-    custom_html: Option<&str>,
+    body: String,
 ) -> pingora::Result<bool> {
-    let body = match custom_html {
-        Some(html) => html.to_owned(),
-        None => loading_page_html(host),
-    };
     let mut resp = pingora::http::ResponseHeader::build(202, None)?;
     resp.insert_header(http::header::CONTENT_TYPE, "text/html; charset=utf-8")?;
     resp.insert_header(http::header::CONTENT_LENGTH, body.len().to_string())?;
@@ -262,36 +482,247 @@ async fn respond_with_loading_page(
     Ok(true)
 }
 
+/// Answers 502 for an app penny does not manage whose health check failed,
+/// with the built-in page for browsers.
+async fn respond_unavailable(
+    session: &mut pingora::proxy::Session,
+    host: &Host,
+    page: &ColdStartPage,
+    error: &str,
+    navigation: bool,
+) -> pingora::Result<bool> {
+    let (content_type, body) = if navigation {
+        (
+            "text/html; charset=utf-8",
+            loading_page::render_unavailable(&host.0, &page.lang, error, &page.branding),
+        )
+    } else {
+        (
+            "text/plain; charset=utf-8",
+            format!("{host} is unavailable: {error}\n"),
+        )
+    };
+    let mut resp = pingora::http::ResponseHeader::build(502, None)?;
+    resp.insert_header(http::header::CONTENT_TYPE, content_type)?;
+    resp.insert_header(http::header::CONTENT_LENGTH, body.len().to_string())?;
+    resp.insert_header(http::header::CACHE_CONTROL, "no-store")?;
+
+    session.write_response_header(Box::new(resp), false).await?;
+    session
+        .write_response_body(Some(Bytes::from(body)), true)
+        .await?;
+
+    Ok(true)
+}
+
+/// Answers `421 Misdirected Request`, so clients that reused a connection
+/// opened for another host retry on a new one.
+async fn respond_misdirected(session: &mut pingora::proxy::Session) -> pingora::Result<bool> {
+    let body = "misdirected request\n";
+    let mut resp = pingora::http::ResponseHeader::build(421, None)?;
+    resp.insert_header(http::header::CONTENT_TYPE, "text/plain; charset=utf-8")?;
+    resp.insert_header(http::header::CONTENT_LENGTH, body.len().to_string())?;
+
+    session.write_response_header(Box::new(resp), false).await?;
+    session
+        .write_response_body(Some(Bytes::from(body)), true)
+        .await?;
+
+    Ok(true)
+}
+
+/// Whether the request's `Authorization` is `Bearer <token>`. Compares in
+/// constant time, so the token cannot be guessed byte by byte.
+fn bearer_matches(session: &pingora::proxy::Session, token: &str) -> bool {
+    let Some(provided) = session
+        .get_header(http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+    provided.len() == token.len()
+        && provided
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Answers an uptime monitor request for a sleeping app without waking it.
+async fn respond_asleep(
+    session: &mut pingora::proxy::Session,
+    host: &Host,
+) -> pingora::Result<bool> {
+    debug!(host = %host, "app is asleep, answering monitor request");
+    let mut resp = pingora::http::ResponseHeader::build(204, None)?;
+    resp.insert_header(http::header::CACHE_CONTROL, "no-store")?;
+    resp.insert_header(monitor::ASLEEP_HEADER, "1")?;
+
+    session.write_response_header(Box::new(resp), true).await?;
+
+    Ok(true)
+}
+
+/// Answers a request for one of the app's `asleep_robots_txt` or
+/// `asleep_favicon` without waking it.
+async fn respond_asleep_file(
+    session: &mut pingora::proxy::Session,
+    host: &Host,
+    path: &str,
+    file: AsleepFile,
+) -> pingora::Result<bool> {
+    debug!(host = %host, path = %path, "app is asleep, serving file");
+    let mut resp = pingora::http::ResponseHeader::build(200, None)?;
+    resp.insert_header(http::header::CONTENT_TYPE, file.content_type.as_str())?;
+    resp.insert_header(http::header::CONTENT_LENGTH, file.body.len().to_string())?;
+
+    let head = session.req_header().method == http::Method::HEAD;
+    session.write_response_header(Box::new(resp), head).await?;
+    if !head {
+        session.write_response_body(Some(file.body), true).await?;
+    }
+
+    Ok(true)
+}
+
+/// `Retry-After` for apps without a previous cold start to estimate from.
+const DEFAULT_RETRY_AFTER_SECS: u64 = 2;
+
+/// Response headers an app sends to keep itself up for a while, e.g.
+/// `X-Penny-Keep-Alive: 30m`, or to be stopped right away.
+const KEEP_ALIVE_HEADER: &str = "x-penny-keep-alive";
+const SLEEP_NOW_HEADER: &str = "x-penny-sleep-now";
+
+async fn respond_starting(
+    session: &mut pingora::proxy::Session,
+    host: &Host,
+    retry_after: u64,
+) -> pingora::Result<bool> {
+    let body = format!("{host} is starting, retry in {retry_after}s\n");
+    let mut resp = pingora::http::ResponseHeader::build(503, None)?;
+    resp.insert_header(http::header::CONTENT_TYPE, "text/plain; charset=utf-8")?;
+    resp.insert_header(http::header::CONTENT_LENGTH, body.len().to_string())?;
+    resp.insert_header(http::header::CACHE_CONTROL, "no-store")?;
+    resp.insert_header(http::header::RETRY_AFTER, retry_after.to_string())?;
+
+    session.write_response_header(Box::new(resp), false).await?;
+    session
+        .write_response_body(Some(Bytes::from(body)), true)
+        .await?;
+
+    Ok(true)
+}
+
 pub struct ProxyContext {
     pub host: Host,
     pub app: Option<Arc<RwLock<App>>>,
-    pub peer: Box<pingora::prelude::HttpPeer>,
+    /// Set for the dashboard API on `api_domain`. Apps resolve their address
+    /// per request instead, once they are running.
+    pub api_address: Option<std::net::SocketAddr>,
+    /// `Strict-Transport-Security` value to add to HTTPS responses.
+    pub hsts: Option<String>,
+    /// Set for monitoring: requests to one of the app's `ignore_paths`, from
+    /// one of its `ignore_user_agents`, or by penny's uptime monitor.
+    pub idle_exempt: bool,
+    /// Path prefix the dashboard is served under (`api_path`), stripped
+    /// before forwarding.
+    pub api_prefix: Option<&'static str>,
+    /// Counts this request as in progress, against the app's
+    /// `keepalive_max_connections` and its idle stop.
+    pub in_flight: Option<InFlight>,
+    /// Close the upstream connection after this request instead of pooling
+    /// it.
+    pub close_upstream: bool,
+    /// Records where the request's time went; logged when it closes.
+    pub span: tracing::Span,
+    /// When the upstream was picked, after any cold start.
+    pub upstream_started: Option<Instant>,
+    /// When the connection to the upstream was ready.
+    pub connected_at: Option<Instant>,
+    /// When penny received the request.
+    pub received_at: Instant,
+    /// What to record as having woken the app, if this request does.
+    pub wake_reason: WakeReason,
+    /// Sent by penny's uptime monitor: not counted as traffic, and answered
+    /// by penny itself while the app sleeps.
+    pub monitor: bool,
+    /// Who sent the request, honoring `X-Forwarded-For` from
+    /// `trusted_proxies`.
+    pub client: Option<ClientIp>,
+    /// The request's path, recorded with any failure.
+    pub path: String,
+    /// Why the request could not be served, when penny knows better than
+    /// the error it fails with.
+    pub failure: Option<ProxyFailure>,
+    /// Sent to the app's `fallback_address`, since the app was not up in
+    /// time.
+    pub fallback: bool,
 }
 
-impl ProxyContext {
-    pub async fn new(host: &str, app: Arc<RwLock<App>>) -> Self {
-        let address = app.read().await.address;
+/// One request to an app in progress, until dropped.
+pub struct InFlight(Arc<AtomicUsize>);
+
+impl InFlight {
+    /// Counts a request. Returns it with how many are now in progress.
+    fn start(counter: &Arc<AtomicUsize>) -> (Self, usize) {
+        let count = counter.fetch_add(1, Ordering::AcqRel) + 1;
+        (Self(counter.clone()), count)
+    }
+}
 
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+impl ProxyContext {
+    /// A context for a request to `app`, with the global `hsts` until
+    /// `request_filter` reads the app's own.
+    pub fn new(host: &str, app: Arc<RwLock<App>>, default_hsts: Option<&HstsConfig>) -> Self {
         Self {
             app: Some(app),
+            api_address: None,
+            hsts: default_hsts.map(HstsConfig::header_value),
+            idle_exempt: false,
+            api_prefix: None,
+            in_flight: None,
+            close_upstream: false,
+            span: tracing::Span::none(),
+            upstream_started: None,
+            connected_at: None,
+            received_at: Instant::now(),
+            wake_reason: WakeReason::Client,
+            monitor: false,
+            client: None,
+            path: String::new(),
+            failure: None,
+            fallback: false,
             host: Host(host.to_owned()),
-            peer: Box::new(pingora::prelude::HttpPeer::new(
-                address,
-                false,
-                host.to_owned(),
-            )),
         }
     }
 
-    pub fn new_api(host: &str, address: std::net::SocketAddr) -> Self {
+    pub fn new_api(host: &str, address: std::net::SocketAddr, hsts: Option<&HstsConfig>) -> Self {
         Self {
             app: None,
+            api_address: Some(address),
+            hsts: hsts.map(HstsConfig::header_value),
+            idle_exempt: false,
+            api_prefix: None,
+            in_flight: None,
+            close_upstream: false,
+            span: tracing::Span::none(),
+            upstream_started: None,
+            connected_at: None,
+            received_at: Instant::now(),
+            wake_reason: WakeReason::Client,
+            monitor: false,
+            client: None,
+            path: String::new(),
+            failure: None,
+            fallback: false,
             host: Host(host.to_owned()),
-            peer: Box::new(pingora::prelude::HttpPeer::new(
-                address,
-                false,
-                host.to_owned(),
-            )),
         }
     }
 }
@@ -318,39 +749,174 @@ where
             return Ok(result);
         }
 
-        let host = get_host(session)
+        let mut config = self.config.read().await;
+        let host = get_host(session, config.trust_forwarded_headers)
             .ok_or_else(|| {
                 warn!("request missing host header");
                 pingora::Error::explain(pingora::ErrorType::InvalidHTTPHeader, "failed to get host")
             })?
             .to_owned();
 
+        if config.spawns_preview(&host) {
+            drop(config);
+            self.config.write().await.spawn_preview(&host).await;
+            config = self.config.read().await;
+        }
+
+        if let Some(sni) = server_name(session)
+            && !config.same_route(sni, &host)
+        {
+            warn!(sni = %sni, host = %host, "request host does not match TLS server name");
+            drop(config);
+            return respond_misdirected(session).await;
+        }
+
         debug!(host = %host, "processing request");
-        *ctx = self.config.get_proxy_context(&host).await;
+        *ctx = config.get_proxy_context(&host, &path);
+        if let Some(proxy_ctx) = ctx.as_mut() {
+            proxy_ctx.path = path.clone();
+            proxy_ctx.client = client_addr(session).map(|peer| {
+                client_ip::resolve(
+                    peer,
+                    forwarded_for(session).as_deref(),
+                    &config.trusted_proxies,
+                )
+            });
+            proxy_ctx.span = tracing::info_span!(
+                "request",
+                host = %proxy_ctx.host,
+                client = tracing::field::Empty,
+                method = %session.req_header().method,
+                path = %path,
+                status = tracing::field::Empty,
+                cold_start_wait_ms = tracing::field::Empty,
+                connect_ms = tracing::field::Empty,
+                reused = tracing::field::Empty,
+                ttfb_ms = tracing::field::Empty,
+            );
+            if let Some(client) = &proxy_ctx.client {
+                proxy_ctx
+                    .span
+                    .record("client", tracing::field::display(client.ip));
+            }
+            proxy_ctx.monitor = monitor::is_monitor_request(&session.req_header().headers);
+        }
 
-        if let Some(proxy_ctx) = ctx.as_ref()
+        // Not held while waiting for an app's lock, so a request to an app
+        // that is busy starting cannot hold up config writers and, behind
+        // them, requests to every other app.
+        drop(config);
+
+        if let Some(proxy_ctx) = ctx.as_mut()
             && let Some(app) = &proxy_ctx.app
         {
-            let guard = app.read().await;
-            let cold_start_page = guard.cold_start_page;
-            let cold_start_page_html = guard.cold_start_page_html.clone();
+            let guard = metrics::read_app(app).await;
+            if let Some(hsts) = &guard.hsts {
+                proxy_ctx.hsts = Some(hsts.header_value());
+            }
+            let agent = session
+                .get_header(http::header::USER_AGENT)
+                .and_then(|agent| agent.to_str().ok());
+            proxy_ctx.idle_exempt = guard.ignores_path(&path)
+                || agent.is_some_and(|agent| guard.ignores_user_agent(agent))
+                || proxy_ctx.monitor;
+            let asleep = guard.managed && !guard.command.is_running();
+            let asleep_file = asleep
+                .then(|| guard.asleep_files.get(path.as_str()).cloned())
+                .flatten();
+            // With a fallback, requests to a down app go there instead.
+            let unmanaged_page = !guard.managed && guard.fallback_address.is_none();
+            let page =
+                (unmanaged_page || guard.cold_start_page).then(|| guard.own_cold_start_page());
+            let non_navigation = (
+                guard.non_navigation,
+                guard.non_navigation_queue_timeout.unsigned_abs(),
+            );
             let also_warm = guard.also_warm.clone();
+            let managed = guard.managed;
+            let sleep_token = guard
+                .sleep_token
+                .clone()
+                .filter(|_| managed && path == SLEEP_PATH);
             drop(guard);
+            let page = match page {
+                Some(page) => Some(self.config.read().await.cold_start_page(page)),
+                None => None,
+            };
+            let (unmanaged_page, cold_start_page) = if unmanaged_page {
+                (page, None)
+            } else {
+                (None, page)
+            };
+
+            if let Some(token) = sleep_token {
+                return self
+                    .respond_sleep(session, &proxy_ctx.host, app, &token)
+                    .await;
+            }
+
+            if proxy_ctx.monitor && asleep {
+                return respond_asleep(session, &proxy_ctx.host).await;
+            }
+            if let Some(file) = asleep_file {
+                return respond_asleep_file(session, &proxy_ctx.host, &path, file).await;
+            }
 
-            if cold_start_page
-                && is_browser_navigation(session)
+            let navigation = is_browser_navigation(session);
+            proxy_ctx.wake_reason = if is_bot(session) {
+                WakeReason::Bot
+            } else if navigation {
+                WakeReason::Browser
+            } else {
+                WakeReason::Client
+            };
+            if let Some(page) = unmanaged_page {
+                if let Err(error) =
+                    App::check_unmanaged(&proxy_ctx.host, app, self.collector.clone()).await
+                {
+                    return respond_unavailable(
+                        session,
+                        &proxy_ctx.host,
+                        &page,
+                        &error,
+                        navigation,
+                    )
+                    .await;
+                }
+            } else if let Some(page) = cold_start_page
+                && navigation
                 && let Some(result) = self
-                    .handle_cold_start(session, proxy_ctx, app, cold_start_page_html.as_deref())
+                    .handle_cold_start(session, proxy_ctx, app, page)
                     .await?
             {
                 return Ok(result);
             }
 
-            self.warm_related_apps(also_warm);
+            if !navigation
+                && managed
+                && non_navigation.0 != NonNavigationPolicy::Block
+                && let Some(result) = self
+                    .handle_non_navigation(session, proxy_ctx, app, non_navigation)
+                    .await?
+            {
+                return Ok(result);
+            }
+
+            // Monitors polling an ignored path should not wake other apps.
+            if !proxy_ctx.idle_exempt {
+                self.warm_related_apps(also_warm).await;
+            }
         }
 
         if ctx.is_none() {
             warn!(host = %host, "no app configured for host");
+            self.record_failure(
+                &Host(host),
+                &path,
+                ProxyFailure::NoApp,
+                "no app configured for host",
+            )
+            .await;
         }
 
         Ok(false)
@@ -361,7 +927,7 @@ where
         _session: &mut pingora::proxy::Session,
         ctx: &mut Self::CTX,
     ) -> pingora::Result<Box<pingora::prelude::HttpPeer>> {
-        let ctx = ctx.take().ok_or_else(|| {
+        let ctx = ctx.as_mut().ok_or_else(|| {
             error!("no proxy context available");
             pingora::Error::explain(
                 pingora::ErrorType::ConnectError,
@@ -371,11 +937,368 @@ where
 
         info!(host = %ctx.host, "proxying request");
 
-        if let Some(ref app) = ctx.app {
-            App::start_app(&ctx.host, app, self.collector.clone()).await?;
-            App::schedule_kill(&ctx.host, app, self.collector.clone()).await;
+        let mut keepalive_timeout = None;
+        let address = match (ctx.app.clone(), ctx.api_address) {
+            (Some(app), _) => {
+                let waiting = Instant::now();
+                let started = self.start_or_fall_back(ctx, &app).await;
+                if started.is_err() {
+                    ctx.failure = Some(ProxyFailure::StartFailed);
+                }
+                let fallback = started?;
+                ctx.span
+                    .record("cold_start_wait_ms", waiting.elapsed().as_millis() as u64);
+                self.record_activity(ctx, &app).await;
+
+                let guard = metrics::read_app(&app).await;
+                let upstream = match &guard.fallback_address {
+                    Some(fallback_address) if fallback => {
+                        warn!(host = %ctx.host, fallback = %fallback_address, "app is not up yet, using its fallback_address");
+                        ctx.fallback = true;
+                        fallback_address
+                    }
+                    _ => {
+                        keepalive_timeout = guard
+                            .keepalive_timeout
+                            .map(|timeout| timeout.unsigned_abs());
+                        let (in_flight, count) = InFlight::start(&guard.in_flight);
+                        ctx.in_flight = Some(in_flight);
+                        ctx.close_upstream = guard
+                            .keepalive_max_connections
+                            .is_some_and(|max| count > max);
+                        &guard.address
+                    }
+                };
+                upstream.resolve().await.map_err(|e| {
+                    ctx.failure = Some(ProxyFailure::Connect);
+                    pingora::Error::explain(
+                        pingora::ErrorType::ConnectError,
+                        format!("failed to resolve {upstream}: {e}"),
+                    )
+                })?
+            }
+            (None, Some(address)) => address,
+            (None, None) => {
+                return Err(pingora::Error::explain(
+                    pingora::ErrorType::ConnectError,
+                    "no upstream for host",
+                ));
+            }
+        };
+
+        ctx.upstream_started = Some(Instant::now());
+        let mut peer = pingora::prelude::HttpPeer::new(address, false, ctx.host.0.clone());
+        peer.options.idle_timeout = keepalive_timeout;
+        Ok(Box::new(peer))
+    }
+
+    async fn upstream_request_filter(
+        &self,
+        _session: &mut pingora::prelude::Session,
+        upstream_request: &mut pingora::http::RequestHeader,
+        ctx: &mut Self::CTX,
+    ) -> pingora::Result<()> {
+        // Connections are pooled unless the request or response asks to
+        // close them.
+        if ctx.as_ref().is_some_and(|ctx| ctx.close_upstream) {
+            upstream_request.insert_header(http::header::CONNECTION, "close")?;
+        }
+        upstream_request.remove_header(monitor::HEADER);
+        if let Some(client) = ctx.as_ref().and_then(|ctx| ctx.client.as_ref()) {
+            upstream_request.insert_header("X-Forwarded-For", client.forwarded_for.as_str())?;
+        }
+
+        if let Some(prefix) = ctx.as_ref().and_then(|ctx| ctx.api_prefix)
+            && let Some(uri) = strip_path_prefix(&upstream_request.uri, prefix)
+        {
+            upstream_request.set_uri(uri);
+            upstream_request.insert_header("X-Forwarded-Prefix", prefix)?;
+        }
+
+        Ok(())
+    }
+
+    async fn connected_to_upstream(
+        &self,
+        _session: &mut pingora::prelude::Session,
+        reused: bool,
+        _peer: &pingora::prelude::HttpPeer,
+        _fd: std::os::unix::io::RawFd,
+        _digest: Option<&pingora::protocols::Digest>,
+        ctx: &mut Self::CTX,
+    ) -> pingora::Result<()> {
+        if let Some(proxy_ctx) = ctx.as_mut() {
+            let now = Instant::now();
+            if let Some(started) = proxy_ctx.upstream_started {
+                proxy_ctx
+                    .span
+                    .record("connect_ms", (now - started).as_millis() as u64);
+            }
+            proxy_ctx.span.record("reused", reused);
+            proxy_ctx.connected_at = Some(now);
+        }
+        Ok(())
+    }
+
+    async fn response_filter(
+        &self,
+        session: &mut pingora::prelude::Session,
+        upstream_response: &mut pingora::http::ResponseHeader,
+        ctx: &mut Self::CTX,
+    ) -> pingora::Result<()> {
+        if let Some(proxy_ctx) = ctx.as_ref()
+            && let Some(connected) = proxy_ctx.connected_at
+        {
+            proxy_ctx
+                .span
+                .record("ttfb_ms", connected.elapsed().as_millis() as u64);
         }
 
-        Ok(ctx.peer.clone())
+        if let Some(proxy_ctx) = ctx.as_ref()
+            && let Some(hsts) = &proxy_ctx.hsts
+            && is_tls(session, self.config.read().await.trust_forwarded_headers)
+        {
+            upstream_response
+                .insert_header(http::header::STRICT_TRANSPORT_SECURITY, hsts.as_str())?;
+        }
+
+        if let Some(proxy_ctx) = ctx.as_ref()
+            && let Some(app) = &proxy_ctx.app
+            && !proxy_ctx.fallback
+        {
+            self.apply_idle_hints(&proxy_ctx.host, app, upstream_response)
+                .await;
+        }
+
+        Ok(())
+    }
+
+    async fn logging(
+        &self,
+        session: &mut pingora::prelude::Session,
+        error: Option<&pingora::Error>,
+        ctx: &mut Self::CTX,
+    ) {
+        // The span is logged with its timings once the context drops.
+        if let Some(proxy_ctx) = ctx.as_ref() {
+            let status = session
+                .response_written()
+                .map(|response| response.status.as_u16());
+            if let Some(status) = status {
+                proxy_ctx.span.record("status", status);
+            }
+            if let Some(error) = error {
+                proxy_ctx
+                    .span
+                    .in_scope(|| warn!(error = %error, "request failed"));
+                // Clients going away are not penny's or the app's failure.
+                if proxy_ctx.app.is_some() && *error.esource() != pingora::ErrorSource::Downstream {
+                    let failure = proxy_ctx
+                        .failure
+                        .unwrap_or_else(|| classify_failure(error.etype()));
+                    self.record_failure(
+                        &proxy_ctx.host,
+                        &proxy_ctx.path,
+                        failure,
+                        &error.to_string(),
+                    )
+                    .await;
+                }
+            }
+
+            // Count requests to apps for their overview, leaving out
+            // monitoring; a request that failed before a response was
+            // written is counted as a 502.
+            let status = status.or(error.map(|_| 502));
+            if let Some(app) = &proxy_ctx.app
+                && !proxy_ctx.idle_exempt
+                && let Some(status) = status
+            {
+                let duration_ms = proxy_ctx.received_at.elapsed().as_millis() as u64;
+                app.read().await.count_request(status, duration_ms);
+            }
+        }
+    }
+}
+
+/// Whether `etype` means penny never reached the app.
+fn classify_failure(etype: &pingora::ErrorType) -> ProxyFailure {
+    use pingora::ErrorType::*;
+    match etype {
+        ConnectTimedout | ConnectRefused | ConnectNoRoute | ConnectError | TLSHandshakeFailure
+        | TLSHandshakeTimedout => ProxyFailure::Connect,
+        _ => ProxyFailure::Upstream,
+    }
+}
+
+/// Removes `prefix` from the front of `uri`'s path, keeping the query.
+fn strip_path_prefix(uri: &http::Uri, prefix: &str) -> Option<http::Uri> {
+    let rest = uri.path_and_query()?.as_str().strip_prefix(prefix)?;
+    if rest.starts_with('/') {
+        rest.parse().ok()
+    } else {
+        format!("/{rest}").parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn in_flight_requests_are_counted_until_dropped() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let (first, count) = InFlight::start(&counter);
+        assert_eq!(count, 1);
+        let (second, count) = InFlight::start(&counter);
+        assert_eq!(count, 2);
+
+        drop(first);
+        assert_eq!(counter.load(Ordering::Acquire), 1);
+        drop(second);
+        assert_eq!(counter.load(Ordering::Acquire), 0);
+    }
+
+    #[tokio::test]
+    async fn requests_fall_back_while_the_app_is_not_up() {
+        let config: SharedConfig = Arc::new(RwLock::new(
+            toml::from_str(
+                r#"
+                ["app.example.com"]
+                address = "127.0.0.1:1"
+                fallback_address = "127.0.0.1:2"
+                fallback_after = "100ms"
+                command = "sleep 30"
+                health_check = "/"
+                health_check_initial_backoff_ms = 10
+                "#,
+            )
+            .unwrap(),
+        ));
+        let db = crate::db::SqliteDatabase::new("sqlite::memory:")
+            .await
+            .unwrap();
+        let idle = IdleScheduler::spawn(db.clone(), config.clone());
+        let proxy = YarpProxy::new(
+            config.clone(),
+            db.clone(),
+            crate::challenge::create_challenge_store(None),
+            idle,
+        );
+        let app = config.read().await.apps["app.example.com"].clone();
+        let ctx = ProxyContext::new("app.example.com", app.clone(), None);
+
+        let started = Instant::now();
+        assert!(proxy.start_or_fall_back(&ctx, &app).await.unwrap());
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+        // The app keeps starting for the requests after this one.
+        assert!(app.write().await.command.is_child_running());
+
+        App::stop_app(&ctx.host, &app, db).await;
+    }
+
+    #[tokio::test]
+    async fn the_cold_start_page_follows_a_start_in_progress() {
+        let config: crate::config::Config = toml::from_str(
+            r#"
+            ["app.example.com"]
+            address = "127.0.0.1:1"
+            command = "sleep 30"
+            health_check = "/"
+            health_check_initial_backoff_ms = 10
+            "#,
+        )
+        .unwrap();
+        let db = crate::db::SqliteDatabase::new("sqlite::memory:")
+            .await
+            .unwrap();
+        let app = config.apps["app.example.com"].clone();
+        let host = Host("app.example.com".to_owned());
+        let page = ColdStartPage {
+            template: Some("{{ checks }}: {{ last_error }}".to_owned()),
+            lang: "en".to_owned(),
+            branding: Default::default(),
+        };
+
+        assert!(
+            !App::begin_start_app(&host, &app, db.clone(), WakeReason::Browser)
+                .await
+                .unwrap()
+        );
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        // Each refresh asks for the start again before rendering the page.
+        let refresh = async {
+            let ready = App::begin_start_app(&host, &app, db.clone(), WakeReason::Browser)
+                .await
+                .unwrap();
+            (ready, loading_page_body(&host, &app, &page).await)
+        };
+        let (ready, body) = tokio::time::timeout(std::time::Duration::from_millis(500), refresh)
+            .await
+            .expect("the page waits for the start's health checks");
+        assert!(!ready);
+        let (checks, error) = body.split_once(": ").unwrap();
+        assert!(checks.parse::<u32>().unwrap() >= 1);
+        assert_eq!(error, "could not connect");
+
+        App::stop_app(&host, &app, db).await;
+    }
+
+    #[tokio::test]
+    async fn the_cold_start_page_shows_output_while_the_app_starts() {
+        let config: crate::config::Config = toml::from_str(
+            r#"
+            ["app.example.com"]
+            address = "127.0.0.1:1"
+            command = "sh: echo migrating && sleep 30"
+            health_check = "/"
+            health_check_initial_backoff_ms = 10
+            cold_start_output_lines = 5
+            "#,
+        )
+        .unwrap();
+        let db = crate::db::SqliteDatabase::new("sqlite::memory:")
+            .await
+            .unwrap();
+        let app = config.apps["app.example.com"].clone();
+        let host = Host("app.example.com".to_owned());
+        let page = ColdStartPage {
+            template: Some("{{ output|join(',') }}".to_owned()),
+            lang: "en".to_owned(),
+            branding: Default::default(),
+        };
+
+        App::begin_start_app(&host, &app, db.clone(), WakeReason::Browser)
+            .await
+            .unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let body = tokio::time::timeout(
+            std::time::Duration::from_millis(500),
+            loading_page_body(&host, &app, &page),
+        )
+        .await
+        .expect("the page renders while the start's health checks run");
+        assert_eq!(body, "migrating");
+
+        App::stop_app(&host, &app, db).await;
+    }
+
+    #[test]
+    fn api_prefix_is_stripped_keeping_the_query() {
+        let strip = |uri: &str| {
+            strip_path_prefix(&uri.parse().unwrap(), "/.penny").map(|uri| uri.to_string())
+        };
+
+        assert_eq!(
+            strip("/.penny/api/version").as_deref(),
+            Some("/api/version")
+        );
+        assert_eq!(
+            strip("/.penny/api/apps-overview?start=1").as_deref(),
+            Some("/api/apps-overview?start=1")
+        );
+        assert_eq!(strip("/.penny").as_deref(), Some("/"));
+        assert_eq!(strip("/.penny?x=1").as_deref(), Some("/?x=1"));
+        assert_eq!(strip("/api/version"), None);
     }
 }
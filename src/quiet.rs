@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+
+use jiff::Timestamp;
+use jiff::civil::Time;
+use jiff::tz::TimeZone;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::config::SharedConfig;
+use crate::idle::IdleScheduler;
+use crate::types::Host;
+
+/// How often penny looks for apps entering their quiet hours.
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A daily window like `23:00-07:00 Europe/Berlin`, which may wrap past
+/// midnight. Without a time zone, the system's is used.
+#[derive(Debug, Clone)]
+pub struct QuietHours {
+    start: Time,
+    end: Time,
+    tz: TimeZone,
+    source: String,
+}
+
+impl QuietHours {
+    /// Whether `now` falls within the window.
+    pub fn contains(&self, now: Timestamp) -> bool {
+        let time = now.to_zoned(self.tz.clone()).time();
+        if self.start < self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+
+    pub fn is_now(&self) -> bool {
+        self.contains(Timestamp::now())
+    }
+}
+
+impl FromStr for QuietHours {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (range, tz) = match s.trim().split_once(char::is_whitespace) {
+            Some((range, tz)) => {
+                let tz = TimeZone::get(tz.trim())
+                    .map_err(|e| format!("unknown time zone '{}': {e}", tz.trim()))?;
+                (range, tz)
+            }
+            None => (s.trim(), TimeZone::system()),
+        };
+        let (start, end) = range
+            .split_once('-')
+            .ok_or_else(|| format!("expected quiet hours like '23:00-07:00', got '{s}'"))?;
+        let time = |t: &str| {
+            Time::strptime("%H:%M", t.trim()).map_err(|e| format!("invalid time '{t}': {e}"))
+        };
+        let (start, end) = (time(start)?, time(end)?);
+        if start == end {
+            return Err(format!("quiet hours '{s}' start and end at the same time"));
+        }
+
+        Ok(Self {
+            start,
+            end,
+            tz,
+            source: s.to_owned(),
+        })
+    }
+}
+
+impl Serialize for QuietHours {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for QuietHours {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let quiet_hours = String::deserialize(deserializer)?;
+        quiet_hours.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Brings forward the pending stops of awake apps as their quiet hours
+/// begin, to their `quiet_wait_period`.
+pub fn spawn_watcher(config: SharedConfig, idle: IdleScheduler) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut was_quiet: HashMap<String, bool> = HashMap::new();
+
+        loop {
+            interval.tick().await;
+            let apps: Vec<_> = config
+                .read()
+                .await
+                .apps
+                .iter()
+                .map(|(host, app)| (host.clone(), app.clone()))
+                .collect();
+            for (host, app) in apps {
+                let guard = app.read().await;
+                let Some(quiet_hours) = &guard.quiet_hours else {
+                    continue;
+                };
+                let quiet = quiet_hours.is_now();
+                let wait = guard.quiet_wait_period.unsigned_abs();
+                drop(guard);
+
+                if quiet && !was_quiet.get(&host).copied().unwrap_or_default() {
+                    info!(host = %host, ?wait, "quiet hours started");
+                    idle.shorten(&Host(host.clone()), &app, wait).await;
+                }
+                was_quiet.insert(host, quiet);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> Timestamp {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn quiet_hours_wrap_past_midnight_in_their_time_zone() {
+        let quiet: QuietHours = "23:00-07:00 Europe/Berlin".parse().unwrap();
+
+        // 23:30 and 06:59 in Berlin (UTC+1 in winter).
+        assert!(quiet.contains(at("2026-01-10T22:30:00Z")));
+        assert!(quiet.contains(at("2026-01-11T05:59:00Z")));
+        // 07:00 and 22:59 in Berlin.
+        assert!(!quiet.contains(at("2026-01-11T06:00:00Z")));
+        assert!(!quiet.contains(at("2026-01-10T21:59:00Z")));
+
+        let daytime: QuietHours = "12:00-13:30 UTC".parse().unwrap();
+        assert!(daytime.contains(at("2026-01-10T13:00:00Z")));
+        assert!(!daytime.contains(at("2026-01-10T14:00:00Z")));
+    }
+
+    #[test]
+    fn invalid_quiet_hours_are_rejected() {
+        assert!("23:00".parse::<QuietHours>().is_err());
+        assert!("25:00-07:00".parse::<QuietHours>().is_err());
+        assert!("23:00-07:00 Mars/Olympus".parse::<QuietHours>().is_err());
+        assert!("07:00-07:00 UTC".parse::<QuietHours>().is_err());
+    }
+}
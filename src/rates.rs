@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+use tracing::{debug, info, warn};
+
+use crate::config::{App, SharedConfig};
+use crate::db::SqliteDatabase;
+
+/// How often request rates are written to the database. Rates cover the
+/// last 5 and 30 minutes, so saving more often gains little.
+const PERSIST_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Restores an app's request rates saved by a previous run of penny, so
+/// adaptive wait periods do not start from zero after a restart.
+pub async fn restore(db: &SqliteDatabase, host: &str, app: &App) {
+    let (buckets, last_request_at) = match db.get_request_rates(host).await {
+        Ok(Some(saved)) => saved,
+        Ok(None) => return,
+        Err(e) => {
+            warn!(host = %host, error = %e, "failed to read saved request rates");
+            return;
+        }
+    };
+
+    match serde_json::from_str(&buckets) {
+        Ok(buckets) => {
+            app.request_tracker.restore(buckets, last_request_at);
+            debug!(host = %host, "restored request rates");
+        }
+        Err(e) => warn!(host = %host, error = %e, "ignoring invalid saved request rates"),
+    }
+}
+
+/// Restores the request rates of every configured app.
+pub async fn restore_all(db: &SqliteDatabase, config: &SharedConfig) {
+    let config = config.read().await;
+    for (host, app) in &config.apps {
+        restore(db, host, &*app.read().await).await;
+    }
+    info!(apps = config.apps.len(), "restored saved request rates");
+}
+
+async fn persist(db: &SqliteDatabase, config: &SharedConfig) {
+    let config = config.read().await;
+    for (host, app) in &config.apps {
+        let (buckets, last_request_at) = app.read().await.request_tracker.snapshot();
+        if last_request_at.is_none() {
+            continue;
+        }
+
+        let buckets = match serde_json::to_string(&buckets) {
+            Ok(buckets) => buckets,
+            Err(e) => {
+                warn!(host = %host, error = %e, "failed to encode request rates");
+                continue;
+            }
+        };
+        if let Err(e) = db.save_request_rates(host, &buckets, last_request_at).await {
+            warn!(host = %host, error = %e, "failed to save request rates");
+        }
+    }
+}
+
+/// Periodically saves every app's request rates.
+pub fn spawn_persist_loop(db: SqliteDatabase, config: SharedConfig) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PERSIST_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        // The first tick fires immediately; nothing has changed yet.
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+            persist(&db, &config).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn rates_survive_a_restart() {
+        let toml_str = r#"
+            ["myapp.example.com"]
+            address = "127.0.0.1:3001"
+            command = "node server.js"
+            health_check = "/"
+        "#;
+        let db = SqliteDatabase::new("sqlite::memory:").await.unwrap();
+
+        let before: SharedConfig =
+            std::sync::Arc::new(tokio::sync::RwLock::new(toml::from_str(toml_str).unwrap()));
+        {
+            let config = before.read().await;
+            let app = config.apps["myapp.example.com"].read().await;
+            app.request_tracker.record_request();
+            app.request_tracker.record_request();
+        }
+        persist(&db, &before).await;
+
+        let after: SharedConfig =
+            std::sync::Arc::new(tokio::sync::RwLock::new(toml::from_str(toml_str).unwrap()));
+        restore_all(&db, &after).await;
+
+        let config = after.read().await;
+        let app = config.apps["myapp.example.com"].read().await;
+        assert_eq!(app.request_tracker.total_recent_requests(), 2);
+        assert!(app.request_tracker.last_request_at().is_some());
+    }
+}
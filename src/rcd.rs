@@ -0,0 +1,201 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::service::{ServeOpts, ServiceManager, login_shell, run_cmd};
+
+const SERVICE_NAME: &str = "penny";
+const RC_SCRIPT: &str = "/usr/local/etc/rc.d/penny";
+/// Environment for the service; holds the dashboard password, so it is
+/// only readable by root (unlike `/etc/rc.conf`).
+const ENV_FILE: &str = "/usr/local/etc/penny.env";
+const LOG_FILE: &str = "/var/log/penny.log";
+/// Written and locked by daemon(8), which supervises and restarts penny.
+const DAEMON_PID_FILE: &str = "/var/run/penny.pid";
+/// Written and locked by penny itself.
+const SERVE_PID_FILE: &str = "/var/run/penny-serve.pid";
+
+/// penny as a FreeBSD rc.d service, e.g. inside a jail.
+pub struct RcD;
+
+fn generate_rc_script(opts: &ServeOpts) -> color_eyre::Result<String> {
+    let serve = opts.serve_command()?;
+    let shell = login_shell();
+    let working_dir = serve.working_dir.display();
+    let command_line = format!("{} --pid-file {SERVE_PID_FILE}", serve.command_line);
+
+    Ok(format!(
+        "\
+#!/bin/sh
+
+# PROVIDE: penny
+# REQUIRE: LOGIN NETWORKING
+# KEYWORD: shutdown
+
+. /etc/rc.subr
+
+name=\"penny\"
+rcvar=\"penny_enable\"
+
+load_rc_config $name
+
+: ${{penny_enable:=\"NO\"}}
+
+pidfile=\"{DAEMON_PID_FILE}\"
+penny_chdir=\"{working_dir}\"
+command=\"/usr/sbin/daemon\"
+command_args=\"-r -o {LOG_FILE} -P ${{pidfile}} {shell} -lc 'exec {command_line}'\"
+extra_commands=\"reload\"
+reload_cmd=\"penny_reload\"
+
+# daemon(8) keeps SIGHUP for itself, so signal penny directly.
+penny_reload()
+{{
+    kill -HUP $(cat {SERVE_PID_FILE})
+}}
+
+run_rc_command \"$1\"
+"
+    ))
+}
+
+fn generate_env_file(opts: &ServeOpts) -> String {
+    let mut env = String::new();
+    if let Some(password) = &opts.password {
+        env.push_str(&format!("PENNY_PASSWORD={password}\n"));
+    }
+    if let Ok(rust_log) = std::env::var("RUST_LOG") {
+        env.push_str(&format!("RUST_LOG={rust_log}\n"));
+    }
+    env
+}
+
+impl ServiceManager for RcD {
+    fn hint(&self, action: &str) -> String {
+        format!("penny rc {action}")
+    }
+
+    fn check_supported(&self) -> color_eyre::Result<()> {
+        if !cfg!(target_os = "freebsd") {
+            return Err(color_eyre::eyre::eyre!(
+                "the `rc` command is only available on FreeBSD"
+            ));
+        }
+        Ok(())
+    }
+
+    fn service_path(&self) -> color_eyre::Result<PathBuf> {
+        Ok(PathBuf::from(RC_SCRIPT))
+    }
+
+    fn install(&self, opts: &ServeOpts) -> color_eyre::Result<()> {
+        self.ensure_not_installed()?;
+
+        let script = generate_rc_script(opts)?;
+        fs::create_dir_all("/usr/local/etc/rc.d")?;
+        fs::write(RC_SCRIPT, script)?;
+        fs::set_permissions(RC_SCRIPT, fs::Permissions::from_mode(0o755))?;
+        println!("wrote rc script to {RC_SCRIPT}");
+
+        fs::write(ENV_FILE, generate_env_file(opts))?;
+        fs::set_permissions(ENV_FILE, fs::Permissions::from_mode(0o600))?;
+        println!("wrote {ENV_FILE}");
+
+        run_cmd(
+            "sysrc",
+            &["penny_enable=YES", &format!("penny_env_file={ENV_FILE}")],
+        )?;
+        println!("enabled {SERVICE_NAME} in /etc/rc.conf");
+
+        run_cmd("service", &[SERVICE_NAME, "start"])?;
+        println!("started {SERVICE_NAME}");
+
+        println!("\npenny service installed and running.");
+        println!("use `{}` to check status", self.hint("status"));
+        println!("use `{} --follow` to watch logs", self.hint("logs"));
+
+        Ok(())
+    }
+
+    fn uninstall(&self) -> color_eyre::Result<()> {
+        let script = self.installed_path()?;
+
+        // Ignore errors — the service might already be stopped.
+        let _ = run_cmd("service", &[SERVICE_NAME, "stop"]);
+        println!("stopped {SERVICE_NAME}");
+
+        let _ = run_cmd("sysrc", &["-x", "penny_enable", "penny_env_file"]);
+        println!("removed {SERVICE_NAME} from /etc/rc.conf");
+
+        fs::remove_file(&script)?;
+        println!("removed {}", script.display());
+
+        if fs::remove_file(ENV_FILE).is_ok() {
+            println!("removed {ENV_FILE}");
+        }
+
+        println!("\npenny service uninstalled.");
+
+        Ok(())
+    }
+
+    fn status(&self) -> color_eyre::Result<()> {
+        self.installed_path()?;
+
+        // Pass through directly; rc.d exits with 1 when the service is
+        // stopped, which is not an error here.
+        let status = Command::new("service")
+            .args([SERVICE_NAME, "status"])
+            .status()
+            .map_err(|e| color_eyre::eyre::eyre!("failed to run service: {e}"))?;
+
+        if !status.success() && status.code() != Some(1) {
+            return Err(color_eyre::eyre::eyre!(
+                "service status exited with {}",
+                status
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn restart(&self) -> color_eyre::Result<()> {
+        self.installed_path()?;
+
+        run_cmd("service", &[SERVICE_NAME, "restart"])?;
+        println!("restarted {SERVICE_NAME}");
+
+        Ok(())
+    }
+
+    fn reload(&self) -> color_eyre::Result<()> {
+        self.installed_path()?;
+
+        run_cmd("service", &[SERVICE_NAME, "reload"])?;
+        println!("reloaded {SERVICE_NAME}");
+
+        Ok(())
+    }
+
+    fn logs(&self, follow: bool) -> color_eyre::Result<()> {
+        self.installed_path()?;
+
+        let mut args = vec!["-n", "100"];
+        if follow {
+            args.push("-F");
+        }
+        args.push(LOG_FILE);
+
+        let status = Command::new("tail")
+            .args(&args)
+            .status()
+            .map_err(|e| color_eyre::eyre::eyre!("failed to run tail: {e}"))?;
+
+        if !status.success() {
+            return Err(color_eyre::eyre::eyre!("tail exited with {}", status));
+        }
+
+        Ok(())
+    }
+}
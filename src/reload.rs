@@ -0,0 +1,537 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use color_eyre::eyre::{Context, eyre};
+use tokio::sync::RwLock;
+use tracing::{error, info, warn};
+
+use crate::collector::Collector;
+use crate::config::{App, Config, ConfigFormat, SharedConfig};
+use crate::db::SqliteDatabase;
+use crate::preview::Preview;
+use crate::types::Host;
+
+/// How long a removed or changed app may finish the requests it is serving
+/// before it is stopped.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often `penny serve --watch` looks for changes to the config file.
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Where `penny serve` records its PID by default so `penny reload` can
+/// signal it.
+pub fn pid_file_path() -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+        .join("penny.pid")
+}
+
+/// An exclusive lock on the PID file, held for as long as `penny serve` runs
+/// so a second instance fails fast instead of fighting over app processes.
+pub struct PidFile {
+    _file: File,
+}
+
+/// Locks the PID file and records the current process ID in it.
+pub fn acquire_pid_file(path: &Path) -> color_eyre::Result<PidFile> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .wrap_err_with(|| format!("failed to open pid file {}", path.display()))?;
+
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            let mut pid = String::new();
+            let _ = file.read_to_string(&mut pid);
+            return Err(eyre!(
+                "another penny serve is already running (pid {}, pid file {})",
+                pid.trim(),
+                path.display()
+            ));
+        }
+        Err(TryLockError::Error(e)) => {
+            return Err(e).wrap_err_with(|| format!("failed to lock pid file {}", path.display()));
+        }
+    }
+
+    file.set_len(0)
+        .and_then(|()| file.write_all(std::process::id().to_string().as_bytes()))
+        .wrap_err_with(|| format!("failed to write pid file {}", path.display()))?;
+
+    Ok(PidFile { _file: file })
+}
+
+/// Asks the running `penny serve` to reload its config by sending SIGHUP.
+pub fn send_reload(pid_file: Option<PathBuf>) -> color_eyre::Result<()> {
+    let path = pid_file.unwrap_or_else(pid_file_path);
+    let mut file = File::open(&path).wrap_err_with(|| {
+        format!(
+            "no running penny serve found (could not read {})",
+            path.display()
+        )
+    })?;
+
+    // A running server holds the lock; if we can take it, the file is stale.
+    if file.try_lock_shared().is_ok() {
+        return Err(eyre!(
+            "no running penny serve found ({} is stale)",
+            path.display()
+        ));
+    }
+
+    let mut pid = String::new();
+    file.read_to_string(&mut pid)
+        .wrap_err_with(|| format!("failed to read {}", path.display()))?;
+    let pid = pid.trim();
+
+    let status = Command::new("kill")
+        .args(["-HUP", pid])
+        .status()
+        .wrap_err("failed to run `kill`")?;
+    if !status.success() {
+        return Err(eyre!(
+            "failed to signal penny serve (pid {pid}); is it still running?"
+        ));
+    }
+
+    println!("sent reload signal to penny serve (pid {pid})");
+    Ok(())
+}
+
+/// The config file `penny serve` runs from, for API requests that add apps
+/// to it.
+#[derive(Debug, Clone)]
+pub struct ConfigFile {
+    pub path: String,
+    pub collector: SqliteDatabase,
+    /// Held while the file is rewritten, so concurrent edits are not lost.
+    lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl ConfigFile {
+    pub fn new(path: String, collector: SqliteDatabase) -> Self {
+        Self {
+            path,
+            collector,
+            lock: Arc::default(),
+        }
+    }
+
+    /// Appends an app to the config file and reloads it. The file is left
+    /// as it was if the new config is invalid or fails to load. Errors are
+    /// `Ok(Err(..))` when the app's settings are at fault.
+    pub async fn add_app(
+        &self,
+        config: &SharedConfig,
+        host: &str,
+        settings: toml::Table,
+    ) -> color_eyre::Result<Result<(), String>> {
+        if ConfigFormat::of(&self.path) != ConfigFormat::Toml {
+            return Ok(Err(format!(
+                "apps can only be added to TOML config files; add it to '{}' by hand",
+                self.path
+            )));
+        }
+        let _lock = self.lock.lock().await;
+        let original = tokio::fs::read_to_string(&self.path)
+            .await
+            .wrap_err_with(|| format!("failed to read config file '{}'", self.path))?;
+        let content = match with_app(&original, host, settings) {
+            Ok(content) => content,
+            Err(e) => return Ok(Err(e)),
+        };
+        tokio::fs::write(&self.path, content)
+            .await
+            .wrap_err_with(|| format!("failed to write config file '{}'", self.path))?;
+
+        if let Err(e) = reload(config, &self.path, &self.collector).await {
+            tokio::fs::write(&self.path, original)
+                .await
+                .wrap_err_with(|| format!("failed to restore config file '{}'", self.path))?;
+            return Ok(Err(format!("{e:#}")));
+        }
+        Ok(Ok(()))
+    }
+}
+
+/// Appends a table for `host` to a config file's contents, failing if the
+/// result is not a valid config.
+fn with_app(content: &str, host: &str, settings: toml::Table) -> Result<String, String> {
+    let mut table = toml::Table::new();
+    table.insert(host.to_owned(), toml::Value::Table(settings));
+    let app = toml::to_string(&table).map_err(|e| e.to_string())?;
+
+    let mut content = content.trim_end().to_owned();
+    if !content.is_empty() {
+        content.push_str("\n\n");
+    }
+    content.push_str(&app);
+
+    toml::from_str::<Config>(&content).map_err(|e| e.message().to_owned())?;
+    Ok(content)
+}
+
+/// Waits up to `timeout` for the requests an app is serving to finish.
+/// Returns whether they did.
+async fn drain(app: &Arc<RwLock<App>>, timeout: Duration) -> bool {
+    let in_flight = app.read().await.in_flight.clone();
+    let deadline = tokio::time::Instant::now() + timeout;
+    while in_flight.load(Ordering::Acquire) > 0 {
+        if tokio::time::Instant::now() >= deadline {
+            return false;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    true
+}
+
+/// Stops an app that was removed or replaced by a reload, once the
+/// requests it is serving have finished. Its replacement, if any, is told
+/// through `retired` once it may start.
+async fn retire_app(
+    host: &str,
+    app: &Arc<RwLock<App>>,
+    collector: &SqliteDatabase,
+    retired: Option<tokio::sync::watch::Sender<bool>>,
+) {
+    if !drain(app, DRAIN_TIMEOUT).await {
+        warn!(host = %host, "requests still in flight, stopping the app anyway");
+    }
+
+    let mut guard = app.write().await;
+    let was_scheduled = guard.idle_deadline.cancel();
+    let was_running = guard.command.is_child_running();
+    // An unmanaged app that was up has a run open too.
+    let was_up = !guard.managed && guard.confirmed_healthy;
+    let cwd = guard.cwd.clone();
+    guard.command.stop(cwd.as_ref()).await;
+    guard.confirmed_healthy = false;
+    drop(guard);
+    if let Some(retired) = retired {
+        retired.send_replace(true);
+    }
+
+    if (was_scheduled || was_running || was_up)
+        && let Err(e) = collector.app_stopped(&Host(host.to_owned())).await
+    {
+        error!(host = %host, "failed to record app stop: {e}");
+    }
+}
+
+/// An app's settings, to tell whether a reload changed them.
+async fn settings(app: &Arc<RwLock<App>>) -> Option<serde_json::Value> {
+    serde_json::to_value(&*app.read().await).ok()
+}
+
+/// Held through a reload, so two at once do not both swap in their own
+/// config.
+static RELOADING: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Re-reads the config file and swaps it in. Apps whose settings did not
+/// change keep running untouched; changed and removed apps are stopped.
+/// Listener, TLS, and API server settings still need a restart.
+pub async fn reload(
+    config: &SharedConfig,
+    path: &str,
+    collector: &SqliteDatabase,
+) -> color_eyre::Result<()> {
+    let owned_path = path.to_owned();
+    let mut new_config = tokio::task::spawn_blocking(move || {
+        let mut config = Config::load(&owned_path)?;
+        config.load_cold_start_pages()?;
+        Ok::<_, color_eyre::Report>(config)
+    })
+    .await
+    .wrap_err("config reload task failed")??;
+
+    let reloading = RELOADING.lock().await;
+
+    // Everything that needs an app's lock is done before the config's
+    // write lock is taken, so an app busy starting cannot hold up requests
+    // to every other app.
+    let current = config.read().await;
+    let current_apps = current.apps.clone();
+    let current_previews = current.previews.clone();
+    let current_start_slots = (current.max_concurrent_starts == new_config.max_concurrent_starts)
+        .then(|| current.start_slots.clone());
+    drop(current);
+
+    let mut retired = Vec::new();
+    let (mut added, mut changed, mut unchanged) = (0, 0, 0);
+
+    for (host, app) in new_config.apps.iter_mut() {
+        let Some(existing) = current_apps.get(host) else {
+            info!(host = %host, "app added");
+            crate::rates::restore(collector, host, &*app.read().await).await;
+            added += 1;
+            continue;
+        };
+        let new_settings = settings(app).await;
+        if new_settings.is_some() && new_settings == settings(existing).await {
+            *app = existing.clone();
+            unchanged += 1;
+            continue;
+        }
+        info!(host = %host, "app settings changed, restarting on next request");
+        let (buckets, last_request_at) = existing.read().await.request_tracker.snapshot();
+        let (replaced, replacing) = tokio::sync::watch::channel(false);
+        let mut guard = app.write().await;
+        guard.request_tracker.restore(buckets, last_request_at);
+        guard.replacing = Some(replacing);
+        drop(guard);
+        retired.push((host.clone(), existing.clone(), Some(replaced)));
+        changed += 1;
+    }
+
+    let mut removed = 0;
+    for (host, app) in &current_apps {
+        if new_config.apps.contains_key(host) {
+            continue;
+        }
+        // Preview instances outlive reloads that leave their preview as it
+        // was.
+        let preview = app.read().await.preview.clone();
+        if let Some(domain) = preview {
+            let find = |previews: &[Preview]| {
+                previews
+                    .iter()
+                    .find(|preview| preview.domain == domain)
+                    .cloned()
+            };
+            let kept = find(&current_previews);
+            if kept.is_some() && kept == find(&new_config.previews) {
+                new_config.apps.insert(host.clone(), app.clone());
+                unchanged += 1;
+                continue;
+            }
+        }
+        info!(host = %host, "app removed");
+        retired.push((host.clone(), app.clone(), None));
+        removed += 1;
+    }
+
+    // Keep the limiter, and the starts holding its slots, unless the limit
+    // itself changed.
+    if let Some(start_slots) = current_start_slots {
+        new_config.start_slots = start_slots;
+    }
+    let node = new_config
+        .cluster
+        .as_ref()
+        .map(|cluster| cluster.node.clone());
+    for app in new_config.apps.values() {
+        let mut app = app.write().await;
+        app.start_slots = new_config.start_slots.clone();
+        app.cluster_node = node.clone();
+    }
+
+    let mut current = config.write().await;
+    // Preview instances spawned since the apps were compared are left for
+    // the next reload to judge.
+    for (host, app) in &current.apps {
+        if !current_apps.contains_key(host) && !new_config.apps.contains_key(host) {
+            new_config.apps.insert(host.clone(), app.clone());
+        }
+    }
+    *current = new_config;
+    drop(current);
+    drop(reloading);
+
+    // Stop old processes after the swap so requests are not held up by
+    // slow stop commands. New requests already go to the new apps, so the
+    // old ones only finish what they are serving.
+    let mut retiring = tokio::task::JoinSet::new();
+    for (host, app, replaced) in retired {
+        let collector = collector.clone();
+        retiring.spawn(async move { retire_app(&host, &app, &collector, replaced).await });
+    }
+    retiring.join_all().await;
+
+    info!(added, changed, removed, unchanged, "configuration reloaded");
+    Ok(())
+}
+
+/// Reloads the config whenever the process receives SIGHUP.
+pub fn spawn_reload_listener(config: SharedConfig, path: String, collector: SqliteDatabase) {
+    tokio::spawn(async move {
+        let mut hangup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+        {
+            Ok(signal) => signal,
+            Err(e) => {
+                error!(error = %e, "failed to listen for SIGHUP, config reload disabled");
+                return;
+            }
+        };
+
+        while hangup.recv().await.is_some() {
+            info!(path = %path, "received SIGHUP, reloading configuration");
+            if let Err(e) = reload(&config, &path, &collector).await {
+                error!(error = %e, "config reload failed, keeping the current configuration");
+            }
+        }
+    });
+}
+
+/// Reloads the config whenever its file changes, for `penny serve --watch`.
+/// A half-written file fails to load and is picked up again once complete.
+pub fn spawn_config_watcher(config: SharedConfig, path: String, collector: SqliteDatabase) {
+    tokio::spawn(async move {
+        let mut last = tokio::fs::read(&path).await.ok();
+        let mut interval = tokio::time::interval(WATCH_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        info!(path = %path, "watching configuration for changes");
+
+        loop {
+            interval.tick().await;
+            let Ok(content) = tokio::fs::read(&path).await else {
+                // Editors may replace the file rather than write to it.
+                continue;
+            };
+            if last.as_ref() == Some(&content) {
+                continue;
+            }
+            last = Some(content);
+
+            info!(path = %path, "configuration file changed, reloading");
+            if let Err(e) = reload(&config, &path, &collector).await {
+                error!(error = %e, "config reload failed, keeping the current configuration");
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::WakeReason;
+
+    #[tokio::test]
+    async fn changed_apps_start_once_the_old_one_finished_its_requests() {
+        let path = std::env::temp_dir().join(format!("penny-reload-{}.toml", std::process::id()));
+        let path = path.to_str().unwrap().to_owned();
+        let with_command = |command: &str| {
+            format!(
+                r#"
+                ["app.example.com"]
+                address = "127.0.0.1:3001"
+                command = "{command}"
+                health_check = "/"
+                "#
+            )
+        };
+        std::fs::write(&path, with_command("sleep 30")).unwrap();
+        let config: SharedConfig = Arc::new(RwLock::new(Config::load(&path).unwrap()));
+        let db = SqliteDatabase::new("sqlite::memory:").await.unwrap();
+        let host = Host("app.example.com".to_owned());
+        let old = config.read().await.apps["app.example.com"].clone();
+        let in_flight = old.read().await.in_flight.clone();
+        in_flight.fetch_add(1, Ordering::AcqRel);
+
+        std::fs::write(&path, with_command("sleep 31")).unwrap();
+        let reloading = tokio::spawn({
+            let (config, path, db) = (config.clone(), path.clone(), db.clone());
+            async move { reload(&config, &path, &db).await }
+        });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let new = config.read().await.apps["app.example.com"].clone();
+        assert!(!Arc::ptr_eq(&old, &new));
+        assert!(
+            !App::begin_start_app(&host, &new, db.clone(), WakeReason::Browser)
+                .await
+                .unwrap()
+        );
+        assert!(!new.write().await.command.is_child_running());
+
+        in_flight.fetch_sub(1, Ordering::AcqRel);
+        reloading.await.unwrap().unwrap();
+        assert!(
+            !App::begin_start_app(&host, &new, db.clone(), WakeReason::Browser)
+                .await
+                .unwrap()
+        );
+        assert!(new.write().await.command.is_child_running());
+
+        App::stop_app(&host, &new, db).await;
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn second_pid_file_lock_fails() {
+        let path = std::env::temp_dir().join(format!("penny-test-{}.pid", std::process::id()));
+
+        let first = acquire_pid_file(&path).unwrap();
+        let err = acquire_pid_file(&path).err().unwrap();
+        assert!(err.to_string().contains("already running"));
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            std::process::id().to_string()
+        );
+
+        drop(first);
+        let _again = acquire_pid_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn retired_apps_finish_their_requests_first() {
+        let config: Config = toml::from_str(
+            r#"
+            ["app.example.com"]
+            address = "127.0.0.1:3001"
+            command = "node server.js"
+            health_check = "/"
+            "#,
+        )
+        .unwrap();
+        let app = config.apps["app.example.com"].clone();
+        let in_flight = app.read().await.in_flight.clone();
+
+        assert!(drain(&app, Duration::ZERO).await);
+
+        in_flight.fetch_add(1, Ordering::AcqRel);
+        assert!(!drain(&app, Duration::from_millis(10)).await);
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            in_flight.fetch_sub(1, Ordering::AcqRel);
+        });
+        assert!(drain(&app, Duration::from_secs(5)).await);
+    }
+
+    #[test]
+    fn apps_are_appended_only_when_the_config_stays_valid() {
+        let settings = |toml: &str| toml::from_str::<toml::Table>(toml).unwrap();
+        let content = "api_address = \"127.0.0.1:3031\"\n";
+
+        let added = with_app(
+            content,
+            "app.example.com",
+            settings("address = \"127.0.0.1:3001\"\ncommand = \"node server.js\""),
+        )
+        .unwrap();
+        assert!(added.starts_with(content));
+        let config: Config = toml::from_str(&added).unwrap();
+        assert_eq!(config.apps.len(), 1);
+        assert!(config.apps.contains_key("app.example.com"));
+
+        assert!(with_app(content, "app.example.com", settings("command = \"node\"")).is_err());
+        assert!(
+            with_app(
+                &added,
+                "app.example.com",
+                settings("address = \"127.0.0.1:3002\"")
+            )
+            .is_err()
+        );
+    }
+}
@@ -1,5 +1,7 @@
 use std::fmt::Debug;
 
+use jiff::tz::TimeZone;
+use jiff::{Span, Timestamp};
 use serde::{Deserialize, Serialize};
 use tracing::error;
 
@@ -12,9 +14,10 @@ mod queries {
                 SELECT
                     started_at,
                     stopped_at,
+                    duration_ms,
                     start_failed,
                     stop_failed,
-                    LAG(stopped_at) OVER (ORDER BY started_at) as prev_stopped_at
+                    LAG(CASE WHEN interrupted = 0 THEN stopped_at END) OVER (ORDER BY started_at) as prev_stopped_at
                 FROM runs
                 WHERE ($1 IS NULL OR started_at >= $1)
                   AND ($2 IS NULL OR started_at <= $2)
@@ -24,15 +27,15 @@ mod queries {
                     CASE
                         WHEN NOT EXISTS (SELECT 1 FROM runs WHERE stopped_at IS NULL)
                         THEN CAST(strftime('%s', 'now') * 1000 AS INTEGER) -
-                             (SELECT MAX(stopped_at) FROM runs)
+                             (SELECT MAX(stopped_at) FROM runs WHERE interrupted = 0)
                         ELSE 0
                     END as ongoing_sleep_ms
             )
             SELECT
                 COUNT(*) as total_runs,
-                COALESCE(SUM(CASE WHEN stopped_at IS NOT NULL THEN stopped_at - started_at ELSE 0 END), 0) as total_awake_time_ms,
+                COALESCE(SUM(CASE WHEN stopped_at IS NOT NULL THEN COALESCE(duration_ms, stopped_at - started_at) ELSE 0 END), 0) as total_awake_time_ms,
                 COALESCE(SUM(CASE WHEN prev_stopped_at IS NOT NULL AND started_at > prev_stopped_at THEN started_at - prev_stopped_at ELSE 0 END), 0)
-                    + (SELECT ongoing_sleep_ms FROM current_sleep) as total_sleep_time_ms,
+                    + COALESCE((SELECT ongoing_sleep_ms FROM current_sleep), 0) as total_sleep_time_ms,
                 COALESCE(SUM(start_failed), 0) as total_start_failures,
                 COALESCE(SUM(stop_failed), 0) as total_stop_failures
             FROM ordered_runs
@@ -45,9 +48,10 @@ mod queries {
                     run_id,
                     started_at,
                     stopped_at,
+                    duration_ms,
                     start_failed,
                     stop_failed,
-                    LAG(stopped_at) OVER (PARTITION BY host ORDER BY started_at) as prev_stopped_at
+                    LAG(CASE WHEN interrupted = 0 THEN stopped_at END) OVER (PARTITION BY host ORDER BY started_at) as prev_stopped_at
                 FROM runs
                 WHERE ($1 IS NULL OR started_at >= $1)
                   AND ($2 IS NULL OR started_at <= $2)
@@ -55,7 +59,7 @@ mod queries {
             latest_per_host AS (
                 SELECT
                     host,
-                    MAX(stopped_at) as last_stopped_at,
+                    MAX(CASE WHEN interrupted = 0 THEN stopped_at END) as last_stopped_at,
                     MAX(CASE WHEN stopped_at IS NULL THEN 1 ELSE 0 END) as has_running
                 FROM runs
                 GROUP BY host
@@ -69,17 +73,31 @@ mod queries {
                         ELSE 0
                     END as ongoing_sleep_ms
                 FROM latest_per_host
+            ),
+            request_stats AS (
+                SELECT
+                    host,
+                    SUM(requests) as total_requests,
+                    CAST(SUM(duration_ms) AS REAL) / SUM(requests) as avg_latency_ms,
+                    CAST(SUM(errors) AS REAL) / SUM(requests) as error_rate
+                FROM request_counts
+                WHERE ($1 IS NULL OR timestamp >= $1)
+                  AND ($2 IS NULL OR timestamp <= $2)
+                GROUP BY host
             )
             SELECT
                 o.host,
                 COUNT(*) as total_runs,
-                COALESCE(SUM(CASE WHEN o.stopped_at IS NOT NULL THEN o.stopped_at - o.started_at ELSE 0 END), 0) as total_awake_time_ms,
+                COALESCE(SUM(CASE WHEN o.stopped_at IS NOT NULL THEN COALESCE(o.duration_ms, o.stopped_at - o.started_at) ELSE 0 END), 0) as total_awake_time_ms,
                 COALESCE(SUM(CASE WHEN o.prev_stopped_at IS NOT NULL AND o.started_at > o.prev_stopped_at THEN o.started_at - o.prev_stopped_at ELSE 0 END), 0)
                     + COALESCE((SELECT ongoing_sleep_ms FROM current_sleep_per_host WHERE host = o.host), 0) as total_sleep_time_ms,
                 COALESCE(SUM(o.start_failed), 0) as total_start_failures,
                 COALESCE(SUM(o.stop_failed), 0) as total_stop_failures,
                 COALESCE((SELECT has_running FROM latest_per_host WHERE host = o.host), 0) as is_running,
-                MAX(o.started_at) as last_run_at
+                MAX(o.started_at) as last_run_at,
+                COALESCE((SELECT total_requests FROM request_stats WHERE host = o.host), 0) as total_requests,
+                COALESCE((SELECT avg_latency_ms FROM request_stats WHERE host = o.host), 0.0) as avg_latency_ms,
+                COALESCE((SELECT error_rate FROM request_stats WHERE host = o.host), 0.0) as error_rate
             FROM ordered_runs o
             GROUP BY o.host
             ORDER BY o.host
@@ -92,9 +110,10 @@ mod queries {
                     run_id,
                     started_at,
                     stopped_at,
+                    duration_ms,
                     start_failed,
                     stop_failed,
-                    LAG(stopped_at) OVER (ORDER BY started_at) as prev_stopped_at
+                    LAG(CASE WHEN interrupted = 0 THEN stopped_at END) OVER (ORDER BY started_at) as prev_stopped_at
                 FROM runs
                 WHERE host = $1
                   AND ($2 IS NULL OR started_at >= $2)
@@ -102,7 +121,7 @@ mod queries {
             ),
             latest_info AS (
                 SELECT
-                    MAX(stopped_at) as last_stopped_at,
+                    MAX(CASE WHEN interrupted = 0 THEN stopped_at END) as last_stopped_at,
                     MAX(CASE WHEN stopped_at IS NULL THEN 1 ELSE 0 END) as has_running
                 FROM runs
                 WHERE host = $1
@@ -115,16 +134,29 @@ mod queries {
                         ELSE 0
                     END as ongoing_sleep_ms
                 FROM latest_info
+            ),
+            request_stats AS (
+                SELECT
+                    SUM(requests) as total_requests,
+                    CAST(SUM(duration_ms) AS REAL) / SUM(requests) as avg_latency_ms,
+                    CAST(SUM(errors) AS REAL) / SUM(requests) as error_rate
+                FROM request_counts
+                WHERE host = $1
+                  AND ($2 IS NULL OR timestamp >= $2)
+                  AND ($3 IS NULL OR timestamp <= $3)
             )
             SELECT
                 COUNT(*) as total_runs,
-                COALESCE(SUM(CASE WHEN stopped_at IS NOT NULL THEN stopped_at - started_at ELSE 0 END), 0) as total_awake_time_ms,
+                COALESCE(SUM(CASE WHEN stopped_at IS NOT NULL THEN COALESCE(duration_ms, stopped_at - started_at) ELSE 0 END), 0) as total_awake_time_ms,
                 COALESCE(SUM(CASE WHEN prev_stopped_at IS NOT NULL AND started_at > prev_stopped_at THEN started_at - prev_stopped_at ELSE 0 END), 0)
                     + COALESCE((SELECT ongoing_sleep_ms FROM current_sleep), 0) as total_sleep_time_ms,
                 COALESCE(SUM(start_failed), 0) as total_start_failures,
                 COALESCE(SUM(stop_failed), 0) as total_stop_failures,
                 COALESCE((SELECT has_running FROM latest_info), 0) as is_running,
-                MAX(started_at) as last_run_at
+                MAX(started_at) as last_run_at,
+                COALESCE((SELECT total_requests FROM request_stats), 0) as total_requests,
+                COALESCE((SELECT avg_latency_ms FROM request_stats), 0.0) as avg_latency_ms,
+                COALESCE((SELECT error_rate FROM request_stats), 0.0) as error_rate
             FROM ordered_runs
         "#;
 
@@ -134,11 +166,17 @@ mod queries {
                 r.started_at,
                 COALESCE(r.stopped_at, CAST(strftime('%s', 'now') * 1000 AS INTEGER)) as end_time,
                 CASE
-                    WHEN r.stopped_at IS NOT NULL THEN r.stopped_at - r.started_at
+                    WHEN r.stopped_at IS NOT NULL THEN COALESCE(r.duration_ms, r.stopped_at - r.started_at)
                     ELSE CAST(strftime('%s', 'now') * 1000 AS INTEGER) - r.started_at
                 END as awake_time,
                 COALESCE(so.cnt, 0) as stdout_lines,
-                COALESCE(se.cnt, 0) as stderr_lines
+                COALESCE(se.cnt, 0) as stderr_lines,
+                r.interrupted,
+                r.dropped_lines,
+                r.peak_memory_bytes,
+                CASE WHEN r.usage_samples > 0 THEN r.memory_bytes_sum / r.usage_samples END as avg_memory_bytes,
+                r.peak_cpu_percent,
+                CASE WHEN r.usage_samples > 0 THEN r.cpu_percent_sum / r.usage_samples END as avg_cpu_percent
             FROM runs r
             LEFT JOIN (SELECT run_id, COUNT(*) as cnt FROM stdout GROUP BY run_id) so ON so.run_id = r.run_id
             LEFT JOIN (SELECT run_id, COUNT(*) as cnt FROM stderr GROUP BY run_id) se ON se.run_id = r.run_id
@@ -150,6 +188,98 @@ mod queries {
             LIMIT $5
         "#;
 
+    pub const WAKE_REASONS: &str = r#"
+            SELECT
+                COALESCE(wake_reason, 'unknown') as reason,
+                COUNT(*) as wakes,
+                COALESCE(SUM(CASE WHEN stopped_at IS NOT NULL THEN COALESCE(duration_ms, stopped_at - started_at) ELSE 0 END), 0) as awake_time_ms,
+                COALESCE(SUM(CASE WHEN stopped_at IS NOT NULL THEN 1 ELSE 0 END), 0) as finished
+            FROM runs
+            WHERE host = $1
+              AND ($2 IS NULL OR started_at >= $2)
+              AND ($3 IS NULL OR started_at <= $3)
+            GROUP BY reason
+            ORDER BY wakes DESC, reason ASC
+        "#;
+
+    pub const WAKES_PER_DAY: &str = r#"
+            SELECT
+                date(started_at / 1000, 'unixepoch') as day,
+                COUNT(*) as wakes
+            FROM runs
+            WHERE host = $1
+              AND ($2 IS NULL OR started_at >= $2)
+              AND ($3 IS NULL OR started_at <= $3)
+            GROUP BY day
+            ORDER BY day ASC
+        "#;
+
+    pub const MONITOR_SUMMARY: &str = r#"
+            SELECT
+                COUNT(*) as checks,
+                COALESCE(SUM(CASE WHEN error IS NOT NULL THEN 1 ELSE 0 END), 0) as failures,
+                COALESCE(AVG(CASE WHEN error IS NULL THEN latency_ms END), 0.0) as avg_latency_ms
+            FROM monitor_checks
+            WHERE host = $1
+              AND ($2 IS NULL OR timestamp >= $2)
+              AND ($3 IS NULL OR timestamp <= $3)
+        "#;
+
+    pub const MONITOR_CHECKS: &str = r#"
+            SELECT status, latency_ms, error, asleep, timestamp
+            FROM monitor_checks
+            WHERE host = $1
+              AND ($2 IS NULL OR timestamp >= $2)
+              AND ($3 IS NULL OR timestamp <= $3)
+            ORDER BY timestamp DESC, id DESC
+            LIMIT $4
+        "#;
+
+    pub const WAIT_DECISIONS: &str = r#"
+            SELECT wait_period_ms, short_rate, long_rate, adaptive, quiet_hours, timestamp
+            FROM wait_decisions
+            WHERE host = $1
+              AND ($2 IS NULL OR timestamp >= $2)
+              AND ($3 IS NULL OR timestamp <= $3)
+            ORDER BY timestamp DESC, id DESC
+            LIMIT $4
+        "#;
+
+    pub const CERTIFICATE_EVENTS: &str = r#"
+            SELECT domain, event, detail, timestamp
+            FROM certificate_events
+            WHERE ($1 IS NULL OR domain = $1)
+            ORDER BY timestamp DESC, id DESC
+            LIMIT $2
+        "#;
+
+    pub const PROXY_ERRORS: &str = r#"
+            SELECT host, path, kind, detail, timestamp
+            FROM proxy_errors
+            WHERE ($1 IS NULL OR host = $1)
+              AND ($2 IS NULL OR timestamp >= $2)
+              AND ($3 IS NULL OR timestamp <= $3)
+            ORDER BY timestamp DESC, id DESC
+            LIMIT $4
+        "#;
+
+    pub const DATABASE_SIZE: &str = r#"
+            SELECT page_count * page_size, (page_count - freelist_count) * page_size
+            FROM pragma_page_count(), pragma_freelist_count(), pragma_page_size()
+        "#;
+
+    pub const TABLES: &str = r#"
+            SELECT name FROM sqlite_master
+            WHERE type = 'table' AND name NOT LIKE 'sqlite_%'
+            ORDER BY name
+        "#;
+
+    pub const CERTIFICATE_FAILURES: &str = r#"
+            SELECT domain, failures, last_error, rate_limited, last_failure_at, retry_at
+            FROM certificate_failures
+            ORDER BY domain ASC
+        "#;
+
     pub const RUN_EXISTS: &str = "SELECT 1 FROM runs WHERE run_id = $1";
 
     pub const RUN_STDOUT: &str = r#"
@@ -183,10 +313,68 @@ impl TimeRange {
     }
 }
 
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+/// A time range as the API takes it: `start` and `end` in Unix
+/// milliseconds, or `since`, a span back from now like `7d` or `12h`, or
+/// `today`. Days are counted in `tz`, an IANA name like `Europe/Berlin`, or
+/// UTC.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TimeRangeQuery {
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+    pub since: Option<String>,
+    pub tz: Option<String>,
+}
+
+impl TimeRangeQuery {
+    /// The range asked for at `now`, or `None` for all time. `since` takes
+    /// precedence over `start`.
+    pub fn resolve(&self, now: Timestamp) -> Result<Option<TimeRange>, String> {
+        let tz = match &self.tz {
+            Some(name) => TimeZone::get(name).map_err(|_| format!("unknown timezone '{name}'"))?,
+            None => TimeZone::UTC,
+        };
+        let start = match self.since.as_deref().map(str::trim) {
+            None => self.start,
+            Some(since) => {
+                let now = now.to_zoned(tz);
+                let start = if since.eq_ignore_ascii_case("today") {
+                    now.start_of_day()
+                } else {
+                    let span: Span = since.parse().map_err(|_| {
+                        format!("invalid since '{since}', expected e.g. '7d', '12h' or 'today'")
+                    })?;
+                    now.checked_sub(span.abs())
+                };
+                Some(
+                    start
+                        .map_err(|e| e.to_string())?
+                        .timestamp()
+                        .as_millisecond(),
+                )
+            }
+        };
+        Ok(TimeRange {
+            start,
+            end: self.end,
+        }
+        .into_option())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PaginationParams {
     pub cursor: Option<i64>,
-    pub limit: Option<u32>,
+    /// Page size, already clamped to the configured `max_page_limit`.
+    pub limit: u32,
+}
+
+impl Default for PaginationParams {
+    fn default() -> Self {
+        Self {
+            cursor: None,
+            limit: crate::config::DEFAULT_PAGE_LIMIT,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -203,9 +391,12 @@ pub struct TotalOverview {
     pub total_sleep_time_ms: i64,
     pub total_start_failures: i64,
     pub total_stop_failures: i64,
+    /// Estimated energy saved while apps with `watts` slept, or none if no
+    /// app sets it. Filled in by the API, which knows the config.
+    pub energy_saved_kwh: Option<f64>,
 }
 
-#[derive(Debug, Clone, Default, Serialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct AppOverview {
     pub host: String,
     pub total_runs: i64,
@@ -215,6 +406,19 @@ pub struct AppOverview {
     pub total_stop_failures: i64,
     pub is_running: bool,
     pub last_run_at: Option<i64>,
+    /// Requests proxied to the app.
+    #[serde(default)]
+    pub total_requests: i64,
+    /// Mean time from receiving a request to finishing its response.
+    #[serde(default)]
+    pub avg_latency_ms: f64,
+    /// Share of requests answered with a 5xx status, from 0 to 1.
+    #[serde(default)]
+    pub error_rate: f64,
+    /// Estimated energy saved while asleep, if the app sets `watts`.
+    /// Filled in by the API, which knows the config.
+    #[serde(default)]
+    pub energy_saved_kwh: Option<f64>,
 }
 
 #[derive(Debug, Clone, Default, Serialize)]
@@ -225,6 +429,82 @@ pub struct AppRun {
     pub total_awake_time_ms: i64,
     pub stdout_lines: i64,
     pub stderr_lines: i64,
+    /// penny exited while the run was open, so its end time is a guess.
+    pub interrupted: bool,
+    /// Output lines not stored for being over `capture_rate_limit`.
+    pub dropped_lines: i64,
+    /// Resident memory and CPU use of the app's processes, sampled while it
+    /// ran. None for runs too short to sample, or not started by penny.
+    pub peak_memory_bytes: Option<i64>,
+    pub avg_memory_bytes: Option<i64>,
+    pub peak_cpu_percent: Option<f64>,
+    pub avg_cpu_percent: Option<f64>,
+}
+
+/// How often an app is woken and by what, to help pick its wait period.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WakeStats {
+    pub host: String,
+    pub total_wakes: i64,
+    /// Mean time awake per wake that has ended.
+    pub avg_awake_time_ms: i64,
+    /// Most common first. Runs from before reasons were recorded, or that
+    /// penny found already running, are `unknown`.
+    pub by_reason: Vec<WakeReasonStats>,
+    /// Wakes per UTC day, oldest first. Days without any are left out.
+    pub per_day: Vec<DailyWakes>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WakeReasonStats {
+    pub reason: String,
+    pub wakes: i64,
+    pub avg_awake_time_ms: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DailyWakes {
+    /// `YYYY-MM-DD`.
+    pub day: String,
+    pub wakes: i64,
+}
+
+/// Uptime monitor results for an app.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MonitorHistory {
+    pub host: String,
+    pub checks: i64,
+    pub failures: i64,
+    /// Share of checks that passed, from 0 to 100. None without checks.
+    pub uptime_percent: Option<f64>,
+    /// Mean latency of passing checks.
+    pub avg_latency_ms: f64,
+    /// Newest first.
+    pub recent: Vec<MonitorCheck>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MonitorCheck {
+    /// Missing when the request failed before a response.
+    pub status: Option<i64>,
+    pub latency_ms: i64,
+    pub error: Option<String>,
+    /// The app was asleep, so only penny answered.
+    pub asleep: bool,
+    pub timestamp: i64,
+}
+
+/// A wait period chosen when an app's idle stop was scheduled.
+#[derive(Debug, Clone, Serialize)]
+pub struct WaitDecisionEntry {
+    pub wait_period_ms: i64,
+    /// Requests per minute over the short and long windows.
+    pub short_rate: f64,
+    pub long_rate: f64,
+    pub adaptive: bool,
+    /// `quiet_hours` shortened the wait period.
+    pub quiet_hours: bool,
+    pub timestamp: i64,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -239,6 +519,55 @@ pub struct RunLogs {
     pub stderr: Vec<LogEntry>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CertificateEventEntry {
+    pub domain: String,
+    pub event: String,
+    pub detail: Option<String>,
+    pub timestamp: i64,
+}
+
+/// How much room penny's database takes.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StorageReport {
+    /// Size of the database file.
+    pub size_bytes: i64,
+    /// Part of the file holding data; the rest is free pages that are
+    /// reused before the file grows.
+    pub used_bytes: i64,
+    /// `max_database_mb` in bytes, if set. Filled in by the API, which
+    /// knows the config.
+    pub max_bytes: Option<u64>,
+    pub tables: Vec<TableRows>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TableRows {
+    pub name: String,
+    pub rows: i64,
+}
+
+/// A request penny could not serve.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProxyErrorEntry {
+    pub host: String,
+    pub path: String,
+    /// `no_app`, `start_failed`, `connect` or `upstream`.
+    pub kind: String,
+    pub detail: String,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CertificateFailureEntry {
+    pub domain: String,
+    pub failures: u32,
+    pub last_error: String,
+    pub rate_limited: bool,
+    pub last_failure_at: i64,
+    pub retry_at: i64,
+}
+
 #[async_trait::async_trait]
 pub trait Reporter: Sync + Send + Clone + Debug + 'static {
     async fn total_overview(&self, time_range: Option<TimeRange>) -> TotalOverview;
@@ -256,6 +585,43 @@ pub trait Reporter: Sync + Send + Clone + Debug + 'static {
     ) -> PaginatedResponse<AppRun>;
 
     async fn run_logs(&self, run_id: &RunId) -> Option<RunLogs>;
+
+    async fn wake_stats(&self, host: &Host, time_range: Option<TimeRange>) -> WakeStats;
+
+    async fn monitor_history(
+        &self,
+        host: &Host,
+        time_range: Option<TimeRange>,
+        limit: u32,
+    ) -> MonitorHistory;
+
+    /// Recorded wait decisions for an app, newest first.
+    async fn wait_decisions(
+        &self,
+        host: &Host,
+        time_range: Option<TimeRange>,
+        limit: u32,
+    ) -> Vec<WaitDecisionEntry>;
+
+    async fn certificate_events(
+        &self,
+        domain: Option<&str>,
+        limit: u32,
+    ) -> Vec<CertificateEventEntry>;
+
+    async fn certificate_failures(&self) -> Vec<CertificateFailureEntry>;
+
+    /// Requests penny could not serve, newest first, for every host or just
+    /// `host`.
+    async fn proxy_errors(
+        &self,
+        host: Option<&str>,
+        time_range: Option<TimeRange>,
+        limit: u32,
+    ) -> Vec<ProxyErrorEntry>;
+
+    /// Size of the database and row counts of its tables.
+    async fn storage(&self) -> StorageReport;
 }
 
 #[async_trait::async_trait]
@@ -282,6 +648,7 @@ impl Reporter for SqliteDatabase {
                 total_sleep_time_ms,
                 total_start_failures,
                 total_stop_failures,
+                energy_saved_kwh: None,
             },
             Err(e) => {
                 error!("failed to query total overview: {e}");
@@ -293,7 +660,7 @@ impl Reporter for SqliteDatabase {
     async fn apps_overview(&self, time_range: Option<TimeRange>) -> Vec<AppOverview> {
         let time_range = time_range.unwrap_or_default();
 
-        let rows = sqlx::query_as::<_, (String, i64, i64, i64, i64, i64, i64, i64)>(
+        let rows = sqlx::query_as::<_, (String, i64, i64, i64, i64, i64, i64, i64, i64, f64, f64)>(
             queries::APPS_OVERVIEW,
         )
         .bind(time_range.start)
@@ -314,6 +681,9 @@ impl Reporter for SqliteDatabase {
                         total_stop_failures,
                         is_running,
                         last_run_at,
+                        total_requests,
+                        avg_latency_ms,
+                        error_rate,
                     )| AppOverview {
                         host,
                         total_runs,
@@ -323,6 +693,10 @@ impl Reporter for SqliteDatabase {
                         total_stop_failures,
                         is_running: is_running != 0,
                         last_run_at: Some(last_run_at),
+                        total_requests,
+                        avg_latency_ms,
+                        error_rate,
+                        energy_saved_kwh: None,
                     },
                 )
                 .collect(),
@@ -340,12 +714,14 @@ impl Reporter for SqliteDatabase {
     ) -> Option<AppOverview> {
         let time_range = time_range.unwrap_or_default();
 
-        let row = sqlx::query_as::<_, (i64, i64, i64, i64, i64, i64, i64)>(queries::APP_OVERVIEW)
-            .bind(&host.0)
-            .bind(time_range.start)
-            .bind(time_range.end)
-            .fetch_optional(&self.pool)
-            .await;
+        let row = sqlx::query_as::<_, (i64, i64, i64, i64, i64, i64, i64, i64, f64, f64)>(
+            queries::APP_OVERVIEW,
+        )
+        .bind(&host.0)
+        .bind(time_range.start)
+        .bind(time_range.end)
+        .fetch_optional(&self.pool)
+        .await;
 
         match row {
             Ok(Some((
@@ -356,6 +732,9 @@ impl Reporter for SqliteDatabase {
                 total_stop_failures,
                 is_running,
                 last_run_at,
+                total_requests,
+                avg_latency_ms,
+                error_rate,
             ))) => {
                 if total_runs == 0 {
                     return None;
@@ -369,6 +748,10 @@ impl Reporter for SqliteDatabase {
                     total_stop_failures,
                     is_running: is_running != 0,
                     last_run_at: Some(last_run_at),
+                    total_requests,
+                    avg_latency_ms,
+                    error_rate,
+                    energy_saved_kwh: None,
                 })
             }
             Ok(None) => None,
@@ -386,17 +769,33 @@ impl Reporter for SqliteDatabase {
         pagination: PaginationParams,
     ) -> PaginatedResponse<AppRun> {
         let time_range = time_range.unwrap_or_default();
-        let limit = pagination.limit.unwrap_or(20) as i64;
+        let limit = pagination.limit as i64;
         let fetch_limit = limit + 1; // Fetch one extra to detect if more pages exist
 
-        let rows = sqlx::query_as::<_, (String, i64, i64, i64, i64, i64)>(queries::APP_RUNS)
-            .bind(&host.0)
-            .bind(time_range.start)
-            .bind(time_range.end)
-            .bind(pagination.cursor)
-            .bind(fetch_limit)
-            .fetch_all(&self.pool)
-            .await;
+        let rows = sqlx::query_as::<
+            _,
+            (
+                String,
+                i64,
+                i64,
+                i64,
+                i64,
+                i64,
+                bool,
+                i64,
+                Option<i64>,
+                Option<i64>,
+                Option<f64>,
+                Option<f64>,
+            ),
+        >(queries::APP_RUNS)
+        .bind(&host.0)
+        .bind(time_range.start)
+        .bind(time_range.end)
+        .bind(pagination.cursor)
+        .bind(fetch_limit)
+        .fetch_all(&self.pool)
+        .await;
 
         match rows {
             Ok(mut rows) => {
@@ -406,8 +805,7 @@ impl Reporter for SqliteDatabase {
                 }
 
                 let next_cursor = if has_more {
-                    rows.last()
-                        .map(|(_, start_time_ms, _, _, _, _)| *start_time_ms)
+                    rows.last().map(|row| row.1)
                 } else {
                     None
                 };
@@ -422,6 +820,12 @@ impl Reporter for SqliteDatabase {
                             total_awake_time_ms,
                             stdout_lines,
                             stderr_lines,
+                            interrupted,
+                            dropped_lines,
+                            peak_memory_bytes,
+                            avg_memory_bytes,
+                            peak_cpu_percent,
+                            avg_cpu_percent,
                         )| AppRun {
                             run_id,
                             start_time_ms,
@@ -429,6 +833,12 @@ impl Reporter for SqliteDatabase {
                             total_awake_time_ms,
                             stdout_lines,
                             stderr_lines,
+                            interrupted,
+                            dropped_lines,
+                            peak_memory_bytes,
+                            avg_memory_bytes,
+                            peak_cpu_percent,
+                            avg_cpu_percent,
                         },
                     )
                     .collect();
@@ -493,12 +903,296 @@ impl Reporter for SqliteDatabase {
 
         Some(RunLogs { stdout, stderr })
     }
+
+    async fn certificate_events(
+        &self,
+        domain: Option<&str>,
+        limit: u32,
+    ) -> Vec<CertificateEventEntry> {
+        sqlx::query_as::<_, (String, String, Option<String>, i64)>(queries::CERTIFICATE_EVENTS)
+            .bind(domain)
+            .bind(limit as i64)
+            .fetch_all(&self.pool)
+            .await
+            .map(|rows| {
+                rows.into_iter()
+                    .map(|(domain, event, detail, timestamp)| CertificateEventEntry {
+                        domain,
+                        event,
+                        detail,
+                        timestamp,
+                    })
+                    .collect()
+            })
+            .unwrap_or_else(|e| {
+                error!("failed to query certificate events: {e}");
+                Vec::new()
+            })
+    }
+
+    async fn wake_stats(&self, host: &Host, time_range: Option<TimeRange>) -> WakeStats {
+        let time_range = time_range.unwrap_or_default();
+
+        let reasons = sqlx::query_as::<_, (String, i64, i64, i64)>(queries::WAKE_REASONS)
+            .bind(&host.0)
+            .bind(time_range.start)
+            .bind(time_range.end)
+            .fetch_all(&self.pool)
+            .await
+            .unwrap_or_else(|e| {
+                error!("failed to query wake reasons: {e}");
+                Vec::new()
+            });
+        let per_day = sqlx::query_as::<_, (String, i64)>(queries::WAKES_PER_DAY)
+            .bind(&host.0)
+            .bind(time_range.start)
+            .bind(time_range.end)
+            .fetch_all(&self.pool)
+            .await
+            .unwrap_or_else(|e| {
+                error!("failed to query wakes per day: {e}");
+                Vec::new()
+            });
+
+        let average = |awake_time_ms: i64, finished: i64| {
+            if finished > 0 {
+                awake_time_ms / finished
+            } else {
+                0
+            }
+        };
+        let (awake_time_ms, finished) =
+            reasons
+                .iter()
+                .fold((0, 0), |(awake, done), (_, _, awake_time_ms, finished)| {
+                    (awake + awake_time_ms, done + finished)
+                });
+
+        WakeStats {
+            host: host.0.clone(),
+            total_wakes: reasons.iter().map(|(_, wakes, _, _)| wakes).sum(),
+            avg_awake_time_ms: average(awake_time_ms, finished),
+            by_reason: reasons
+                .into_iter()
+                .map(|(reason, wakes, awake_time_ms, finished)| WakeReasonStats {
+                    reason,
+                    wakes,
+                    avg_awake_time_ms: average(awake_time_ms, finished),
+                })
+                .collect(),
+            per_day: per_day
+                .into_iter()
+                .map(|(day, wakes)| DailyWakes { day, wakes })
+                .collect(),
+        }
+    }
+
+    async fn monitor_history(
+        &self,
+        host: &Host,
+        time_range: Option<TimeRange>,
+        limit: u32,
+    ) -> MonitorHistory {
+        let time_range = time_range.unwrap_or_default();
+
+        let (checks, failures, avg_latency_ms) =
+            sqlx::query_as::<_, (i64, i64, f64)>(queries::MONITOR_SUMMARY)
+                .bind(&host.0)
+                .bind(time_range.start)
+                .bind(time_range.end)
+                .fetch_one(&self.pool)
+                .await
+                .unwrap_or_else(|e| {
+                    error!("failed to query monitor summary: {e}");
+                    (0, 0, 0.0)
+                });
+        let recent = sqlx::query_as::<_, (Option<i64>, i64, Option<String>, bool, i64)>(
+            queries::MONITOR_CHECKS,
+        )
+        .bind(&host.0)
+        .bind(time_range.start)
+        .bind(time_range.end)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|e| {
+            error!("failed to query monitor checks: {e}");
+            Vec::new()
+        });
+
+        MonitorHistory {
+            host: host.0.clone(),
+            checks,
+            failures,
+            uptime_percent: (checks > 0)
+                .then(|| (checks - failures) as f64 * 100.0 / checks as f64),
+            avg_latency_ms,
+            recent: recent
+                .into_iter()
+                .map(
+                    |(status, latency_ms, error, asleep, timestamp)| MonitorCheck {
+                        status,
+                        latency_ms,
+                        error,
+                        asleep,
+                        timestamp,
+                    },
+                )
+                .collect(),
+        }
+    }
+
+    async fn wait_decisions(
+        &self,
+        host: &Host,
+        time_range: Option<TimeRange>,
+        limit: u32,
+    ) -> Vec<WaitDecisionEntry> {
+        let time_range = time_range.unwrap_or_default();
+
+        sqlx::query_as::<_, (i64, f64, f64, bool, bool, i64)>(queries::WAIT_DECISIONS)
+            .bind(&host.0)
+            .bind(time_range.start)
+            .bind(time_range.end)
+            .bind(limit as i64)
+            .fetch_all(&self.pool)
+            .await
+            .map(|rows| {
+                rows.into_iter()
+                    .map(
+                        |(
+                            wait_period_ms,
+                            short_rate,
+                            long_rate,
+                            adaptive,
+                            quiet_hours,
+                            timestamp,
+                        )| {
+                            WaitDecisionEntry {
+                                wait_period_ms,
+                                short_rate,
+                                long_rate,
+                                adaptive,
+                                quiet_hours,
+                                timestamp,
+                            }
+                        },
+                    )
+                    .collect()
+            })
+            .unwrap_or_else(|e| {
+                error!("failed to query wait decisions: {e}");
+                Vec::new()
+            })
+    }
+
+    async fn certificate_failures(&self) -> Vec<CertificateFailureEntry> {
+        sqlx::query_as::<_, (String, i64, String, bool, i64, i64)>(queries::CERTIFICATE_FAILURES)
+            .fetch_all(&self.pool)
+            .await
+            .map(|rows| {
+                rows.into_iter()
+                    .map(
+                        |(
+                            domain,
+                            failures,
+                            last_error,
+                            rate_limited,
+                            last_failure_at,
+                            retry_at,
+                        )| {
+                            CertificateFailureEntry {
+                                domain,
+                                failures: failures as u32,
+                                last_error,
+                                rate_limited,
+                                last_failure_at,
+                                retry_at,
+                            }
+                        },
+                    )
+                    .collect()
+            })
+            .unwrap_or_else(|e| {
+                error!("failed to query certificate failures: {e}");
+                Vec::new()
+            })
+    }
+
+    async fn proxy_errors(
+        &self,
+        host: Option<&str>,
+        time_range: Option<TimeRange>,
+        limit: u32,
+    ) -> Vec<ProxyErrorEntry> {
+        let time_range = time_range.unwrap_or_default();
+
+        sqlx::query_as::<_, (String, String, String, String, i64)>(queries::PROXY_ERRORS)
+            .bind(host)
+            .bind(time_range.start)
+            .bind(time_range.end)
+            .bind(limit as i64)
+            .fetch_all(&self.pool)
+            .await
+            .map(|rows| {
+                rows.into_iter()
+                    .map(|(host, path, kind, detail, timestamp)| ProxyErrorEntry {
+                        host,
+                        path,
+                        kind,
+                        detail,
+                        timestamp,
+                    })
+                    .collect()
+            })
+            .unwrap_or_else(|e| {
+                error!("failed to query proxy errors: {e}");
+                Vec::new()
+            })
+    }
+
+    async fn storage(&self) -> StorageReport {
+        let (size_bytes, used_bytes) = sqlx::query_as::<_, (i64, i64)>(queries::DATABASE_SIZE)
+            .fetch_one(&self.pool)
+            .await
+            .unwrap_or_else(|e| {
+                error!("failed to query database size: {e}");
+                (0, 0)
+            });
+
+        let names = sqlx::query_scalar::<_, String>(queries::TABLES)
+            .fetch_all(&self.pool)
+            .await
+            .unwrap_or_else(|e| {
+                error!("failed to list tables: {e}");
+                Vec::new()
+            });
+        let mut tables = Vec::with_capacity(names.len());
+        for name in names {
+            let count = format!("SELECT COUNT(*) FROM \"{}\"", name.replace('"', "\"\""));
+            match sqlx::query_scalar::<_, i64>(&count)
+                .fetch_one(&self.pool)
+                .await
+            {
+                Ok(rows) => tables.push(TableRows { name, rows }),
+                Err(e) => error!(table = %name, "failed to count rows: {e}"),
+            }
+        }
+
+        StorageReport {
+            size_bytes,
+            used_bytes,
+            max_bytes: None,
+            tables,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::collector::Collector;
+    use crate::types::{ProxyFailure, RequestCounts, WaitDecision, WakeReason};
 
     async fn create_test_db() -> SqliteDatabase {
         SqliteDatabase::new("sqlite::memory:")
@@ -598,6 +1292,160 @@ mod tests {
         assert_eq!(overview.total_start_failures, 1);
     }
 
+    #[tokio::test]
+    async fn app_overviews_include_request_stats() {
+        let db = create_test_db().await;
+        let host = Host("myapp.local".to_string());
+        let other = Host("other.local".to_string());
+
+        db.app_started(&host).await.unwrap();
+        db.app_started(&other).await.unwrap();
+        let mut counts = RequestCounts::default();
+        counts.add(200, 10);
+        counts.add(200, 20);
+        counts.add(404, 30);
+        db.requests_completed(&host, &counts).await.unwrap();
+        let mut counts = RequestCounts::default();
+        counts.add(502, 40);
+        db.requests_completed(&host, &counts).await.unwrap();
+
+        let overview = db.app_overview(&host, None).await.unwrap();
+        assert_eq!(overview.total_requests, 4);
+        assert_eq!(overview.avg_latency_ms, 25.0);
+        assert_eq!(overview.error_rate, 0.25);
+
+        let overviews = db.apps_overview(None).await;
+        let other = overviews
+            .iter()
+            .find(|overview| overview.host == "other.local")
+            .unwrap();
+        assert_eq!(other.total_requests, 0);
+        assert_eq!(other.error_rate, 0.0);
+    }
+
+    #[tokio::test]
+    async fn wake_stats_break_down_wakes_by_reason() {
+        let db = create_test_db().await;
+        let host = Host("myapp.local".to_string());
+
+        for reason in [WakeReason::Browser, WakeReason::Browser, WakeReason::Bot] {
+            let run_id = db.app_started(&host).await.unwrap();
+            db.app_woken(&run_id, reason).await.unwrap();
+            db.app_stopped(&host).await.unwrap();
+        }
+        db.app_started(&host).await.unwrap();
+        db.app_started(&Host("other.local".to_string()))
+            .await
+            .unwrap();
+
+        let stats = db.wake_stats(&host, None).await;
+        assert_eq!(stats.total_wakes, 4);
+        let reasons: Vec<_> = stats
+            .by_reason
+            .iter()
+            .map(|stats| (stats.reason.as_str(), stats.wakes))
+            .collect();
+        assert_eq!(reasons, [("browser", 2), ("bot", 1), ("unknown", 1)]);
+        assert_eq!(stats.per_day.len(), 1);
+        assert_eq!(stats.per_day[0].wakes, 4);
+    }
+
+    #[tokio::test]
+    async fn monitor_history_reports_uptime_and_recent_checks() {
+        let db = create_test_db().await;
+        let host = Host("myapp.local".to_string());
+
+        db.monitor_checked(&host, Some(200), 40, None, false)
+            .await
+            .unwrap();
+        db.monitor_checked(&host, Some(204), 20, None, true)
+            .await
+            .unwrap();
+        db.monitor_checked(&host, None, 10_000, Some("timed out"), false)
+            .await
+            .unwrap();
+        db.monitor_checked(&Host("other.local".to_string()), Some(200), 5, None, false)
+            .await
+            .unwrap();
+
+        let history = db.monitor_history(&host, None, 2).await;
+        assert_eq!(history.checks, 3);
+        assert_eq!(history.failures, 1);
+        assert!((history.uptime_percent.unwrap() - 200.0 / 3.0).abs() < 1e-9);
+        assert!((history.avg_latency_ms - 30.0).abs() < 1e-9);
+        assert_eq!(history.recent.len(), 2);
+        assert_eq!(history.recent[0].error.as_deref(), Some("timed out"));
+        assert!(history.recent[1].asleep);
+
+        let empty = db
+            .monitor_history(&Host("none.local".to_string()), None, 10)
+            .await;
+        assert_eq!(empty.uptime_percent, None);
+    }
+
+    #[tokio::test]
+    async fn wait_decisions_are_listed_newest_first() {
+        let db = create_test_db().await;
+        let host = Host("myapp.local".to_string());
+        let decision = |wait_period_ms| WaitDecision {
+            wait_period_ms,
+            short_rate: 0.5,
+            long_rate: 0.25,
+            adaptive: true,
+            quiet_hours: false,
+        };
+
+        db.wait_decided(&host, &decision(60_000)).await.unwrap();
+        db.wait_decided(&host, &decision(300_000)).await.unwrap();
+        db.wait_decided(&Host("other.local".to_string()), &decision(1_000))
+            .await
+            .unwrap();
+
+        let decisions = db.wait_decisions(&host, None, 10).await;
+        assert_eq!(decisions.len(), 2);
+        assert_eq!(decisions[0].wait_period_ms, 300_000);
+        assert!((decisions[0].short_rate - 0.5).abs() < 1e-9);
+        assert!(decisions[0].adaptive);
+        assert_eq!(db.wait_decisions(&host, None, 1).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn proxy_errors_are_listed_newest_first() {
+        let db = create_test_db().await;
+        let host = Host("myapp.local".to_string());
+
+        db.proxy_failed(
+            &host,
+            "/",
+            ProxyFailure::StartFailed,
+            "health check timed out",
+        )
+        .await
+        .unwrap();
+        db.proxy_failed(&host, "/api", ProxyFailure::Connect, "connection refused")
+            .await
+            .unwrap();
+        db.proxy_failed(
+            &Host("unknown.local".to_string()),
+            "/",
+            ProxyFailure::NoApp,
+            "no app configured for host",
+        )
+        .await
+        .unwrap();
+
+        let errors = db.proxy_errors(None, None, 10).await;
+        assert_eq!(errors.len(), 3);
+        assert_eq!(errors[0].kind, "no_app");
+
+        let errors = db.proxy_errors(Some("myapp.local"), None, 10).await;
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].path, "/api");
+        assert_eq!(errors[0].kind, "connect");
+        assert_eq!(errors[1].detail, "health check timed out");
+        assert_eq!(db.proxy_errors(None, None, 1).await.len(), 1);
+    }
+
     #[tokio::test]
     async fn app_runs_returns_runs_for_host() {
         let db = create_test_db().await;
@@ -706,7 +1554,7 @@ mod tests {
 
         let pagination = PaginationParams {
             cursor: None,
-            limit: Some(3),
+            limit: 3,
         };
         let response = db.app_runs(&host, None, pagination).await;
 
@@ -730,7 +1578,7 @@ mod tests {
         // Get first page
         let pagination = PaginationParams {
             cursor: None,
-            limit: Some(3),
+            limit: 3,
         };
         let first_page = db.app_runs(&host, None, pagination).await;
         assert_eq!(first_page.items.len(), 3);
@@ -739,7 +1587,7 @@ mod tests {
         // Get second page using cursor
         let pagination = PaginationParams {
             cursor: first_page.next_cursor,
-            limit: Some(3),
+            limit: 3,
         };
         let second_page = db.app_runs(&host, None, pagination).await;
         assert_eq!(second_page.items.len(), 2);
@@ -765,4 +1613,81 @@ mod tests {
         assert!(!response.has_more);
         assert!(response.next_cursor.is_none());
     }
+
+    #[tokio::test]
+    async fn certificate_failures_listed_until_cleared() {
+        let db = create_test_db().await;
+
+        db.save_certificate_failure("a.example.com", 2, "order invalid", false, 1_000)
+            .await
+            .unwrap();
+        db.save_certificate_failure("b.example.com", 1, "rate limited", true, 2_000)
+            .await
+            .unwrap();
+
+        let failures = db.certificate_failures().await;
+        assert_eq!(failures.len(), 2);
+        assert_eq!(failures[0].domain, "a.example.com");
+        assert_eq!(failures[0].failures, 2);
+        assert!(!failures[0].rate_limited);
+        assert!(failures[1].rate_limited);
+        assert_eq!(failures[1].retry_at, 2_000);
+
+        db.clear_certificate_failure("a.example.com").await.unwrap();
+        let failures = db.certificate_failures().await;
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].domain, "b.example.com");
+    }
+
+    #[tokio::test]
+    async fn dangling_runs_closed_as_interrupted() {
+        let db = create_test_db().await;
+        let host = Host("app.local".to_string());
+
+        let run_id = db.app_started(&host).await.unwrap();
+        db.append_stdout(&run_id, "still alive".to_string())
+            .await
+            .unwrap();
+
+        assert_eq!(db.close_dangling_runs().await.unwrap(), 1);
+        assert_eq!(db.close_dangling_runs().await.unwrap(), 0);
+
+        let overview = db.app_overview(&host, None).await.unwrap();
+        assert!(!overview.is_running);
+        // The downtime after an interrupted run is not known to be sleep.
+        assert_eq!(overview.total_sleep_time_ms, 0);
+
+        let runs = db.app_runs(&host, None, PaginationParams::default()).await;
+        assert!(runs.items[0].interrupted);
+    }
+
+    #[test]
+    fn since_is_resolved_back_from_now() {
+        let now: Timestamp = "2026-03-10T15:30:00Z".parse().unwrap();
+        let start = |since: &str, tz: Option<&str>| {
+            TimeRangeQuery {
+                since: Some(since.to_owned()),
+                tz: tz.map(str::to_owned),
+                ..Default::default()
+            }
+            .resolve(now)
+            .map(|range| range.and_then(|range| range.start))
+        };
+        let ms = |at: &str| Some(at.parse::<Timestamp>().unwrap().as_millisecond());
+
+        assert_eq!(start("7d", None), Ok(ms("2026-03-03T15:30:00Z")));
+        assert_eq!(start("12h", None), Ok(ms("2026-03-10T03:30:00Z")));
+        assert_eq!(start("today", None), Ok(ms("2026-03-10T00:00:00Z")));
+        assert!(start("soon", None).is_err());
+        assert!(start("today", Some("Nowhere/Special")).is_err());
+
+        let explicit = TimeRangeQuery {
+            start: Some(1),
+            end: Some(2),
+            ..Default::default()
+        };
+        let range = explicit.resolve(now).unwrap().unwrap();
+        assert_eq!((range.start, range.end), (Some(1), Some(2)));
+        assert!(TimeRangeQuery::default().resolve(now).unwrap().is_none());
+    }
 }
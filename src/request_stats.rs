@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use tracing::error;
+
+use crate::collector::Collector;
+use crate::config::SharedConfig;
+use crate::types::Host;
+
+/// How often the requests counted on each app are stored. Counts not yet
+/// stored are lost when penny exits or a reload replaces the app.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Stores the requests every app served since the last flush, one row per
+/// app rather than one per request.
+async fn flush<C: Collector>(config: &SharedConfig, collector: &C) {
+    let apps: Vec<_> = config
+        .read()
+        .await
+        .apps
+        .iter()
+        .map(|(host, app)| (Host(host.clone()), app.clone()))
+        .collect();
+
+    for (host, app) in apps {
+        let counts = app.read().await.take_request_counts();
+        if counts.requests == 0 {
+            continue;
+        }
+        if let Err(e) = collector.requests_completed(&host, &counts).await {
+            error!(host = %host, error = %e, "failed to record requests");
+        }
+    }
+}
+
+/// Stores the request counts of the apps' overviews off the request path.
+pub fn spawn<C: Collector>(config: SharedConfig, collector: C) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+            flush(&config, &collector).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::sync::RwLock;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::db::SqliteDatabase;
+    use crate::reporter::Reporter;
+
+    #[tokio::test]
+    async fn requests_are_stored_in_one_row_per_flush() {
+        let toml_str = r#"
+            ["myapp.local"]
+            address = "127.0.0.1:3000"
+            command = "node server.js"
+        "#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let config: SharedConfig = Arc::new(RwLock::new(config));
+        let db = SqliteDatabase::new("sqlite::memory:").await.unwrap();
+        let host = Host("myapp.local".to_owned());
+        db.app_started(&host).await.unwrap();
+
+        let app = config.read().await.apps["myapp.local"].clone();
+        app.read().await.count_request(200, 10);
+        app.read().await.count_request(503, 30);
+        flush(&config, &db).await;
+        flush(&config, &db).await;
+
+        let (rows,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM request_counts")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(rows, 1);
+        assert_eq!(app.read().await.take_request_counts().requests, 0);
+        let overview = db.app_overview(&host, None).await.unwrap();
+        assert_eq!(overview.total_requests, 2);
+        assert_eq!(overview.avg_latency_ms, 20.0);
+        assert_eq!(overview.error_rate, 0.5);
+    }
+}
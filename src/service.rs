@@ -0,0 +1,151 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Options for the installed `penny serve` command, mirroring its flags.
+pub struct ServeOpts {
+    pub config: String,
+    pub address: Vec<String>,
+    pub https_address: Vec<String>,
+    pub no_tls: bool,
+    pub password: Option<String>,
+}
+
+/// The validated pieces of a `penny serve` invocation for a service file.
+pub struct ServeCommand {
+    pub working_dir: PathBuf,
+    /// `penny serve ...` with the absolute binary and config paths.
+    pub command_line: String,
+}
+
+impl ServeOpts {
+    /// Checks that the config file exists and parses, and builds the command
+    /// the service should run.
+    pub fn serve_command(&self) -> color_eyre::Result<ServeCommand> {
+        let config_path = fs::canonicalize(&self.config).map_err(|e| {
+            color_eyre::eyre::eyre!(
+                "config file '{}' not found or inaccessible: {e}",
+                self.config
+            )
+        })?;
+
+        // Validate the config file parses correctly.
+        let config_content = fs::read_to_string(&config_path)?;
+        let format = crate::config::ConfigFormat::of(&config_path);
+        crate::config::Config::parse(&config_content, format)
+            .map_err(|e| color_eyre::eyre::eyre!("invalid config file: {e}"))?;
+
+        let mut command_line = format!(
+            "{} serve {} --address {} --https-address {}",
+            penny_binary_path()?.display(),
+            config_path.display(),
+            self.address.join(","),
+            self.https_address.join(","),
+        );
+        if self.no_tls {
+            command_line.push_str(" --no-tls");
+        }
+
+        let working_dir = config_path.parent().unwrap_or(Path::new("/")).to_path_buf();
+
+        Ok(ServeCommand {
+            working_dir,
+            command_line,
+        })
+    }
+}
+
+/// A service manager penny can install itself into. Each one backs a CLI
+/// subcommand (`penny systemd`, `penny openrc`) with the same actions.
+pub trait ServiceManager {
+    /// The full command for `action`, used in hints such as
+    /// "run `penny systemd install` first".
+    fn hint(&self, action: &str) -> String;
+
+    /// Errors unless this service manager is usable on this platform.
+    fn check_supported(&self) -> color_eyre::Result<()>;
+
+    /// Where the service definition lives once installed.
+    fn service_path(&self) -> color_eyre::Result<PathBuf>;
+
+    fn install(&self, opts: &ServeOpts) -> color_eyre::Result<()>;
+    fn uninstall(&self) -> color_eyre::Result<()>;
+    fn status(&self) -> color_eyre::Result<()>;
+    fn restart(&self) -> color_eyre::Result<()>;
+    fn reload(&self) -> color_eyre::Result<()>;
+    fn logs(&self, follow: bool) -> color_eyre::Result<()>;
+
+    /// Returns the service definition path, failing if it is not installed.
+    fn installed_path(&self) -> color_eyre::Result<PathBuf> {
+        self.check_supported()?;
+
+        let path = self.service_path()?;
+        if !path.exists() {
+            return Err(color_eyre::eyre::eyre!(
+                "service not installed (nothing at {}), run `{}` first",
+                path.display(),
+                self.hint("install")
+            ));
+        }
+        Ok(path)
+    }
+
+    /// Fails if the service is already installed.
+    fn ensure_not_installed(&self) -> color_eyre::Result<()> {
+        self.check_supported()?;
+
+        let path = self.service_path()?;
+        if path.exists() {
+            return Err(color_eyre::eyre::eyre!(
+                "service already installed at {}, run `{}` first",
+                path.display(),
+                self.hint("uninstall")
+            ));
+        }
+        Ok(())
+    }
+}
+
+pub fn penny_binary_path() -> color_eyre::Result<PathBuf> {
+    std::env::current_exe()
+        .map_err(|e| color_eyre::eyre::eyre!("failed to resolve penny binary path: {e}"))
+}
+
+pub fn login_shell() -> String {
+    std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_owned())
+}
+
+pub fn run_cmd(program: &str, args: &[&str]) -> color_eyre::Result<()> {
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .map_err(|e| color_eyre::eyre::eyre!("failed to run `{program}`: {e}"))?;
+
+    if !status.success() {
+        return Err(color_eyre::eyre::eyre!(
+            "`{program} {}` exited with {}",
+            args.join(" "),
+            status
+        ));
+    }
+
+    Ok(())
+}
+
+/// Runs a status command, letting it print directly. Exit code 3 means
+/// "not running" for both `systemctl` and OpenRC, which is not an error.
+pub fn run_status_cmd(program: &str, args: &[&str]) -> color_eyre::Result<()> {
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .map_err(|e| color_eyre::eyre::eyre!("failed to run {program}: {e}"))?;
+
+    if !status.success() && status.code() != Some(3) {
+        return Err(color_eyre::eyre::eyre!(
+            "{program} status exited with {}",
+            status
+        ));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,112 @@
+use std::time::Duration;
+
+use tracing::{error, info, warn};
+
+use crate::config::SharedConfig;
+use crate::db::SqliteDatabase;
+
+/// How often the database size is checked against `max_database_mb`.
+const CHECK_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Share of the remaining history's time span deleted per pruning round.
+const PRUNE_FRACTION: i64 = 10;
+
+/// `mb` megabytes, in bytes.
+pub fn megabytes(mb: u64) -> u64 {
+    mb.saturating_mul(1024 * 1024)
+}
+
+/// Deletes history oldest-first until the database uses at most
+/// `max_bytes`, or there is no history left. Returns how many rows were
+/// deleted.
+pub async fn prune_to(db: &SqliteDatabase, max_bytes: u64) -> color_eyre::Result<u64> {
+    let mut deleted = 0;
+    while db.used_bytes().await? > max_bytes {
+        let Some(oldest) = db.oldest_prunable().await? else {
+            break;
+        };
+        let now = jiff::Timestamp::now().as_millisecond();
+        let cutoff = oldest + (now - oldest).max(0) / PRUNE_FRACTION + 1;
+        deleted += db.prune_older_than(cutoff).await?;
+    }
+    Ok(deleted)
+}
+
+/// Keeps the database under `max_database_mb`, checking now and then.
+pub fn spawn(db: SqliteDatabase, config: SharedConfig) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+            let Some(max_mb) = config.read().await.max_database_mb else {
+                continue;
+            };
+            let max_bytes = megabytes(max_mb);
+            match prune_to(&db, max_bytes).await {
+                Ok(0) => {}
+                Ok(deleted) => info!(
+                    deleted,
+                    max_mb, "pruned oldest history to fit max_database_mb"
+                ),
+                Err(e) => error!(error = %e, "failed to prune database"),
+            }
+            if let Ok(used) = db.used_bytes().await
+                && used > max_bytes
+            {
+                warn!(
+                    used_mb = used / 1024 / 1024,
+                    max_mb, "database is over max_database_mb with no history left to prune"
+                );
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collector::Collector;
+    use crate::reporter::{PaginationParams, Reporter};
+    use crate::types::Host;
+
+    #[tokio::test]
+    async fn history_is_pruned_oldest_first() {
+        let db = SqliteDatabase::new("sqlite::memory:").await.unwrap();
+        let host = Host("myapp.local".to_string());
+        let run_id = db.app_started(&host).await.unwrap();
+        for timestamp in [1_000, 2_000, 3_000] {
+            sqlx::query("INSERT INTO stdout (run_id, line, timestamp) VALUES (?, 'hello', ?)")
+                .bind(&run_id.0)
+                .bind(timestamp)
+                .execute(&db.pool)
+                .await
+                .unwrap();
+        }
+        let mut counts = crate::types::RequestCounts::default();
+        counts.add(200, 5);
+        db.requests_completed(&host, &counts).await.unwrap();
+
+        assert_eq!(db.oldest_prunable().await.unwrap(), Some(1_000));
+        assert_eq!(db.prune_older_than(2_500).await.unwrap(), 2);
+        assert_eq!(db.oldest_prunable().await.unwrap(), Some(3_000));
+
+        assert_eq!(prune_to(&db, u64::MAX).await.unwrap(), 0);
+        assert_eq!(prune_to(&db, 0).await.unwrap(), 2);
+        assert_eq!(db.oldest_prunable().await.unwrap(), None);
+
+        // Runs are kept for the overviews.
+        let runs = db.app_runs(&host, None, PaginationParams::default()).await;
+        assert_eq!(runs.items.len(), 1);
+
+        let storage = db.storage().await;
+        assert!(storage.size_bytes >= storage.used_bytes);
+        let stdout = storage
+            .tables
+            .iter()
+            .find(|table| table.name == "stdout")
+            .unwrap();
+        assert_eq!(stdout.rows, 0);
+    }
+}
@@ -1,17 +1,125 @@
 use std::fs;
+use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
+use crate::service::{ServeOpts, ServiceManager, login_shell, run_cmd, run_status_cmd};
+
 const SERVICE_NAME: &str = "penny.service";
+const HTTP_SOCKET_NAME: &str = "penny-http.socket";
+const HTTPS_SOCKET_NAME: &str = "penny-https.socket";
+
+/// Value for the unit's `ProtectSystem=` setting.
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum ProtectSystem {
+    /// Mount `/usr` and `/boot` read-only.
+    Yes,
+    /// Also mount `/etc` read-only.
+    Full,
+    /// Mount the whole file system read-only, except `--read-write-path`s.
+    Strict,
+}
 
-/// Options for generating the systemd unit file, mirroring `serve` flags.
-pub struct InstallOpts {
-    pub config: String,
-    pub address: String,
-    pub https_address: String,
-    pub no_tls: bool,
-    pub password: Option<String>,
-    pub system: bool,
+impl ProtectSystem {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Yes => "yes",
+            Self::Full => "full",
+            Self::Strict => "strict",
+        }
+    }
+}
+
+/// Sandboxing and resource limits for the generated unit.
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct Hardening {
+    /// Shorthand for `--no-new-privileges --private-tmp --protect-system full`.
+    #[arg(long)]
+    pub harden: bool,
+
+    /// Set `ProtectSystem=` to make system directories read-only.
+    #[arg(long, value_enum)]
+    pub protect_system: Option<ProtectSystem>,
+
+    /// Extra writable paths when using `--protect-system strict`.
+    #[arg(long = "read-write-path")]
+    pub read_write_paths: Vec<PathBuf>,
+
+    /// Give penny and its apps a private /tmp.
+    #[arg(long)]
+    pub private_tmp: bool,
+
+    /// Prevent penny and its apps from gaining privileges (e.g. via setuid).
+    #[arg(long)]
+    pub no_new_privileges: bool,
+
+    /// Grant CAP_NET_BIND_SERVICE so a non-root service can bind ports below 1024.
+    #[arg(long)]
+    pub bind_low_ports: bool,
+
+    /// Memory limit for penny and its apps, e.g. `2G` (`MemoryMax=`).
+    #[arg(long)]
+    pub memory_max: Option<String>,
+
+    /// CPU limit, e.g. `200%` for two cores (`CPUQuota=`).
+    #[arg(long)]
+    pub cpu_quota: Option<String>,
+
+    /// Maximum number of processes and threads (`TasksMax=`).
+    #[arg(long)]
+    pub tasks_max: Option<u32>,
+
+    /// Maximum number of open files (`LimitNOFILE=`).
+    #[arg(long)]
+    pub limit_nofile: Option<u64>,
+}
+
+impl Hardening {
+    /// `[Service]` lines for the selected options. `working_dir` stays
+    /// writable under `ProtectSystem=strict` since the database usually
+    /// lives next to the config.
+    fn unit_lines(&self, working_dir: &str) -> String {
+        let mut lines = String::new();
+
+        if self.harden || self.no_new_privileges {
+            lines.push_str("NoNewPrivileges=yes\n");
+        }
+        if self.harden || self.private_tmp {
+            lines.push_str("PrivateTmp=yes\n");
+        }
+
+        let protect_system = self
+            .protect_system
+            .or(self.harden.then_some(ProtectSystem::Full));
+        if let Some(protect_system) = protect_system {
+            lines.push_str(&format!("ProtectSystem={}\n", protect_system.as_str()));
+            if let ProtectSystem::Strict = protect_system {
+                lines.push_str(&format!("ReadWritePaths={working_dir}\n"));
+            }
+        }
+        for path in &self.read_write_paths {
+            lines.push_str(&format!("ReadWritePaths={}\n", path.display()));
+        }
+
+        if self.bind_low_ports {
+            lines.push_str("AmbientCapabilities=CAP_NET_BIND_SERVICE\n");
+        }
+
+        if let Some(memory_max) = &self.memory_max {
+            lines.push_str(&format!("MemoryMax={memory_max}\n"));
+        }
+        if let Some(cpu_quota) = &self.cpu_quota {
+            lines.push_str(&format!("CPUQuota={cpu_quota}\n"));
+        }
+        if let Some(tasks_max) = self.tasks_max {
+            lines.push_str(&format!("TasksMax={tasks_max}\n"));
+        }
+        if let Some(limit_nofile) = self.limit_nofile {
+            lines.push_str(&format!("LimitNOFILE={limit_nofile}\n"));
+        }
+
+        lines
+    }
 }
 
 fn user_service_dir() -> color_eyre::Result<PathBuf> {
@@ -32,34 +140,24 @@ fn service_dir(system: bool) -> color_eyre::Result<PathBuf> {
     }
 }
 
-fn service_file_path(system: bool) -> color_eyre::Result<PathBuf> {
-    Ok(service_dir(system)?.join(SERVICE_NAME))
-}
-
-fn penny_binary_path() -> color_eyre::Result<PathBuf> {
-    std::env::current_exe()
-        .map_err(|e| color_eyre::eyre::eyre!("failed to resolve penny binary path: {e}"))
-}
-
-fn login_shell() -> String {
-    std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_owned())
-}
+fn generate_socket_unit(addresses: &[String], fd_name: &str) -> String {
+    let listen_lines: String = addresses
+        .iter()
+        .map(|address| format!("ListenStream={address}\n"))
+        .collect();
+    format!(
+        "\
+[Unit]
+Description=Penny reverse proxy ({fd_name} listener)
 
-fn run_cmd(program: &str, args: &[&str]) -> color_eyre::Result<()> {
-    let status = Command::new(program)
-        .args(args)
-        .status()
-        .map_err(|e| color_eyre::eyre::eyre!("failed to run `{program}`: {e}"))?;
-
-    if !status.success() {
-        return Err(color_eyre::eyre::eyre!(
-            "`{program} {}` exited with {}",
-            args.join(" "),
-            status
-        ));
-    }
+[Socket]
+{listen_lines}FileDescriptorName={fd_name}
+Service={SERVICE_NAME}
 
-    Ok(())
+[Install]
+WantedBy=sockets.target
+"
+    )
 }
 
 fn systemctl_args(system: bool, rest: &[&str]) -> Vec<String> {
@@ -79,244 +177,500 @@ fn run_systemctl(system: bool, rest: &[&str]) -> color_eyre::Result<()> {
     run_cmd("systemctl", &arg_refs)
 }
 
-fn generate_unit_file(opts: &InstallOpts) -> color_eyre::Result<String> {
-    let config_path = fs::canonicalize(&opts.config).map_err(|e| {
-        color_eyre::eyre::eyre!(
-            "config file '{}' not found or inaccessible: {e}",
-            opts.config
-        )
-    })?;
-
-    // Validate the config file parses correctly.
-    let config_content = fs::read_to_string(&config_path)?;
-    let _config: crate::config::Config = toml::from_str(&config_content)
-        .map_err(|e| color_eyre::eyre::eyre!("invalid config file: {e}"))?;
+/// Default unit name for an app, e.g. `penny-app-blog-example-com.service`.
+pub fn app_unit_name(host: &str) -> String {
+    let name: String = host
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    format!("penny-app-{name}.service")
+}
 
-    let penny_bin = penny_binary_path()?;
+fn generate_app_unit_file(host: &str, app: &crate::config::App, config_dir: &Path) -> String {
     let shell = login_shell();
-    let working_dir = config_path
-        .parent()
-        .unwrap_or(Path::new("/"))
-        .to_string_lossy();
-
-    let mut serve_args = format!(
-        "serve {} --address {} --https-address {}",
-        config_path.display(),
-        opts.address,
-        opts.https_address,
-    );
-    if opts.no_tls {
-        serve_args.push_str(" --no-tls");
+    let (start, end) = app.command.command_lines();
+    // A shell command line may be a pipeline or a list, which `exec`
+    // cannot take.
+    let exec = if app.command.start_in_shell() {
+        ""
+    } else {
+        "exec "
+    };
+    let exec_stop = end
+        .map(|end| format!("ExecStop={shell} -lc '{end}'\n"))
+        .unwrap_or_default();
+    let working_dir = app.cwd.as_deref().unwrap_or(config_dir).display();
+    let environment_lines: String = app
+        .command
+        .env()
+        .iter()
+        .map(|(key, value)| {
+            let assignment = format!("{key}={value}")
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('%', "%%");
+            format!("Environment=\"{assignment}\"\n")
+        })
+        .collect();
+
+    format!(
+        "\
+[Unit]
+Description=Penny app {host}
+PartOf={SERVICE_NAME}
+
+[Service]
+Type=simple
+ExecStart={shell} -lc '{exec}{start}'
+{exec_stop}Restart=on-failure
+RestartSec=5
+TimeoutStopSec={stop_timeout}
+WorkingDirectory={working_dir}
+{environment_lines}",
+        stop_timeout = app.stop_timeout.as_secs().max(1),
+    )
+}
+
+/// Sets `PENNY_PASSWORD` in an environment file, keeping its other lines,
+/// and makes the file readable only by its owner. Without a password the
+/// file is left as is, but it must already exist.
+fn write_env_file(path: &Path, password: Option<&str>) -> color_eyre::Result<()> {
+    let Some(password) = password else {
+        if !path.exists() {
+            return Err(color_eyre::eyre::eyre!(
+                "env file {} does not exist; create it or pass --password",
+                path.display()
+            ));
+        }
+        return Ok(());
+    };
+
+    let existing = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let mut content: String = existing
+        .lines()
+        .filter(|line| !line.starts_with("PENNY_PASSWORD="))
+        .map(|line| format!("{line}\n"))
+        .collect();
+    content.push_str(&format!("PENNY_PASSWORD={password}\n"));
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
     }
+    fs::write(path, content)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    println!("wrote PENNY_PASSWORD to {}", path.display());
+
+    Ok(())
+}
 
-    let exec_start = format!("{shell} -lc 'exec {} {serve_args}'", penny_bin.display(),);
+/// penny as a systemd user service, or a system service with `system`.
+#[derive(Default)]
+pub struct Systemd {
+    pub system: bool,
+    /// Let systemd bind the listeners through `.socket` units.
+    pub socket_activation: bool,
+    /// Load secrets from this `EnvironmentFile=` instead of inlining them
+    /// in the (world-readable) unit file.
+    pub env_file: Option<PathBuf>,
+    pub hardening: Hardening,
+}
 
-    let mut environment_lines = String::new();
-    if let Some(ref password) = opts.password {
-        environment_lines.push_str(&format!("Environment=PENNY_PASSWORD={password}\n"));
+impl Systemd {
+    pub fn new(system: bool) -> Self {
+        Self {
+            system,
+            ..Default::default()
+        }
     }
-    if let Ok(rust_log) = std::env::var("RUST_LOG") {
-        environment_lines.push_str(&format!("Environment=RUST_LOG={rust_log}\n"));
+
+    fn flag(&self) -> &'static str {
+        if self.system { " --system" } else { "" }
     }
 
-    let wanted_by = if opts.system {
-        "multi-user.target"
-    } else {
-        "default.target"
-    };
+    /// Socket units to generate alongside the service, as (unit name,
+    /// addresses, `FileDescriptorName=`).
+    fn socket_units<'a>(
+        &self,
+        opts: &'a ServeOpts,
+    ) -> Vec<(&'static str, &'a [String], &'static str)> {
+        if !self.socket_activation {
+            return Vec::new();
+        }
+        let mut units = vec![(HTTP_SOCKET_NAME, opts.address.as_slice(), "http")];
+        if !opts.no_tls {
+            units.push((HTTPS_SOCKET_NAME, opts.https_address.as_slice(), "https"));
+        }
+        units
+    }
 
-    Ok(format!(
-        "\
+    fn generate_unit_file(&self, opts: &ServeOpts) -> color_eyre::Result<String> {
+        let serve = opts.serve_command()?;
+        let shell = login_shell();
+        let working_dir = serve.working_dir.to_string_lossy();
+
+        let exec_start = format!("{shell} -lc 'exec {}'", serve.command_line);
+
+        let mut environment_lines = String::new();
+        if let Some(env_file) = &self.env_file {
+            environment_lines.push_str(&format!("EnvironmentFile={}\n", env_file.display()));
+        } else if let Some(ref password) = opts.password {
+            environment_lines.push_str(&format!("Environment=PENNY_PASSWORD={password}\n"));
+        }
+        if let Ok(rust_log) = std::env::var("RUST_LOG") {
+            environment_lines.push_str(&format!("Environment=RUST_LOG={rust_log}\n"));
+        }
+
+        let sockets: Vec<&str> = self
+            .socket_units(opts)
+            .into_iter()
+            .map(|(name, _, _)| name)
+            .collect();
+        let (socket_deps, socket_lines) = if sockets.is_empty() {
+            (String::new(), String::new())
+        } else {
+            let sockets = sockets.join(" ");
+            (
+                format!("Requires={sockets}\nAfter={sockets}\n"),
+                format!("Sockets={sockets}\n"),
+            )
+        };
+
+        let hardening_lines = self.hardening.unit_lines(&working_dir);
+
+        let wanted_by = if self.system {
+            "multi-user.target"
+        } else {
+            "default.target"
+        };
+
+        Ok(format!(
+            "\
 [Unit]
 Description=Penny reverse proxy
 After=network-online.target
 Wants=network-online.target
-
+{socket_deps}
 [Service]
 Type=simple
-ExecStart={exec_start}
+{socket_lines}ExecStart={exec_start}
+ExecReload=/bin/kill -HUP $MAINPID
 Restart=on-failure
 RestartSec=5
 WorkingDirectory={working_dir}
-{environment_lines}
+{environment_lines}{hardening_lines}
 [Install]
 WantedBy={wanted_by}
 "
-    ))
+        ))
+    }
+
+    /// Writes one unit per configured app so systemd supervises app processes,
+    /// then prints the `systemd_unit` settings that hand them over to it.
+    pub fn install_apps(&self, config: &str) -> color_eyre::Result<()> {
+        self.check_supported()?;
+
+        let config_path = fs::canonicalize(config).map_err(|e| {
+            color_eyre::eyre::eyre!("config file '{config}' not found or inaccessible: {e}")
+        })?;
+        let parsed = crate::config::Config::load(&config_path.to_string_lossy())?;
+        let config_dir = config_path.parent().unwrap_or(Path::new("/"));
+
+        let svc_dir = service_dir(self.system)?;
+        fs::create_dir_all(&svc_dir)?;
+
+        let mut hosts: Vec<_> = parsed.apps.keys().cloned().collect();
+        hosts.sort();
+
+        let mut pending = Vec::new();
+        for host in &hosts {
+            let app = parsed.apps[host].blocking_read();
+            if !app.managed {
+                continue;
+            }
+            let unit_name = app
+                .systemd_unit
+                .clone()
+                .unwrap_or_else(|| app_unit_name(host));
+            let unit_path = svc_dir.join(&unit_name);
+            fs::write(&unit_path, generate_app_unit_file(host, &app, config_dir))?;
+            println!("wrote unit file to {}", unit_path.display());
+
+            if app.systemd_unit.is_none() {
+                pending.push((host, unit_name));
+            }
+        }
+
+        run_systemctl(self.system, &["daemon-reload"])?;
+        println!("reloaded systemd daemon");
+
+        if pending.is_empty() {
+            println!("\nall apps already start through systemd.");
+        } else {
+            println!(
+                "\nadd these settings to {} so penny starts and stops the apps through systemctl:\n",
+                config_path.display()
+            );
+            for (host, unit_name) in pending {
+                println!("[\"{host}\"]\nsystemd_unit = \"{unit_name}\"\n");
+            }
+            println!("then run `penny reload` to apply them.");
+        }
+
+        Ok(())
+    }
 }
 
-pub fn install(opts: InstallOpts) -> color_eyre::Result<()> {
-    if !cfg!(target_os = "linux") {
-        return Err(color_eyre::eyre::eyre!(
-            "the `systemd` command is only available on Linux"
-        ));
+impl ServiceManager for Systemd {
+    fn hint(&self, action: &str) -> String {
+        format!("penny systemd {action}{}", self.flag())
     }
 
-    let system = opts.system;
-    let service_path = service_file_path(system)?;
-    if service_path.exists() {
-        return Err(color_eyre::eyre::eyre!(
-            "service already installed at {}, run `penny systemd uninstall{}` first",
-            service_path.display(),
-            if system { " --system" } else { "" }
-        ));
+    fn check_supported(&self) -> color_eyre::Result<()> {
+        if !cfg!(target_os = "linux") {
+            return Err(color_eyre::eyre::eyre!(
+                "the `systemd` command is only available on Linux"
+            ));
+        }
+        Ok(())
     }
 
-    let unit_content = generate_unit_file(&opts)?;
+    fn service_path(&self) -> color_eyre::Result<PathBuf> {
+        Ok(service_dir(self.system)?.join(SERVICE_NAME))
+    }
 
-    // Ensure the directory exists.
-    let svc_dir = service_dir(system)?;
-    fs::create_dir_all(&svc_dir)?;
+    fn install(&self, opts: &ServeOpts) -> color_eyre::Result<()> {
+        self.ensure_not_installed()?;
 
-    fs::write(&service_path, &unit_content)?;
-    println!("wrote unit file to {}", service_path.display());
+        let system = self.system;
+        let service_path = self.service_path()?;
+        let unit_content = self.generate_unit_file(opts)?;
 
-    run_systemctl(system, &["daemon-reload"])?;
-    println!("reloaded systemd daemon");
+        if let Some(env_file) = &self.env_file {
+            write_env_file(env_file, opts.password.as_deref())?;
+        }
 
-    run_systemctl(system, &["enable", SERVICE_NAME])?;
-    println!("enabled {SERVICE_NAME}");
+        // Ensure the directory exists.
+        let svc_dir = service_dir(system)?;
+        fs::create_dir_all(&svc_dir)?;
 
-    run_systemctl(system, &["start", SERVICE_NAME])?;
-    println!("started {SERVICE_NAME}");
+        fs::write(&service_path, &unit_content)?;
+        println!("wrote unit file to {}", service_path.display());
 
-    if !system {
-        // enable-linger is non-fatal — service still works when logged in.
-        if let Ok(user) = std::env::var("USER") {
-            if let Err(e) = run_cmd("loginctl", &["enable-linger", &user]) {
-                eprintln!(
-                    "warning: failed to enable linger (service won't start at boot without a login session): {e}"
-                );
-            } else {
-                println!("enabled linger for user {user}");
-            }
+        let sockets = self.socket_units(opts);
+        for (name, addresses, fd_name) in &sockets {
+            let socket_path = svc_dir.join(name);
+            fs::write(&socket_path, generate_socket_unit(addresses, fd_name))?;
+            println!("wrote socket unit to {}", socket_path.display());
         }
-    }
 
-    println!("\npenny service installed and running.");
-    let flag = if system { " --system" } else { "" };
-    println!("use `penny systemd status{flag}` to check status");
-    println!("use `penny systemd logs{flag} --follow` to watch logs");
+        run_systemctl(system, &["daemon-reload"])?;
+        println!("reloaded systemd daemon");
 
-    Ok(())
-}
+        for (name, _, _) in &sockets {
+            run_systemctl(system, &["enable", "--now", name])?;
+            println!("enabled and started {name}");
+        }
 
-pub fn uninstall(system: bool) -> color_eyre::Result<()> {
-    if !cfg!(target_os = "linux") {
-        return Err(color_eyre::eyre::eyre!(
-            "the `systemd` command is only available on Linux"
-        ));
-    }
+        run_systemctl(system, &["enable", SERVICE_NAME])?;
+        println!("enabled {SERVICE_NAME}");
+
+        run_systemctl(system, &["start", SERVICE_NAME])?;
+        println!("started {SERVICE_NAME}");
+
+        if !system {
+            // enable-linger is non-fatal — service still works when logged in.
+            if let Ok(user) = std::env::var("USER") {
+                if let Err(e) = run_cmd("loginctl", &["enable-linger", &user]) {
+                    eprintln!(
+                        "warning: failed to enable linger (service won't start at boot without a login session): {e}"
+                    );
+                } else {
+                    println!("enabled linger for user {user}");
+                }
+            }
+        }
 
-    let service_path = service_file_path(system)?;
-    if !service_path.exists() {
-        return Err(color_eyre::eyre::eyre!(
-            "service not installed (no unit file at {})",
-            service_path.display()
-        ));
+        println!("\npenny service installed and running.");
+        println!("use `{}` to check status", self.hint("status"));
+        println!("use `{} --follow` to watch logs", self.hint("logs"));
+
+        Ok(())
     }
 
-    // Stop and disable (ignore errors — service might already be stopped).
-    let _ = run_systemctl(system, &["stop", SERVICE_NAME]);
-    println!("stopped {SERVICE_NAME}");
+    fn uninstall(&self) -> color_eyre::Result<()> {
+        let system = self.system;
+        let service_path = self.installed_path()?;
 
-    let _ = run_systemctl(system, &["disable", SERVICE_NAME]);
-    println!("disabled {SERVICE_NAME}");
+        // Stop and disable (ignore errors — service might already be stopped).
+        let _ = run_systemctl(system, &["stop", SERVICE_NAME]);
+        println!("stopped {SERVICE_NAME}");
 
-    fs::remove_file(&service_path)?;
-    println!("removed {}", service_path.display());
+        let _ = run_systemctl(system, &["disable", SERVICE_NAME]);
+        println!("disabled {SERVICE_NAME}");
 
-    run_systemctl(system, &["daemon-reload"])?;
-    println!("reloaded systemd daemon");
+        fs::remove_file(&service_path)?;
+        println!("removed {}", service_path.display());
 
-    println!("\npenny service uninstalled.");
+        for name in [HTTP_SOCKET_NAME, HTTPS_SOCKET_NAME] {
+            let socket_path = service_dir(system)?.join(name);
+            if socket_path.exists() {
+                let _ = run_systemctl(system, &["disable", "--now", name]);
+                fs::remove_file(&socket_path)?;
+                println!("removed {}", socket_path.display());
+            }
+        }
 
-    Ok(())
-}
+        run_systemctl(system, &["daemon-reload"])?;
+        println!("reloaded systemd daemon");
 
-pub fn status(system: bool) -> color_eyre::Result<()> {
-    if !cfg!(target_os = "linux") {
-        return Err(color_eyre::eyre::eyre!(
-            "the `systemd` command is only available on Linux"
-        ));
-    }
+        println!("\npenny service uninstalled.");
 
-    let service_path = service_file_path(system)?;
-    if !service_path.exists() {
-        return Err(color_eyre::eyre::eyre!(
-            "service not installed (no unit file at {})",
-            service_path.display()
-        ));
+        Ok(())
     }
 
-    let args = systemctl_args(system, &["status", SERVICE_NAME]);
-    let arg_refs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    fn status(&self) -> color_eyre::Result<()> {
+        self.installed_path()?;
+
+        let args = systemctl_args(self.system, &["status", SERVICE_NAME]);
+        let arg_refs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
 
-    // Pass through directly — let systemctl print its output.
-    let status = Command::new("systemctl")
-        .args(&arg_refs)
-        .status()
-        .map_err(|e| color_eyre::eyre::eyre!("failed to run systemctl: {e}"))?;
-
-    // systemctl status exits with 3 when service is inactive, which is not an error.
-    if !status.success() && status.code() != Some(3) {
-        return Err(color_eyre::eyre::eyre!(
-            "systemctl status exited with {}",
-            status
-        ));
+        // Pass through directly — let systemctl print its output.
+        run_status_cmd("systemctl", &arg_refs)
     }
 
-    Ok(())
-}
+    fn restart(&self) -> color_eyre::Result<()> {
+        self.installed_path()?;
 
-pub fn restart(system: bool) -> color_eyre::Result<()> {
-    if !cfg!(target_os = "linux") {
-        return Err(color_eyre::eyre::eyre!(
-            "the `systemd` command is only available on Linux"
-        ));
+        run_systemctl(self.system, &["restart", SERVICE_NAME])?;
+        println!("restarted {SERVICE_NAME}");
+
+        Ok(())
     }
 
-    let service_path = service_file_path(system)?;
-    if !service_path.exists() {
-        let flag = if system { " --system" } else { "" };
-        return Err(color_eyre::eyre::eyre!(
-            "service not installed (no unit file at {}), run `penny systemd install{flag}` first",
-            service_path.display()
-        ));
+    fn reload(&self) -> color_eyre::Result<()> {
+        self.installed_path()?;
+
+        run_systemctl(self.system, &["reload", SERVICE_NAME])?;
+        println!("reloaded {SERVICE_NAME}");
+
+        Ok(())
     }
 
-    run_systemctl(system, &["restart", SERVICE_NAME])?;
-    println!("restarted {SERVICE_NAME}");
+    fn logs(&self, follow: bool) -> color_eyre::Result<()> {
+        self.check_supported()?;
 
-    Ok(())
+        let mut args = Vec::new();
+        if self.system {
+            args.push("--unit");
+        } else {
+            args.push("--user-unit");
+        }
+        args.push(SERVICE_NAME);
+        if follow {
+            args.push("--follow");
+        }
+
+        let status = Command::new("journalctl")
+            .args(&args)
+            .status()
+            .map_err(|e| color_eyre::eyre::eyre!("failed to run journalctl: {e}"))?;
+
+        if !status.success() {
+            return Err(color_eyre::eyre::eyre!("journalctl exited with {}", status));
+        }
+
+        Ok(())
+    }
 }
 
-pub fn logs(follow: bool, system: bool) -> color_eyre::Result<()> {
-    if !cfg!(target_os = "linux") {
-        return Err(color_eyre::eyre::eyre!(
-            "the `systemd` command is only available on Linux"
-        ));
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn app_unit_names_are_sanitized() {
+        assert_eq!(
+            app_unit_name("blog.example.com"),
+            "penny-app-blog-example-com.service"
+        );
+        assert_eq!(
+            app_unit_name("127.0.0.1:8080"),
+            "penny-app-127-0-0-1-8080.service"
+        );
     }
 
-    let mut args = Vec::new();
-    if system {
-        args.push("--unit");
-    } else {
-        args.push("--user-unit");
+    #[test]
+    fn app_units_carry_the_app_env() {
+        let config: crate::config::Config = toml::from_str(
+            r#"
+            ["blog.example.com"]
+            address = "127.0.0.1:3001"
+            command = "node server.js"
+            health_check = "/"
+            env = { GREETING = "say \"hi\" 100%" }
+            "#,
+        )
+        .unwrap();
+        let app = config.apps["blog.example.com"].blocking_read();
+        let unit = generate_app_unit_file("blog.example.com", &app, Path::new("/srv"));
+        assert!(unit.contains("Environment=\"GREETING=say \\\"hi\\\" 100%%\"\n"));
     }
-    args.push(SERVICE_NAME);
-    if follow {
-        args.push("--follow");
+
+    #[test]
+    fn env_file_password_is_replaced() {
+        let path = std::env::temp_dir().join(format!("penny-test-{}.env", std::process::id()));
+        fs::write(&path, "RUST_LOG=info\nPENNY_PASSWORD=old\n").unwrap();
+
+        write_env_file(&path, Some("new")).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "RUST_LOG=info\nPENNY_PASSWORD=new\n"
+        );
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        fs::remove_file(&path).unwrap();
     }
 
-    let status = Command::new("journalctl")
-        .args(&args)
-        .status()
-        .map_err(|e| color_eyre::eyre::eyre!("failed to run journalctl: {e}"))?;
+    #[test]
+    fn socket_unit_listens_on_every_address() {
+        let unit = generate_socket_unit(&["0.0.0.0:80".to_owned(), "[::]:80".to_owned()], "http");
+        assert!(
+            unit.contains(
+                "ListenStream=0.0.0.0:80\nListenStream=[::]:80\nFileDescriptorName=http\n"
+            )
+        );
+    }
 
-    if !status.success() {
-        return Err(color_eyre::eyre::eyre!("journalctl exited with {}", status));
+    #[test]
+    fn default_hardening_adds_nothing() {
+        assert_eq!(Hardening::default().unit_lines("/srv/penny"), "");
     }
 
-    Ok(())
+    #[test]
+    fn harden_preset_can_be_tightened() {
+        let hardening = Hardening {
+            harden: true,
+            protect_system: Some(ProtectSystem::Strict),
+            read_write_paths: vec![PathBuf::from("/var/lib/apps")],
+            tasks_max: Some(512),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            hardening.unit_lines("/srv/penny"),
+            "NoNewPrivileges=yes\n\
+             PrivateTmp=yes\n\
+             ProtectSystem=strict\n\
+             ReadWritePaths=/srv/penny\n\
+             ReadWritePaths=/var/lib/apps\n\
+             TasksMax=512\n"
+        );
+    }
 }
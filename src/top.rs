@@ -0,0 +1,252 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Cell, List, ListItem, Paragraph, Row, Table};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::api::LiveAppStatus;
+use crate::client::ApiClient;
+use crate::reporter::{AppOverview, CertificateEventEntry};
+
+/// Number of events kept in the events pane.
+const MAX_EVENTS: usize = 100;
+
+fn clock(timestamp: jiff::Timestamp) -> String {
+    timestamp
+        .to_zoned(jiff::tz::TimeZone::system())
+        .strftime("%H:%M:%S")
+        .to_string()
+}
+
+struct Dashboard {
+    client: ApiClient,
+    apps: Vec<LiveAppStatus>,
+    overviews: HashMap<String, AppOverview>,
+    states: HashMap<String, String>,
+    last_certificate_event: i64,
+    events: VecDeque<String>,
+    error: Option<String>,
+    refreshed_at: Option<jiff::Timestamp>,
+}
+
+impl Dashboard {
+    fn new(url: String, password: Option<String>) -> Self {
+        Self {
+            client: ApiClient::new(url, password),
+            apps: Vec::new(),
+            overviews: HashMap::new(),
+            states: HashMap::new(),
+            last_certificate_event: jiff::Timestamp::now().as_millisecond(),
+            events: VecDeque::new(),
+            error: None,
+            refreshed_at: None,
+        }
+    }
+
+    fn push_event(&mut self, event: String) {
+        self.events.push_front(event);
+        self.events.truncate(MAX_EVENTS);
+    }
+
+    async fn refresh(&mut self) {
+        let result = tokio::try_join!(
+            self.client.get::<Vec<LiveAppStatus>>("/api/live"),
+            self.client.get::<Vec<AppOverview>>("/api/apps-overview"),
+            self.client
+                .get::<Vec<CertificateEventEntry>>("/api/certificate-events?limit=20"),
+        );
+        let (apps, overviews, certificate_events) = match result {
+            Ok(data) => data,
+            Err(e) => {
+                self.error = Some(format!("{e:#}"));
+                return;
+            }
+        };
+
+        let now = jiff::Timestamp::now();
+        for app in &apps {
+            let previous = self.states.insert(app.host.clone(), app.state.clone());
+            if let Some(previous) = previous
+                && previous != app.state
+            {
+                self.push_event(format!(
+                    "{} {}: {previous} -> {}",
+                    clock(now),
+                    app.host,
+                    app.state
+                ));
+            }
+        }
+
+        for entry in certificate_events.iter().rev() {
+            if entry.timestamp <= self.last_certificate_event {
+                continue;
+            }
+            self.last_certificate_event = entry.timestamp;
+            let at = jiff::Timestamp::from_millisecond(entry.timestamp).unwrap_or(now);
+            let detail = entry
+                .detail
+                .as_deref()
+                .map(|d| format!(" ({d})"))
+                .unwrap_or_default();
+            self.push_event(format!(
+                "{} {}: certificate {}{detail}",
+                clock(at),
+                entry.domain,
+                entry.event
+            ));
+        }
+
+        self.apps = apps;
+        self.overviews = overviews
+            .into_iter()
+            .map(|overview| (overview.host.clone(), overview))
+            .collect();
+        self.error = None;
+        self.refreshed_at = Some(now);
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [header, table, events] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(5),
+            Constraint::Length(10),
+        ])
+        .areas(frame.area());
+
+        let status: Span = match (&self.error, self.refreshed_at) {
+            (Some(error), _) => error.as_str().red(),
+            (None, Some(at)) => format!("updated {}", clock(at)).into(),
+            (None, None) => "loading...".into(),
+        };
+        frame.render_widget(
+            Paragraph::new(Line::from(vec![
+                format!("penny top - {} - ", self.client.url()).bold(),
+                status,
+                "  (q to quit)".dark_gray(),
+            ])),
+            header,
+        );
+
+        let rows = self.apps.iter().map(|app| {
+            let state_style = match app.state.as_str() {
+                "running" => Style::new().fg(Color::Green),
+                "starting" => Style::new().fg(Color::Yellow),
+                _ => Style::new().fg(Color::DarkGray),
+            };
+            // While starting, show how far along the start is instead.
+            let cold_start = match (app.starting_for_ms, app.start_timeout_ms) {
+                (Some(elapsed), Some(timeout)) => {
+                    format!("{}s/{}s", elapsed / 1000, timeout / 1000)
+                }
+                _ => app
+                    .last_cold_start_ms
+                    .map(|ms| format!("{:.1}s", ms as f64 / 1000.0))
+                    .unwrap_or_else(|| "-".to_owned()),
+            };
+            let overview = self.overviews.get(&app.host);
+
+            Row::new(vec![
+                Cell::from(app.host.as_str()),
+                Cell::from(app.state.as_str()).style(state_style),
+                Cell::from(format!("{:.1}", app.requests_per_min)),
+                Cell::from(format!("{:.1}", app.requests_per_min_long)),
+                Cell::from(cold_start),
+                Cell::from(overview.map_or(0, |o| o.total_runs).to_string()),
+                Cell::from(overview.map_or(0, |o| o.total_start_failures).to_string()),
+            ])
+        });
+        let widths = [
+            Constraint::Fill(1),
+            Constraint::Length(9),
+            Constraint::Length(9),
+            Constraint::Length(9),
+            Constraint::Length(11),
+            Constraint::Length(6),
+            Constraint::Length(7),
+        ];
+        frame.render_widget(
+            Table::new(rows, widths)
+                .header(
+                    Row::new([
+                        "HOST",
+                        "STATE",
+                        "REQ/MIN",
+                        "REQ/MIN*",
+                        "COLD START",
+                        "RUNS",
+                        "FAILED",
+                    ])
+                    .style(Style::new().add_modifier(Modifier::BOLD)),
+                )
+                .block(Block::bordered().title(" apps (* long window) ")),
+            table,
+        );
+
+        let items: Vec<ListItem> = self
+            .events
+            .iter()
+            .map(|event| ListItem::new(event.as_str()))
+            .collect();
+        frame.render_widget(
+            List::new(items).block(Block::bordered().title(" events ")),
+            events,
+        );
+    }
+
+    async fn run(
+        &mut self,
+        terminal: &mut DefaultTerminal,
+        interval: Duration,
+    ) -> color_eyre::Result<()> {
+        loop {
+            self.refresh().await;
+            terminal.draw(|frame| self.draw(frame))?;
+
+            if tokio::task::block_in_place(|| wait_for_quit(interval))? {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Waits up to `timeout` for input. Returns true if the user asked to quit;
+/// returns early on resize so the next frame picks up the new size.
+fn wait_for_quit(timeout: Duration) -> std::io::Result<bool> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() || !event::poll(remaining)? {
+            return Ok(false);
+        }
+
+        match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(true),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(true);
+                }
+                _ => {}
+            },
+            Event::Resize(..) => return Ok(false),
+            _ => {}
+        }
+    }
+}
+
+/// Runs the live terminal dashboard against a penny API server.
+pub async fn run(
+    url: String,
+    password: Option<String>,
+    interval: Duration,
+) -> color_eyre::Result<()> {
+    let mut dashboard = Dashboard::new(url, password);
+    let mut terminal = ratatui::init();
+    let result = dashboard.run(&mut terminal, interval).await;
+    ratatui::restore();
+    result
+}
@@ -1,3 +1,4 @@
+use serde::Serialize;
 use ulid::Ulid;
 
 #[derive(Debug, Clone)]
@@ -27,3 +28,121 @@ impl Default for RunId {
         Self::new()
     }
 }
+
+/// Certificate lifecycle events reported by the renewal loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CertificateEvent {
+    Issued,
+    Renewed,
+    RenewalFailed,
+    Expiring,
+}
+
+impl CertificateEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CertificateEvent::Issued => "issued",
+            CertificateEvent::Renewed => "renewed",
+            CertificateEvent::RenewalFailed => "renewal_failed",
+            CertificateEvent::Expiring => "expiring",
+        }
+    }
+}
+
+/// What woke an app, recorded with the run penny starts for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WakeReason {
+    /// A browser navigating to the app.
+    Browser,
+    /// Any other HTTP client, like a page's fetch calls or a script.
+    Client,
+    /// A crawler or link preview, going by its `User-Agent`.
+    Bot,
+    /// Started along with another app through its `also_warm`.
+    Prewarm,
+    /// The dashboard or `POST /api/apps/{host}/start`.
+    Api,
+    /// `penny ctl start`, usually run from cron or a script.
+    Schedule,
+}
+
+impl WakeReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WakeReason::Browser => "browser",
+            WakeReason::Client => "client",
+            WakeReason::Bot => "bot",
+            WakeReason::Prewarm => "prewarm",
+            WakeReason::Api => "api",
+            WakeReason::Schedule => "schedule",
+        }
+    }
+}
+
+/// The wait period chosen when an app's idle stop was scheduled, with the
+/// inputs that produced it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WaitDecision {
+    pub wait_period_ms: u64,
+    /// Requests per minute over the short and long windows.
+    pub short_rate: f64,
+    pub long_rate: f64,
+    pub adaptive: bool,
+    /// `quiet_hours` shortened the wait period.
+    pub quiet_hours: bool,
+}
+
+/// What an app's processes used at one point of a run.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct UsageSample {
+    pub memory_bytes: u64,
+    /// CPU time since the previous sample, as a share of one core: 200 is
+    /// two cores kept busy.
+    pub cpu_percent: f64,
+}
+
+/// Requests proxied to an app since they were last stored.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RequestCounts {
+    pub requests: u64,
+    /// Requests answered with a `5xx` status.
+    pub errors: u64,
+    /// Time all of them took together.
+    pub duration_ms: u64,
+}
+
+impl RequestCounts {
+    pub fn add(&mut self, status: u16, duration_ms: u64) {
+        self.requests += 1;
+        self.errors += u64::from(status >= 500);
+        self.duration_ms += duration_ms;
+    }
+}
+
+/// Why penny could not serve a proxied request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProxyFailure {
+    /// No app is configured for the request's host.
+    NoApp,
+    /// The app did not start, or did not pass its health check in time.
+    StartFailed,
+    /// Connecting to the app failed.
+    Connect,
+    /// The app failed after penny connected, e.g. by timing out or closing
+    /// the connection.
+    Upstream,
+}
+
+impl ProxyFailure {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProxyFailure::NoApp => "no_app",
+            ProxyFailure::StartFailed => "start_failed",
+            ProxyFailure::Connect => "connect",
+            ProxyFailure::Upstream => "upstream",
+        }
+    }
+}
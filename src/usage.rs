@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use tracing::error;
+
+use crate::collector::Collector;
+use crate::config::SharedConfig;
+use crate::procfs::{self, CLOCK_TICKS_PER_SEC};
+use crate::types::{RunId, UsageSample};
+
+/// How often the processes of running apps are sampled.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// The previous sample of an app's process, to take CPU time since.
+struct Previous {
+    pid: u32,
+    cpu_ticks: u64,
+    at: Instant,
+}
+
+/// CPU time used between two samples, as a share of one core.
+fn cpu_percent(ticks: u64, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs_f64();
+    if secs <= 0.0 {
+        return 0.0;
+    }
+    ticks as f64 / CLOCK_TICKS_PER_SEC as f64 / secs * 100.0
+}
+
+/// Samples the memory and CPU use of every app's processes, keeping the
+/// latest sample on the app and adding it to the run's peak and average.
+/// Does nothing where `/proc` is not available.
+pub fn spawn<C: Collector>(config: SharedConfig, collector: C) {
+    tokio::spawn(async move {
+        let mut previous: HashMap<String, Previous> = HashMap::new();
+        let mut interval = tokio::time::interval(SAMPLE_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+
+            let guard = config.read().await;
+            let mut running: Vec<(String, u32, Option<RunId>)> = Vec::new();
+            for (host, app) in &guard.apps {
+                let mut app = app.write().await;
+                match app.command.child_pid() {
+                    Some(pid) if app.command.is_child_running() => {
+                        running.push((host.clone(), pid, app.current_run.clone()));
+                    }
+                    _ => app.usage = None,
+                }
+            }
+            drop(guard);
+            previous.retain(|host, _| running.iter().any(|(running, ..)| running == host));
+
+            let pids: Vec<u32> = running.iter().map(|(_, pid, _)| *pid).collect();
+            let Ok(usages) = tokio::task::spawn_blocking(move || {
+                pids.iter()
+                    .map(|pid| procfs::tree_usage(&[*pid]))
+                    .collect::<Vec<_>>()
+            })
+            .await
+            else {
+                continue;
+            };
+
+            let now = Instant::now();
+            for ((host, pid, run_id), usage) in running.into_iter().zip(usages) {
+                let Some(usage) = usage else {
+                    continue;
+                };
+                let last = previous.insert(
+                    host.clone(),
+                    Previous {
+                        pid,
+                        cpu_ticks: usage.cpu_ticks,
+                        at: now,
+                    },
+                );
+                // CPU use needs two samples of the same process.
+                let Some(last) = last.filter(|last| last.pid == pid) else {
+                    continue;
+                };
+                let sample = UsageSample {
+                    memory_bytes: usage.memory_bytes,
+                    cpu_percent: cpu_percent(
+                        usage.cpu_ticks.saturating_sub(last.cpu_ticks),
+                        now - last.at,
+                    ),
+                };
+
+                if let Some(app) = config.read().await.apps.get(&host) {
+                    app.write().await.usage = Some(sample);
+                }
+                if let Some(run_id) = run_id
+                    && let Err(e) = collector.usage_sampled(&run_id, &sample).await
+                {
+                    error!(host = %host, error = %e, "failed to record resource usage");
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::SqliteDatabase;
+    use crate::reporter::{PaginationParams, Reporter};
+    use crate::types::Host;
+
+    #[tokio::test]
+    async fn runs_keep_the_peak_and_average_of_their_samples() {
+        assert_eq!(cpu_percent(150, Duration::from_secs(3)), 50.0);
+
+        let db = SqliteDatabase::new("sqlite::memory:").await.unwrap();
+        let host = Host("myapp.local".to_string());
+        let run_id = db.app_started(&host).await.unwrap();
+        for (memory_bytes, cpu_percent) in [(100, 10.0), (300, 30.0)] {
+            db.usage_sampled(
+                &run_id,
+                &UsageSample {
+                    memory_bytes,
+                    cpu_percent,
+                },
+            )
+            .await
+            .unwrap();
+        }
+
+        let runs = db.app_runs(&host, None, PaginationParams::default()).await;
+        let run = &runs.items[0];
+        assert_eq!(run.peak_memory_bytes, Some(300));
+        assert_eq!(run.avg_memory_bytes, Some(200));
+        assert_eq!(run.peak_cpu_percent, Some(30.0));
+        assert_eq!(run.avg_cpu_percent, Some(20.0));
+
+        let unsampled = db.app_started(&host).await.unwrap();
+        let runs = db.app_runs(&host, None, PaginationParams::default()).await;
+        let run = runs
+            .items
+            .iter()
+            .find(|run| run.run_id == unsampled.0)
+            .unwrap();
+        assert_eq!(run.peak_memory_bytes, None);
+        assert_eq!(run.avg_cpu_percent, None);
+    }
+}
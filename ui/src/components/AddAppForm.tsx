@@ -0,0 +1,106 @@
+import { useMutation, useQueryClient } from '@tanstack/react-query';
+import { Plus } from 'lucide-react';
+import { type FormEvent, useState } from 'react';
+import { Button } from '@/components/ui/button';
+import { Input } from '@/components/ui/input';
+import { Label } from '@/components/ui/label';
+import { $fetch, type NewApp } from '@/lib/api';
+
+const fields = [
+  { name: 'host', label: 'Hostname', placeholder: 'app.example.com' },
+  { name: 'address', label: 'Address', placeholder: '127.0.0.1:3001' },
+  { name: 'command', label: 'Command', placeholder: 'node server.js' },
+  { name: 'health_check', label: 'Health Check', placeholder: '/' },
+  { name: 'wait_period', label: 'Wait Period', placeholder: '10m' },
+] as const;
+
+type Field = (typeof fields)[number]['name'];
+
+/** The message the API sent with a failed request, if any. */
+function errorMessage(error: unknown): string {
+  if (error && typeof error === 'object' && 'error' in error) {
+    const body = (error as { error: unknown }).error;
+    if (typeof body === 'string' && body) {
+      return body;
+    }
+  }
+  return error instanceof Error ? error.message : 'Failed to add the app';
+}
+
+/** Adds an app to penny's config file, so apps can be set up without editing it. */
+export function AddAppForm() {
+  const queryClient = useQueryClient();
+  const [values, setValues] = useState<Record<Field, string>>({
+    host: '',
+    address: '',
+    command: '',
+    health_check: '',
+    wait_period: '',
+  });
+
+  const mutation = useMutation({
+    mutationFn: (app: NewApp) =>
+      $fetch('@post/api/config/apps', { body: app }),
+    onSuccess: () => {
+      setValues({
+        host: '',
+        address: '',
+        command: '',
+        health_check: '',
+        wait_period: '',
+      });
+      queryClient.invalidateQueries({ queryKey: ['apps-overview'] });
+      queryClient.invalidateQueries({ queryKey: ['total-overview'] });
+    },
+  });
+
+  const handleSubmit = (e: FormEvent) => {
+    e.preventDefault();
+    const { host, ...rest } = values;
+    const settings = Object.fromEntries(
+      Object.entries(rest).filter(([, value]) => value.trim() !== ''),
+    );
+    mutation.mutate({ host: host.trim(), settings });
+  };
+
+  return (
+    <form
+      onSubmit={handleSubmit}
+      className="p-5 border border-border rounded bg-card space-y-4"
+    >
+      <div className="flex items-center gap-3">
+        <Plus className="w-5 h-5 text-chart-3" />
+        <h3 className="text-base font-semibold text-foreground">Add an app</h3>
+      </div>
+      <div className="grid grid-cols-1 md:grid-cols-2 lg:grid-cols-3 gap-4">
+        {fields.map((field) => (
+          <div key={field.name} className="space-y-2">
+            <Label htmlFor={`new-app-${field.name}`}>{field.label}</Label>
+            <Input
+              id={`new-app-${field.name}`}
+              value={values[field.name]}
+              onChange={(e) =>
+                setValues({ ...values, [field.name]: e.target.value })
+              }
+              placeholder={field.placeholder}
+              disabled={mutation.isPending}
+            />
+          </div>
+        ))}
+      </div>
+
+      {mutation.isError && (
+        <p className="text-sm text-destructive">
+          {errorMessage(mutation.error)}
+        </p>
+      )}
+
+      <Button
+        type="submit"
+        disabled={mutation.isPending || !values.host || !values.address}
+      >
+        {mutation.isPending ? 'Adding...' : 'Add app'}
+      </Button>
+    </form>
+  );
+}
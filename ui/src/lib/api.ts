@@ -2,6 +2,7 @@ import { createFetch, createSchema } from '@better-fetch/fetch';
 import { z } from 'zod';
 import { env } from '../env';
 import { getStoredAuth } from './auth';
+import { basePath } from './base';
 
 const timeRangeQuery = z.object({
   start: z.number().optional(),
@@ -14,6 +15,7 @@ const totalOverviewSchema = z.object({
   total_sleep_time_ms: z.number(),
   total_start_failures: z.number(),
   total_stop_failures: z.number(),
+  energy_saved_kwh: z.number().nullable(),
 });
 
 const appOverviewSchema = z.object({
@@ -25,6 +27,10 @@ const appOverviewSchema = z.object({
   total_stop_failures: z.number(),
   is_running: z.boolean(),
   last_run_at: z.number().nullable(),
+  total_requests: z.number(),
+  avg_latency_ms: z.number(),
+  error_rate: z.number(),
+  energy_saved_kwh: z.number().nullable(),
 });
 
 const appRunSchema = z.object({
@@ -34,6 +40,12 @@ const appRunSchema = z.object({
   total_awake_time_ms: z.number(),
   stdout_lines: z.number(),
   stderr_lines: z.number(),
+  interrupted: z.boolean(),
+  dropped_lines: z.number(),
+  peak_memory_bytes: z.number().nullable(),
+  avg_memory_bytes: z.number().nullable(),
+  peak_cpu_percent: z.number().nullable(),
+  avg_cpu_percent: z.number().nullable(),
 });
 
 const paginatedAppRunsSchema = z.object({
@@ -52,6 +64,16 @@ const runLogsSchema = z.object({
   stderr: z.array(logEntrySchema),
 });
 
+const newAppSchema = z.object({
+  host: z.string(),
+  settings: z.record(z.string(), z.unknown()),
+});
+
+const appActionSchema = z.object({
+  host: z.string(),
+  state: z.string(),
+});
+
 const authStatusSchema = z.object({
   auth_required: z.boolean(),
 });
@@ -95,12 +117,16 @@ export const schema = createSchema(
       }),
       output: runLogsSchema,
     },
+    '@post/api/config/apps': {
+      input: newAppSchema,
+      output: appActionSchema,
+    },
   },
   { strict: true },
 );
 
 export const $fetch = createFetch({
-  baseURL: env.VITE_API_URL ?? '',
+  baseURL: env.VITE_API_URL ?? basePath,
   schema,
   throw: true,
   auth: {
@@ -117,3 +143,4 @@ export type PaginatedAppRuns = z.infer<typeof paginatedAppRunsSchema>;
 export type LogEntry = z.infer<typeof logEntrySchema>;
 export type RunLogs = z.infer<typeof runLogsSchema>;
 export type AuthStatus = z.infer<typeof authStatusSchema>;
+export type NewApp = z.infer<typeof newAppSchema>;
@@ -0,0 +1,10 @@
+// Penny serves the dashboard at the root of `api_domain`, and with
+// `api_path` also under /.penny/ on every app's host.
+const API_PATH_PREFIX = '/.penny';
+
+const { pathname } = window.location;
+
+export const basePath =
+  pathname === API_PATH_PREFIX || pathname.startsWith(`${API_PATH_PREFIX}/`)
+    ? API_PATH_PREFIX
+    : '';
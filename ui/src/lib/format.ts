@@ -43,3 +43,10 @@ export function formatMs(ms: number): string {
   const remainingMinutes = minutes % 60;
   return remainingMinutes > 0 ? `${hours}h ${remainingMinutes}m` : `${hours}h`;
 }
+
+export function formatKwh(kwh: number): string {
+  if (kwh === 0) return '0 kWh';
+  if (kwh < 1) return `${Math.round(kwh * 1000)} Wh`;
+  if (kwh < 100) return `${kwh.toFixed(1)} kWh`;
+  return `${Math.round(kwh)} kWh`;
+}
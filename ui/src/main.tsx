@@ -7,6 +7,7 @@ import * as TanStackQueryProvider from './integrations/tanstack-query/root-provi
 // Import the generated route tree
 import { routeTree } from './routeTree.gen';
 
+import { basePath } from './lib/base.ts';
 import './styles.css';
 import reportWebVitals from './reportWebVitals.ts';
 
@@ -15,6 +16,7 @@ import reportWebVitals from './reportWebVitals.ts';
 const TanStackQueryProviderContext = TanStackQueryProvider.getContext();
 const router = createRouter({
   routeTree,
+  basepath: basePath || '/',
   context: {
     ...TanStackQueryProviderContext,
   },
@@ -1,10 +1,13 @@
 import { useInfiniteQuery, useQuery } from '@tanstack/react-query';
 import { createFileRoute, Link, useNavigate } from '@tanstack/react-router';
 import {
+  Activity,
+  AlertTriangle,
   ArrowLeft,
   Circle,
   Clock,
   FileText,
+  Gauge,
   Loader,
   Moon,
   Percent,
@@ -13,6 +16,7 @@ import {
   Sun,
   Terminal,
   Timer,
+  Zap,
 } from 'lucide-react';
 import { useEffect, useRef } from 'react';
 import { ErrorBanner } from '@/components/ErrorBanner';
@@ -24,7 +28,12 @@ import {
 } from '@/components/TimeRangeSelector';
 import { $fetch } from '@/lib/api';
 import { getErrorDisplay } from '@/lib/error-utils';
-import { formatFailureRate, formatMs, formatRelativeTime } from '@/lib/format';
+import {
+  formatFailureRate,
+  formatKwh,
+  formatMs,
+  formatRelativeTime,
+} from '@/lib/format';
 import { timeRangeSearchSchema } from '@/lib/searchSchemas';
 
 export const Route = createFileRoute('/app/$host')({
@@ -234,6 +243,59 @@ function AppDetailPage() {
         </div>
       )}
 
+      {/* Request Stats */}
+      {appOverview && (
+        <div
+          className={`grid grid-cols-1 gap-4 mt-4 ${
+            appOverview.energy_saved_kwh !== null
+              ? 'md:grid-cols-4'
+              : 'md:grid-cols-3'
+          }`}
+        >
+          <StatCard
+            title="Requests"
+            value={appOverview.total_requests}
+            icon={Activity}
+            iconColor="text-accent"
+            valueClassName="text-accent"
+          />
+          <StatCard
+            title="Avg Latency"
+            value={formatMs(Math.round(appOverview.avg_latency_ms))}
+            icon={Gauge}
+            iconColor="text-chart-1"
+            valueClassName="text-chart-1"
+          />
+          <StatCard
+            title="Error Rate"
+            value={formatFailureRate(
+              appOverview.error_rate * appOverview.total_requests,
+              appOverview.total_requests,
+            )}
+            icon={AlertTriangle}
+            iconColor={
+              appOverview.error_rate > 0
+                ? 'text-destructive'
+                : 'text-muted-foreground'
+            }
+            valueClassName={
+              appOverview.error_rate > 0
+                ? 'text-destructive'
+                : 'text-muted-foreground'
+            }
+          />
+          {appOverview.energy_saved_kwh !== null && (
+            <StatCard
+              title="Energy Saved"
+              value={formatKwh(appOverview.energy_saved_kwh)}
+              icon={Zap}
+              iconColor="text-chart-2"
+              valueClassName="text-chart-2"
+            />
+          )}
+        </div>
+      )}
+
       {/* Runs Timeline */}
       {isLoadingRuns && (
         <div className="mt-8">
@@ -288,6 +350,22 @@ function AppDetailPage() {
                             {startDate.toLocaleDateString()}{' '}
                             {startDate.toLocaleTimeString()}
                           </span>
+                          {run.interrupted && (
+                            <span
+                              className="px-2 py-0.5 rounded-full bg-muted text-muted-foreground text-xs"
+                              title="Penny exited while this run was open, so its end time is estimated"
+                            >
+                              interrupted
+                            </span>
+                          )}
+                          {run.dropped_lines > 0 && (
+                            <span
+                              className="px-2 py-0.5 rounded-full bg-muted text-muted-foreground text-xs"
+                              title="Output lines not stored for being over the app's capture_rate_limit"
+                            >
+                              {run.dropped_lines} lines dropped
+                            </span>
+                          )}
                         </div>
                         <div className="flex items-center gap-3">
                           {/* Log line counts */}
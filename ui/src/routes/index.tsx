@@ -12,7 +12,9 @@ import {
   Server,
   Sun,
   Timer,
+  Zap,
 } from 'lucide-react';
+import { AddAppForm } from '@/components/AddAppForm';
 import { ErrorBanner } from '@/components/ErrorBanner';
 import { PageContainer } from '@/components/PageContainer';
 import { StatCard, StatCardSkeleton } from '@/components/StatCard';
@@ -23,7 +25,12 @@ import {
 import { Skeleton } from '@/components/ui/skeleton';
 import { $fetch, type AppOverview } from '@/lib/api';
 import { getErrorDisplay } from '@/lib/error-utils';
-import { formatFailureRate, formatMs, formatRelativeTime } from '@/lib/format';
+import {
+  formatFailureRate,
+  formatKwh,
+  formatMs,
+  formatRelativeTime,
+} from '@/lib/format';
 import { timeRangeSearchSchema } from '@/lib/searchSchemas';
 
 export const Route = createFileRoute('/')({
@@ -324,6 +331,19 @@ function App() {
         ) : null}
       </div>
 
+      {/* Energy estimate, for apps that set `watts` */}
+      {totalOverview && totalOverview.energy_saved_kwh !== null && (
+        <div className="grid grid-cols-1 md:grid-cols-2 lg:grid-cols-5 gap-4 mt-4">
+          <StatCard
+            title="Energy Saved"
+            value={formatKwh(totalOverview.energy_saved_kwh)}
+            icon={Zap}
+            iconColor="text-chart-2"
+            valueClassName="text-chart-2"
+          />
+        </div>
+      )}
+
       {/* Apps List Section */}
       <div className="mt-10">
         <div className="flex items-center gap-3 mb-4">
@@ -341,11 +361,14 @@ function App() {
             ))}
           </div>
         ) : (
-          <div className="px-5 py-4 border border-border rounded bg-card flex items-center gap-3">
-            <Server className="w-5 h-5 text-muted-foreground shrink-0" />
-            <p className="text-muted-foreground text-sm">
-              No applications found.
-            </p>
+          <div className="space-y-4">
+            <div className="px-5 py-4 border border-border rounded bg-card flex items-center gap-3">
+              <Server className="w-5 h-5 text-muted-foreground shrink-0" />
+              <p className="text-muted-foreground text-sm">
+                No applications found.
+              </p>
+            </div>
+            {!appsError && <AddAppForm />}
           </div>
         )}
       </div>
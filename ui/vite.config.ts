@@ -8,6 +8,8 @@ import { fileURLToPath, URL } from 'node:url'
 
 // https://vitejs.dev/config/
 export default defineConfig({
+  // Relative asset paths, so the dashboard also works under /.penny/.
+  base: './',
   plugins: [
     devtools(),
     tanstackRouter({